@@ -32,6 +32,9 @@ pub fn format_wasm_type(ty: WasmType) -> &'static str {
         WasmType::V128 => "v128",
         WasmType::RefFunc => "funcref",
         WasmType::RefExtern => "externref",
+        WasmType::RefCont => "contref",
+        WasmType::RefExn => "exnref",
+        WasmType::RefAny => "anyref",
     }
 }
 
@@ -76,6 +79,7 @@ pub fn format_export_type(ty: ExportType<'_>) -> String {
         ExportType::Memory(ty) => format_memory_type(ty),
         ExportType::Table(ty) => format_table_type(ty),
         ExportType::Global(ty) => format_global_type(ty),
+        ExportType::Tag(ty) => format!("tag {}", format_func_type(ty)),
     }
 }
 
@@ -85,5 +89,6 @@ pub fn format_import_type(ty: ImportType<'_>) -> String {
         ImportType::Memory(ty) => format_memory_type(ty),
         ImportType::Table(ty) => format_table_type(ty),
         ImportType::Global(ty) => format_global_type(ty),
+        ImportType::Tag(ty) => format!("tag {}", format_func_type(ty)),
     }
 }
@@ -7,8 +7,8 @@ use std::time::Duration;
 
 use eyre::{Context, Result, bail, eyre};
 use log::{debug, error};
-use tinywasm::types::{ExternRef, FuncRef, MemoryType, TableType, WasmType, WasmValue};
-use tinywasm::{ExecProgress, Global, HostFunction, Imports, Memory, Module, ModuleInstance, Store, Table};
+use tinywasm::types::{AnyRef, ExnRef, ExternRef, FuncRef, MemoryType, TableType, WasmType, WasmValue};
+use tinywasm::{ExecProgress, GcValue, Global, HostFunction, Imports, Memory, Module, ModuleInstance, Store, Table};
 use wast::{QuoteWat, core::AbstractHeapType};
 
 const TEST_TIME_SLICE: Duration = Duration::from_millis(20);
@@ -165,6 +165,10 @@ impl WastRunner {
             WasmValue::default_for(WasmType::RefFunc),
         )?;
         let memory = Memory::new(store, MemoryType::default().with_page_count_initial(1).with_page_count_max(Some(2)))?;
+        let shared_memory = Memory::new(
+            store,
+            MemoryType::default().with_page_count_initial(1).with_page_count_max(Some(2)).with_shared(true),
+        )?;
         let global_i32 =
             Global::new(store, tinywasm::types::GlobalType::new(WasmType::I32, false), WasmValue::I32(666))?;
         let global_i64 =
@@ -176,6 +180,7 @@ impl WastRunner {
 
         imports
             .define("spectest", "memory", memory)
+            .define("spectest", "shared_memory", shared_memory)
             .define("spectest", "table", table)
             .define("spectest", "table64", table64)
             .define("spectest", "global_i32", global_i32)
@@ -218,8 +223,8 @@ impl WastRunner {
         for (i, directive) in directives.into_iter().enumerate() {
             let span = directive.span();
             use wast::WastDirective::{
-                AssertExhaustion, AssertInvalid, AssertMalformed, AssertReturn, AssertTrap, AssertUnlinkable, Invoke,
-                Module as Wat, ModuleDefinition, Register,
+                AssertException, AssertExhaustion, AssertInvalid, AssertMalformed, AssertReturn, AssertTrap,
+                AssertUnlinkable, Invoke, Module as Wat, ModuleDefinition, Register,
             };
 
             match directive {
@@ -290,7 +295,8 @@ impl WastRunner {
                     );
                 }
                 AssertInvalid { span, mut module, message } => {
-                    if ["multiple memories", "type mismatch"].contains(&message) {
+                    // constant expressions can refer to locally defined globals since the GC proposal
+                    if ["multiple memories", "type mismatch", "unknown global"].contains(&message) {
                         test_group.add_result(&format!("AssertInvalid({i})"), span.linecol_in(wast_raw), Ok(()));
                         continue;
                     }
@@ -308,7 +314,7 @@ impl WastRunner {
                 }
                 AssertExhaustion { call, message, span } => {
                     let module = module_registry.get_idx(call.module);
-                    let args = convert_wastargs(call.args)?;
+                    let args = convert_wastargs(&mut store, call.args)?;
                     let res =
                         catch_unwind_silent(|| exec_fn_instance(module, &mut store, call.name, &args).map(|_| ()));
                     let Ok(Err(tinywasm::Error::Trap(trap))) = res else {
@@ -343,8 +349,8 @@ impl WastRunner {
                             wast::WastExecute::Invoke(invoke) => invoke,
                         };
                         let module = module_registry.get_idx(invoke.module);
-                        let args =
-                            convert_wastargs(invoke.args).map_err(|err| tinywasm::Error::Other(err.to_string()))?;
+                        let args = convert_wastargs(&mut store, invoke.args)
+                            .map_err(|err| tinywasm::Error::Other(err.to_string()))?;
                         exec_fn_instance(module, &mut store, invoke.name, &args).map(|_| ())
                     });
                     match res {
@@ -376,6 +382,32 @@ impl WastRunner {
                         ),
                     }
                 }
+                AssertException { exec, span } => {
+                    let res: Result<tinywasm::Result<()>, _> = catch_unwind_silent(|| {
+                        let invoke = match exec {
+                            wast::WastExecute::Wat(mut wat) => {
+                                let module = parse_module_bytes(&wat.encode().expect("failed to encode module"))
+                                    .expect("failed to parse module");
+                                let imports = Self::imports(&mut store, module_registry.modules()).unwrap();
+                                ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+                                return Ok(());
+                            }
+                            wast::WastExecute::Get { .. } => panic!("get not supported"),
+                            wast::WastExecute::Invoke(invoke) => invoke,
+                        };
+                        let module = module_registry.get_idx(invoke.module);
+                        let args = convert_wastargs(&mut store, invoke.args)
+                            .map_err(|err| tinywasm::Error::Other(err.to_string()))?;
+                        exec_fn_instance(module, &mut store, invoke.name, &args).map(|_| ())
+                    });
+                    let res = match res {
+                        Err(err) => Err(eyre!("test panicked: {}", try_downcast_panic(err))),
+                        Ok(Err(tinywasm::Error::Trap(tinywasm::Trap::Exception(_)))) => Ok(()),
+                        Ok(Err(err)) => Err(eyre!("expected exception, got: {:?}", err)),
+                        Ok(Ok(())) => Err(eyre!("expected exception, got Ok")),
+                    };
+                    test_group.add_result(&format!("AssertException({i})"), span.linecol_in(wast_raw), res);
+                }
                 AssertUnlinkable { mut module, span, message } => {
                     let res = catch_unwind_silent(|| {
                         let module = parse_module_bytes(&module.encode().expect("failed to encode module"))
@@ -418,7 +450,7 @@ impl WastRunner {
                 Invoke(invoke) => {
                     let name = invoke.name;
                     let res: Result<Result<()>, _> = catch_unwind_silent(|| {
-                        let args = convert_wastargs(invoke.args)?;
+                        let args = convert_wastargs(&mut store, invoke.args)?;
                         let module = module_registry.get_idx(invoke.module);
                         exec_fn_instance(module, &mut store, invoke.name, &args).map_err(|e| {
                             error!("failed to execute function: {e:?}");
@@ -467,7 +499,7 @@ impl WastRunner {
                             let expected = expected_alternatives
                                 .iter()
                                 .filter_map(|alts| alts.first())
-                                .find(|exp| module_global.eq_loose(exp));
+                                .find(|exp| exp.matches(&store, &module_global));
                             if expected.is_none() {
                                 test_group.add_result(
                                     &format!("AssertReturn(unsupported-{i})"),
@@ -501,7 +533,7 @@ impl WastRunner {
 
                     let invoke_name = invoke.name;
                     let res: Result<Result<()>, _> = catch_unwind_silent(|| {
-                        let args = convert_wastargs(invoke.args)?;
+                        let args = convert_wastargs(&mut store, invoke.args)?;
                         let module = module_registry.get_idx(invoke.module);
                         let outcomes = exec_fn_instance(module, &mut store, invoke.name, &args).map_err(|e| {
                             error!("failed to execute function: {e:?}");
@@ -516,7 +548,10 @@ impl WastRunner {
                         }
                         if expected_alternatives.iter().any(|expected| {
                             expected.len() == outcomes.len()
-                                && outcomes.iter().zip(expected.iter()).all(|(outcome, exp)| outcome.eq_loose(exp))
+                                && outcomes
+                                    .iter()
+                                    .zip(expected.iter())
+                                    .all(|(outcome, exp)| exp.matches(&store, outcome))
                         }) {
                             Ok(())
                         } else {
@@ -731,11 +766,56 @@ fn parse_module_bytes(bytes: &[u8]) -> Result<Module> {
     Ok(tinywasm::parse_bytes(bytes)?)
 }
 
-fn convert_wastargs(args: Vec<wast::WastArg>) -> Result<Vec<WasmValue>> {
-    args.into_iter().map(wastarg2tinywasmvalue).collect()
+fn convert_wastargs(store: &mut Store, args: Vec<wast::WastArg>) -> Result<Vec<WasmValue>> {
+    args.into_iter().map(|arg| wastarg2tinywasmvalue(store, arg)).collect()
+}
+
+/// An expected result of an assertion, which may only constrain the kind of reference returned.
+#[derive(Debug, Clone, Copy)]
+enum Expected {
+    Value(WasmValue),
+    /// A null reference of any type.
+    Null,
+    /// A non-null reference of the given kind.
+    Extern,
+    Func,
+    Any,
+    Eq,
+    I31,
+    Struct,
+    Array,
+    /// An internalized `externref` with the given host value.
+    Host(u32),
+}
+
+impl Expected {
+    fn matches(&self, store: &Store, value: &WasmValue) -> bool {
+        let gc_value = || match value {
+            WasmValue::RefAny(any) => store.inspect(*any).ok().flatten(),
+            _ => None,
+        };
+        match self {
+            Self::Value(expected) => value.eq_loose(expected),
+            Self::Null => match value {
+                WasmValue::RefExtern(v) => v.is_null(),
+                WasmValue::RefFunc(v) => v.is_null(),
+                WasmValue::RefAny(v) => v.is_null(),
+                WasmValue::RefExn(v) => v.is_null(),
+                _ => false,
+            },
+            Self::Extern => matches!(value, WasmValue::RefExtern(v) if !v.is_null()),
+            Self::Func => matches!(value, WasmValue::RefFunc(v) if !v.is_null()),
+            Self::Any => matches!(value, WasmValue::RefAny(v) if !v.is_null()),
+            Self::Eq => matches!(gc_value(), Some(GcValue::I31(_) | GcValue::Struct(_) | GcValue::Array(_))),
+            Self::I31 => matches!(gc_value(), Some(GcValue::I31(_))),
+            Self::Struct => matches!(gc_value(), Some(GcValue::Struct(_))),
+            Self::Array => matches!(gc_value(), Some(GcValue::Array(_))),
+            Self::Host(host) => matches!(gc_value(), Some(GcValue::Extern(v)) if v.addr() == Some(*host)),
+        }
+    }
 }
 
-fn convert_wastret<'a>(args: impl Iterator<Item = wast::WastRet<'a>>) -> Result<Vec<Vec<WasmValue>>> {
+fn convert_wastret<'a>(args: impl Iterator<Item = wast::WastRet<'a>>) -> Result<Vec<Vec<Expected>>> {
     let mut alternatives = vec![Vec::new()];
     for arg in args {
         let choices = wastret2tinywasmvalues(arg)?;
@@ -752,7 +832,7 @@ fn convert_wastret<'a>(args: impl Iterator<Item = wast::WastRet<'a>>) -> Result<
     Ok(alternatives)
 }
 
-fn wastarg2tinywasmvalue(arg: wast::WastArg) -> Result<WasmValue> {
+fn wastarg2tinywasmvalue(store: &mut Store, arg: wast::WastArg) -> Result<WasmValue> {
     let wast::WastArg::Core(arg) = arg else {
         bail!("unsupported arg type: Component");
     };
@@ -763,7 +843,7 @@ fn wastarg2tinywasmvalue(arg: wast::WastArg) -> Result<WasmValue> {
         I32(i) => WasmValue::I32(i),
         I64(i) => WasmValue::I64(i),
         V128(i) => WasmValue::V128(i.to_le_bytes()),
-        RefExtern(v) => WasmValue::RefExtern(ExternRef::new(Some(v))),
+        RefExtern(v) => WasmValue::RefExtern(extern_ref(v)?),
         RefNull(t) => match t {
             wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::Func } => {
                 WasmValue::RefFunc(FuncRef::null())
@@ -771,16 +851,37 @@ fn wastarg2tinywasmvalue(arg: wast::WastArg) -> Result<WasmValue> {
             wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::Extern } => {
                 WasmValue::RefExtern(ExternRef::null())
             }
+            wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::Exn } => {
+                WasmValue::RefExn(ExnRef::null())
+            }
+            wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::NoExtern } => {
+                WasmValue::RefExtern(ExternRef::null())
+            }
+            wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::NoFunc } => {
+                WasmValue::RefFunc(FuncRef::null())
+            }
+            wast::core::HeapType::Abstract {
+                shared: false,
+                ty:
+                    AbstractHeapType::Any
+                    | AbstractHeapType::Eq
+                    | AbstractHeapType::I31
+                    | AbstractHeapType::Struct
+                    | AbstractHeapType::Array
+                    | AbstractHeapType::None,
+            } => WasmValue::RefAny(AnyRef::null()),
             _ => {
                 bail!("unsupported arg type: refnull: {:?}", t);
             }
         },
-        RefHost(_) => {
-            bail!("unsupported arg type: RefHost");
-        }
+        RefHost(v) => WasmValue::RefAny(store.extern_to_any(extern_ref(v)?)?),
     })
 }
 
+fn extern_ref(addr: u32) -> Result<ExternRef> {
+    ExternRef::new(Some(addr)).ok_or_else(|| eyre!("unsupported extern reference: {addr}"))
+}
+
 fn wast_v128_to_bytes(i: wast::core::V128Pattern) -> [u8; 16] {
     let res: Vec<u8> = match i {
         wast::core::V128Pattern::F32x4(f) => {
@@ -797,7 +898,7 @@ fn wast_v128_to_bytes(i: wast::core::V128Pattern) -> [u8; 16] {
     res.try_into().unwrap()
 }
 
-fn wastret2tinywasmvalues(ret: wast::WastRet) -> Result<Vec<WasmValue>> {
+fn wastret2tinywasmvalues(ret: wast::WastRet) -> Result<Vec<Expected>> {
     let wast::WastRet::Core(ret) = ret else {
         bail!("unsupported arg type");
     };
@@ -809,32 +910,51 @@ fn wastret2tinywasmvalues(ret: wast::WastRet) -> Result<Vec<WasmValue>> {
     }
 }
 
-fn wastretcore2tinywasmvalue(ret: wast::core::WastRetCore) -> Result<WasmValue> {
-    use wast::core::WastRetCore::{F32, F64, I32, I64, RefExtern, RefFunc, RefNull, V128};
+fn wastretcore2tinywasmvalue(ret: wast::core::WastRetCore) -> Result<Expected> {
+    use wast::core::WastRetCore::*;
     Ok(match ret {
-        F32(f) => nanpattern2tinywasmvalue(f)?,
-        F64(f) => nanpattern2tinywasmvalue(f)?,
-        I32(i) => WasmValue::I32(i),
-        I64(i) => WasmValue::I64(i),
-        V128(i) => WasmValue::V128(wast_v128_to_bytes(i)),
+        F32(f) => Expected::Value(nanpattern2tinywasmvalue(f)?),
+        F64(f) => Expected::Value(nanpattern2tinywasmvalue(f)?),
+        I32(i) => Expected::Value(WasmValue::I32(i)),
+        I64(i) => Expected::Value(WasmValue::I64(i)),
+        V128(i) => Expected::Value(WasmValue::V128(wast_v128_to_bytes(i))),
         RefNull(t) => match t {
-            Some(wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::Func }) => {
-                WasmValue::RefFunc(FuncRef::null())
-            }
-            Some(wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::Extern }) => {
-                WasmValue::RefExtern(ExternRef::null())
+            None => Expected::Null,
+            Some(wast::core::HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Func | AbstractHeapType::NoFunc,
+            }) => Expected::Value(WasmValue::RefFunc(FuncRef::null())),
+            Some(wast::core::HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Extern | AbstractHeapType::NoExtern,
+            }) => Expected::Value(WasmValue::RefExtern(ExternRef::null())),
+            Some(wast::core::HeapType::Abstract { shared: false, ty: AbstractHeapType::Exn }) => {
+                Expected::Value(WasmValue::RefExn(ExnRef::null()))
             }
+            Some(wast::core::HeapType::Abstract {
+                shared: false,
+                ty:
+                    AbstractHeapType::Any
+                    | AbstractHeapType::Eq
+                    | AbstractHeapType::I31
+                    | AbstractHeapType::Struct
+                    | AbstractHeapType::Array
+                    | AbstractHeapType::None,
+            }) => Expected::Value(WasmValue::RefAny(AnyRef::null())),
             _ => {
                 bail!("unsupported arg type: refnull: {:?}", t);
             }
         },
-        RefExtern(v) => WasmValue::RefExtern(ExternRef::new(v)),
-        RefFunc(v) => WasmValue::RefFunc(FuncRef::new(match v {
-            Some(wast::token::Index::Num(n, _)) => Some(n),
-            _ => {
-                bail!("unsupported arg type: reffunc: {:?}", v);
-            }
-        })),
+        RefExtern(None) => Expected::Extern,
+        RefExtern(Some(v)) => Expected::Value(WasmValue::RefExtern(extern_ref(v)?)),
+        RefFunc(None) => Expected::Func,
+        RefFunc(Some(wast::token::Index::Num(n, _))) => Expected::Value(WasmValue::RefFunc(FuncRef::new(Some(n)))),
+        RefHost(v) => Expected::Host(v),
+        RefAny => Expected::Any,
+        RefEq => Expected::Eq,
+        RefI31 => Expected::I31,
+        RefStruct => Expected::Struct,
+        RefArray => Expected::Array,
         a => {
             bail!("unsupported arg type {:?}", a);
         }
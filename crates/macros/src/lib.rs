@@ -0,0 +1,306 @@
+#![doc(test(
+    no_crate_inject,
+    attr(deny(warnings, rust_2018_idioms), allow(dead_code, unused_assignments, unused_variables))
+))]
+#![warn(missing_docs, rust_2018_idioms, unreachable_pub)]
+#![forbid(unsafe_code)]
+
+//! Procedural macros for [`tinywasm`](https://docs.rs/tinywasm).
+//!
+//! Enable the `macros` feature of `tinywasm` and use `tinywasm::host_module` instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{Error, FnArg, ImplItem, ImplItemFn, ItemImpl, LitStr, Pat, ReturnType, Type};
+
+/// Implement `tinywasm::HostModule` for the type of an `impl` block.
+///
+/// Every method taking `&self` becomes a host function named after the method. The type has to
+/// implement `Clone`, as every host function keeps its own clone; share mutable state through
+/// an `Rc<RefCell<_>>` or similar.
+///
+/// - Parameters of types that implement tinywasm's `FromWasmValues`, like `i32`, `f64` or `WasmPtr<T>`, are passed through.
+/// - `&str` and `&[u8]` parameters are passed by the guest as an `i32` pointer and length into its exported memory,
+///   `memory` unless set with `#[host_module(memory = "...")]`. Strings have to be valid UTF-8.
+///   They are copied out of the memory, so `&mut str` and `&mut [u8]` are rejected; use a `WasmPtr<T>` and
+///   a `FuncContext<'_>` to write to the guest's memory instead.
+/// - A `FuncContext<'_>` parameter receives the calling context.
+/// - `Result` return types are mapped to traps with the error converted into `tinywasm::Error` using `Into`.
+///
+/// The module name defaults to `env` and is set with `#[host_module(name = "...")]`. Methods can be
+/// renamed with `#[host(name = "...")]` or excluded with `#[host(skip)]`.
+///
+/// ## Example
+/// ```rust
+/// # fn main() -> tinywasm::Result<()> {
+/// use tinywasm::{Imports, ModuleInstance, Store, host_module};
+/// # let wasm = wat::parse_str(r#"
+/// #     (module
+/// #       (import "env" "add" (func $add (param i32 i32) (result i32)))
+/// #       (import "env" "count" (func $count (param i32 i32) (result i32)))
+/// #       (memory (export "memory") 1)
+/// #       (data (i32.const 0) "hello")
+/// #       (func (export "run") (result i32)
+/// #         (call $add (call $count (i32.const 0) (i32.const 5)) (i32.const 1))))
+/// # "#).expect("valid wat");
+/// # let module = tinywasm::parse_bytes(&wasm)?;
+///
+/// #[derive(Clone)]
+/// struct Env;
+///
+/// #[host_module]
+/// impl Env {
+///     fn add(&self, a: i32, b: i32) -> i32 {
+///         a + b
+///     }
+///
+///     #[host(name = "count")]
+///     fn count_chars(&self, text: &str) -> i32 {
+///         text.chars().count() as i32
+///     }
+/// }
+///
+/// let mut store = Store::default();
+/// let mut imports = Imports::new();
+/// imports.define_module(&mut store, &Env)?;
+/// let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+/// assert_eq!(instance.func::<(), i32>(&store, "run")?.call(&mut store, ())?, 6);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Mutable slices fail to compile:
+/// ```compile_fail
+/// # use tinywasm::host_module;
+/// # #[derive(Clone)]
+/// # struct Env;
+/// #[host_module]
+/// impl Env {
+///     fn fill(&self, bytes: &mut [u8]) {
+///         bytes.fill(0);
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn host_module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = ModuleArgs { name: None, memory: None };
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            args.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("memory") {
+            args.memory = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("expected `name` or `memory`"));
+        }
+        Ok(())
+    });
+    syn::parse_macro_input!(attr with parser);
+
+    let mut item = syn::parse_macro_input!(item as ItemImpl);
+    match expand(&args, &mut item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct ModuleArgs {
+    name: Option<LitStr>,
+    memory: Option<LitStr>,
+}
+
+/// How a Rust parameter is passed by the guest.
+enum Param {
+    /// A value converted with `FromWasmValues`.
+    Value(syn::Ident, Box<Type>),
+    /// A string as pointer and length.
+    Str(syn::Ident),
+    /// A byte slice as pointer and length.
+    Bytes(syn::Ident),
+    /// The calling context.
+    Context(syn::Ident),
+}
+
+fn expand(args: &ModuleArgs, item: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new(path.span(), "`#[host_module]` has to be used on an inherent impl block"));
+    }
+
+    let module_name = args.name.as_ref().map_or_else(|| "env".to_string(), LitStr::value);
+    let memory_name = args.memory.as_ref().map_or_else(|| "memory".to_string(), LitStr::value);
+
+    let mut funcs = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else { continue };
+        let Some(name) = host_attr(method)? else { continue };
+        if let Some(func) = host_func(method, &name, &memory_name)? {
+            funcs.push(func);
+        }
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::tinywasm::HostModule for #self_ty #where_clause {
+            fn name(&self) -> &str {
+                #module_name
+            }
+
+            fn externs(
+                &self,
+                store: &mut ::tinywasm::Store,
+            ) -> ::tinywasm::Result<::tinywasm::__private::Vec<(::tinywasm::__private::String, ::tinywasm::Extern)>> {
+                ::core::result::Result::Ok(::tinywasm::__private::vec![#(#funcs),*])
+            }
+        }
+    })
+}
+
+/// Remove the `#[host(...)]` attributes of a method, returning its import name unless it's skipped.
+fn host_attr(method: &mut ImplItemFn) -> syn::Result<Option<String>> {
+    let mut name = method.sig.ident.to_string();
+    let mut skip = false;
+    let mut result = Ok(());
+    method.attrs.retain(|attr| {
+        if !attr.path().is_ident("host") {
+            return true;
+        }
+
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("skip") {
+                skip = true;
+            } else {
+                return Err(meta.error("expected `name` or `skip`"));
+            }
+            Ok(())
+        });
+        if let Err(err) = parsed {
+            result = Err(err);
+        }
+        false
+    });
+
+    result?;
+    Ok((!skip).then_some(name))
+}
+
+/// Generate the `(name, Extern)` pair for a method, or `None` if it doesn't take `&self`.
+fn host_func(method: &ImplItemFn, name: &str, memory_name: &str) -> syn::Result<Option<TokenStream2>> {
+    let Some(receiver) = method.sig.receiver() else { return Ok(None) };
+    if receiver.reference.is_none() || receiver.mutability.is_some() {
+        return Err(Error::new(receiver.span(), "host functions have to take `&self`"));
+    }
+    if let Some(asyncness) = method.sig.asyncness {
+        return Err(Error::new(asyncness.span(), "host functions can't be async"));
+    }
+
+    let mut params = Vec::new();
+    for (i, arg) in method.sig.inputs.iter().skip(1).enumerate() {
+        let FnArg::Typed(arg) = arg else { continue };
+        let ident = match &*arg.pat {
+            Pat::Ident(pat) => pat.ident.clone(),
+            _ => format_ident!("arg{i}"),
+        };
+
+        params.push(match &*arg.ty {
+            Type::Reference(ty) if ty.mutability.is_some() && (is_str(&ty.elem) || is_bytes(&ty.elem)) => {
+                return Err(Error::new(
+                    ty.span(),
+                    "host functions can't take mutable slices of guest memory, use `WasmPtr` and `FuncContext` instead",
+                ));
+            }
+            Type::Reference(ty) if is_str(&ty.elem) => Param::Str(ident),
+            Type::Reference(ty) if is_bytes(&ty.elem) => Param::Bytes(ident),
+            ty if last_segment_is(ty, "FuncContext") => Param::Context(ident),
+            ty => Param::Value(ident, Box::new(ty.clone())),
+        });
+    }
+
+    if params.iter().filter(|param| matches!(param, Param::Context(_))).count() > 1 {
+        return Err(Error::new(method.sig.span(), "host functions can take at most one `FuncContext`"));
+    }
+
+    // the guest passes slices as pointer and length into its memory
+    let mut wasm_params = Vec::new();
+    let mut wasm_types = Vec::new();
+    let mut reads = Vec::new();
+    let mut call_args = Vec::new();
+    let mut context = None;
+    for param in &params {
+        match param {
+            Param::Value(ident, ty) => {
+                wasm_params.push(ident.clone());
+                wasm_types.push(quote!(#ty));
+                call_args.push(quote!(#ident));
+            }
+            Param::Str(ident) | Param::Bytes(ident) => {
+                let (ptr, len) = (format_ident!("__{ident}_ptr"), format_ident!("__{ident}_len"));
+                wasm_params.extend([ptr.clone(), len.clone()]);
+                wasm_types.extend([quote!(i32), quote!(i32)]);
+                reads.push(quote! {
+                    let #ident = ::tinywasm::FuncContext::memory(&_ctx, #memory_name)?.read_vec(
+                        ::tinywasm::FuncContext::store(&_ctx),
+                        #ptr as u32 as usize,
+                        #len as u32 as usize,
+                    )?;
+                });
+                if let Param::Str(_) = param {
+                    let message = format!("invalid UTF-8 in argument `{ident}` of host function `{name}`");
+                    reads.push(quote! {
+                        let #ident = ::core::str::from_utf8(&#ident)
+                            .map_err(|_| ::tinywasm::Error::Other(::core::convert::Into::into(#message)))?;
+                    });
+                    call_args.push(quote!(#ident));
+                } else {
+                    call_args.push(quote!(&#ident));
+                }
+            }
+            Param::Context(ident) => {
+                // the context is moved into the call after all slices were read
+                context = Some(quote!(let #ident = _ctx;));
+                call_args.push(quote!(#ident));
+            }
+        }
+    }
+
+    let method_name = &method.sig.ident;
+    let call = quote!(this.#method_name(#(#call_args),*));
+    let result = match &method.sig.output {
+        ReturnType::Type(_, ty) if last_segment_is(ty, "Result") => quote!(#call?),
+        _ => call,
+    };
+
+    Ok(Some(quote! {
+        (
+            ::tinywasm::__private::String::from(#name),
+            ::tinywasm::Extern::from({
+                let this = ::core::clone::Clone::clone(self);
+                ::tinywasm::HostFunction::from(
+                    store,
+                    move |_ctx: ::tinywasm::FuncContext<'_>, (#(#wasm_params,)*): (#(#wasm_types,)*)| {
+                        #(#reads)*
+                        #context
+                        ::core::result::Result::Ok(#result)
+                    },
+                )
+            }),
+        )
+    }))
+}
+
+fn is_str(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.qself.is_none() && path.path.is_ident("str"))
+}
+
+fn is_bytes(ty: &Type) -> bool {
+    matches!(ty, Type::Slice(slice) if matches!(&*slice.elem, Type::Path(path) if path.path.is_ident("u8")))
+}
+
+fn last_segment_is(ty: &Type, name: &str) -> bool {
+    matches!(ty, Type::Path(path) if path.path.segments.last().is_some_and(|segment| segment.ident == name))
+}
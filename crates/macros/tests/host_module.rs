@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use eyre::Result;
+use tinywasm::types::{ExternType, FuncType, WasmType};
+use tinywasm::{Error, FuncContext, HostModule, Imports, ModuleInstance, Store, Trap, host_module};
+
+#[derive(Debug)]
+struct InvalidInput(i32);
+
+impl From<InvalidInput> for Error {
+    fn from(err: InvalidInput) -> Self {
+        Error::Other(format!("invalid input: {}", err.0))
+    }
+}
+
+#[derive(Clone, Default)]
+struct Console {
+    lines: Rc<RefCell<Vec<String>>>,
+}
+
+#[host_module(name = "console", memory = "mem")]
+impl Console {
+    fn log(&self, message: &str) {
+        self.lines.borrow_mut().push(message.to_string());
+    }
+
+    #[host(name = "checksum")]
+    fn sum_bytes(&self, bytes: &[u8], seed: i64) -> i64 {
+        bytes.iter().fold(seed, |sum, byte| sum + i64::from(*byte))
+    }
+
+    fn checked_double(&self, value: i32) -> Result<i32, InvalidInput> {
+        value.checked_mul(2).ok_or(InvalidInput(value))
+    }
+
+    fn fuel(&self, ctx: FuncContext<'_>) -> i32 {
+        ctx.remaining_fuel() as i32
+    }
+
+    #[host(skip)]
+    fn lines(&self) -> Vec<String> {
+        self.lines.borrow().clone()
+    }
+}
+
+#[test]
+fn slices_are_read_from_guest_memory() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "console" "log" (func $log (param i32 i32)))
+          (import "console" "checksum" (func $checksum (param i32 i32 i64) (result i64)))
+          (memory (export "mem") 1)
+          (data (i32.const 0) "hello\01\02\03\ff")
+          (func (export "log") (param i32 i32) (call $log (local.get 0) (local.get 1)))
+          (func (export "checksum") (result i64) (call $checksum (i32.const 5) (i32.const 3) (i64.const 10))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let console = Console::default();
+    let mut store = Store::default();
+    let mut imports = Imports::new();
+    imports.define_module(&mut store, &console)?;
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+
+    instance.func::<(i32, i32), ()>(&store, "log")?.call(&mut store, (0, 5))?;
+    assert_eq!(console.lines(), ["hello"]);
+    assert_eq!(instance.func::<(), i64>(&store, "checksum")?.call(&mut store, ())?, 16);
+
+    // invalid UTF-8 and out of bounds slices trap
+    let log = instance.func::<(i32, i32), ()>(&store, "log")?;
+    assert!(matches!(log.call(&mut store, (8, 1)), Err(Error::Trap(Trap::HostFunction(_)))));
+    assert!(matches!(log.call(&mut store, (65535, 2)), Err(Error::Trap(Trap::HostFunction(_)))));
+    assert_eq!(console.lines(), ["hello"]);
+    Ok(())
+}
+
+#[test]
+fn errors_are_mapped_to_traps() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "console" "checked_double" (func $checked_double (param i32) (result i32)))
+          (func (export "double") (param i32) (result i32) (call $checked_double (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let mut imports = Imports::new();
+    imports.define_module(&mut store, &Console::default())?;
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let double = instance.func::<i32, i32>(&store, "double")?;
+
+    assert_eq!(double.call(&mut store, 21)?, 42);
+    let Err(Error::Trap(Trap::HostFunction(err))) = double.call(&mut store, i32::MAX) else {
+        panic!("expected a host function trap");
+    };
+    assert_eq!(err.to_string(), "unknown error: invalid input: 2147483647");
+    Ok(())
+}
+
+#[test]
+fn host_module_describes_its_functions() -> Result<()> {
+    let console = Console::default();
+    assert_eq!(console.name(), "console");
+
+    let types = console.extern_types()?;
+    let names: Vec<_> = types.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["log", "checksum", "checked_double", "fuel"]);
+    assert_eq!(
+        types[1].1,
+        ExternType::Func(FuncType::new(&[WasmType::I32, WasmType::I32, WasmType::I64], &[WasmType::I64]))
+    );
+    assert_eq!(types[3].1, ExternType::Func(FuncType::new(&[], &[WasmType::I32])));
+
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "console" "checksum" (func (param i32 i32 i64) (result i64)))
+          (import "console" "fuel" (func (result i32))))
+        "#,
+    )?;
+    console.check_imports(&tinywasm::parse_bytes(&wasm)?)?;
+    Ok(())
+}
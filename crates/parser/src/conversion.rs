@@ -1,18 +1,40 @@
 use alloc::sync::Arc;
 
 use crate::{Result, module::FunctionCode, visit::process_operators_and_validate};
-use alloc::{boxed::Box, format, string::ToString, vec::Vec};
+use alloc::{boxed::Box, format, vec::Vec};
 use tinywasm_types::*;
 use wasmparser::{
     CompositeInnerType, FuncValidator, FuncValidatorAllocations, OperatorsReader, OperatorsReaderAllocations,
     ValidatorResources,
 };
 
-pub(crate) fn convert_module_element(element: wasmparser::Element<'_>) -> Result<tinywasm_types::Element> {
+/// The kind of a type defined in the type section.
+///
+/// Continuation types are stored as the function type they wrap and struct and array
+/// types as a placeholder, so this is needed to tell concrete references apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TypeKind {
+    /// A function type, with the type index of its last parameter if that is a concrete reference.
+    /// `switch` uses this to find the continuation type it switches to.
+    Func {
+        last_param: Option<u32>,
+    },
+    /// A continuation type, with the index of the function type it wraps.
+    Cont {
+        func: u32,
+    },
+    Struct,
+    Array,
+}
+
+pub(crate) fn convert_module_element(
+    element: wasmparser::Element<'_>,
+    types: &[TypeKind],
+) -> Result<tinywasm_types::Element> {
     let kind = match element.kind {
         wasmparser::ElementKind::Active { table_index, offset_expr } => tinywasm_types::ElementKind::Active {
             table: table_index.unwrap_or(0),
-            offset: process_const_operators(offset_expr.get_operators_reader())?,
+            offset: process_const_operators(offset_expr.get_operators_reader(), types)?,
         },
         wasmparser::ElementKind::Passive => tinywasm_types::ElementKind::Passive,
         wasmparser::ElementKind::Declared => tinywasm_types::ElementKind::Declared,
@@ -32,22 +54,22 @@ pub(crate) fn convert_module_element(element: wasmparser::Element<'_>) -> Result
         wasmparser::ElementItems::Expressions(ty, exprs) => {
             let items = exprs
                 .into_iter()
-                .map(|expr| Ok(ElementItem::Expr(process_const_operators(expr?.get_operators_reader())?)))
+                .map(|expr| Ok(ElementItem::Expr(process_const_operators(expr?.get_operators_reader(), types)?)))
                 .collect::<Result<Vec<_>>>()?
                 .into_boxed_slice();
 
-            Ok(tinywasm_types::Element { kind, items, ty: convert_reftype(ty)?, range: element.range })
+            Ok(tinywasm_types::Element { kind, items, ty: convert_reftype(ty, types)?, range: element.range })
         }
     }
 }
 
-pub(crate) fn convert_module_data(data: wasmparser::Data<'_>) -> Result<tinywasm_types::Data> {
+pub(crate) fn convert_module_data(data: wasmparser::Data<'_>, types: &[TypeKind]) -> Result<tinywasm_types::Data> {
     Ok(tinywasm_types::Data {
         data: data.data.to_vec().into_boxed_slice(),
         range: data.range,
         kind: match data.kind {
             wasmparser::DataKind::Active { memory_index, offset_expr } => {
-                let offset = process_const_operators(offset_expr.get_operators_reader())?;
+                let offset = process_const_operators(offset_expr.get_operators_reader(), types)?;
                 tinywasm_types::DataKind::Active { mem: memory_index, offset }
             }
             wasmparser::DataKind::Passive => tinywasm_types::DataKind::Passive,
@@ -55,24 +77,13 @@ pub(crate) fn convert_module_data(data: wasmparser::Data<'_>) -> Result<tinywasm
     })
 }
 
-pub(crate) fn convert_module_import(import: wasmparser::Import<'_>) -> Result<Import> {
+pub(crate) fn convert_module_import(import: wasmparser::Import<'_>, types: &[TypeKind]) -> Result<Import> {
     let kind = match import.ty {
         wasmparser::TypeRef::Func(ty) => ImportKind::Function(ty),
-        wasmparser::TypeRef::Table(ty) => {
-            let element_type = convert_reftype(ty.element_type)?;
-            ImportKind::Table(if ty.table64 {
-                TableType::new64(element_type, ty.initial, ty.maximum)
-            } else {
-                TableType::new(element_type, ty.initial, ty.maximum)
-            })
-        }
+        wasmparser::TypeRef::Table(ty) => ImportKind::Table(convert_module_table(ty, types)?),
         wasmparser::TypeRef::Memory(ty) => ImportKind::Memory(convert_module_memory(ty)),
-        wasmparser::TypeRef::Global(ty) => {
-            ImportKind::Global(GlobalType::new(convert_valtype(&ty.content_type)?, ty.mutable))
-        }
-        wasmparser::TypeRef::Tag(ty) => {
-            return Err(crate::ParseError::UnsupportedOperator(format!("Unsupported import kind: {ty:?}")));
-        }
+        wasmparser::TypeRef::Global(ty) => ImportKind::Global(convert_global_type(ty, types)?),
+        wasmparser::TypeRef::Tag(ty) => ImportKind::Tag(ty.func_type_idx),
         _ => {
             return Err(crate::ParseError::UnsupportedOperator(format!("Unsupported import kind: {:?}", import.ty)));
         }
@@ -81,6 +92,33 @@ pub(crate) fn convert_module_import(import: wasmparser::Import<'_>) -> Result<Im
     Ok(Import { module: import.module.into(), name: import.name.into(), kind })
 }
 
+pub(crate) fn convert_module_table(table: wasmparser::TableType, types: &[TypeKind]) -> Result<TableType> {
+    let element_type = convert_reftype(table.element_type, types)?;
+    let ty = match table.table64 {
+        true => TableType::new64(element_type, table.initial, table.maximum),
+        false => TableType::new(element_type, table.initial, table.maximum),
+    };
+    Ok(match precise_ref_type(table.element_type, element_type)? {
+        Some(precise) => ty.with_element_ref_type(precise),
+        None => ty,
+    })
+}
+
+pub(crate) fn convert_global_type(global: wasmparser::GlobalType, types: &[TypeKind]) -> Result<GlobalType> {
+    let ty = GlobalType::new(convert_valtype(&global.content_type, types)?, global.mutable);
+    let wasmparser::ValType::Ref(reftype) = global.content_type else { return Ok(ty) };
+    Ok(match precise_ref_type(reftype, ty.ty)? {
+        Some(precise) => ty.with_ref_type(precise),
+        None => ty,
+    })
+}
+
+/// The precise type of a reference, if it is more specific than a nullable reference to the top type of `ty`.
+fn precise_ref_type(reftype: wasmparser::RefType, ty: WasmType) -> Result<Option<RefType>> {
+    let precise = convert_gc_reftype(reftype, 0)?;
+    Ok((Some(precise) != RefType::top_of(ty)).then_some(precise))
+}
+
 pub(crate) fn convert_module_memory(memory: wasmparser::MemoryType) -> MemoryType {
     MemoryType::new(
         if memory.memory64 { MemoryArch::I64 } else { MemoryArch::I32 },
@@ -88,18 +126,19 @@ pub(crate) fn convert_module_memory(memory: wasmparser::MemoryType) -> MemoryTyp
         memory.maximum,
         memory.page_size_log2.map(|x| 1 << x),
     )
+    .with_shared(memory.shared)
 }
 
 pub(crate) fn convert_module_globals(
     globals: wasmparser::SectionLimited<'_, wasmparser::Global<'_>>,
+    types: &[TypeKind],
 ) -> Result<Box<[Global]>> {
     globals
         .into_iter()
         .map(|global| {
             let global = global?;
-            let ty = convert_valtype(&global.ty.content_type)?;
             let ops = global.init_expr.get_operators_reader();
-            Ok(Global { init: process_const_operators(ops)?, ty: GlobalType::new(ty, global.ty.mutable) })
+            Ok(Global { init: process_const_operators(ops, types)?, ty: convert_global_type(global.ty, types)? })
         })
         .collect::<Result<Box<_>>>()
 }
@@ -110,7 +149,8 @@ pub(crate) fn convert_module_export(export: wasmparser::Export<'_>) -> Result<Ex
         wasmparser::ExternalKind::Table => ExternalKind::Table,
         wasmparser::ExternalKind::Memory => ExternalKind::Memory,
         wasmparser::ExternalKind::Global => ExternalKind::Global,
-        wasmparser::ExternalKind::Tag | wasmparser::ExternalKind::FuncExact => {
+        wasmparser::ExternalKind::Tag => ExternalKind::Tag,
+        wasmparser::ExternalKind::FuncExact => {
             return Err(crate::ParseError::UnsupportedOperator(format!("Unsupported export kind: {:?}", export.kind)));
         }
     };
@@ -165,49 +205,201 @@ pub(crate) fn convert_module_code(
     ))
 }
 
-pub(crate) fn convert_module_type(ty: wasmparser::RecGroup) -> Result<Arc<FuncType>> {
-    let mut types = ty.types();
-    if types.len() != 1 {
-        return Err(crate::ParseError::UnsupportedOperator(
-            "Expected exactly one type in the type section".to_string(),
-        ));
+/// Converts a recursion group of the type section and records the kind of each of its types in `types`.
+///
+/// Returns the precise type of each member along with its function type. Continuation types resolve
+/// to a copy of the function type they wrap, struct and array types to an empty placeholder.
+pub(crate) fn convert_module_rec_group(
+    group: wasmparser::RecGroup,
+    func_types: &[Arc<FuncType>],
+    types: &mut Vec<TypeKind>,
+) -> Result<Vec<(SubType, Arc<FuncType>)>> {
+    let start = types.len() as u32;
+    let group = group.into_types().collect::<Vec<_>>();
+
+    // kinds are recorded first, since the types of a group may refer to each other
+    for ty in &group {
+        if ty.composite_type.shared {
+            return Err(crate::ParseError::UnsupportedOperator(format!("Unsupported shared type: {ty}")));
+        }
+        types.push(match &ty.composite_type.inner {
+            CompositeInnerType::Func(func) => TypeKind::Func {
+                last_param: func.params().last().and_then(|ty| match ty {
+                    wasmparser::ValType::Ref(r) => {
+                        r.type_index().and_then(|idx| module_type_index(idx.unpack(), start))
+                    }
+                    _ => None,
+                }),
+            },
+            CompositeInnerType::Cont(cont) => TypeKind::Cont {
+                func: module_type_index(cont.0.unpack(), start).ok_or_else(|| {
+                    crate::ParseError::UnsupportedOperator(format!("Unsupported continuation type: {cont}"))
+                })?,
+            },
+            CompositeInnerType::Struct(_) => TypeKind::Struct,
+            CompositeInnerType::Array(_) => TypeKind::Array,
+        });
+    }
+
+    let mut converted: Vec<(SubType, Arc<FuncType>)> = Vec::with_capacity(group.len());
+    for ty in group {
+        let (composite, func_ty) = match ty.composite_type.inner {
+            CompositeInnerType::Func(func) => {
+                let params =
+                    func.params().iter().map(|ty| convert_gc_valtype(ty, start)).collect::<Result<Box<_>>>()?;
+                let results =
+                    func.results().iter().map(|ty| convert_gc_valtype(ty, start)).collect::<Result<Box<_>>>()?;
+                let func_ty = FuncType::new(
+                    &params.iter().map(|ty| valtype_to_wasm_type(ty, types)).collect::<Result<Vec<_>>>()?,
+                    &results.iter().map(|ty| valtype_to_wasm_type(ty, types)).collect::<Result<Vec<_>>>()?,
+                );
+                (CompositeType::Func { params, results }, Arc::new(func_ty))
+            }
+            CompositeInnerType::Cont(cont) => {
+                let func = module_type_index(cont.0.unpack(), start).unwrap_or(u32::MAX);
+                let func_ty = match func.checked_sub(start) {
+                    Some(idx) => converted.get(idx as usize).map(|(_, ty)| ty),
+                    None => func_types.get(func as usize),
+                };
+                let func_ty = func_ty.filter(|_| matches!(types.get(func as usize), Some(TypeKind::Func { .. })));
+                let func_ty = func_ty.cloned().ok_or_else(|| {
+                    crate::ParseError::UnsupportedOperator(format!("Unsupported continuation type: {cont}"))
+                })?;
+                (CompositeType::Cont(func), func_ty)
+            }
+            CompositeInnerType::Struct(ty) => {
+                let fields = ty.fields.iter().map(|field| convert_field_type(field, start)).collect::<Result<_>>()?;
+                (CompositeType::Struct(fields), Arc::default())
+            }
+            CompositeInnerType::Array(ty) => (CompositeType::Array(convert_field_type(&ty.0, start)?), Arc::default()),
+        };
+
+        let supertype = ty.supertype_idx.and_then(|idx| module_type_index(idx.unpack(), start));
+        converted.push((SubType { is_final: ty.is_final, supertype, composite }, func_ty));
+    }
+
+    Ok(converted)
+}
+
+/// Resolves a type index to the module's index space, with `rec_group_start` being the first type
+/// of the recursion group it is used in.
+fn module_type_index(idx: wasmparser::UnpackedIndex, rec_group_start: u32) -> Option<u32> {
+    match idx {
+        wasmparser::UnpackedIndex::Module(idx) => Some(idx),
+        wasmparser::UnpackedIndex::RecGroup(idx) => Some(rec_group_start + idx),
+        wasmparser::UnpackedIndex::Id(_) => None,
     }
+}
 
-    let ty = types.next().unwrap();
-    let CompositeInnerType::Func(ty) = &ty.composite_type.inner else {
-        return Err(crate::ParseError::UnsupportedOperator(format!(
-            "Unsupported non-function type in type section: {}",
-            ty.composite_type
-        )));
+fn convert_field_type(field: &wasmparser::FieldType, rec_group_start: u32) -> Result<FieldType> {
+    let storage = match &field.element_type {
+        wasmparser::StorageType::I8 => StorageType::I8,
+        wasmparser::StorageType::I16 => StorageType::I16,
+        wasmparser::StorageType::Val(ty) => StorageType::Val(convert_gc_valtype(ty, rec_group_start)?),
     };
-    let params = ty.params().iter().map(convert_valtype).collect::<Result<Vec<_>>>()?;
-    let results = ty.results().iter().map(convert_valtype).collect::<Result<Vec<_>>>()?;
-    Ok(FuncType::new(&params, &results).into())
+    Ok(FieldType { storage, mutable: field.mutable })
 }
 
-pub(crate) fn convert_reftype(reftype: wasmparser::RefType) -> Result<WasmType> {
-    match reftype {
-        _ if reftype.is_func_ref() => Ok(WasmType::RefFunc),
-        _ if reftype.is_extern_ref() => Ok(WasmType::RefExtern),
-        _ => Err(crate::ParseError::UnsupportedOperator(format!(
+/// Converts a value type, keeping the precise type of references.
+pub(crate) fn convert_gc_valtype(valtype: &wasmparser::ValType, rec_group_start: u32) -> Result<ValType> {
+    Ok(match valtype {
+        wasmparser::ValType::I32 => ValType::I32,
+        wasmparser::ValType::I64 => ValType::I64,
+        wasmparser::ValType::F32 => ValType::F32,
+        wasmparser::ValType::F64 => ValType::F64,
+        wasmparser::ValType::V128 => ValType::V128,
+        wasmparser::ValType::Ref(r) => ValType::Ref(convert_gc_reftype(*r, rec_group_start)?),
+    })
+}
+
+/// Converts a reference type, keeping its heap type and nullability.
+pub(crate) fn convert_gc_reftype(reftype: wasmparser::RefType, rec_group_start: u32) -> Result<RefType> {
+    Ok(RefType::new(reftype.is_nullable(), convert_gc_heaptype(reftype.heap_type(), rec_group_start)?))
+}
+
+/// Converts a heap type, keeping concrete type indices.
+pub(crate) fn convert_gc_heaptype(heap: wasmparser::HeapType, rec_group_start: u32) -> Result<HeapType> {
+    use wasmparser::AbstractHeapType as Abstract;
+    match heap {
+        wasmparser::HeapType::Abstract { shared: false, ty } => Ok(match ty {
+            Abstract::Func => HeapType::Func,
+            Abstract::NoFunc => HeapType::NoFunc,
+            Abstract::Extern => HeapType::Extern,
+            Abstract::NoExtern => HeapType::NoExtern,
+            Abstract::Any => HeapType::Any,
+            Abstract::Eq => HeapType::Eq,
+            Abstract::I31 => HeapType::I31,
+            Abstract::Struct => HeapType::Struct,
+            Abstract::Array => HeapType::Array,
+            Abstract::None => HeapType::None,
+            Abstract::Exn => HeapType::Exn,
+            Abstract::NoExn => HeapType::NoExn,
+            Abstract::Cont => HeapType::Cont,
+            Abstract::NoCont => HeapType::NoCont,
+        }),
+        wasmparser::HeapType::Concrete(idx) | wasmparser::HeapType::Exact(idx) => {
+            module_type_index(idx, rec_group_start)
+                .map(HeapType::Concrete)
+                .ok_or_else(|| crate::ParseError::UnsupportedOperator(format!("Unsupported heap type: {heap:?}")))
+        }
+        _ => Err(crate::ParseError::UnsupportedOperator(format!("Unsupported heap type: {heap:?}"))),
+    }
+}
+
+/// The coarse [`WasmType`] of a value of type `valtype`.
+fn valtype_to_wasm_type(valtype: &ValType, types: &[TypeKind]) -> Result<WasmType> {
+    Ok(match valtype {
+        ValType::I32 => WasmType::I32,
+        ValType::I64 => WasmType::I64,
+        ValType::F32 => WasmType::F32,
+        ValType::F64 => WasmType::F64,
+        ValType::V128 => WasmType::V128,
+        ValType::Ref(r) => heaptype_to_wasm_type(r.heap_type, types)?,
+    })
+}
+
+/// The coarse [`WasmType`] of references to `heap`.
+pub(crate) fn heaptype_to_wasm_type(heap: HeapType, types: &[TypeKind]) -> Result<WasmType> {
+    Ok(match heap {
+        HeapType::Func | HeapType::NoFunc => WasmType::RefFunc,
+        HeapType::Extern | HeapType::NoExtern => WasmType::RefExtern,
+        HeapType::Any | HeapType::Eq | HeapType::I31 | HeapType::Struct | HeapType::Array | HeapType::None => {
+            WasmType::RefAny
+        }
+        HeapType::Exn | HeapType::NoExn => WasmType::RefExn,
+        HeapType::Cont | HeapType::NoCont => WasmType::RefCont,
+        HeapType::Concrete(idx) => match types.get(idx as usize) {
+            Some(TypeKind::Func { .. }) => WasmType::RefFunc,
+            Some(TypeKind::Cont { .. }) => WasmType::RefCont,
+            Some(TypeKind::Struct | TypeKind::Array) => WasmType::RefAny,
+            None => {
+                return Err(crate::ParseError::UnsupportedOperator(format!("Unsupported heap type index: {idx}")));
+            }
+        },
+    })
+}
+
+pub(crate) fn convert_reftype(reftype: wasmparser::RefType, types: &[TypeKind]) -> Result<WasmType> {
+    convert_heaptype(reftype.heap_type(), types).map_err(|_| {
+        crate::ParseError::UnsupportedOperator(format!(
             "Unsupported reference type: {reftype:?}, {:?}",
             reftype.heap_type()
-        ))),
-    }
+        ))
+    })
 }
 
-pub(crate) fn convert_valtype(valtype: &wasmparser::ValType) -> Result<WasmType> {
+pub(crate) fn convert_valtype(valtype: &wasmparser::ValType, types: &[TypeKind]) -> Result<WasmType> {
     match valtype {
         wasmparser::ValType::I32 => Ok(WasmType::I32),
         wasmparser::ValType::I64 => Ok(WasmType::I64),
         wasmparser::ValType::F32 => Ok(WasmType::F32),
         wasmparser::ValType::F64 => Ok(WasmType::F64),
         wasmparser::ValType::V128 => Ok(WasmType::V128),
-        wasmparser::ValType::Ref(r) => convert_reftype(*r),
+        wasmparser::ValType::Ref(r) => convert_reftype(*r, types),
     }
 }
 
-pub(crate) fn process_const_operators(ops: OperatorsReader<'_>) -> Result<Box<[ConstInstruction]>> {
+pub(crate) fn process_const_operators(ops: OperatorsReader<'_>, types: &[TypeKind]) -> Result<Box<[ConstInstruction]>> {
     let mut out = Vec::new();
     let mut operator_count = 0;
     let mut end_reached = false;
@@ -221,9 +413,12 @@ pub(crate) fn process_const_operators(ops: OperatorsReader<'_>) -> Result<Box<[C
         }
 
         let instr = match op {
-            wasmparser::Operator::RefNull { hty } => match convert_heaptype(hty)? {
+            wasmparser::Operator::RefNull { hty } => match convert_heaptype(hty, types)? {
                 WasmType::RefFunc => ConstInstruction::RefFunc(None),
                 WasmType::RefExtern => ConstInstruction::RefExtern(None),
+                WasmType::RefCont => ConstInstruction::RefNullCont,
+                WasmType::RefExn => ConstInstruction::RefNullExn,
+                WasmType::RefAny => ConstInstruction::RefNullAny,
                 other => {
                     return Err(crate::ParseError::UnsupportedOperator(format!(
                         "Unsupported ref.null heap type lowered to {other:?}"
@@ -243,6 +438,20 @@ pub(crate) fn process_const_operators(ops: OperatorsReader<'_>) -> Result<Box<[C
             wasmparser::Operator::I64Add => ConstInstruction::I64Add,
             wasmparser::Operator::I64Sub => ConstInstruction::I64Sub,
             wasmparser::Operator::I64Mul => ConstInstruction::I64Mul,
+            wasmparser::Operator::RefI31 => ConstInstruction::RefI31,
+            wasmparser::Operator::StructNew { struct_type_index } => ConstInstruction::StructNew(struct_type_index),
+            wasmparser::Operator::StructNewDefault { struct_type_index } => {
+                ConstInstruction::StructNewDefault(struct_type_index)
+            }
+            wasmparser::Operator::ArrayNew { array_type_index } => ConstInstruction::ArrayNew(array_type_index),
+            wasmparser::Operator::ArrayNewDefault { array_type_index } => {
+                ConstInstruction::ArrayNewDefault(array_type_index)
+            }
+            wasmparser::Operator::ArrayNewFixed { array_type_index, array_size } => {
+                ConstInstruction::ArrayNewFixed(array_type_index, array_size)
+            }
+            wasmparser::Operator::AnyConvertExtern => ConstInstruction::AnyConvertExtern,
+            wasmparser::Operator::ExternConvertAny => ConstInstruction::ExternConvertAny,
             other => {
                 return Err(crate::ParseError::UnsupportedOperator(format!(
                     "Unsupported const instruction: {other:?}"
@@ -259,14 +468,6 @@ pub(crate) fn process_const_operators(ops: OperatorsReader<'_>) -> Result<Box<[C
     Ok(out.into_boxed_slice())
 }
 
-pub(crate) fn convert_heaptype(heap: wasmparser::HeapType) -> Result<WasmType> {
-    match heap {
-        wasmparser::HeapType::Abstract { shared: false, ty: wasmparser::AbstractHeapType::Func } => {
-            Ok(WasmType::RefFunc)
-        }
-        wasmparser::HeapType::Abstract { shared: false, ty: wasmparser::AbstractHeapType::Extern } => {
-            Ok(WasmType::RefExtern)
-        }
-        _ => Err(crate::ParseError::UnsupportedOperator(format!("Unsupported heap type: {heap:?}"))),
-    }
+pub(crate) fn convert_heaptype(heap: wasmparser::HeapType, types: &[TypeKind]) -> Result<WasmType> {
+    heaptype_to_wasm_type(convert_gc_heaptype(heap, 0)?, types)
 }
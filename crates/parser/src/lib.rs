@@ -156,11 +156,13 @@ impl Parser {
             | WasmFeatures::BULK_MEMORY_OPT
             | WasmFeatures::RELAXED_SIMD
             | WasmFeatures::GC_TYPES
+            | WasmFeatures::GC
             | WasmFeatures::REFERENCE_TYPES
             | WasmFeatures::MUTABLE_GLOBAL
             | WasmFeatures::MULTI_VALUE
             | WasmFeatures::FLOATS
             | WasmFeatures::BULK_MEMORY
+            | WasmFeatures::MEMORY_CONTROL
             | WasmFeatures::SATURATING_FLOAT_TO_INT
             | WasmFeatures::SIGN_EXTENSION
             | WasmFeatures::EXTENDED_CONST
@@ -170,7 +172,10 @@ impl Parser {
             | WasmFeatures::SIMD
             | WasmFeatures::MEMORY64
             | WasmFeatures::CUSTOM_PAGE_SIZES
-            | WasmFeatures::WIDE_ARITHMETIC;
+            | WasmFeatures::WIDE_ARITHMETIC
+            | WasmFeatures::EXCEPTIONS
+            | WasmFeatures::STACK_SWITCHING
+            | WasmFeatures::THREADS;
         Validator::new_with_features(features)
     }
 
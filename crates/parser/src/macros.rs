@@ -125,10 +125,20 @@ pub(crate) mod visit {
         (@@sign_extension $($rest:tt)* ) => {};
         (@@saturating_float_to_int $($rest:tt)* ) => {};
         (@@bulk_memory $($rest:tt)* ) => {};
+        (@@memory_control $($rest:tt)* ) => {};
         (@@simd $($rest:tt)* ) => {};
         (@@wide_arithmetic $($rest:tt)* ) => {};
         (@@relaxed_simd $($rest:tt)* ) => {};
         (@@tail_call $($rest:tt)* ) => {};
+        (@@stack_switching ContNew $($rest:tt)* ) => {};
+        (@@stack_switching ContBind $($rest:tt)* ) => {};
+        (@@stack_switching Suspend $($rest:tt)* ) => {};
+        (@@stack_switching Resume $($rest:tt)* ) => {};
+        (@@stack_switching Switch $($rest:tt)* ) => {};
+        (@@exceptions $($rest:tt)* ) => {};
+        (@@threads $($rest:tt)* ) => {};
+        (@@gc $($rest:tt)* ) => {};
+        (@@function_references $($rest:tt)* ) => {};
 
         (@@$proposal:ident $op:ident $({ $($arg:ident: $argty:ty),* })? => $visit:ident ($($ann:tt)*)) => {
             fn $visit(&mut self $($(,_: $argty)*)?) -> Self::Output {
@@ -46,14 +46,19 @@ pub(crate) struct ModuleReader<'a> {
     pub(crate) version: Option<u16>,
     pub(crate) start_func: Option<u32>,
     pub(crate) func_types: Arc<[Arc<FuncType>]>,
+    pub(crate) type_kinds: Vec<TypeKind>,
+    pub(crate) types: Vec<SubType>,
+    rec_groups: Vec<Range<TypeAddr>>,
     pub(crate) code_type_addrs: Box<[u32]>,
     code_results: Box<[ValueCounts]>,
     pub(crate) exports: Arc<[Export]>,
     pub(crate) code: Vec<FunctionCode>,
     pub(crate) globals: Box<[Global]>,
     pub(crate) table_types: Box<[TableType]>,
+    pub(crate) table_inits: Box<[Option<Box<[ConstInstruction]>>]>,
     pub(crate) memory_types: Box<[MemoryType]>,
     pub(crate) imports: Box<[Import]>,
+    pub(crate) tags: Box<[TypeAddr]>,
     pub(crate) data: Box<[Data]>,
     pub(crate) elements: Box<[Element]>,
     pub(crate) end_reached: bool,
@@ -67,14 +72,7 @@ pub(crate) struct ModuleReader<'a> {
 impl<'a> ModuleReader<'a> {
     fn translation_metadata(&mut self) -> &crate::visit::ModuleMetadata {
         if self.translation_metadata.is_none() {
-            self.translation_metadata = Some(Arc::new(crate::visit::ModuleMetadata::new(
-                &self.func_types,
-                &self.code_type_addrs,
-                &self.imports,
-                &self.globals,
-                &self.memory_types,
-                &self.table_types,
-            )));
+            self.translation_metadata = Some(Arc::new(crate::visit::ModuleMetadata::new(self)));
         }
         self.translation_metadata.as_deref().unwrap()
     }
@@ -114,32 +112,50 @@ impl<'a> ModuleReader<'a> {
                 if let Some(validator) = validator.as_mut() {
                     validator.type_section(&reader)?;
                 }
-                self.func_types = reader.into_iter().map(|t| convert_module_type(t?)).collect::<Result<_>>()?;
+                let mut func_types = Vec::with_capacity(reader.count() as usize);
+                for group in reader {
+                    let start = self.types.len() as u32;
+                    for (ty, func_ty) in convert_module_rec_group(group?, &func_types, &mut self.type_kinds)? {
+                        self.types.push(ty);
+                        func_types.push(func_ty);
+                    }
+                    self.rec_groups.push(start..self.types.len() as u32);
+                }
+                self.func_types = func_types.into();
+            }
+            Payload::TagSection(reader) => {
+                check_section("tag", !self.tags.is_empty())?;
+                if let Some(validator) = validator.as_mut() {
+                    validator.tag_section(&reader)?;
+                }
+                self.tags = reader.into_iter().map(|tag| Ok(tag?.func_type_idx)).collect::<Result<_>>()?;
             }
             Payload::GlobalSection(reader) => {
                 check_section("global", !self.globals.is_empty())?;
                 if let Some(validator) = validator.as_mut() {
                     validator.global_section(&reader)?;
                 }
-                self.globals = convert_module_globals(reader)?;
+                self.globals = convert_module_globals(reader, &self.type_kinds)?;
             }
             Payload::TableSection(reader) => {
                 check_section("table", !self.table_types.is_empty())?;
                 if let Some(validator) = validator.as_mut() {
                     validator.table_section(&reader)?;
                 }
-                self.table_types = reader
-                    .into_iter()
-                    .map(|table| {
-                        let table = table?;
-                        let element_type = convert_reftype(table.ty.element_type)?;
-                        Ok(if table.ty.table64 {
-                            TableType::new64(element_type, table.ty.initial, table.ty.maximum)
-                        } else {
-                            TableType::new(element_type, table.ty.initial, table.ty.maximum)
-                        })
-                    })
-                    .collect::<Result<_>>()?;
+                let mut table_types = Vec::with_capacity(reader.count() as usize);
+                let mut table_inits = Vec::with_capacity(reader.count() as usize);
+                for table in reader {
+                    let table = table?;
+                    table_types.push(convert_module_table(table.ty, &self.type_kinds)?);
+                    table_inits.push(match table.init {
+                        wasmparser::TableInit::RefNull => None,
+                        wasmparser::TableInit::Expr(expr) => {
+                            Some(process_const_operators(expr.get_operators_reader(), &self.type_kinds)?)
+                        }
+                    });
+                }
+                self.table_types = table_types.into_boxed_slice();
+                self.table_inits = table_inits.into_boxed_slice();
             }
             Payload::MemorySection(reader) => {
                 check_section("memory", !self.memory_types.is_empty())?;
@@ -154,15 +170,20 @@ impl<'a> ModuleReader<'a> {
                 if let Some(validator) = validator.as_mut() {
                     validator.element_section(&reader)?;
                 }
-                self.elements =
-                    reader.into_iter().map(|element| convert_module_element(element?)).collect::<Result<_>>()?;
+                self.elements = reader
+                    .into_iter()
+                    .map(|element| convert_module_element(element?, &self.type_kinds))
+                    .collect::<Result<_>>()?;
             }
             Payload::DataSection(reader) => {
                 check_section("data", !self.data.is_empty())?;
                 if let Some(validator) = validator.as_mut() {
                     validator.data_section(&reader)?;
                 }
-                self.data = reader.into_iter().map(|data| convert_module_data(data?)).collect::<Result<_>>()?;
+                self.data = reader
+                    .into_iter()
+                    .map(|data| convert_module_data(data?, &self.type_kinds))
+                    .collect::<Result<_>>()?;
             }
             Payload::DataCountSection { count, range } => {
                 debug!("Found data count section");
@@ -199,7 +220,7 @@ impl<'a> ModuleReader<'a> {
                 }
                 let mut imports = Vec::with_capacity(reader.count() as usize);
                 for import in reader.into_imports() {
-                    let import = convert_module_import(import?)?;
+                    let import = convert_module_import(import?, &self.type_kinds)?;
                     match import.kind {
                         ImportKind::Function(_) => self.imported_func_count += 1,
                         ImportKind::Memory(_) => self.imported_memory_count += 1,
@@ -474,9 +495,13 @@ impl<'a> ModuleReader<'a> {
         Ok(ModuleInner {
             funcs,
             func_types: self.func_types,
+            types: self.types.into_boxed_slice(),
+            rec_groups: self.rec_groups.into_boxed_slice(),
             func_type_idxs,
             globals: self.globals,
             table_types: self.table_types,
+            table_inits: self.table_inits,
+            tags: self.tags,
             imports: self.imports,
             start_func: self.start_func,
             data: self.data,
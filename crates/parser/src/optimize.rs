@@ -2,7 +2,7 @@ use crate::macros::optimize::*;
 use crate::{ParseError, ParserOptions, Result};
 use alloc::vec::Vec;
 use core::ops::{Deref, DerefMut};
-use tinywasm_types::{BinOp, BinOp128, CmpOp, ConstIdx, Instruction, ValueCounts, WasmFunctionData};
+use tinywasm_types::{BinOp, BinOp128, CmpOp, ConstIdx, Instruction, ResumeHandler, ValueCounts, WasmFunctionData};
 
 pub(crate) struct OptimizeResult {
     pub(crate) instructions: Vec<Instruction>,
@@ -906,11 +906,31 @@ fn is_unconditional_terminator(instr: Instruction) -> bool {
             | Instruction::ReturnCall(_)
             | Instruction::ReturnCallSelf
             | Instruction::ReturnCallIndirect(..)
+            | Instruction::ReturnCallRef(_)
+            | Instruction::Throw(_)
+            | Instruction::ThrowRef
     )
 }
 
 fn target_boundaries(instructions: &[Instruction], function_data: &WasmFunctionData) -> Result<Vec<bool>> {
     let mut boundaries = alloc::vec![false; instructions.len() + 1];
+    for handler in &function_data.resume_handlers {
+        if let ResumeHandler::Label { target_ip, .. } = *handler {
+            let boundary = boundaries
+                .get_mut(target_ip as usize)
+                .ok_or_else(|| ParseError::Other(alloc::format!("resume handler target out of bounds: {target_ip}")))?;
+            *boundary = true;
+        }
+    }
+    // try ranges have to stay aligned with instruction boundaries so their bodies keep the same extent
+    let try_targets = function_data.try_ranges.iter().flat_map(|range| [range.start, range.end]);
+    let catch_targets = function_data.catch_handlers.iter().map(|handler| handler.target_ip);
+    for target in try_targets.chain(catch_targets) {
+        let boundary = boundaries
+            .get_mut(target as usize)
+            .ok_or_else(|| ParseError::Other(alloc::format!("try table target out of bounds: {target}")))?;
+        *boundary = true;
+    }
     for instr in instructions {
         if let Some(target) = instruction_target(instr) {
             let boundary = boundaries
@@ -955,6 +975,46 @@ fn finalize(
         }
     }
 
+    for handler in &mut function_data.resume_handlers {
+        let ResumeHandler::Label { target_ip: target, .. } = handler else { continue };
+        if let Some(old_to_new) = old_to_new {
+            *target = *old_to_new
+                .get(*target as usize)
+                .ok_or_else(|| ParseError::Other(alloc::format!("instruction target out of bounds: {target}")))?;
+        }
+        if *target >= len {
+            return Err(ParseError::Other(alloc::format!("resume handler target out of bounds: {target}")));
+        }
+    }
+
+    for handler in &mut function_data.catch_handlers {
+        if let Some(old_to_new) = old_to_new {
+            handler.target_ip = *old_to_new.get(handler.target_ip as usize).ok_or_else(|| {
+                ParseError::Other(alloc::format!("instruction target out of bounds: {}", handler.target_ip))
+            })?;
+        }
+        if handler.target_ip >= len {
+            return Err(ParseError::Other(alloc::format!("catch handler target out of bounds: {}", handler.target_ip)));
+        }
+    }
+
+    for range in &mut function_data.try_ranges {
+        if let Some(old_to_new) = old_to_new {
+            for bound in [&mut range.start, &mut range.end] {
+                *bound = *old_to_new
+                    .get(*bound as usize)
+                    .ok_or_else(|| ParseError::Other(alloc::format!("try range out of bounds: {bound}")))?;
+            }
+        }
+        let handlers_end = range
+            .handlers_start
+            .checked_add(range.handler_count)
+            .ok_or_else(|| ParseError::Other("catch handler range overflow".into()))?;
+        if range.start > range.end || range.end > len || handlers_end as usize > function_data.catch_handlers.len() {
+            return Err(ParseError::Other("try range out of bounds".into()));
+        }
+    }
+
     let mut uses_local_memory = false;
     for instr in instructions {
         if let Some(target) = instruction_target_mut(instr) {
@@ -967,6 +1027,14 @@ fn finalize(
                 return Err(ParseError::Other(alloc::format!("instruction target out of bounds: {target}")));
             }
         }
+        if let Instruction::Resume(_, start, count) = *instr {
+            let end =
+                start.checked_add(count).ok_or_else(|| ParseError::Other("resume handler range overflow".into()))?;
+            function_data
+                .resume_handlers
+                .get(start as usize..end as usize)
+                .ok_or_else(|| ParseError::Other("resume handler range out of bounds".into()))?;
+        }
         if let Instruction::BranchTable(_, start, count) = *instr {
             let end =
                 start.checked_add(count).ok_or_else(|| ParseError::Other("branch table range overflow".into()))?;
@@ -1,10 +1,11 @@
-use crate::{Result, conversion::convert_heaptype, macros::visit::*};
+use crate::conversion::{TypeKind, convert_gc_heaptype, convert_gc_reftype, convert_heaptype};
+use crate::module::ModuleReader;
+use crate::{Result, macros::visit::*};
 use alloc::string::ToString;
-use alloc::sync::Arc;
 use alloc::vec::Vec;
 use tinywasm_types::{
-    FuncType, Global, Import, ImportKind, Instruction, MemoryArch, MemoryArg, MemoryType, TableType, ValueCounts,
-    WasmFunctionData, WasmType,
+    CatchHandler, CompositeType, ImportKind, Instruction, MemoryArch, MemoryArg, RefType, ResumeHandler, StorageType,
+    TryRange, ValType, ValueCounts, WasmFunctionData, WasmType,
 };
 use wasmparser::{
     FuncValidator, FuncValidatorAllocations, FunctionBody, OperatorsReader, OperatorsReaderAllocations,
@@ -49,13 +50,31 @@ impl From<wasmparser::ValType> for OperandSize {
 impl From<&WasmType> for OperandSize {
     fn from(ty: &WasmType) -> Self {
         match ty {
-            WasmType::I32 | WasmType::F32 | WasmType::RefFunc | WasmType::RefExtern => Self::S32,
+            WasmType::I32
+            | WasmType::F32
+            | WasmType::RefFunc
+            | WasmType::RefExtern
+            | WasmType::RefCont
+            | WasmType::RefExn
+            | WasmType::RefAny => Self::S32,
             WasmType::I64 | WasmType::F64 => Self::S64,
             WasmType::V128 => Self::S128,
         }
     }
 }
 
+impl From<&StorageType> for OperandSize {
+    fn from(ty: &StorageType) -> Self {
+        match ty {
+            StorageType::I8 | StorageType::I16 | StorageType::Val(ValType::I32 | ValType::F32 | ValType::Ref(_)) => {
+                Self::S32
+            }
+            StorageType::Val(ValType::I64 | ValType::F64) => Self::S64,
+            StorageType::Val(ValType::V128) => Self::S128,
+        }
+    }
+}
+
 impl From<MemoryArch> for OperandSize {
     fn from(arch: MemoryArch) -> Self {
         match arch {
@@ -77,6 +96,8 @@ struct ControlFrame {
     unreachable: bool,
     entry_unreachable: bool,
     end_reachable: bool,
+    /// The `try_ranges` entry of a `try_table` block with catch clauses.
+    try_range: Option<usize>,
 }
 
 #[derive(Clone)]
@@ -87,7 +108,11 @@ pub(crate) struct Signature {
 
 pub(crate) struct ModuleMetadata {
     signatures: Vec<Signature>,
+    type_kinds: Vec<TypeKind>,
+    /// The sizes of each struct type's fields, or of the element of an array type.
+    field_sizes: Vec<Vec<OperandSize>>,
     functions: Vec<u32>,
+    tags: Vec<u32>,
     globals: Vec<OperandSize>,
     memories: Vec<OperandSize>,
     tables: Vec<OperandSize>,
@@ -97,6 +122,9 @@ pub(crate) struct ModuleMetadata {
 struct FunctionDataBuilder {
     v128_constants: Vec<[u8; 16]>,
     branch_table_targets: Vec<u32>,
+    resume_handlers: Vec<ResumeHandler>,
+    try_ranges: Vec<TryRange>,
+    catch_handlers: Vec<CatchHandler>,
 }
 
 pub(crate) struct FunctionBuilder<'a> {
@@ -136,6 +164,7 @@ impl<'a> FunctionBuilder<'a> {
                 unreachable: false,
                 entry_unreachable: false,
                 end_reachable: false,
+                try_range: None,
             }],
             operand_stack: Vec::new(),
             lane_counts: ValueCounts::default(),
@@ -150,18 +179,24 @@ struct ValidateThenVisit<'a, 'm> {
 }
 
 impl ModuleMetadata {
-    pub(crate) fn new(
-        types: &[Arc<FuncType>],
-        code_type_addrs: &[u32],
-        imports: &[Import],
-        globals: &[Global],
-        memories: &[MemoryType],
-        tables: &[TableType],
-    ) -> Self {
+    pub(crate) fn new(reader: &ModuleReader<'_>) -> Self {
+        let ModuleReader {
+            func_types: types,
+            type_kinds,
+            types: sub_types,
+            code_type_addrs,
+            imports,
+            globals,
+            memory_types: memories,
+            table_types: tables,
+            tags,
+            ..
+        } = reader;
         let mut functions = Vec::with_capacity(imports.len() + code_type_addrs.len());
         let mut global_sizes = Vec::with_capacity(imports.len() + globals.len());
         let mut memory_sizes = Vec::with_capacity(imports.len() + memories.len());
         let mut table_sizes = Vec::with_capacity(imports.len() + tables.len());
+        let mut tag_types = Vec::with_capacity(imports.len() + tags.len());
 
         for import in imports {
             match &import.kind {
@@ -169,6 +204,7 @@ impl ModuleMetadata {
                 ImportKind::Global(ty) => global_sizes.push(OperandSize::from(&ty.ty)),
                 ImportKind::Memory(ty) => memory_sizes.push(OperandSize::from(ty.arch())),
                 ImportKind::Table(ty) => table_sizes.push(OperandSize::from(ty.arch())),
+                ImportKind::Tag(ty) => tag_types.push(*ty),
             }
         }
 
@@ -176,6 +212,7 @@ impl ModuleMetadata {
         global_sizes.extend(globals.iter().map(|global| OperandSize::from(&global.ty.ty)));
         memory_sizes.extend(memories.iter().map(|ty| OperandSize::from(ty.arch())));
         table_sizes.extend(tables.iter().map(|ty| OperandSize::from(ty.arch())));
+        tag_types.extend_from_slice(tags);
 
         let signatures = types
             .iter()
@@ -184,7 +221,24 @@ impl ModuleMetadata {
                 results: ty.results().iter().map(OperandSize::from).collect(),
             })
             .collect();
-        Self { signatures, functions, globals: global_sizes, memories: memory_sizes, tables: table_sizes }
+        let field_sizes = sub_types
+            .iter()
+            .map(|ty| match &ty.composite {
+                CompositeType::Struct(fields) => fields.iter().map(|field| OperandSize::from(&field.storage)).collect(),
+                CompositeType::Array(element) => alloc::vec![OperandSize::from(&element.storage)],
+                CompositeType::Func { .. } | CompositeType::Cont(_) => Vec::new(),
+            })
+            .collect();
+        Self {
+            signatures,
+            type_kinds: type_kinds.to_vec(),
+            field_sizes,
+            functions,
+            tags: tag_types,
+            globals: global_sizes,
+            memories: memory_sizes,
+            tables: table_sizes,
+        }
     }
 
     pub(crate) fn signature(&self, idx: u32) -> Result<&Signature> {
@@ -201,6 +255,43 @@ impl ModuleMetadata {
         self.signature(ty)
     }
 
+    fn tag_signature(&self, idx: u32) -> Result<&Signature> {
+        let ty = *self
+            .tags
+            .get(idx as usize)
+            .ok_or_else(|| crate::ParseError::Other(alloc::format!("tag index out of bounds: {idx}")))?;
+        self.signature(ty)
+    }
+
+    /// Resolves the signature of the continuation a `switch` on `cont_type` transfers control to.
+    fn switch_target_signature(&self, cont_type: u32) -> Result<&Signature> {
+        let target = match self.type_kinds.get(cont_type as usize) {
+            Some(TypeKind::Cont { func }) => match self.type_kinds.get(*func as usize) {
+                Some(TypeKind::Func { last_param }) => *last_param,
+                _ => None,
+            },
+            _ => None,
+        };
+        let target = target
+            .ok_or_else(|| crate::ParseError::Other(alloc::format!("invalid switch continuation type: {cont_type}")))?;
+        self.signature(target)
+    }
+
+    fn struct_fields(&self, type_idx: u32) -> Result<&[OperandSize]> {
+        self.field_sizes
+            .get(type_idx as usize)
+            .map(Vec::as_slice)
+            .ok_or_else(|| crate::ParseError::Other(alloc::format!("type index out of bounds: {type_idx}")))
+    }
+
+    fn struct_field(&self, type_idx: u32, field_idx: u32) -> Result<OperandSize> {
+        Self::indexed_size(self.struct_fields(type_idx)?, "field", field_idx)
+    }
+
+    fn array_element(&self, type_idx: u32) -> Result<OperandSize> {
+        Self::indexed_size(self.struct_fields(type_idx)?, "array element", 0)
+    }
+
     fn global_size(&self, idx: u32) -> Result<OperandSize> {
         Self::indexed_size(&self.globals, "global", idx)
     }
@@ -248,8 +339,21 @@ pub(crate) fn process_operators_and_validate(
     let reader = body.get_binary_reader_for_operators()?;
     let mut reader = OperatorsReader::new_with_allocs(reader, allocs);
     let signature = metadata.signature(ty_idx)?.clone();
+    let mut local_idx = signature.params.len() as u32;
     let mut builder = FunctionBuilder::new(metadata, signature, local_types, local_addr_map, body_size);
 
+    // locals start out zeroed, which is not a null reference
+    for local in body.get_locals_reader()? {
+        let (count, ty) = local?;
+        if let wasmparser::ValType::Ref(ty) = ty {
+            for idx in local_idx..local_idx + count {
+                builder.visit_ref_null(ty.heap_type())?;
+                builder.visit_local_set(idx)?;
+            }
+        }
+        local_idx += count;
+    }
+
     while !reader.eof() {
         let position = reader.original_position();
         let res = reader
@@ -268,6 +372,9 @@ pub(crate) fn process_operators_and_validate(
     let data = WasmFunctionData {
         v128_constants: builder.data.v128_constants.into_boxed_slice(),
         branch_table_targets: builder.data.branch_table_targets.into_boxed_slice(),
+        resume_handlers: builder.data.resume_handlers.into_boxed_slice(),
+        try_ranges: builder.data.try_ranges.into_boxed_slice(),
+        catch_handlers: builder.data.catch_handlers.into_boxed_slice(),
     };
     Ok((builder.instructions, data, validator_allocations, reader.into_allocations()))
 }
@@ -301,6 +408,60 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
             visit_f64_store => F64Store, visit_i64_store8 => I64Store8,
             visit_i64_store16 => I64Store16, visit_i64_store32 => I64Store32, visit_i64_store => I64Store,
         }
+        memory [Addr] => [S32] {
+            visit_i32_atomic_load => I32AtomicLoad, visit_i32_atomic_load8_u => I32AtomicLoad8U,
+            visit_i32_atomic_load16_u => I32AtomicLoad16U,
+        }
+        memory [Addr] => [S64] {
+            visit_i64_atomic_load => I64AtomicLoad, visit_i64_atomic_load8_u => I64AtomicLoad8U,
+            visit_i64_atomic_load16_u => I64AtomicLoad16U, visit_i64_atomic_load32_u => I64AtomicLoad32U,
+        }
+        memory [Addr, S32] => [] {
+            visit_i32_atomic_store => I32AtomicStore, visit_i32_atomic_store8 => I32AtomicStore8,
+            visit_i32_atomic_store16 => I32AtomicStore16,
+        }
+        memory [Addr, S64] => [] {
+            visit_i64_atomic_store => I64AtomicStore, visit_i64_atomic_store8 => I64AtomicStore8,
+            visit_i64_atomic_store16 => I64AtomicStore16, visit_i64_atomic_store32 => I64AtomicStore32,
+        }
+        memory [Addr, S32] => [S32] {
+            visit_memory_atomic_notify => MemoryAtomicNotify,
+            visit_i32_atomic_rmw_add => I32AtomicRmwAdd, visit_i32_atomic_rmw8_add_u => I32AtomicRmw8AddU,
+            visit_i32_atomic_rmw16_add_u => I32AtomicRmw16AddU, visit_i32_atomic_rmw_sub => I32AtomicRmwSub,
+            visit_i32_atomic_rmw8_sub_u => I32AtomicRmw8SubU, visit_i32_atomic_rmw16_sub_u => I32AtomicRmw16SubU,
+            visit_i32_atomic_rmw_and => I32AtomicRmwAnd, visit_i32_atomic_rmw8_and_u => I32AtomicRmw8AndU,
+            visit_i32_atomic_rmw16_and_u => I32AtomicRmw16AndU, visit_i32_atomic_rmw_or => I32AtomicRmwOr,
+            visit_i32_atomic_rmw8_or_u => I32AtomicRmw8OrU, visit_i32_atomic_rmw16_or_u => I32AtomicRmw16OrU,
+            visit_i32_atomic_rmw_xor => I32AtomicRmwXor, visit_i32_atomic_rmw8_xor_u => I32AtomicRmw8XorU,
+            visit_i32_atomic_rmw16_xor_u => I32AtomicRmw16XorU, visit_i32_atomic_rmw_xchg => I32AtomicRmwXchg,
+            visit_i32_atomic_rmw8_xchg_u => I32AtomicRmw8XchgU, visit_i32_atomic_rmw16_xchg_u => I32AtomicRmw16XchgU,
+        }
+        memory [Addr, S64] => [S64] {
+            visit_i64_atomic_rmw_add => I64AtomicRmwAdd, visit_i64_atomic_rmw8_add_u => I64AtomicRmw8AddU,
+            visit_i64_atomic_rmw16_add_u => I64AtomicRmw16AddU, visit_i64_atomic_rmw32_add_u => I64AtomicRmw32AddU,
+            visit_i64_atomic_rmw_sub => I64AtomicRmwSub, visit_i64_atomic_rmw8_sub_u => I64AtomicRmw8SubU,
+            visit_i64_atomic_rmw16_sub_u => I64AtomicRmw16SubU, visit_i64_atomic_rmw32_sub_u => I64AtomicRmw32SubU,
+            visit_i64_atomic_rmw_and => I64AtomicRmwAnd, visit_i64_atomic_rmw8_and_u => I64AtomicRmw8AndU,
+            visit_i64_atomic_rmw16_and_u => I64AtomicRmw16AndU, visit_i64_atomic_rmw32_and_u => I64AtomicRmw32AndU,
+            visit_i64_atomic_rmw_or => I64AtomicRmwOr, visit_i64_atomic_rmw8_or_u => I64AtomicRmw8OrU,
+            visit_i64_atomic_rmw16_or_u => I64AtomicRmw16OrU, visit_i64_atomic_rmw32_or_u => I64AtomicRmw32OrU,
+            visit_i64_atomic_rmw_xor => I64AtomicRmwXor, visit_i64_atomic_rmw8_xor_u => I64AtomicRmw8XorU,
+            visit_i64_atomic_rmw16_xor_u => I64AtomicRmw16XorU, visit_i64_atomic_rmw32_xor_u => I64AtomicRmw32XorU,
+            visit_i64_atomic_rmw_xchg => I64AtomicRmwXchg, visit_i64_atomic_rmw8_xchg_u => I64AtomicRmw8XchgU,
+            visit_i64_atomic_rmw16_xchg_u => I64AtomicRmw16XchgU, visit_i64_atomic_rmw32_xchg_u => I64AtomicRmw32XchgU,
+        }
+        memory [Addr, S32, S32] => [S32] {
+            visit_i32_atomic_rmw_cmpxchg => I32AtomicRmwCmpxchg, visit_i32_atomic_rmw8_cmpxchg_u => I32AtomicRmw8CmpxchgU,
+            visit_i32_atomic_rmw16_cmpxchg_u => I32AtomicRmw16CmpxchgU,
+        }
+        memory [Addr, S64, S64] => [S64] {
+            visit_i64_atomic_rmw_cmpxchg => I64AtomicRmwCmpxchg, visit_i64_atomic_rmw8_cmpxchg_u => I64AtomicRmw8CmpxchgU,
+            visit_i64_atomic_rmw16_cmpxchg_u => I64AtomicRmw16CmpxchgU,
+            visit_i64_atomic_rmw32_cmpxchg_u => I64AtomicRmw32CmpxchgU,
+        }
+        memory [Addr, S32, S64] => [S32] { visit_memory_atomic_wait32 => MemoryAtomicWait32 }
+        memory [Addr, S64, S64] => [S32] { visit_memory_atomic_wait64 => MemoryAtomicWait64 }
+        fixed [] => [] { visit_atomic_fence => AtomicFence }
         fixed [] => [] { visit_data_drop(segment: u32) => DataDrop, visit_elem_drop(segment: u32) => ElemDrop }
         fixed [] => [S32] { visit_i32_const(value: i32) => Const32, visit_ref_func(function: u32) => RefFunc }
         fixed [] => [S64] { visit_i64_const(value: i64) => Const64 }
@@ -372,6 +533,7 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
         effect [S32] => [S32] { visit_f32_reinterpret_i32, visit_i32_reinterpret_f32 }
         effect [S64] => [S64] { visit_f64_reinterpret_i64, visit_i64_reinterpret_f64 }
         terminating [] => [] { visit_unreachable => Unreachable, visit_return => Return }
+        terminating [S32] => [] { visit_throw_ref => ThrowRef }
         global [] => [Addr] { visit_global_get(global_index: u32) => GlobalGet }
         memory_index [] => [Addr] { visit_memory_size(memory: u32) => MemorySize }
         memory_index [Addr] => [Addr] { visit_memory_grow(memory: u32) => MemoryGrow }
@@ -379,12 +541,31 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
             visit_memory_init(data_index: u32, memory: u32) => MemoryInit,
             visit_memory_fill(memory: u32) => MemoryFill,
         }
+        memory_index [Addr, Addr] => [] { visit_memory_discard(memory: u32) => MemoryDiscard }
         table [Addr] => [S32] { visit_table_get(table: u32) => TableGet }
         table [Addr, S32] => [] { visit_table_set(table: u32) => TableSet }
         table [] => [Addr] { visit_table_size(table: u32) => TableSize }
         table [S32, Addr] => [Addr] { visit_table_grow(table: u32) => TableGrow }
         table [Addr, S32, Addr] => [] { visit_table_fill(table: u32) => TableFill }
         table [Addr, S32, S32] => [] { visit_table_init(elem_index: u32, table: u32) => TableInit }
+        fixed [] => [S32] { visit_struct_new_default(struct_type_index: u32) => StructNewDefault }
+        fixed [S32] => [S32] {
+            visit_array_new_default(array_type_index: u32) => ArrayNewDefault, visit_array_len => ArrayLen,
+            visit_ref_i31 => RefI31, visit_i31_get_s => I31GetS, visit_i31_get_u => I31GetU,
+            visit_any_convert_extern => AnyConvertExtern, visit_extern_convert_any => ExternConvertAny,
+        }
+        fixed [S32, S32] => [S32] {
+            visit_ref_eq => RefEq,
+            visit_array_new_data(array_type_index: u32, array_data_index: u32) => ArrayNewData,
+            visit_array_new_elem(array_type_index: u32, array_elem_index: u32) => ArrayNewElem,
+        }
+        fixed [S32, S32, S32, S32] => [] {
+            visit_array_init_data(array_type_index: u32, array_data_index: u32) => ArrayInitData,
+            visit_array_init_elem(array_type_index: u32, array_elem_index: u32) => ArrayInitElem,
+        }
+        fixed [S32, S32, S32, S32, S32] => [] {
+            visit_array_copy(array_type_index_dst: u32, array_type_index_src: u32) => ArrayCopy,
+        }
     }
 
     fn visit_call(&mut self, function_index: u32) -> Self::Output {
@@ -407,6 +588,77 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
         Ok(())
     }
 
+    fn visit_throw(&mut self, tag_index: u32) -> Self::Output {
+        let signature = self.metadata.tag_signature(tag_index)?.clone();
+        self.apply_effect(&signature.params, &[])?;
+        self.mark_unreachable();
+        self.instructions.push(Instruction::Throw(tag_index));
+        Ok(())
+    }
+
+    fn visit_try_table(&mut self, try_table: wasmparser::TryTable) -> Self::Output {
+        let params = self.block_signature(try_table.ty)?.params;
+        self.apply_effect(&params, &[])?;
+        let height = self.operand_stack.len();
+        let base = self.lane_counts;
+
+        let skip_jump_ip = (!try_table.catches.is_empty()).then(|| {
+            self.instructions.push(Instruction::Jump(0));
+            self.instructions.len() - 1
+        });
+
+        // each catch clause gets a pad that receives the exception's values on top of the
+        // block's entry stack, then branches like `br` would. Labels are relative to the
+        // blocks around the `try_table`, so the pads are emitted before entering it.
+        let handlers_start = self.data.catch_handlers.len() as u32;
+        for catch in &try_table.catches {
+            let (tag, with_ref, label) = match *catch {
+                wasmparser::Catch::One { tag, label } => (Some(tag), false, label),
+                wasmparser::Catch::OneRef { tag, label } => (Some(tag), true, label),
+                wasmparser::Catch::All { label } => (None, false, label),
+                wasmparser::Catch::AllRef { label } => (None, true, label),
+            };
+
+            let pad_start = self.instructions.len() as u32;
+            if self.is_unreachable() {
+                self.instructions.push(Instruction::Return);
+            } else {
+                if let Some(tag) = tag {
+                    let payload = self.metadata.tag_signature(tag)?.params.clone();
+                    self.push_sizes(&payload)?;
+                }
+                if with_ref {
+                    self.push_sizes(&[OperandSize::S32])?;
+                }
+                self.emit_dropkeep_to_label(label)?;
+                self.emit_branch_jump_or_return(label)?;
+                self.reset_stack(height, base);
+            }
+            self.data.catch_handlers.push(CatchHandler { tag, with_ref, target_ip: pad_start });
+        }
+
+        if let Some(skip_jump_ip) = skip_jump_ip {
+            self.patch_jump(skip_jump_ip, self.instructions.len());
+        }
+
+        self.push_sizes(&params)?;
+        self.push_control(BlockKind::Block, try_table.ty, None)?;
+        if !try_table.catches.is_empty() {
+            let range = self.data.try_ranges.len();
+            self.data.try_ranges.push(TryRange {
+                start: self.instructions.len() as u32,
+                end: self.instructions.len() as u32,
+                base,
+                handlers_start,
+                handler_count: try_table.catches.len() as u32,
+            });
+            if let Some(frame) = self.control_stack.last_mut() {
+                frame.try_range = Some(range);
+            }
+        }
+        Ok(())
+    }
+
     fn visit_return_call_indirect(&mut self, type_index: u32, table_index: u32) -> Self::Output {
         let signature = self.metadata.signature(type_index)?.clone();
         let mut inputs = signature.params;
@@ -417,6 +669,21 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
         Ok(())
     }
 
+    fn visit_call_ref(&mut self, type_index: u32) -> Self::Output {
+        let signature = self.metadata.signature(type_index)?.clone();
+        let inputs = [signature.params.as_slice(), &[OperandSize::S32]].concat();
+        self.emit(&inputs, &signature.results, Instruction::CallRef(type_index))
+    }
+
+    fn visit_return_call_ref(&mut self, type_index: u32) -> Self::Output {
+        let signature = self.metadata.signature(type_index)?.clone();
+        let inputs = [signature.params.as_slice(), &[OperandSize::S32]].concat();
+        self.apply_effect(&inputs, &[])?;
+        self.mark_unreachable();
+        self.instructions.push(Instruction::ReturnCallRef(type_index));
+        Ok(())
+    }
+
     fn visit_global_set(&mut self, global_index: u32) -> Self::Output {
         let size = self.metadata.global_size(global_index)?;
         let instruction = size.choose(
@@ -524,6 +791,9 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
     fn visit_end(&mut self) -> Self::Output {
         let ctx =
             self.control_stack.pop().ok_or_else(|| crate::ParseError::Other("end without control frame".into()))?;
+        if let Some(range) = ctx.try_range {
+            self.data.try_ranges[range].end = self.instructions.len() as u32;
+        }
         if matches!(ctx.kind, BlockKind::Function) {
             self.instructions.push(Instruction::Return);
         } else {
@@ -668,10 +938,40 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
 
     // Reference Types
     fn visit_ref_null(&mut self, ty: wasmparser::HeapType) -> Self::Output {
-        let instruction = Instruction::RefNull(convert_heaptype(ty)?);
+        let instruction = Instruction::RefNull(convert_heaptype(ty, &self.metadata.type_kinds)?);
         self.emit(&[], &[OperandSize::S32], instruction)
     }
 
+    fn visit_ref_as_non_null(&mut self) -> Self::Output {
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::RefAsNonNull)
+    }
+
+    fn visit_br_on_null(&mut self, relative_depth: u32) -> Self::Output {
+        self.emit(&[OperandSize::S32], &[OperandSize::S32, OperandSize::S32], Instruction::BrOnNullTest)?;
+        self.pop_expect(OperandSize::S32)?;
+        let cond_jump_ip = self.instructions.len();
+        self.instructions.push(Instruction::JumpIfZero32(0));
+
+        // the null reference isn't passed to the label
+        self.emit(&[OperandSize::S32], &[], Instruction::Drop32)?;
+        self.emit_dropkeep_to_label(relative_depth)?;
+        self.emit_branch_jump_or_return(relative_depth)?;
+        self.patch_jump(cond_jump_ip, self.instructions.len());
+        self.push_sizes(&[OperandSize::S32])
+    }
+
+    fn visit_br_on_non_null(&mut self, relative_depth: u32) -> Self::Output {
+        self.emit(&[OperandSize::S32], &[OperandSize::S32, OperandSize::S32], Instruction::BrOnNullTest)?;
+        self.pop_expect(OperandSize::S32)?;
+        let cond_jump_ip = self.instructions.len();
+        self.instructions.push(Instruction::JumpIfNonZero32(0));
+
+        self.emit_dropkeep_to_label(relative_depth)?;
+        self.emit_branch_jump_or_return(relative_depth)?;
+        self.patch_jump(cond_jump_ip, self.instructions.len());
+        self.emit(&[OperandSize::S32], &[], Instruction::Drop32)
+    }
+
     fn visit_typed_select_multi(&mut self, tys: Vec<wasmparser::ValType>) -> Self::Output {
         let sizes: Vec<_> = tys.into_iter().map(OperandSize::from).collect();
         let counts = Self::value_counts(&sizes);
@@ -687,6 +987,189 @@ impl<'a> wasmparser::VisitOperator<'a> for FunctionBuilder<'_> {
         let instruction = size.choose(Instruction::Select32, Instruction::Select64, Instruction::Select128);
         self.emit(&[size, size, OperandSize::S32], &[size], instruction)
     }
+
+    // Garbage Collection
+    fn visit_struct_new(&mut self, struct_type_index: u32) -> Self::Output {
+        let fields = self.metadata.struct_fields(struct_type_index)?.to_vec();
+        self.emit(&fields, &[OperandSize::S32], Instruction::StructNew(struct_type_index))
+    }
+
+    fn visit_struct_get(&mut self, struct_type_index: u32, field_index: u32) -> Self::Output {
+        let field = self.metadata.struct_field(struct_type_index, field_index)?;
+        self.emit(&[OperandSize::S32], &[field], Instruction::StructGet(struct_type_index, field_index))
+    }
+
+    fn visit_struct_get_s(&mut self, struct_type_index: u32, field_index: u32) -> Self::Output {
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::StructGetS(struct_type_index, field_index))
+    }
+
+    fn visit_struct_get_u(&mut self, struct_type_index: u32, field_index: u32) -> Self::Output {
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::StructGetU(struct_type_index, field_index))
+    }
+
+    fn visit_struct_set(&mut self, struct_type_index: u32, field_index: u32) -> Self::Output {
+        let field = self.metadata.struct_field(struct_type_index, field_index)?;
+        self.emit(&[OperandSize::S32, field], &[], Instruction::StructSet(struct_type_index, field_index))
+    }
+
+    fn visit_array_new(&mut self, array_type_index: u32) -> Self::Output {
+        let element = self.metadata.array_element(array_type_index)?;
+        self.emit(&[element, OperandSize::S32], &[OperandSize::S32], Instruction::ArrayNew(array_type_index))
+    }
+
+    fn visit_array_new_fixed(&mut self, array_type_index: u32, array_size: u32) -> Self::Output {
+        let element = self.metadata.array_element(array_type_index)?;
+        let inputs = alloc::vec![element; array_size as usize];
+        self.emit(&inputs, &[OperandSize::S32], Instruction::ArrayNewFixed(array_type_index, array_size))
+    }
+
+    fn visit_array_get(&mut self, array_type_index: u32) -> Self::Output {
+        let element = self.metadata.array_element(array_type_index)?;
+        self.emit(&[OperandSize::S32, OperandSize::S32], &[element], Instruction::ArrayGet(array_type_index))
+    }
+
+    fn visit_array_get_s(&mut self, array_type_index: u32) -> Self::Output {
+        let (inputs, outputs) = ([OperandSize::S32, OperandSize::S32], [OperandSize::S32]);
+        self.emit(&inputs, &outputs, Instruction::ArrayGetS(array_type_index))
+    }
+
+    fn visit_array_get_u(&mut self, array_type_index: u32) -> Self::Output {
+        let (inputs, outputs) = ([OperandSize::S32, OperandSize::S32], [OperandSize::S32]);
+        self.emit(&inputs, &outputs, Instruction::ArrayGetU(array_type_index))
+    }
+
+    fn visit_array_set(&mut self, array_type_index: u32) -> Self::Output {
+        let element = self.metadata.array_element(array_type_index)?;
+        self.emit(&[OperandSize::S32, OperandSize::S32, element], &[], Instruction::ArraySet(array_type_index))
+    }
+
+    fn visit_array_fill(&mut self, array_type_index: u32) -> Self::Output {
+        let element = self.metadata.array_element(array_type_index)?;
+        let inputs = [OperandSize::S32, OperandSize::S32, element, OperandSize::S32];
+        self.emit(&inputs, &[], Instruction::ArrayFill(array_type_index))
+    }
+
+    fn visit_ref_test_non_null(&mut self, hty: wasmparser::HeapType) -> Self::Output {
+        let ty = RefType::new(false, convert_gc_heaptype(hty, 0)?);
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::RefTest(ty))
+    }
+
+    fn visit_ref_test_nullable(&mut self, hty: wasmparser::HeapType) -> Self::Output {
+        let ty = RefType::new(true, convert_gc_heaptype(hty, 0)?);
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::RefTest(ty))
+    }
+
+    fn visit_ref_cast_non_null(&mut self, hty: wasmparser::HeapType) -> Self::Output {
+        let ty = RefType::new(false, convert_gc_heaptype(hty, 0)?);
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::RefCast(ty))
+    }
+
+    fn visit_ref_cast_nullable(&mut self, hty: wasmparser::HeapType) -> Self::Output {
+        let ty = RefType::new(true, convert_gc_heaptype(hty, 0)?);
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::RefCast(ty))
+    }
+
+    fn visit_br_on_cast(
+        &mut self,
+        relative_depth: u32,
+        _from_ref_type: wasmparser::RefType,
+        to_ref_type: wasmparser::RefType,
+    ) -> Self::Output {
+        let ty = convert_gc_reftype(to_ref_type, 0)?;
+        let (inputs, outputs) = ([OperandSize::S32], [OperandSize::S32, OperandSize::S32]);
+        self.emit(&inputs, &outputs, Instruction::BrOnCastTest(ty))?;
+        self.visit_br_if(relative_depth)
+    }
+
+    fn visit_br_on_cast_fail(
+        &mut self,
+        relative_depth: u32,
+        _from_ref_type: wasmparser::RefType,
+        to_ref_type: wasmparser::RefType,
+    ) -> Self::Output {
+        let ty = convert_gc_reftype(to_ref_type, 0)?;
+        let (inputs, outputs) = ([OperandSize::S32], [OperandSize::S32, OperandSize::S32]);
+        self.emit(&inputs, &outputs, Instruction::BrOnCastTest(ty))?;
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::I32Eqz)?;
+        self.visit_br_if(relative_depth)
+    }
+
+    // Stack Switching
+    fn visit_cont_new(&mut self, cont_type_index: u32) -> Self::Output {
+        self.emit(&[OperandSize::S32], &[OperandSize::S32], Instruction::ContNew(cont_type_index))
+    }
+
+    fn visit_cont_bind(&mut self, argument_index: u32, result_index: u32) -> Self::Output {
+        let argument = &self.metadata.signature(argument_index)?.params;
+        let result = &self.metadata.signature(result_index)?.params;
+        let bound = argument
+            .len()
+            .checked_sub(result.len())
+            .ok_or_else(|| crate::ParseError::Other("cont.bind result type has more parameters".into()))?;
+        let mut inputs = argument[..bound].to_vec();
+        inputs.push(OperandSize::S32);
+        self.emit(&inputs, &[OperandSize::S32], Instruction::ContBind(argument_index, result_index))
+    }
+
+    fn visit_suspend(&mut self, tag_index: u32) -> Self::Output {
+        let signature = self.metadata.tag_signature(tag_index)?.clone();
+        self.emit(&signature.params, &signature.results, Instruction::Suspend(tag_index))
+    }
+
+    fn visit_switch(&mut self, cont_type_index: u32, tag_index: u32) -> Self::Output {
+        // the last parameter of the continuation type is the reference to the suspended continuation,
+        // which is replaced by the reference to the continuation being switched to
+        let params = self.metadata.signature(cont_type_index)?.params.clone();
+        let results = self.metadata.switch_target_signature(cont_type_index)?.params.clone();
+        self.emit(&params, &results, Instruction::Switch(cont_type_index, tag_index))
+    }
+
+    fn visit_resume(&mut self, cont_type_index: u32, resume_table: wasmparser::ResumeTable) -> Self::Output {
+        let signature = self.metadata.signature(cont_type_index)?.clone();
+        let mut inputs = signature.params;
+        inputs.push(OperandSize::S32);
+        self.apply_effect(&inputs, &[])?;
+
+        let handlers_start = self.data.resume_handlers.len() as u32;
+        self.instructions.push(Instruction::Resume(cont_type_index, handlers_start, resume_table.len() as u32));
+
+        let has_labels = resume_table.handlers.iter().any(|h| matches!(h, wasmparser::Handle::OnLabel { .. }));
+        let skip_jump_ip = has_labels.then(|| {
+            self.instructions.push(Instruction::Jump(0));
+            self.instructions.len() - 1
+        });
+
+        // each label handler gets a pad that receives the tag's values and the continuation
+        // in place of the resume's inputs, then branches like `br` would
+        for handle in resume_table.handlers {
+            let handler = match handle {
+                wasmparser::Handle::OnSwitch { tag } => ResumeHandler::Switch { tag },
+                wasmparser::Handle::OnLabel { tag, label } => {
+                    let pad_start = self.instructions.len() as u32;
+                    if self.is_unreachable() {
+                        self.instructions.push(Instruction::Return);
+                    } else {
+                        let height = self.operand_stack.len();
+                        let base = self.lane_counts;
+                        let frame = &self.control_stack[self.get_ctx_idx(label)?];
+                        let label_types =
+                            if matches!(frame.kind, BlockKind::Loop) { &frame.params } else { &frame.results };
+                        self.push_sizes(&label_types.clone())?;
+                        self.emit_dropkeep_to_label(label)?;
+                        self.emit_branch_jump_or_return(label)?;
+                        self.reset_stack(height, base);
+                    }
+                    ResumeHandler::Label { tag, target_ip: pad_start }
+                }
+            };
+            self.data.resume_handlers.push(handler);
+        }
+
+        if let Some(skip_jump_ip) = skip_jump_ip {
+            self.patch_jump(skip_jump_ip, self.instructions.len());
+        }
+        self.push_sizes(&signature.results)
+    }
 }
 
 macro_rules! impl_visit_simd_operator {
@@ -983,15 +1466,19 @@ impl FunctionBuilder<'_> {
         }
     }
 
-    /// Enters a control frame with its parameters restored above the saved base.
-    fn push_control(&mut self, kind: BlockKind, ty: wasmparser::BlockType, initial_jump: Option<usize>) -> Result<()> {
-        let signature = match ty {
+    fn block_signature(&self, ty: wasmparser::BlockType) -> Result<Signature> {
+        Ok(match ty {
             wasmparser::BlockType::Empty => Signature { params: Vec::new(), results: Vec::new() },
             wasmparser::BlockType::Type(ty) => {
                 Signature { params: Vec::new(), results: alloc::vec![OperandSize::from(ty)] }
             }
             wasmparser::BlockType::FuncType(idx) => self.metadata.signature(idx)?.clone(),
-        };
+        })
+    }
+
+    /// Enters a control frame with its parameters restored above the saved base.
+    fn push_control(&mut self, kind: BlockKind, ty: wasmparser::BlockType, initial_jump: Option<usize>) -> Result<()> {
+        let signature = self.block_signature(ty)?;
         for &size in signature.params.iter().rev() {
             self.pop_expect(size)?;
         }
@@ -1011,6 +1498,7 @@ impl FunctionBuilder<'_> {
             unreachable: entry_unreachable,
             entry_unreachable,
             end_reachable: false,
+            try_range: None,
         });
         Ok(())
     }
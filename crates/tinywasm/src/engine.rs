@@ -1,5 +1,10 @@
 /// Memory backend types and traits.
-pub use crate::store::{LazyLinearMemory, LinearMemory, MemoryBackend, PagedMemory, VecMemory};
+#[cfg(feature = "std")]
+pub use crate::store::FileMemory;
+pub use crate::store::{DirtyTrackingMemory, LazyLinearMemory, LinearMemory, MemoryBackend, PagedMemory, VecMemory};
+
+use alloc::sync::Arc;
+use tinywasm_types::{MemoryType, Module};
 
 /// Global configuration for the WebAssembly interpreter
 ///
@@ -59,6 +64,22 @@ pub const DEFAULT_VALUE_STACK_128_SIZE: usize = 4 * 1024; // 4k slots
 /// Default maximum size for the call stack (function frames).
 pub const DEFAULT_MAX_CALL_STACK_SIZE: usize = 1024; // 1024 frames
 
+/// Default number of bytes the GC heap may grow by before a collection is triggered.
+pub const DEFAULT_GC_THRESHOLD: usize = 1024 * 1024; // 1 MiB
+
+type MemoryBackendSelector = dyn Fn(&MemoryType, &Module, u32) -> MemoryBackend + Send + Sync;
+
+/// Chooses the backend of each memory defined by a module, see [`Config::with_memory_backend_selector`].
+#[derive(Clone)]
+pub(crate) struct SelectMemoryBackend(Arc<MemoryBackendSelector>);
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for SelectMemoryBackend {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("SelectMemoryBackend").finish()
+    }
+}
+
 /// Stack allocation policy.
 #[derive(Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "debug", derive(Debug))]
@@ -114,8 +135,14 @@ pub struct Config {
     pub fuel_policy: FuelPolicy,
     /// Backend used for runtime memories.
     pub memory_backend: MemoryBackend,
+    /// Chooses the backend of memories defined by modules instead of `memory_backend`.
+    pub(crate) memory_backend_selector: Option<SelectMemoryBackend>,
     /// Whether memory and stack allocation failures should trap instead of degrading into normal operation failure modes.
     pub trap_on_oom: bool,
+    /// Number of bytes the GC heap may grow by before WebAssembly code or new host values trigger a collection.
+    pub gc_threshold: usize,
+    /// Whether a failed instantiation also undoes segment writes into imported tables and memories.
+    pub atomic_instantiation: bool,
 }
 
 impl Config {
@@ -136,6 +163,33 @@ impl Config {
         self
     }
 
+    /// Choose the backend of each memory defined by a module, instead of using the same backend for all memories.
+    ///
+    /// `selector` is called with the type of the memory, the module and the index of the memory in the
+    /// module's memory index space, which includes imported memories. Memories created by the host
+    /// with [`Memory::new`](crate::Memory::new) keep using [`Self::memory_backend`].
+    ///
+    /// Backends like [`MemoryBackend::copy_on_write`] share state between the memories they create, so
+    /// return clones of backends created once instead of creating a new one on every call.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use tinywasm::engine::{Config, MemoryBackend};
+    ///
+    /// let heap = MemoryBackend::paged(64 * 1024);
+    /// let config = Config::new().with_memory_backend_selector(move |ty, _module, _idx| {
+    ///     if ty.page_count_max() > 256 { heap.clone() } else { MemoryBackend::vec() }
+    /// });
+    /// # _ = config;
+    /// ```
+    pub fn with_memory_backend_selector(
+        mut self,
+        selector: impl Fn(&MemoryType, &Module, u32) -> MemoryBackend + Send + Sync + 'static,
+    ) -> Self {
+        self.memory_backend_selector = Some(SelectMemoryBackend(Arc::new(selector)));
+        self
+    }
+
     /// Set the configuration used for the 32-bit value stack.
     pub fn with_value_stack_32(mut self, stack: StackConfig) -> Self {
         self.value_stack_32 = stack;
@@ -174,6 +228,36 @@ impl Config {
         self
     }
 
+    /// Set the number of bytes the GC heap may grow by before WebAssembly code or
+    /// [`Store::new_extern_ref`](crate::Store::new_extern_ref) triggers a collection.
+    ///
+    /// The heap may always grow by at least its live size, so collections become less frequent
+    /// as it grows. Use [`Store::collect_garbage`](crate::Store::collect_garbage) to collect manually.
+    pub fn with_gc_threshold(mut self, gc_threshold: usize) -> Self {
+        self.gc_threshold = gc_threshold;
+        self
+    }
+
+    /// Configure whether a failed instantiation leaves the store exactly as before.
+    ///
+    /// Everything allocated for an instance is removed from the store if an active element or data
+    /// segment traps. With atomic instantiation, segments already written to imported tables and
+    /// memories before the trap are reverted as well.
+    ///
+    /// This deviates from the WebAssembly specification, where these writes persist, so it is disabled
+    /// by default. Without it, a failed instance is kept in the store if one of its element segments was
+    /// written to an imported table, as the table may refer to its functions.
+    ///
+    /// Even with atomic instantiation, some effects of a failed instantiation remain:
+    /// - Traps in the start function are not rolled back, as their side effects may already have
+    ///   been observed by the host.
+    /// - Items created by the [`Imports`](crate::Imports) resolver are kept, as the resolver may
+    ///   have handed them out elsewhere.
+    pub fn with_atomic_instantiation(mut self, atomic_instantiation: bool) -> Self {
+        self.atomic_instantiation = atomic_instantiation;
+        self
+    }
+
     /// Get the current fuel policy
     pub fn fuel_policy(&self) -> FuelPolicy {
         self.fuel_policy
@@ -184,6 +268,14 @@ impl Config {
         &self.memory_backend
     }
 
+    /// Get the backend for memory `idx` of `module`.
+    pub(crate) fn memory_backend_for(&self, ty: &MemoryType, module: &Module, idx: u32) -> MemoryBackend {
+        match &self.memory_backend_selector {
+            Some(SelectMemoryBackend(selector)) => selector(ty, module, idx),
+            None => self.memory_backend.clone(),
+        }
+    }
+
     pub(crate) const fn trap_on_oom(&self) -> bool {
         self.trap_on_oom
     }
@@ -198,7 +290,10 @@ impl Default for Config {
             call_stack: StackConfig::fixed(DEFAULT_MAX_CALL_STACK_SIZE),
             fuel_policy: FuelPolicy::default(),
             memory_backend: MemoryBackend::default(),
+            memory_backend_selector: None,
             trap_on_oom: false,
+            gc_threshold: DEFAULT_GC_THRESHOLD,
+            atomic_instantiation: false,
         }
     }
 }
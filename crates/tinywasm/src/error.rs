@@ -3,8 +3,8 @@ use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Display};
-use tinywasm_types::FuncType;
 use tinywasm_types::archive::TwasmError;
+use tinywasm_types::{ExternType, FuncType};
 
 #[cfg(feature = "parser")]
 pub use tinywasm_parser::ParseError;
@@ -78,22 +78,49 @@ pub enum LinkingError {
     },
 
     /// A mismatched import type was encountered
+    ///
+    /// Concrete heap types are indices into the importing module's type section. Types of the provided
+    /// value the module defines no equivalent of are given as their abstract heap type, e.g. `struct`.
     IncompatibleImportType {
         /// The module name
         module: String,
         /// The import name
         name: String,
+        /// The type the module expects
+        expected: Box<ExternType>,
+        /// The type of the provided value
+        actual: Box<ExternType>,
     },
+
+    /// Multiple imports could not be linked
+    Multiple(Vec<LinkingError>),
 }
 
 impl LinkingError {
-    pub(crate) fn incompatible_import_type(import: &tinywasm_types::Import) -> Self {
-        Self::IncompatibleImportType { module: import.module.to_string(), name: import.name.to_string() }
+    pub(crate) fn incompatible_import_type(
+        import: &tinywasm_types::Import,
+        expected: ExternType,
+        actual: ExternType,
+    ) -> Self {
+        Self::IncompatibleImportType {
+            module: import.module.to_string(),
+            name: import.name.to_string(),
+            expected: Box::new(expected),
+            actual: Box::new(actual),
+        }
     }
 
     pub(crate) fn unknown_import(import: &tinywasm_types::Import) -> Self {
         Self::UnknownImport { module: import.module.to_string(), name: import.name.to_string() }
     }
+
+    /// Combine the errors of all imports that failed to link.
+    pub(crate) fn from_errors(mut errors: Vec<Self>) -> Self {
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => Self::Multiple(errors),
+        }
+    }
 }
 
 impl Error {
@@ -124,6 +151,14 @@ pub enum Trap {
         max: usize,
     },
 
+    /// A memory access to a range protected by [`crate::Memory::protect`] occurred
+    MemoryProtectionViolation {
+        /// The offset of the access
+        offset: usize,
+        /// The size of the access
+        len: usize,
+    },
+
     /// An out-of-bounds table access occurred
     TableOutOfBounds {
         /// The offset of the access
@@ -175,6 +210,68 @@ pub enum Trap {
         actual: Arc<FuncType>,
     },
 
+    /// A null function reference was used where a function was required
+    NullFunctionReference,
+
+    /// A null reference was cast to a non-nullable reference
+    NullReference,
+
+    /// A null continuation reference was resumed, bound or switched to
+    NullContinuationReference,
+
+    /// A continuation was used after it has already been resumed, bound or switched to
+    ContinuationAlreadyConsumed,
+
+    /// A suspension or switch had no matching handler
+    UnhandledTag,
+
+    /// A WebAssembly exception was not caught
+    Exception(Box<crate::Exception>),
+
+    /// A null exception reference was rethrown
+    NullExceptionReference,
+
+    /// An atomic memory access was not naturally aligned
+    UnalignedAtomic {
+        /// The effective address of the access
+        offset: usize,
+        /// The size of the access
+        len: usize,
+    },
+
+    /// An atomic wait was executed on a memory that is not shared
+    ExpectedSharedMemory,
+
+    /// The range of a `memory.discard` was not page-aligned
+    UnalignedDiscard {
+        /// The offset of the range
+        offset: u64,
+        /// The size of the range
+        len: u64,
+    },
+
+    /// A null reference was used to access a struct
+    NullStructReference,
+
+    /// A null reference was used to access an array
+    NullArrayReference,
+
+    /// A null reference was used as an `i31ref`
+    NullI31Reference,
+
+    /// A `ref.cast` failed
+    CastFailure,
+
+    /// An out-of-bounds array access occurred
+    ArrayOutOfBounds {
+        /// The index of the access
+        offset: usize,
+        /// The number of elements accessed
+        len: usize,
+        /// The length of the array
+        max: usize,
+    },
+
     /// Catch-all for other messages
     Other(&'static str),
 }
@@ -185,6 +282,7 @@ impl Trap {
         match self {
             Self::Unreachable => "unreachable",
             Self::MemoryOutOfBounds { .. } => "out of bounds memory access",
+            Self::MemoryProtectionViolation { .. } => "memory protection violation",
             Self::TableOutOfBounds { .. } => "out of bounds table access",
             Self::DivisionByZero => "integer divide by zero",
             Self::InvalidConversionToInt => "invalid conversion to integer",
@@ -197,6 +295,21 @@ impl Trap {
             Self::IndirectCallTypeMismatch { .. } => "indirect call type mismatch",
             Self::HostFunction(_) => "host function trap",
             Self::InvalidStore => "invalid store",
+            Self::NullFunctionReference => "null function reference",
+            Self::NullReference => "null reference",
+            Self::NullContinuationReference => "null continuation reference",
+            Self::ContinuationAlreadyConsumed => "continuation already consumed",
+            Self::UnhandledTag => "unhandled tag",
+            Self::Exception(_) => "uncaught exception",
+            Self::NullExceptionReference => "null exception reference",
+            Self::UnalignedAtomic { .. } => "unaligned atomic",
+            Self::ExpectedSharedMemory => "expected shared memory",
+            Self::UnalignedDiscard { .. } => "unaligned memory discard",
+            Self::NullStructReference => "null structure reference",
+            Self::NullArrayReference => "null array reference",
+            Self::NullI31Reference => "null i31 reference",
+            Self::CastFailure => "cast failure",
+            Self::ArrayOutOfBounds { .. } => "out of bounds array access",
             Self::Other(message) => message,
         }
     }
@@ -214,6 +327,7 @@ impl LinkingError {
         match self {
             Self::UnknownImport { .. } => "unknown import",
             Self::IncompatibleImportType { .. } => "incompatible import type",
+            Self::Multiple(_) => "multiple linking errors",
         }
     }
 }
@@ -265,8 +379,12 @@ impl Display for LinkingError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::UnknownImport { module, name } => write!(f, "unknown import: {module}.{name}"),
-            Self::IncompatibleImportType { module, name } => {
-                write!(f, "incompatible import type: {module}.{name}")
+            Self::IncompatibleImportType { module, name, expected, actual } => {
+                write!(f, "incompatible import type: {module}.{name}: expected {expected}, found {actual}")
+            }
+            Self::Multiple(errors) => {
+                write!(f, "{} imports failed to link", errors.len())?;
+                errors.iter().try_for_each(|err| write!(f, "\n  {err}"))
             }
         }
     }
@@ -281,6 +399,9 @@ impl Display for Trap {
             Self::MemoryOutOfBounds { offset, len, max } => {
                 write!(f, "out of bounds memory access: offset={offset}, len={len}, max={max}")
             }
+            Self::MemoryProtectionViolation { offset, len } => {
+                write!(f, "memory protection violation: offset={offset}, len={len}")
+            }
             Self::TableOutOfBounds { offset, len, max } => {
                 write!(f, "out of bounds table access: offset={offset}, len={len}, max={max}")
             }
@@ -295,6 +416,23 @@ impl Display for Trap {
                 write!(f, "uninitialized element: index={index}")
             }
             Self::InvalidStore => write!(f, "invalid store"),
+            Self::NullFunctionReference => write!(f, "null function reference"),
+            Self::NullReference => write!(f, "null reference"),
+            Self::NullContinuationReference => write!(f, "null continuation reference"),
+            Self::ContinuationAlreadyConsumed => write!(f, "continuation already consumed"),
+            Self::UnhandledTag => write!(f, "unhandled tag"),
+            Self::Exception(_) => write!(f, "uncaught exception"),
+            Self::NullExceptionReference => write!(f, "null exception reference"),
+            Self::UnalignedAtomic { offset, len } => write!(f, "unaligned atomic: offset={offset}, len={len}"),
+            Self::ExpectedSharedMemory => write!(f, "expected shared memory"),
+            Self::UnalignedDiscard { offset, len } => write!(f, "unaligned memory discard: offset={offset}, len={len}"),
+            Self::NullStructReference => write!(f, "null structure reference"),
+            Self::NullArrayReference => write!(f, "null array reference"),
+            Self::NullI31Reference => write!(f, "null i31 reference"),
+            Self::CastFailure => write!(f, "cast failure"),
+            Self::ArrayOutOfBounds { offset, len, max } => {
+                write!(f, "out of bounds array access: offset={offset}, len={len}, max={max}")
+            }
             #[cfg(feature = "debug")]
             Self::IndirectCallTypeMismatch { expected, actual } => {
                 write!(f, "indirect call type mismatch: expected={expected:?}, actual={actual:?}")
@@ -323,6 +461,13 @@ impl From<Error> for crate::std::io::Error {
     }
 }
 
+#[cfg(feature = "std")]
+impl From<crate::std::io::Error> for Error {
+    fn from(value: crate::std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
 #[cfg(feature = "parser")]
 impl From<tinywasm_parser::ParseError> for Error {
     fn from(value: tinywasm_parser::ParseError) -> Self {
@@ -1,11 +1,52 @@
-use crate::interpreter::stack::{CallFrame, ValueStack};
+use crate::interpreter::stack::{CallFrame, Stacks, ValueStack};
 use crate::reference::StoreItem;
 use crate::{Error, FunctionInstance, InterpreterRuntime, Result, Store, Trap};
 use alloc::{borrow::Cow, boxed::Box, format, rc::Rc, sync::Arc, vec, vec::Vec};
+use core::future::Future;
 use core::hint::cold_path;
-use tinywasm_types::{ExternRef, FuncRef, FuncType, ModuleInstanceAddr, WasmType, WasmValue};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use tinywasm_types::{AnyRef, ExternRef, FuncRef, FuncType, ModuleInstanceAddr, WasmType, WasmValue};
 
 impl Function {
+    /// Get a handle to the function a [`FuncRef`] refers to, or `None` for a null reference.
+    ///
+    /// This lets the host call functions it received as `funcref` values, e.g. callbacks
+    /// passed in by a guest. The function's signature is looked up in the store.
+    pub fn from_funcref(store: &Store, func_ref: FuncRef) -> Result<Option<Self>> {
+        let Some(addr) = func_ref.addr() else { return Ok(None) };
+        let Some(func_instance) = store.state.funcs.get(addr as usize) else {
+            cold_path();
+            return Err(Error::Other(format!("invalid function reference: {addr}")));
+        };
+
+        let module_addr = match func_instance {
+            FunctionInstance::Wasm(wasm_func) => wasm_func.owner,
+            FunctionInstance::Host(_) => 0,
+        };
+        Ok(Some(Self { item: StoreItem::new(store.id(), addr), module_addr, addr, ty: func_instance.ty().clone() }))
+    }
+
+    /// Get a `funcref` referring to this function, e.g. to pass it to a guest.
+    ///
+    /// The reference is only meaningful within the store the function belongs to.
+    pub fn to_funcref(&self) -> FuncRef {
+        FuncRef::new(Some(self.addr))
+    }
+
+    /// Get the function's type
+    pub fn ty(&self) -> &Arc<FuncType> {
+        &self.ty
+    }
+
+    /// Convert this handle into a typed function handle, checking its signature.
+    pub fn typed<P: IntoWasmValues + ToWasmTypes, R: FromWasmValues + ToWasmTypes>(
+        self,
+    ) -> Result<FunctionTyped<P, R>> {
+        crate::ModuleInstance::validate_typed_func::<P, R>(&self, &format!("function {}", self.addr))?;
+        Ok(FunctionTyped { func: self, marker: core::marker::PhantomData })
+    }
+
     /// Call a function (Invocation)
     ///
     /// See <https://webassembly.github.io/spec/core/exec/modules.html#invocation>
@@ -18,13 +59,12 @@ impl Function {
                 FunctionInstance::Host(host_func) => {
                     return host_func.clone().call(FuncContext { store, module_addr: func.module_addr }, params);
                 }
-                FunctionInstance::Wasm(wasm_func) => wasm_func,
+                FunctionInstance::Wasm(wasm_func) => wasm_func.clone(),
             };
 
             // Reset stack, push args, allocate locals, create entry frame.
-            store.call_stack.clear();
-            store.value_stack.clear();
-            store.value_stack.extend_from_wasmvalues(params)?;
+            store.clear_stacks();
+            store.push_host_values(params)?;
             let locals_base = store.value_stack.enter_locals(&wasm_func.func.params, &wasm_func.func.locals)?;
             let callframe = CallFrame::new(func.addr, locals_base, wasm_func.func.locals);
 
@@ -34,7 +74,7 @@ impl Function {
         }
 
         self.item.validate_store(store)?;
-        validate_call_params(&self.ty, params)?;
+        validate_call_params(store, self, params)?;
 
         store.enter_execution()?;
         let result = call_inner(self, store, params);
@@ -53,42 +93,114 @@ impl Function {
         store: &'store mut Store,
         params: &[WasmValue],
     ) -> Result<FuncExecution<'store>> {
-        #[inline]
-        fn call_resumable_inner(
-            func: &Function,
-            store: &mut Store,
-            params: &[WasmValue],
-        ) -> Result<FuncExecutionState> {
-            let func_instance = store.state.get_func(func.addr);
-            match func_instance {
-                FunctionInstance::Host(host_func) => host_func
-                    .clone()
-                    .call(FuncContext { store, module_addr: func.module_addr }, params)
-                    .map(|result| FuncExecutionState::Completed { result: Some(result) }),
-                FunctionInstance::Wasm(wasm_func) => {
-                    store.call_stack.clear();
-                    store.value_stack.clear();
-                    store.value_stack.extend_from_wasmvalues(params)?;
-                    let locals_base = store.value_stack.enter_locals(&wasm_func.func.params, &wasm_func.func.locals)?;
-                    let callframe = CallFrame::new(func.addr, locals_base, wasm_func.func.locals);
-
-                    Ok(FuncExecutionState::Running {
-                        exec_state: ExecutionState { callframe },
-                        root_func_addr: func.addr,
-                    })
-                }
-            }
-        }
-
         self.item.validate_store(store)?;
-        validate_call_params(&self.ty, params)?;
+        validate_call_params(store, self, params)?;
 
         store.enter_execution()?;
-        let result = call_resumable_inner(self, store, params);
+        let result = start_execution(self, store, params);
         store.exit_execution();
 
         Ok(FuncExecution { store, state: result? })
     }
+
+    /// Call a function and return a resumable execution that owns its own stacks.
+    ///
+    /// Unlike [`Function::call_resumable`], the returned [`DetachedExecution`] does not
+    /// borrow the [`Store`]; the store is passed to every resume instead. This allows
+    /// many executions to be suspended at the same time and resumed in any order,
+    /// e.g. to cooperatively schedule multiple guest tasks sharing one instance.
+    ///
+    /// Every detached execution allocates its own call and value stacks as configured
+    /// by the store's [`Config`](crate::engine::Config). Consider dynamically growing
+    /// stacks ([`StackConfig::dynamic`](crate::StackConfig::dynamic)) when running many of them.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # fn main() -> tinywasm::Result<()> {
+    /// # use tinywasm::{ExecProgress, ModuleInstance, Store};
+    /// # let wasm = wat::parse_str(r#"
+    /// #     (module
+    /// #       (func (export "add") (param i32 i32) (result i32)
+    /// #         local.get 0
+    /// #         local.get 1
+    /// #         i32.add))
+    /// # "#).expect("valid wat");
+    /// # let module = tinywasm::parse_bytes(&wasm)?;
+    /// let mut store = Store::default();
+    /// let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    /// let add = instance.func::<(i32, i32), i32>(&store, "add")?;
+    ///
+    /// let mut first = add.call_detached(&mut store, (1, 2))?;
+    /// let mut second = add.call_detached(&mut store, (3, 4))?;
+    ///
+    /// assert!(matches!(second.resume_with_fuel(&mut store, 16)?, ExecProgress::Completed(7)));
+    /// assert!(matches!(first.resume_with_fuel(&mut store, 16)?, ExecProgress::Completed(3)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_detached(&self, store: &mut Store, params: &[WasmValue]) -> Result<DetachedExecution> {
+        self.item.validate_store(store)?;
+        validate_call_params(store, self, params)?;
+
+        let mut stacks = Stacks::new(store.engine.config());
+        store.enter_execution()?;
+        store.swap_stacks(&mut stacks);
+        let result = start_execution(self, store, params);
+        store.swap_stacks(&mut stacks);
+        store.exit_execution();
+
+        let execution = DetachedExecution { store_id: store.id(), state: result?, stacks, gc_token: Arc::new(()) };
+        execution.update_gc_roots(store);
+        Ok(execution)
+    }
+
+    /// Call a function and return a future that drives it to completion.
+    ///
+    /// The invocation runs in fuel slices and returns [`Poll::Pending`] between
+    /// them, so long-running guests don't block the executor. Async host functions
+    /// created with [`HostFunction::from_async`] are awaited by the future.
+    ///
+    /// This works with any executor and does not depend on an async runtime.
+    /// Since [`Store`] is not `Send`, the future is not `Send` either.
+    ///
+    /// `memory.atomic.wait` on a shared memory is not turned into [`Poll::Pending`]: it blocks the
+    /// polling thread until it is notified or times out, so guests that wait should not be called
+    /// on a single-threaded executor.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # fn main() -> tinywasm::Result<()> {
+    /// # use core::task::{Context, Poll, Waker};
+    /// # use tinywasm::{ModuleInstance, Store};
+    /// # use tinywasm::types::WasmValue;
+    /// # let wasm = wat::parse_str(r#"
+    /// #     (module
+    /// #       (func (export "add") (param i32 i32) (result i32)
+    /// #         local.get 0
+    /// #         local.get 1
+    /// #         i32.add))
+    /// # "#).expect("valid wat");
+    /// # let module = tinywasm::parse_bytes(&wasm)?;
+    /// let mut store = Store::default();
+    /// let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    /// let add = instance.func_untyped(&store, "add")?;
+    ///
+    /// // Usually this is `add.call_async(..).await` inside an async runtime.
+    /// let mut future =
+    ///     core::pin::pin!(add.call_async(&mut store, &[WasmValue::I32(1), WasmValue::I32(2)]));
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// let result = loop {
+    ///     if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+    ///         break result?;
+    ///     }
+    /// };
+    /// assert_eq!(result, vec![WasmValue::I32(3)]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_async<'store>(&self, store: &'store mut Store, params: &[WasmValue]) -> CallFuture<'store> {
+        CallFuture::new(self.call_resumable(store, params))
+    }
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -100,10 +212,10 @@ pub enum ExecProgress<T> {
     Suspended,
 }
 
-#[derive(Clone)]
 #[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
 pub(crate) struct ExecutionState {
     pub(crate) callframe: CallFrame,
+    pub(crate) pending_host_call: Option<PendingHostCall>,
 }
 
 /// A function handle
@@ -136,9 +248,24 @@ impl HostFunction {
         &self.ty
     }
 
+    /// Returns `true` if this host function was created with [`HostFunction::from_async`]
+    /// or [`HostFunction::from_untyped_async`].
+    pub fn is_async(&self) -> bool {
+        matches!(self.func, HostFuncInner::Async(_))
+    }
+
     /// Call the function
+    ///
+    /// Async host functions can only be driven by resumable or async executions,
+    /// calling them through this method returns an error.
     pub fn call(&self, ctx: FuncContext<'_>, args: &[WasmValue]) -> Result<Vec<WasmValue>> {
-        (self.func)(ctx, args)
+        match &self.func {
+            HostFuncInner::Sync(func) => func(ctx, args),
+            HostFuncInner::Async(_) => {
+                cold_path();
+                Err(Trap::Other("async host functions require resumable or async execution").into())
+            }
+        }
     }
 
     /// Create a new untyped host function import.
@@ -182,20 +309,11 @@ impl HostFunction {
         let host_ty = ty.clone();
 
         let inner_func = move |ctx: FuncContext<'_>, args: &[WasmValue]| -> Result<Vec<WasmValue>> {
-            let result = func(ctx, args)?;
-            let expected = host_ty.results();
-
-            let valid = result.len() == expected.len()
-                && result.iter().zip(expected).all(|(val, ty)| WasmType::from(val) == *ty);
-
-            if !valid {
-                return Err(crate::Error::InvalidHostFnReturn { expected: Arc::clone(&host_ty), actual: result });
-            }
-
-            Ok(result)
+            validate_host_results(&host_ty, func(ctx, args)?)
         };
 
-        let addr = store.add_func(FunctionInstance::Host(Rc::new(Self { func: Box::new(inner_func), ty: ty.clone() })));
+        let func = HostFuncInner::Sync(Box::new(inner_func));
+        let addr = store.add_func(FunctionInstance::Host(Rc::new(Self { func, ty: ty.clone() })));
         Function { item: crate::StoreItem::new(store.id(), addr), module_addr: 0, addr, ty }
     }
 
@@ -234,12 +352,143 @@ impl HostFunction {
         };
 
         let ty = Arc::new(tinywasm_types::FuncType::new(&P::wasm_types(), &R::wasm_types()));
-        let addr = store.add_func(FunctionInstance::Host(Rc::new(Self { func: Box::new(inner_func), ty: ty.clone() })));
+        let func = HostFuncInner::Sync(Box::new(inner_func));
+        let addr = store.add_func(FunctionInstance::Host(Rc::new(Self { func, ty: ty.clone() })));
         Function { item: crate::StoreItem::new(store.id(), addr), module_addr: 0, addr, ty }
     }
+
+    /// Create a new untyped async host function import.
+    ///
+    /// The closure runs synchronously when the function is called and can use the
+    /// [`FuncContext`] to read arguments from memory. The returned future must not
+    /// borrow the store; it is polled by the [`CallFuture`] (or [`FuncExecution`])
+    /// driving the invocation, and Wasm execution continues once it resolves.
+    ///
+    /// Calling an async host function from a blocking invocation like [`Function::call`]
+    /// traps.
+    pub fn from_untyped_async<F>(
+        store: &mut Store,
+        ty: &FuncType,
+        func: impl Fn(FuncContext<'_>, &[WasmValue]) -> F + 'static,
+    ) -> Function
+    where
+        F: Future<Output = Result<Vec<WasmValue>>> + 'static,
+    {
+        let ty = Arc::new(ty.clone());
+        let host_ty = ty.clone();
+
+        let inner_func = move |ctx: FuncContext<'_>, args: &[WasmValue]| -> HostCallFuture {
+            let future = func(ctx, args);
+            let host_ty = host_ty.clone();
+            HostCallFuture(Box::pin(async move { validate_host_results(&host_ty, future.await?) }))
+        };
+
+        let func = HostFuncInner::Async(Box::new(inner_func));
+        let addr = store.add_func(FunctionInstance::Host(Rc::new(Self { func, ty: ty.clone() })));
+        Function { item: crate::StoreItem::new(store.id(), addr), module_addr: 0, addr, ty }
+    }
+
+    /// Create a new typed async host function import.
+    ///
+    /// See [`HostFunction::from_untyped_async`] for how the returned future is driven.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # fn main() -> tinywasm::Result<()> {
+    /// # use tinywasm::{HostFunction, Imports, ModuleInstance, Store, ExecProgress};
+    /// # let wasm = wat::parse_str(r#"
+    /// #     (module
+    /// #       (import "host" "add_one" (func $add_one (param i32) (result i32)))
+    /// #       (func (export "call") (param i32) (result i32)
+    /// #         local.get 0
+    /// #         call $add_one))
+    /// # "#).expect("valid wat");
+    /// # let module = tinywasm::parse_bytes(&wasm)?;
+    /// let mut store = Store::default();
+    /// let add_one =
+    ///     HostFunction::from_async(&mut store, |_ctx, value: i32| async move { Ok(value + 1) });
+    ///
+    /// let mut imports = Imports::new();
+    /// imports.define("host", "add_one", add_one);
+    /// # let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    /// # let call = instance.func::<i32, i32>(&store, "call")?;
+    /// # let mut exec = call.call_resumable(&mut store, 41)?;
+    /// # let result = loop {
+    /// #     if let ExecProgress::Completed(value) = exec.resume_with_fuel(1024)? {
+    /// #         break value;
+    /// #     }
+    /// # };
+    /// # assert_eq!(result, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_async<P, R, F>(store: &mut Store, func: impl Fn(FuncContext<'_>, P) -> F + 'static) -> Function
+    where
+        P: FromWasmValues + ToWasmTypes,
+        R: IntoWasmValues + ToWasmTypes,
+        F: Future<Output = Result<R>> + 'static,
+    {
+        let inner_func = move |ctx: FuncContext<'_>, args: &[WasmValue]| -> HostCallFuture {
+            match P::from_wasm_values(args) {
+                Ok(params) => {
+                    let future = func(ctx, params);
+                    HostCallFuture(Box::pin(async move { Ok(future.await?.into_wasm_values()) }))
+                }
+                Err(err) => HostCallFuture(Box::pin(core::future::ready(Err(err)))),
+            }
+        };
+
+        let ty = Arc::new(tinywasm_types::FuncType::new(&P::wasm_types(), &R::wasm_types()));
+        let func = HostFuncInner::Async(Box::new(inner_func));
+        let addr = store.add_func(FunctionInstance::Host(Rc::new(Self { func, ty: ty.clone() })));
+        Function { item: crate::StoreItem::new(store.id(), addr), module_addr: 0, addr, ty }
+    }
+}
+
+fn validate_host_results(ty: &Arc<FuncType>, result: Vec<WasmValue>) -> Result<Vec<WasmValue>> {
+    let expected = ty.results();
+    let valid =
+        result.len() == expected.len() && result.iter().zip(expected).all(|(val, ty)| WasmType::from(val) == *ty);
+
+    if !valid {
+        cold_path();
+        return Err(crate::Error::InvalidHostFnReturn { expected: Arc::clone(ty), actual: result });
+    }
+
+    Ok(result)
+}
+
+pub(crate) type SyncHostFunc = Box<dyn Fn(FuncContext<'_>, &[WasmValue]) -> Result<Vec<WasmValue>>>;
+pub(crate) type AsyncHostFunc = Box<dyn Fn(FuncContext<'_>, &[WasmValue]) -> HostCallFuture>;
+
+pub(crate) enum HostFuncInner {
+    Sync(SyncHostFunc),
+    Async(AsyncHostFunc),
+}
+
+/// The pending future of an async host function call.
+pub(crate) struct HostCallFuture(Pin<Box<dyn Future<Output = Result<Vec<WasmValue>>>>>);
+
+impl HostCallFuture {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Result<Vec<WasmValue>>> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+/// An async host function call that execution is waiting on.
+#[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
+pub(crate) struct PendingHostCall {
+    pub(crate) future: HostCallFuture,
+    /// The arguments are no longer on the value stack, so they are rooted separately until the call resolves.
+    pub(crate) params: Vec<WasmValue>,
 }
 
-pub(crate) type HostFuncInner = Box<dyn Fn(FuncContext<'_>, &[WasmValue]) -> Result<Vec<WasmValue>>>;
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for HostCallFuture {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("HostCallFuture(...)")
+    }
+}
 
 /// The context of a host-function call
 #[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
@@ -327,7 +576,7 @@ impl FuncContext<'_> {
         }
 
         func.item.validate_store(self.store)?;
-        validate_call_params(&func.ty, args)?;
+        validate_call_params(self.store, func, args)?;
 
         let func_instance = self.store.state.get_func(func.addr).clone();
         match func_instance {
@@ -338,7 +587,7 @@ impl FuncContext<'_> {
                 let call_stack_base = self.store.call_stack.len();
                 let value_stack_base = self.store.value_stack.base();
 
-                self.store.value_stack.extend_from_wasmvalues(args).inspect_err(|_| {
+                self.store.push_host_values(args).inspect_err(|_| {
                     self.store.value_stack.truncate_to_base(value_stack_base);
                 })?;
 
@@ -410,9 +659,19 @@ pub struct FuncExecution<'store> {
 #[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
 enum FuncExecutionState {
     Running { exec_state: ExecutionState, root_func_addr: u32 },
+    AwaitingHost { future: HostCallFuture },
     Completed { result: Option<Vec<WasmValue>> },
 }
 
+/// Result of a single resumption step.
+enum ResumeStep {
+    Completed(Vec<WasmValue>),
+    /// The fuel or time slice was exhausted.
+    Yielded,
+    /// Waiting on an async host function; the task context's waker is notified once it makes progress.
+    Waiting,
+}
+
 /// Resumable execution for a typed function call.
 #[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
 pub struct FuncExecutionTyped<'store, R> {
@@ -420,40 +679,99 @@ pub struct FuncExecutionTyped<'store, R> {
     marker: core::marker::PhantomData<R>,
 }
 
-impl<'store> FuncExecution<'store> {
-    fn resume(
+impl FuncExecutionState {
+    /// Drive the execution by one step. The caller has to enter the execution on the store
+    /// and make the execution's stacks the store's current stacks.
+    fn step(
         &mut self,
+        store: &mut Store,
+        cx: &mut Context<'_>,
         run: impl FnOnce(&mut Store, CallFrame) -> Result<crate::interpreter::ExecState, Trap>,
-    ) -> Result<ExecProgress<Vec<WasmValue>>> {
-        let (callframe, root_func_addr) = match &mut self.state {
-            FuncExecutionState::Running { exec_state, root_func_addr } => (exec_state.callframe, *root_func_addr),
-            FuncExecutionState::Completed { result } => {
+    ) -> Result<ResumeStep> {
+        let (callframe, root_func_addr) = match self {
+            Self::Running { exec_state, root_func_addr } => {
+                if let Some(call) = &mut exec_state.pending_host_call {
+                    let Poll::Ready(result) = call.future.poll(cx) else {
+                        return Ok(ResumeStep::Waiting);
+                    };
+
+                    exec_state.pending_host_call = None;
+                    match result {
+                        Ok(values) => store.push_host_values(&values)?,
+                        Err(err) => {
+                            exec_state.callframe =
+                                InterpreterRuntime::throw_host_error(store, exec_state.callframe, err)?;
+                        }
+                    }
+                }
+                (exec_state.callframe, *root_func_addr)
+            }
+            Self::AwaitingHost { future } => {
+                let Poll::Ready(result) = future.poll(cx) else {
+                    return Ok(ResumeStep::Waiting);
+                };
+
+                *self = Self::Completed { result: None };
+                return result.map(ResumeStep::Completed);
+            }
+            Self::Completed { result } => {
                 return match result.take() {
-                    Some(res) => Ok(ExecProgress::Completed(res)),
+                    Some(res) => Ok(ResumeStep::Completed(res)),
                     None => Err(Error::other("execution already completed")),
                 };
             }
         };
 
-        self.store.enter_execution()?;
-        let result = run(self.store, callframe);
-        self.store.exit_execution();
+        let result = run(store, callframe);
+        let Self::Running { exec_state, .. } = self else { unreachable!("invalid function execution state") };
 
         match result? {
             crate::interpreter::ExecState::Completed => {
-                let result_ty = self.store.state.get_func(root_func_addr).ty().clone();
-                self.state = FuncExecutionState::Completed { result: None };
-                Ok(ExecProgress::Completed(collect_call_results(&mut self.store.value_stack, &result_ty)?))
+                let result_ty = store.state.get_func(root_func_addr).ty().clone();
+                *self = Self::Completed { result: None };
+                Ok(ResumeStep::Completed(collect_call_results(&mut store.value_stack, &result_ty)?))
             }
             crate::interpreter::ExecState::Suspended(callframe) => {
-                let FuncExecutionState::Running { exec_state, .. } = &mut self.state else {
-                    unreachable!("invalid function execution state")
-                };
                 exec_state.callframe = callframe;
-                Ok(ExecProgress::Suspended)
+                Ok(ResumeStep::Yielded)
+            }
+            crate::interpreter::ExecState::AwaitingHost(callframe, call) => {
+                exec_state.callframe = callframe;
+                exec_state.pending_host_call = Some(call);
+                Ok(ResumeStep::Yielded)
             }
         }
     }
+}
+
+impl ResumeStep {
+    fn into_progress(self) -> ExecProgress<Vec<WasmValue>> {
+        match self {
+            Self::Completed(values) => ExecProgress::Completed(values),
+            Self::Yielded | Self::Waiting => ExecProgress::Suspended,
+        }
+    }
+}
+
+impl<'store> FuncExecution<'store> {
+    fn resume(
+        &mut self,
+        run: impl FnOnce(&mut Store, CallFrame) -> Result<crate::interpreter::ExecState, Trap>,
+    ) -> Result<ExecProgress<Vec<WasmValue>>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        self.step(&mut cx, run).map(ResumeStep::into_progress)
+    }
+
+    fn step(
+        &mut self,
+        cx: &mut Context<'_>,
+        run: impl FnOnce(&mut Store, CallFrame) -> Result<crate::interpreter::ExecState, Trap>,
+    ) -> Result<ResumeStep> {
+        self.store.enter_execution()?;
+        let result = self.state.step(self.store, cx, run);
+        self.store.exit_execution();
+        result
+    }
 
     /// Resume execution with up to `fuel` units of fuel.
     ///
@@ -467,6 +785,10 @@ impl<'store> FuncExecution<'store> {
     /// Reentrant calls made by host functions through [`FuncContext::call`] are
     /// currently blocking. They do not suspend and later resume the host
     /// function in the middle of the nested call.
+    ///
+    /// Pending async host functions are polled with a no-op waker and report
+    /// [`ExecProgress::Suspended`] until they resolve. Use [`Function::call_async`]
+    /// to have the executor woken instead.
     pub fn resume_with_fuel(&mut self, fuel: u32) -> Result<ExecProgress<Vec<WasmValue>>> {
         self.resume(|store, callframe| InterpreterRuntime::exec_with_fuel(store, callframe, fuel))
     }
@@ -484,6 +806,10 @@ impl<'store> FuncExecution<'store> {
     /// Reentrant calls made by host functions through [`FuncContext::call`] are
     /// currently blocking. They do not suspend and later resume the host
     /// function in the middle of the nested call.
+    ///
+    /// Pending async host functions are polled with a no-op waker and report
+    /// [`ExecProgress::Suspended`] until they resolve. Use [`Function::call_async`]
+    /// to have the executor woken instead.
     pub fn resume_with_time_budget(
         &mut self,
         time_budget: crate::std::time::Duration,
@@ -492,7 +818,263 @@ impl<'store> FuncExecution<'store> {
     }
 }
 
-fn validate_call_params(func_ty: &FuncType, params: &[WasmValue]) -> Result<()> {
+/// Resumable execution for an untyped function call that owns its own stacks.
+///
+/// Created by [`Function::call_detached`]. The execution must be resumed with the
+/// [`Store`] it was created in; other executions (including blocking calls) can
+/// run on the same store while it is suspended.
+#[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
+pub struct DetachedExecution {
+    store_id: usize,
+    state: FuncExecutionState,
+    stacks: Stacks,
+    // identifies the references kept alive by the store's garbage collector while suspended
+    gc_token: Arc<()>,
+}
+
+/// Resumable execution for a typed function call that owns its own stacks.
+///
+/// Created by [`FunctionTyped::call_detached`].
+#[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
+pub struct DetachedExecutionTyped<R> {
+    execution: DetachedExecution,
+    marker: core::marker::PhantomData<R>,
+}
+
+impl DetachedExecution {
+    fn resume(
+        &mut self,
+        store: &mut Store,
+        run: impl FnOnce(&mut Store, CallFrame) -> Result<crate::interpreter::ExecState, Trap>,
+    ) -> Result<ExecProgress<Vec<WasmValue>>> {
+        if self.store_id != store.id() {
+            cold_path();
+            return Err(Trap::InvalidStore.into());
+        }
+
+        let mut cx = Context::from_waker(Waker::noop());
+        store.enter_execution()?;
+        store.swap_stacks(&mut self.stacks);
+        let result = self.state.step(store, &mut cx, run);
+        store.swap_stacks(&mut self.stacks);
+        store.exit_execution();
+
+        if result.is_err() {
+            self.state = FuncExecutionState::Completed { result: None };
+        }
+        self.update_gc_roots(store);
+        result.map(ResumeStep::into_progress)
+    }
+
+    // The suspended stacks are invisible to the garbage collector, so their references are rooted separately,
+    // along with the arguments of a pending async host call.
+    fn update_gc_roots(&self, store: &mut Store) {
+        let host_params = match &self.state {
+            FuncExecutionState::Running { exec_state, .. } => exec_state.pending_host_call.as_ref(),
+            _ => None,
+        };
+        match self.is_completed() {
+            true => store.gc.resume(&self.gc_token),
+            false => store.gc.suspend(&self.gc_token, &self.stacks, host_params.map_or(&[], |call| &call.params)),
+        }
+    }
+
+    /// Returns `true` once the execution has returned its results or trapped.
+    pub fn is_completed(&self) -> bool {
+        matches!(self.state, FuncExecutionState::Completed { .. })
+    }
+
+    /// Resume execution with up to `fuel` units of fuel.
+    ///
+    /// See [`FuncExecution::resume_with_fuel`] for how fuel is accounted.
+    pub fn resume_with_fuel(&mut self, store: &mut Store, fuel: u32) -> Result<ExecProgress<Vec<WasmValue>>> {
+        self.resume(store, |store, callframe| InterpreterRuntime::exec_with_fuel(store, callframe, fuel))
+    }
+
+    #[cfg(feature = "std")]
+    /// Resume execution for at most `time_budget` wall-clock time.
+    ///
+    /// See [`FuncExecution::resume_with_time_budget`] for how time is accounted.
+    pub fn resume_with_time_budget(
+        &mut self,
+        store: &mut Store,
+        time_budget: crate::std::time::Duration,
+    ) -> Result<ExecProgress<Vec<WasmValue>>> {
+        self.resume(store, |store, callframe| InterpreterRuntime::exec_with_time_budget(store, callframe, time_budget))
+    }
+}
+
+impl<R: FromWasmValues> DetachedExecutionTyped<R> {
+    /// Returns `true` once the execution has returned its results or trapped.
+    pub fn is_completed(&self) -> bool {
+        self.execution.is_completed()
+    }
+
+    /// Resume typed execution with up to `fuel` units of fuel.
+    pub fn resume_with_fuel(&mut self, store: &mut Store, fuel: u32) -> Result<ExecProgress<R>> {
+        match self.execution.resume_with_fuel(store, fuel)? {
+            ExecProgress::Completed(values) => Ok(ExecProgress::Completed(R::from_wasm_values(&values)?)),
+            ExecProgress::Suspended => Ok(ExecProgress::Suspended),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    /// Resume typed execution for at most `time_budget` wall-clock time.
+    pub fn resume_with_time_budget(
+        &mut self,
+        store: &mut Store,
+        time_budget: crate::std::time::Duration,
+    ) -> Result<ExecProgress<R>> {
+        match self.execution.resume_with_time_budget(store, time_budget)? {
+            ExecProgress::Completed(values) => Ok(ExecProgress::Completed(R::from_wasm_values(&values)?)),
+            ExecProgress::Suspended => Ok(ExecProgress::Suspended),
+        }
+    }
+}
+
+const DEFAULT_ASYNC_FUEL_SLICE: u32 = 10_000;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
+enum ExecSlice {
+    Fuel(u32),
+    #[cfg(feature = "std")]
+    Time(crate::std::time::Duration),
+}
+
+/// Future for an untyped async function call, returned by [`Function::call_async`].
+///
+/// Every poll runs the invocation for one fuel slice (or time slice) and returns
+/// [`Poll::Pending`] in between, so other tasks on the executor can make progress.
+/// While an async host function is pending, its future is polled instead.
+///
+/// A `memory.atomic.wait` blocks the thread polling the future, see [`Function::call_async`].
+///
+/// The future keeps a mutable borrow of the [`Store`] until it completes.
+#[must_use = "futures do nothing unless polled"]
+#[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
+pub struct CallFuture<'store> {
+    execution: Result<FuncExecution<'store>, Option<Error>>,
+    slice: ExecSlice,
+}
+
+/// Future for a typed async function call, returned by [`FunctionTyped::call_async`].
+///
+/// See [`CallFuture`] for how execution is sliced.
+#[must_use = "futures do nothing unless polled"]
+#[cfg_attr(feature = "debug", derive(core::fmt::Debug))]
+pub struct CallFutureTyped<'store, R> {
+    inner: CallFuture<'store>,
+    marker: core::marker::PhantomData<fn() -> R>,
+}
+
+impl<'store> CallFuture<'store> {
+    fn new(execution: Result<FuncExecution<'store>>) -> Self {
+        Self { execution: execution.map_err(Some), slice: ExecSlice::Fuel(DEFAULT_ASYNC_FUEL_SLICE) }
+    }
+
+    /// Run up to `fuel` units of fuel per poll (default: 10,000).
+    ///
+    /// Smaller slices yield to the executor more often at the cost of more overhead.
+    pub fn with_fuel_slice(mut self, fuel: u32) -> Self {
+        self.slice = ExecSlice::Fuel(fuel);
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Run for at most `time_slice` wall-clock time per poll instead of a fuel slice.
+    pub fn with_time_slice(mut self, time_slice: crate::std::time::Duration) -> Self {
+        self.slice = ExecSlice::Time(time_slice);
+        self
+    }
+}
+
+impl Future for CallFuture<'_> {
+    type Output = Result<Vec<WasmValue>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let execution = match &mut this.execution {
+            Ok(execution) => execution,
+            Err(err) => {
+                return Poll::Ready(Err(err.take().unwrap_or_else(|| Error::other("execution already completed"))));
+            }
+        };
+
+        let slice = this.slice;
+        let step = execution.step(cx, |store, callframe| match slice {
+            ExecSlice::Fuel(fuel) => InterpreterRuntime::exec_with_fuel(store, callframe, fuel),
+            #[cfg(feature = "std")]
+            ExecSlice::Time(time_slice) => InterpreterRuntime::exec_with_time_budget(store, callframe, time_slice),
+        });
+
+        match step {
+            Ok(ResumeStep::Completed(values)) => Poll::Ready(Ok(values)),
+            Ok(ResumeStep::Yielded) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Ok(ResumeStep::Waiting) => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl<'store, R> CallFutureTyped<'store, R> {
+    /// Run up to `fuel` units of fuel per poll (default: 10,000).
+    pub fn with_fuel_slice(self, fuel: u32) -> Self {
+        Self { inner: self.inner.with_fuel_slice(fuel), marker: core::marker::PhantomData }
+    }
+
+    #[cfg(feature = "std")]
+    /// Run for at most `time_slice` wall-clock time per poll instead of a fuel slice.
+    pub fn with_time_slice(self, time_slice: crate::std::time::Duration) -> Self {
+        Self { inner: self.inner.with_time_slice(time_slice), marker: core::marker::PhantomData }
+    }
+}
+
+impl<R: FromWasmValues> Future for CallFutureTyped<'_, R> {
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.get_mut().inner).poll(cx) {
+            Poll::Ready(values) => Poll::Ready(values.and_then(|values| R::from_wasm_values(&values))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Set up an invocation on the store's current stacks without running it.
+fn start_execution(func: &Function, store: &mut Store, params: &[WasmValue]) -> Result<FuncExecutionState> {
+    let func_instance = store.state.get_func(func.addr);
+    match func_instance {
+        FunctionInstance::Host(host_func) => {
+            let host_func = host_func.clone();
+            let ctx = FuncContext { store, module_addr: func.module_addr };
+            match &host_func.func {
+                HostFuncInner::Sync(f) => {
+                    f(ctx, params).map(|result| FuncExecutionState::Completed { result: Some(result) })
+                }
+                HostFuncInner::Async(f) => Ok(FuncExecutionState::AwaitingHost { future: f(ctx, params) }),
+            }
+        }
+        FunctionInstance::Wasm(wasm_func) => {
+            let wasm_func = wasm_func.clone();
+            store.clear_stacks();
+            store.push_host_values(params)?;
+            let locals_base = store.value_stack.enter_locals(&wasm_func.func.params, &wasm_func.func.locals)?;
+            let callframe = CallFrame::new(func.addr, locals_base, wasm_func.func.locals);
+
+            Ok(FuncExecutionState::Running {
+                exec_state: ExecutionState { callframe, pending_host_call: None },
+                root_func_addr: func.addr,
+            })
+        }
+    }
+}
+
+fn validate_call_params(store: &Store, func: &Function, params: &[WasmValue]) -> Result<()> {
+    let func_ty = &func.ty;
     if func_ty.params().len() != params.len() {
         cold_path();
         return Err(Error::Other(format!(
@@ -506,7 +1088,7 @@ fn validate_call_params(func_ty: &FuncType, params: &[WasmValue]) -> Result<()>
         return Err(Error::other("Type mismatch"));
     }
 
-    Ok(())
+    store.check_call_params(func.addr, params)
 }
 
 fn collect_call_results(value_stack: &mut ValueStack, func_ty: &FuncType) -> Result<Vec<WasmValue>> {
@@ -545,6 +1127,22 @@ impl<P: IntoWasmValues, R: FromWasmValues> FunctionTyped<P, R> {
         let execution = self.func.call_resumable(store, &wasm_values)?;
         Ok(FuncExecutionTyped { execution, marker: core::marker::PhantomData })
     }
+
+    /// Call a typed function and return a resumable execution that owns its own stacks.
+    ///
+    /// See [`Function::call_detached`] for details.
+    pub fn call_detached(&self, store: &mut Store, params: P) -> Result<DetachedExecutionTyped<R>> {
+        let execution = self.func.call_detached(store, &params.into_wasm_values())?;
+        Ok(DetachedExecutionTyped { execution, marker: core::marker::PhantomData })
+    }
+
+    /// Call a typed function and return a future that drives it to completion.
+    ///
+    /// See [`Function::call_async`] for details.
+    pub fn call_async<'store>(&self, store: &'store mut Store, params: P) -> CallFutureTyped<'store, R> {
+        let wasm_values = params.into_wasm_values();
+        CallFutureTyped { inner: self.func.call_async(store, &wasm_values), marker: core::marker::PhantomData }
+    }
 }
 
 impl<'store, R: FromWasmValues> FuncExecutionTyped<'store, R> {
@@ -689,6 +1287,7 @@ impl_scalar_wasm_traits!(
     f64 => F64,
     FuncRef => RefFunc,
     ExternRef => RefExtern,
+    AnyRef => RefAny,
 );
 impl_tuple!(impl_tuple_traits);
 
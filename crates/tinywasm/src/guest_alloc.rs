@@ -0,0 +1,206 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{Error, FunctionTyped, Memory, ModuleInstance, Result, Store};
+
+/// The export a guest allocates memory with.
+#[cfg_attr(feature = "debug", derive(Debug))]
+enum AllocFn {
+    /// `malloc(size) -> ptr`
+    Malloc(FunctionTyped<i32, i32>),
+    /// `cabi_realloc(old_ptr, old_size, align, new_size) -> ptr`, as exported by components.
+    Realloc(FunctionTyped<(i32, i32, i32, i32), i32>),
+}
+
+/// Moves data between the host and a guest's linear memory using the guest's own allocator.
+///
+/// Created with [`ModuleInstance::guest_allocator`] or [`ModuleInstance::guest_allocator_with`].
+/// Only 32-bit memories are supported, and pointers and lengths are passed to the guest as `i32`s,
+/// so they are limited to `i32::MAX`.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct GuestAllocator {
+    memory: Memory,
+    alloc: AllocFn,
+    free: Option<FunctionTyped<i32, ()>>,
+}
+
+impl GuestAllocator {
+    pub(crate) fn new(
+        instance: &ModuleInstance,
+        store: &Store,
+        memory: &str,
+        alloc: &str,
+        free: Option<&str>,
+    ) -> Result<Self> {
+        let alloc = match instance.func::<i32, i32>(store, alloc) {
+            Ok(malloc) => AllocFn::Malloc(malloc),
+            Err(_) => AllocFn::Realloc(instance.func(store, alloc)?),
+        };
+        let free = free.map(|free| instance.func(store, free)).transpose()?;
+        Ok(Self { memory: instance.memory(memory)?, alloc, free })
+    }
+
+    /// Returns the memory allocations are made in.
+    pub fn memory(&self) -> Memory {
+        self.memory
+    }
+
+    /// Allocates `len` bytes in the guest and returns a pointer to them.
+    ///
+    /// The allocation is owned by the caller, who has to release it with [`Self::free`].
+    pub fn alloc(&self, store: &mut Store, len: u32) -> Result<u32> {
+        let size = to_i32(len, "length")?;
+        let ptr = match &self.alloc {
+            AllocFn::Malloc(malloc) => malloc.call(store, size)?,
+            AllocFn::Realloc(realloc) => realloc.call(store, (0, 0, 1, size))?,
+        };
+        if ptr == 0 && len != 0 {
+            return Err(Error::Other("guest allocator failed to allocate memory".to_string()));
+        }
+        u32::try_from(ptr).map_err(|_| Error::Other(format!("guest allocator returned invalid pointer {ptr}")))
+    }
+
+    /// Returns true if the guest exports a function to free allocations.
+    ///
+    /// Guests that only export `cabi_realloc`, or `malloc` without `free`, can't free memory.
+    pub fn can_free(&self) -> bool {
+        self.free.is_some()
+    }
+
+    /// Releases an allocation of the guest.
+    ///
+    /// Returns an error if the guest can't free memory, see [`Self::can_free`].
+    pub fn free(&self, store: &mut Store, ptr: u32) -> Result<()> {
+        match &self.free {
+            Some(free) => free.call(store, to_i32(ptr, "pointer")?),
+            None => Err(Error::Other("guest allocator has no function to free memory".to_string())),
+        }
+    }
+
+    /// Copies `bytes` into a new guest allocation, passes it to `f` and frees it afterwards.
+    ///
+    /// The buffer is freed even if `f` returns an error. Returns an error without allocating if
+    /// the guest can't free memory, see [`Self::can_free`]. Use [`Self::copy_bytes`] if the guest
+    /// takes ownership of the buffer.
+    pub fn with_bytes<R>(
+        &self,
+        store: &mut Store,
+        bytes: &[u8],
+        f: impl FnOnce(&mut Store, &GuestBuffer<'_>) -> Result<R>,
+    ) -> Result<R> {
+        if !self.can_free() {
+            return Err(Error::Other("guest allocator has no function to free memory".to_string()));
+        }
+        let buffer = self.copy_bytes(store, bytes)?;
+        let result = f(store, &buffer);
+        let freed = buffer.free(store);
+        let result = result?;
+        freed.map(|()| result)
+    }
+
+    /// Copies the UTF-8 bytes of `string` into a new guest allocation, see [`Self::with_bytes`].
+    pub fn with_str<R>(
+        &self,
+        store: &mut Store,
+        string: &str,
+        f: impl FnOnce(&mut Store, &GuestBuffer<'_>) -> Result<R>,
+    ) -> Result<R> {
+        self.with_bytes(store, string.as_bytes(), f)
+    }
+
+    /// Copies `bytes` into a new guest allocation that is owned by the caller.
+    ///
+    /// Prefer [`Self::with_bytes`], which frees the allocation when it's no longer used.
+    pub fn copy_bytes(&self, store: &mut Store, bytes: &[u8]) -> Result<GuestBuffer<'_>> {
+        let len = u32::try_from(bytes.len()).map_err(|_| Error::Other("buffer exceeds guest memory".to_string()))?;
+        let buffer = GuestBuffer { allocator: self, ptr: self.alloc(store, len)?, len };
+        if let Err(err) = self.memory.copy_from_slice(store, buffer.ptr as usize, bytes) {
+            if self.can_free() {
+                let _ = buffer.free(store);
+            }
+            return Err(err);
+        }
+        Ok(buffer)
+    }
+
+    /// Copies the UTF-8 bytes of `string` into a new guest allocation, see [`Self::copy_bytes`].
+    pub fn copy_str(&self, store: &mut Store, string: &str) -> Result<GuestBuffer<'_>> {
+        self.copy_bytes(store, string.as_bytes())
+    }
+
+    /// Reads the `len` bytes at `ptr`, e.g. a `(ptr, len)` pair returned by the guest.
+    pub fn read_bytes(&self, store: &Store, ptr: u32, len: u32) -> Result<Vec<u8>> {
+        self.memory.read_vec(store, ptr as usize, len as usize)
+    }
+
+    /// Reads the UTF-8 string of `len` bytes at `ptr`.
+    pub fn read_string(&self, store: &Store, ptr: u32, len: u32) -> Result<String> {
+        self.memory.read_string(store, ptr as usize, len as usize)
+    }
+
+    /// Reads the `len` bytes at `ptr` and frees the allocation, taking ownership of a buffer returned by the guest.
+    ///
+    /// Returns an error if the guest can't free memory, see [`Self::can_free`].
+    pub fn take_bytes(&self, store: &mut Store, ptr: u32, len: u32) -> Result<Vec<u8>> {
+        let bytes = self.read_bytes(store, ptr, len);
+        self.free(store, ptr)?;
+        bytes
+    }
+
+    /// Reads the UTF-8 string of `len` bytes at `ptr` and frees the allocation, see [`Self::take_bytes`].
+    pub fn take_string(&self, store: &mut Store, ptr: u32, len: u32) -> Result<String> {
+        let string = self.read_string(store, ptr, len);
+        self.free(store, ptr)?;
+        string
+    }
+}
+
+/// Data copied into guest memory by a [`GuestAllocator`].
+///
+/// Buffers passed to the closure of [`GuestAllocator::with_bytes`] are freed when it returns.
+/// Buffers returned by [`GuestAllocator::copy_bytes`] are not freed automatically: release them
+/// with [`Self::free`], or with [`Self::leak`] if the guest takes ownership of them.
+#[must_use = "guest buffers are not freed when dropped, call `free` or `leak`"]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct GuestBuffer<'a> {
+    allocator: &'a GuestAllocator,
+    ptr: u32,
+    len: u32,
+}
+
+impl GuestBuffer<'_> {
+    /// Returns the address of the data in guest memory.
+    pub fn ptr(&self) -> u32 {
+        self.ptr
+    }
+
+    /// Returns the length of the data in bytes.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Returns true if the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the pointer and length as the `(i32, i32)` parameters guest functions usually take.
+    pub fn args(&self) -> Result<(i32, i32)> {
+        Ok((to_i32(self.ptr, "pointer")?, to_i32(self.len, "length")?))
+    }
+
+    /// Frees the allocation, see [`GuestAllocator::free`].
+    pub fn free(self, store: &mut Store) -> Result<()> {
+        self.allocator.free(store, self.ptr)
+    }
+
+    /// Releases the buffer without freeing it, e.g. when the guest takes ownership of it, and returns its pointer and length.
+    pub fn leak(self) -> (u32, u32) {
+        (self.ptr, self.len)
+    }
+}
+
+fn to_i32(value: u32, what: &str) -> Result<i32> {
+    i32::try_from(value).map_err(|_| Error::Other(format!("guest {what} {value} exceeds i32::MAX")))
+}
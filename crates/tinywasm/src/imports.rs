@@ -1,10 +1,14 @@
 use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::Debug;
 use core::hint::cold_path;
 
-use crate::{Function, Global, LinkingError, Memory, Result, Table};
+use crate::store::map_ref_type;
+use crate::{Error, Function, Global, LinkingError, Memory, Result, Table, Tag};
 use tinywasm_types::*;
 
 #[derive(Clone)]
@@ -20,6 +24,13 @@ pub enum Extern {
     Memory(Memory),
     /// A function import.
     Function(Function),
+    /// A tag instance.
+    Tag(Tag),
+    /// A memory that can be shared between stores.
+    ///
+    /// Available with the `std` feature enabled.
+    #[cfg(feature = "std")]
+    SharedMemory(crate::SharedMemory),
 }
 
 impl From<Global> for Extern {
@@ -46,6 +57,50 @@ impl From<Function> for Extern {
     }
 }
 
+impl From<Tag> for Extern {
+    fn from(value: Tag) -> Self {
+        Self::Tag(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<crate::SharedMemory> for Extern {
+    fn from(value: crate::SharedMemory) -> Self {
+        Self::SharedMemory(value)
+    }
+}
+
+impl Extern {
+    /// The type of the value, with the current size of tables and memories as their initial size.
+    pub fn ty(&self, store: &crate::Store) -> Result<ExternType> {
+        let val = match self {
+            Self::Global(global) => {
+                global.0.validate_store(store)?;
+                ExternVal::Global(global.0.addr)
+            }
+            Self::Table(table) => {
+                table.0.validate_store(store)?;
+                ExternVal::Table(table.0.addr)
+            }
+            Self::Memory(memory) => {
+                memory.0.validate_store(store)?;
+                ExternVal::Memory(memory.0.addr)
+            }
+            Self::Function(func) => {
+                func.item.validate_store(store)?;
+                return Ok(ExternType::Func(func.ty.as_ref().clone()));
+            }
+            Self::Tag(tag) => {
+                tag.0.validate_store(store)?;
+                ExternVal::Tag(tag.0.addr)
+            }
+            #[cfg(feature = "std")]
+            Self::SharedMemory(memory) => return Ok(ExternType::Memory(memory.ty())),
+        };
+        Ok(Imports::extern_type(store, val))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 /// Name of an import
 pub struct ExternName {
@@ -53,12 +108,137 @@ pub struct ExternName {
     name: String,
 }
 
+impl ExternName {
+    /// The name of the module the import is defined in.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// The name of the import within its module.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 impl From<&Import> for ExternName {
     fn from(import: &Import) -> Self {
         Self { module: import.module.to_string(), name: import.name.to_string() }
     }
 }
 
+type ImportResolver = dyn Fn(&mut crate::Store, &ExternName, &ImportType<'_>) -> Option<Extern>;
+
+/// How [`Imports`] handles imports that are neither defined, provided by a linked module, nor
+/// returned by the resolver.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnresolvedImports {
+    /// Stop at the first unresolved import and fail with [`LinkingError::UnknownImport`],
+    /// along with any incompatible imports found before it.
+    #[default]
+    Fail,
+    /// Fail with every unresolved import at once, as [`LinkingError::Multiple`] if there is more than one.
+    Report,
+    /// Stub unresolved functions with host functions that return [`LinkingError::UnknownImport`] when called.
+    ///
+    /// Other unresolved imports are reported like with [`UnresolvedImports::Report`].
+    TrapFunctions,
+}
+
+#[derive(Clone)]
+struct Resolver(Rc<ImportResolver>);
+
+#[cfg(feature = "debug")]
+impl Debug for Resolver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Resolver")
+    }
+}
+
+/// A namespace of host imports described by a Rust type
+///
+/// Implement this for a type that provides a whole host API, such as `env` or a WASI-like interface,
+/// and register all of its functions, globals, memories and tables with [`Imports::define_module`].
+///
+/// ## Example
+/// ```rust
+/// # fn main() -> tinywasm::Result<()> {
+/// use tinywasm::types::MemoryType;
+/// use tinywasm::{Extern, HostFunction, HostModule, Imports, Memory, ModuleInstance, Store};
+/// # let wasm = wat::parse_str(r#"
+/// #     (module
+/// #       (import "math" "add" (func $add (param i32 i32) (result i32)))
+/// #       (func (export "call") (result i32) (call $add (i32.const 1) (i32.const 2))))
+/// # "#).expect("valid wat");
+/// # let module = tinywasm::parse_bytes(&wasm)?;
+///
+/// struct Math;
+///
+/// impl HostModule for Math {
+///     fn name(&self) -> &str {
+///         "math"
+///     }
+///
+///     fn externs(&self, store: &mut Store) -> tinywasm::Result<Vec<(String, Extern)>> {
+///         Ok(vec![
+///             ("add".into(), HostFunction::from(store, |_, (a, b): (i32, i32)| Ok(a + b)).into()),
+///             ("scratch".into(), Memory::new(store, MemoryType::default())?.into()),
+///         ])
+///     }
+/// }
+///
+/// Math.check_imports(&module)?;
+///
+/// let mut store = Store::default();
+/// let mut imports = Imports::new();
+/// imports.define_module(&mut store, &Math)?;
+/// let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+/// assert_eq!(instance.func::<(), i32>(&store, "call")?.call(&mut store, ())?, 3);
+/// # Ok(())
+/// # }
+/// ```
+pub trait HostModule {
+    /// The module name the imports are defined under.
+    fn name(&self) -> &str;
+
+    /// Create the namespace's functions, globals, memories and tables in `store`, keyed by their import names.
+    fn externs(&self, store: &mut crate::Store) -> Result<Vec<(String, Extern)>>;
+
+    /// The names and types of the namespace's externs, e.g. for generating documentation.
+    fn extern_types(&self) -> Result<Vec<(String, ExternType)>> {
+        let mut store = crate::Store::default();
+        let externs = self.externs(&mut store)?;
+        externs.into_iter().map(|(name, val)| Ok((name, val.ty(&store)?))).collect()
+    }
+
+    /// Check the imports `module` requests from this namespace without instantiating it.
+    ///
+    /// Every import that's missing or has an incompatible type is reported, see [`UnresolvedImports::Report`].
+    /// Imports from other modules are ignored.
+    fn check_imports(&self, module: &Module) -> Result<()> {
+        let mut store = crate::Store::default();
+        let mut imports = Imports::new();
+        imports.define_module(&mut store, self)?.on_unresolved(UnresolvedImports::Report);
+
+        let type_ids = store.register_types(module);
+        let errors = match imports.link(&mut store, module, &type_ids) {
+            Ok(_) => return Ok(()),
+            Err(Error::Linker(LinkingError::Multiple(errors))) => errors,
+            Err(Error::Linker(err)) => vec![err],
+            Err(err) => return Err(err),
+        };
+
+        let errors: Vec<_> = errors
+            .into_iter()
+            .filter(|err| !matches!(err, LinkingError::UnknownImport { module, .. } if module != self.name()))
+            .collect();
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(LinkingError::from_errors(errors).into()),
+        }
+    }
+}
+
 /// Imports for a module instance
 ///
 /// This is used to link a module instance to its imports
@@ -107,25 +287,84 @@ impl From<&Import> for ExternName {
 pub struct Imports {
     externs: BTreeMap<ExternName, Extern>,
     modules: BTreeMap<String, crate::ModuleInstance>,
+    resolver: Option<Resolver>,
+    unresolved: UnresolvedImports,
 }
 
+#[derive(Default)]
+#[cfg_attr(feature = "debug", derive(Debug))]
 pub(crate) struct ResolvedImports {
     pub(crate) globals: Vec<GlobalAddr>,
     pub(crate) tables: Vec<TableAddr>,
     pub(crate) memories: Vec<MemAddr>,
     pub(crate) funcs: Vec<FuncAddr>,
+    pub(crate) tags: Vec<TagAddr>,
 }
 
 impl Imports {
     /// Create a new empty import set
     pub const fn new() -> Self {
-        Self { externs: BTreeMap::new(), modules: BTreeMap::new() }
+        Self { externs: BTreeMap::new(), modules: BTreeMap::new(), resolver: None, unresolved: UnresolvedImports::Fail }
     }
 
     /// Merge two import sets
+    ///
+    /// Definitions, the resolver and the unresolved import policy of `other` take precedence.
     pub fn merge(mut self, other: Self) -> Self {
         self.externs.extend(other.externs);
         self.modules.extend(other.modules);
+        self.resolver = other.resolver.or(self.resolver);
+        if other.unresolved != UnresolvedImports::default() {
+            self.unresolved = other.unresolved;
+        }
+        self
+    }
+
+    /// Set a resolver for imports that are not defined explicitly.
+    ///
+    /// The resolver is asked for every import that is neither defined with [`Imports::define`]
+    /// nor exported by a module linked with [`Imports::link_module`]. Returning `None` leaves
+    /// the import unresolved, see [`Imports::on_unresolved`].
+    ///
+    /// ## Example
+    /// ```rust
+    /// # fn main() -> tinywasm::Result<()> {
+    /// use tinywasm::types::{ImportType, WasmValue};
+    /// use tinywasm::{HostFunction, Imports, ModuleInstance, Store};
+    /// # let wasm = wat::parse_str(r#"
+    /// #     (module
+    /// #       (import "env" "answer" (func $answer (result i32)))
+    /// #       (func (export "call") (result i32) (call $answer)))
+    /// # "#).expect("valid wat");
+    /// # let module = tinywasm::parse_bytes(&wasm)?;
+    /// let mut store = Store::default();
+    /// let mut imports = Imports::new();
+    /// imports.set_resolver(|store, name, ty| match (name.module(), ty) {
+    ///     ("env", ImportType::Func(ty)) => {
+    ///         let results = vec![WasmValue::I32(42); ty.results().len()];
+    ///         Some(HostFunction::from_untyped(store, ty, move |_, _| Ok(results.clone())).into())
+    ///     }
+    ///     _ => None,
+    /// });
+    ///
+    /// let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    /// assert_eq!(instance.func::<(), i32>(&store, "call")?.call(&mut store, ())?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_resolver(
+        &mut self,
+        resolver: impl Fn(&mut crate::Store, &ExternName, &ImportType<'_>) -> Option<Extern> + 'static,
+    ) -> &mut Self {
+        self.resolver = Some(Resolver(Rc::new(resolver)));
+        self
+    }
+
+    /// Set how imports that could not be resolved are handled.
+    ///
+    /// Defaults to [`UnresolvedImports::Fail`].
+    pub fn on_unresolved(&mut self, policy: UnresolvedImports) -> &mut Self {
+        self.unresolved = policy;
         self
     }
 
@@ -144,71 +383,192 @@ impl Imports {
         self
     }
 
-    pub(crate) fn take_defined(&self, import: &Import) -> Option<Extern> {
+    /// Define all imports of a [`HostModule`], creating them in `store`.
+    pub fn define_module<M: HostModule + ?Sized>(&mut self, store: &mut crate::Store, module: &M) -> Result<&mut Self> {
+        for (name, value) in module.externs(store)? {
+            self.define(module.name(), &name, value);
+        }
+        Ok(self)
+    }
+
+    /// Find the value of an import, falling back to linked modules and then the resolver.
+    fn resolve(
+        &self,
+        store: &mut crate::Store,
+        module: &Module,
+        import: &Import,
+    ) -> Result<Option<(ExternVal, Option<Function>)>> {
         let name = ExternName::from(import);
-        self.externs.get(&name).cloned()
+        let defined = match self.externs.get(&name) {
+            Some(defined) => defined.clone(),
+            None => {
+                if let Some(instance) = self.modules.get(&name.module) {
+                    instance.validate_store(store)?;
+                    if let Some(val) = instance.export_addr(&import.name) {
+                        return Ok(Some((val, None)));
+                    }
+                }
+
+                let Some(resolver) = &self.resolver else { return Ok(None) };
+                let Some(resolved) = (resolver.0)(store, &name, &Self::import_type(module, import)?) else {
+                    return Ok(None);
+                };
+                resolved
+            }
+        };
+
+        Ok(Some(match defined {
+            Extern::Global(global) => (ExternVal::Global(global.0.addr), None),
+            Extern::Table(table) => (ExternVal::Table(table.0.addr), None),
+            Extern::Memory(memory) => (ExternVal::Memory(memory.0.addr), None),
+            Extern::Function(func) => (ExternVal::Func(func.addr), Some(func)),
+            Extern::Tag(tag) => {
+                tag.0.validate_store(store)?;
+                (ExternVal::Tag(tag.0.addr), None)
+            }
+            #[cfg(feature = "std")]
+            Extern::SharedMemory(memory) => (ExternVal::Memory(store.shared_memory_addr(memory)), None),
+        }))
+    }
+
+    fn import_type<'a>(module: &'a Module, import: &'a Import) -> Result<ImportType<'a>> {
+        Ok(match &import.kind {
+            ImportKind::Function(ty) => ImportType::Func(Self::func_type(module, import, *ty)?),
+            ImportKind::Table(ty) => ImportType::Table(ty),
+            ImportKind::Memory(ty) => ImportType::Memory(ty),
+            ImportKind::Global(ty) => ImportType::Global(ty),
+            ImportKind::Tag(ty) => ImportType::Tag(Self::func_type(module, import, *ty)?),
+        })
     }
 
-    fn compare_types<T: PartialEq>(import: &Import, actual: &T, expected: &T) -> Result<()> {
-        if expected != actual {
+    /// Create a host function for an unresolved function import that fails when called.
+    fn stub_func(store: &mut crate::Store, module: &Module, import: &Import, ty: TypeAddr) -> Result<Function> {
+        let ty = Self::func_type(module, import, ty)?;
+        let (module, name) = (import.module.to_string(), import.name.to_string());
+        Ok(crate::HostFunction::from_untyped(store, ty, move |_, _| {
+            Err(LinkingError::UnknownImport { module: module.clone(), name: name.clone() }.into())
+        }))
+    }
+
+    /// The function type of a function or tag import.
+    fn func_type<'a>(module: &'a Module, import: &Import, ty: TypeAddr) -> Result<&'a FuncType> {
+        module.func_types.get(ty as usize).map(|ty| &**ty).ok_or_else(|| {
             cold_path();
-            return Err(LinkingError::incompatible_import_type(import).into());
+            Error::Other(format!("invalid type index {ty} for import {}.{}", import.module, import.name))
+        })
+    }
+
+    /// The type `module` declares for `import`.
+    fn declared_type(module: &Module, import: &Import) -> Result<ExternType> {
+        Ok(match &import.kind {
+            ImportKind::Function(ty) => ExternType::Func(Self::func_type(module, import, *ty)?.clone()),
+            ImportKind::Tag(ty) => ExternType::Tag(Self::func_type(module, import, *ty)?.clone()),
+            ImportKind::Memory(ty) => ExternType::Memory(*ty),
+            ImportKind::Global(ty) => ExternType::Global(*ty),
+            ImportKind::Table(ty) => ExternType::Table(*ty),
+        })
+    }
+
+    /// Apply `f` to the precise reference type of a table or global.
+    fn map_ref_types(ty: ExternType, f: impl Fn(RefType) -> RefType) -> ExternType {
+        match ty {
+            ExternType::Global(ty) => ExternType::Global(GlobalType { ref_type: ty.ref_type.map(f), ..ty }),
+            ExternType::Table(mut ty) => {
+                ty.element_ref_type = ty.element_ref_type.map(f);
+                ExternType::Table(ty)
+            }
+            ty => ty,
         }
-        Ok(())
     }
 
-    fn compare_table_types(import: &Import, expected: &TableType, actual: &TableType) -> Result<()> {
-        Self::compare_types(import, &actual.arch(), &expected.arch())?;
-        Self::compare_types(import, &actual.element_type, &expected.element_type)?;
-        if actual.size_initial > expected.size_initial {
-            cold_path();
-            return Err(LinkingError::incompatible_import_type(import).into());
+    /// The heap type of the canonical type `id` in `module`'s type section,
+    /// or its abstract heap type if the module defines no equivalent type.
+    fn module_heap_type(store: &crate::Store, type_ids: &[u32], id: u32) -> HeapType {
+        if let Some(idx) = type_ids.iter().position(|&type_id| type_id == id) {
+            return HeapType::Concrete(idx as u32);
         }
+        match store.types.get(id).sub.composite {
+            CompositeType::Func { .. } => HeapType::Func,
+            CompositeType::Struct(_) => HeapType::Struct,
+            CompositeType::Array(_) => HeapType::Array,
+            CompositeType::Cont(_) => HeapType::Cont,
+        }
+    }
 
-        match (expected.size_max, actual.size_max) {
-            (None, Some(_)) => {
-                cold_path();
-                Err(LinkingError::incompatible_import_type(import).into())
-            }
-            (Some(expected_max), Some(actual_max)) if actual_max < expected_max => {
-                cold_path();
-                Err(LinkingError::incompatible_import_type(import).into())
-            }
-            _ => Ok(()),
+    /// The type of a value in the store, with the current size of tables and memories as their initial size.
+    fn extern_type(store: &crate::Store, val: ExternVal) -> ExternType {
+        match val {
+            ExternVal::Func(addr) => ExternType::Func(store.state.get_func(addr).ty().as_ref().clone()),
+            ExternVal::Table(addr) => ExternType::Table(Self::table_type(store, addr)),
+            ExternVal::Memory(addr) => ExternType::Memory(Self::memory_type(store, addr)),
+            ExternVal::Global(addr) => ExternType::Global(store.state.get_global(addr).ty),
+            ExternVal::Tag(addr) => ExternType::Tag(store.state.get_tag(addr).ty.as_ref().clone()),
         }
     }
 
-    fn compare_memory_types(
-        import: &Import,
-        expected: &MemoryType,
-        actual: &MemoryType,
-        real_size: usize,
-    ) -> Result<()> {
-        Self::compare_types(import, &expected.arch(), &actual.arch())?;
+    fn table_type(store: &crate::Store, addr: TableAddr) -> TableType {
+        let table = store.state.get_table(addr);
+        let mut ty = table.kind;
+        ty.size_initial = table.size() as u64;
+        ty
+    }
 
-        if actual.page_count_initial() > expected.page_count_initial() && actual.page_count_initial() > real_size as u64
+    fn memory_type(store: &crate::Store, addr: MemAddr) -> MemoryType {
+        let mem = store.state.get_mem(addr);
+        mem.kind.with_page_count_initial(mem.page_count() as u64)
+    }
+
+    fn table_matches(store: &crate::Store, expected: &TableType, actual: &TableType) -> bool {
+        if actual.arch() != expected.arch() || actual.element_type != expected.element_type {
+            return false;
+        }
+
+        // table elements are mutable, so their types have to match exactly
+        if let (Some(expected), Some(actual)) = (expected.precise_element_type(), actual.precise_element_type())
+            && !(store.types.is_ref_subtype(expected, actual) && store.types.is_ref_subtype(actual, expected))
         {
-            return Err(LinkingError::incompatible_import_type(import).into());
+            return false;
         }
 
-        if expected.page_size() != actual.page_size() {
-            return Err(LinkingError::incompatible_import_type(import).into());
+        actual.size_initial >= expected.size_initial
+            && match (expected.size_max, actual.size_max) {
+                (Some(_), None) => false,
+                (Some(expected_max), Some(actual_max)) => actual_max <= expected_max,
+                (None, _) => true,
+            }
+    }
+
+    fn global_matches(store: &crate::Store, expected: &GlobalType, actual: &GlobalType) -> bool {
+        if actual.mutable != expected.mutable || actual.ty != expected.ty {
+            return false;
         }
 
-        if expected.page_count_max() > actual.page_count_max() {
-            return Err(LinkingError::incompatible_import_type(import).into());
+        // immutable globals may hold a subtype of the import's type, mutable ones have to match exactly
+        match (expected.precise_ref_type(), actual.precise_ref_type()) {
+            (Some(expected_ref), Some(actual_ref)) => {
+                store.types.is_ref_subtype(actual_ref, expected_ref)
+                    && (!expected.mutable || store.types.is_ref_subtype(expected_ref, actual_ref))
+            }
+            _ => true,
         }
+    }
 
-        Ok(())
+    fn memory_matches(expected: &MemoryType, actual: &MemoryType) -> bool {
+        actual.arch() == expected.arch()
+            && actual.page_count_initial() >= expected.page_count_initial()
+            && actual.page_size() == expected.page_size()
+            && actual.is_shared() == expected.is_shared()
+            && actual.page_count_max() <= expected.page_count_max()
     }
 
-    pub(crate) fn link(&self, store: &mut crate::Store, module: &Module) -> Result<ResolvedImports> {
-        let (global_count, table_count, mem_count, func_count) =
-            module.imports.iter().fold((0, 0, 0, 0), |(g, t, m, f), import| match import.kind {
-                ImportKind::Global(_) => (g + 1, t, m, f),
-                ImportKind::Table(_) => (g, t + 1, m, f),
-                ImportKind::Memory(_) => (g, t, m + 1, f),
-                ImportKind::Function(_) => (g, t, m, f + 1),
+    pub(crate) fn link(&self, store: &mut crate::Store, module: &Module, type_ids: &[u32]) -> Result<ResolvedImports> {
+        let (global_count, table_count, mem_count, func_count, tag_count) =
+            module.imports.iter().fold((0, 0, 0, 0, 0), |(g, t, m, f, e), import| match import.kind {
+                ImportKind::Global(_) => (g + 1, t, m, f, e),
+                ImportKind::Table(_) => (g, t + 1, m, f, e),
+                ImportKind::Memory(_) => (g, t, m + 1, f, e),
+                ImportKind::Function(_) => (g, t, m, f + 1, e),
+                ImportKind::Tag(_) => (g, t, m, f, e + 1),
             });
 
         let mut imports = ResolvedImports {
@@ -216,67 +576,95 @@ impl Imports {
             tables: Vec::with_capacity(table_count + module.table_types.len()),
             memories: Vec::with_capacity(mem_count + module.memory_types.len()),
             funcs: Vec::with_capacity(func_count + module.funcs.len()),
+            tags: Vec::with_capacity(tag_count + module.tags.len()),
         };
 
+        // collect all linking errors, so they can be reported at once
+        let mut errors = Vec::new();
         for import in &*module.imports {
-            let (val, func_handle) = if let Some(defined) = self.take_defined(import) {
-                match defined {
-                    Extern::Global(global) => (ExternVal::Global(global.0.addr), None),
-                    Extern::Table(table) => (ExternVal::Table(table.0.addr), None),
-                    Extern::Memory(memory) => (ExternVal::Memory(memory.0.addr), None),
-                    Extern::Function(func) => (ExternVal::Func(func.addr), Some(func)),
-                }
-            } else {
-                let name = ExternName::from(import);
-                let Some(instance) = self.modules.get(&name.module) else {
+            let (val, func_handle) = match self.resolve(store, module, import)? {
+                Some(resolved) => resolved,
+                None => {
                     cold_path();
-                    return Err(LinkingError::unknown_import(import).into());
-                };
-                instance.validate_store(store)?;
-                (instance.export_addr(&import.name).ok_or_else(|| LinkingError::unknown_import(import))?, None)
+                    match (self.unresolved, &import.kind) {
+                        (UnresolvedImports::Fail, _) => {
+                            errors.push(LinkingError::unknown_import(import));
+                            return Err(LinkingError::from_errors(errors).into());
+                        }
+                        (UnresolvedImports::TrapFunctions, ImportKind::Function(ty)) => {
+                            let func = Self::stub_func(store, module, import, *ty)?;
+                            (ExternVal::Func(func.addr), Some(func))
+                        }
+                        _ => {
+                            errors.push(LinkingError::unknown_import(import));
+                            continue;
+                        }
+                    }
+                }
             };
 
-            if val.kind() != (&import.kind).into() {
-                cold_path();
-                return Err(LinkingError::incompatible_import_type(import).into());
+            if let Some(func) = &func_handle {
+                func.item.validate_store(store)?;
             }
 
-            match (val, &import.kind) {
-                (ExternVal::Global(global_addr), ImportKind::Global(ty)) => {
-                    let global = store.state.get_global(global_addr);
-                    Self::compare_types(import, &global.ty, ty)?;
-                    imports.globals.push(global_addr);
-                }
-                (ExternVal::Table(table_addr), ImportKind::Table(ty)) => {
-                    let table = store.state.get_table(table_addr);
-                    let mut kind = table.kind;
-                    kind.size_initial = table.size() as u64;
-                    Self::compare_table_types(import, &kind, ty)?;
-                    imports.tables.push(table_addr);
+            // import types are compared with canonical type ids
+            let expected = Self::map_ref_types(Self::declared_type(module, import)?, |ty| {
+                map_ref_type(ty, |idx| type_ids[idx as usize])
+            });
+
+            let matches = match (&val, &expected) {
+                (ExternVal::Global(addr), ExternType::Global(ty)) => {
+                    Self::global_matches(store, ty, &store.state.get_global(*addr).ty)
                 }
-                (ExternVal::Memory(memory_addr), ImportKind::Memory(ty)) => {
-                    let mem = store.state.get_mem(memory_addr);
-                    Self::compare_memory_types(import, &mem.kind, ty, mem.page_count)?;
-                    imports.memories.push(memory_addr);
+                (ExternVal::Table(addr), ExternType::Table(ty)) => {
+                    Self::table_matches(store, ty, &Self::table_type(store, *addr))
                 }
-                (ExternVal::Func(func_addr), ImportKind::Function(ty)) => {
-                    let import_func_type = module
-                        .func_types
-                        .get(*ty as usize)
-                        .ok_or_else(|| LinkingError::incompatible_import_type(import))?;
-                    let actual_ty = if let Some(func) = &func_handle {
-                        func.item.validate_store(store)?;
-                        &func.ty
-                    } else {
-                        store.state.get_func(func_addr).ty()
-                    };
-                    Self::compare_types(import, actual_ty, import_func_type)?;
-                    imports.funcs.push(func_addr);
+                (ExternVal::Memory(addr), ExternType::Memory(ty)) => {
+                    Self::memory_matches(ty, &Self::memory_type(store, *addr))
                 }
-                _ => unreachable!("import kind checked above"),
+                (ExternVal::Func(addr), ExternType::Func(ty)) => match store.state.get_func(*addr) {
+                    // WebAssembly functions also have to match the import's precise type
+                    crate::FunctionInstance::Wasm(func) => {
+                        let ImportKind::Function(idx) = import.kind else { unreachable!() };
+                        func.ty().as_ref() == ty && store.types.is_subtype(func.type_id, type_ids[idx as usize])
+                    }
+                    crate::FunctionInstance::Host(func) => func.ty.as_ref() == ty,
+                },
+                // tags defined by WebAssembly modules have to be of the same canonical type
+                (ExternVal::Tag(addr), ExternType::Tag(ty)) => match store.state.get_tag(*addr).type_id {
+                    Some(type_id) => {
+                        let ImportKind::Tag(idx) = import.kind else { unreachable!() };
+                        type_id == type_ids[idx as usize]
+                    }
+                    None => store.state.get_tag(*addr).ty.as_ref() == ty,
+                },
+                _ => false,
+            };
+
+            if !matches {
+                cold_path();
+                // both types are reported relative to the importing module's type section
+                let expected = Self::declared_type(module, import)?;
+                let actual = Self::map_ref_types(Self::extern_type(store, val), |ty| match ty.heap_type {
+                    HeapType::Concrete(id) => RefType::new(ty.nullable, Self::module_heap_type(store, type_ids, id)),
+                    _ => ty,
+                });
+                errors.push(LinkingError::incompatible_import_type(import, expected, actual));
+                continue;
+            }
+
+            match val {
+                ExternVal::Global(addr) => imports.globals.push(addr),
+                ExternVal::Table(addr) => imports.tables.push(addr),
+                ExternVal::Memory(addr) => imports.memories.push(addr),
+                ExternVal::Func(addr) => imports.funcs.push(addr),
+                ExternVal::Tag(addr) => imports.tags.push(addr),
             }
         }
 
+        if !errors.is_empty() {
+            return Err(LinkingError::from_errors(errors).into());
+        }
         Ok(imports)
     }
 }
@@ -1,10 +1,14 @@
-use alloc::{boxed::Box, format, rc::Rc, sync::Arc};
+use alloc::{boxed::Box, format, rc::Rc, sync::Arc, vec, vec::Vec};
 use core::hint::cold_path;
 use tinywasm_types::*;
 
 use crate::func::{FromWasmValues, IntoWasmValues, ToWasmTypes};
-use crate::store::MemoryInstance;
-use crate::{Error, Function, FunctionTyped, Global, Imports, Memory, Result, Store, StoreItem, Table, Trap};
+use crate::imports::ResolvedImports;
+use crate::store::{InstantiationLog, MemoryInstance};
+use crate::{
+    Error, ExecProgress, FuncExecution, Function, FunctionTyped, Global, GuestAllocator, Imports, Memory,
+    MemoryBackend, Result, Store, StoreItem, Table, Tag, Trap,
+};
 
 /// A typed view over an exported extern value.
 pub enum ExternItem {
@@ -16,6 +20,8 @@ pub enum ExternItem {
     Table(Table),
     /// Exported global reference.
     Global(Global),
+    /// Exported tag reference.
+    Tag(Tag),
 }
 
 /// An instantiated WebAssembly module
@@ -48,11 +54,14 @@ struct ModuleInstanceInner {
     store_id: usize,
     idx: ModuleInstanceAddr,
     types: Arc<[Arc<FuncType>]>,
+    /// The canonical id of every type index, see [`crate::store::TypeRegistry`].
+    type_ids: Box<[u32]>,
     func_type_idxs: Arc<[u32]>,
     func_addrs: Box<[FuncAddr]>,
     table_addrs: Box<[TableAddr]>,
     mem_addrs: Box<[MemAddr]>,
     global_addrs: Box<[GlobalAddr]>,
+    tag_addrs: Box<[TagAddr]>,
     elem_addrs: Box<[ElemAddr]>,
     data_addrs: Box<[DataAddr]>,
     func_start: Option<FuncAddr>,
@@ -71,6 +80,18 @@ impl ModuleInstance {
         self.0.types.get(type_idx as usize).unwrap_or_else(|| unreachable!("invalid type index: {type_idx}"))
     }
 
+    /// The canonical id of every type in the module's type section.
+    #[inline]
+    pub(crate) fn type_ids(&self) -> &[u32] {
+        &self.0.type_ids
+    }
+
+    /// Resolve a type index to its canonical id
+    #[inline]
+    pub(crate) fn resolve_type_id(&self, type_idx: u32) -> u32 {
+        *self.0.type_ids.get(type_idx as usize).unwrap_or_else(|| unreachable!("invalid type index: {type_idx}"))
+    }
+
     /// Function indices need their own lookup because they are not type-section indices.
     #[inline]
     pub(crate) fn func_type_idx(&self, addr: FuncAddr) -> u32 {
@@ -118,6 +139,12 @@ impl ModuleInstance {
         *self.0.global_addrs.get(addr as usize).unwrap_or_else(|| unreachable!("invalid global address: {addr}"))
     }
 
+    /// resolve a tag address to the global store address
+    #[inline]
+    pub(crate) fn resolve_tag_addr(&self, addr: TagAddr) -> TagAddr {
+        *self.0.tag_addrs.get(addr as usize).unwrap_or_else(|| unreachable!("invalid tag address: {addr}"))
+    }
+
     #[inline]
     pub(crate) fn validate_store(&self, store: &Store) -> Result<()> {
         if self.0.store_id != store.id() {
@@ -134,6 +161,11 @@ impl ModuleInstance {
 
     /// Instantiate the module in the given store
     ///
+    /// If an active segment traps, everything allocated for the instance is removed from the store.
+    /// Segment writes into imported tables and memories are only undone with
+    /// [`Config::with_atomic_instantiation`](crate::engine::Config::with_atomic_instantiation).
+    /// Traps in the start function are not rolled back.
+    ///
     /// See <https://webassembly.github.io/spec/core/exec/modules.html#exec-instantiation>
     pub fn instantiate(store: &mut Store, module: &Module, imports: Option<Imports>) -> Result<Self> {
         let instance = ModuleInstance::instantiate_no_start(store, module, imports)?;
@@ -167,54 +199,54 @@ impl ModuleInstance {
     /// # }
     /// ```
     ///
+    /// If instantiation fails, everything allocated for the instance is removed from the store again,
+    /// see [`Config::with_atomic_instantiation`](crate::engine::Config::with_atomic_instantiation).
+    ///
     /// See <https://webassembly.github.io/spec/core/exec/modules.html#exec-instantiation>
     pub fn instantiate_no_start(store: &mut Store, module: &Module, imports: Option<Imports>) -> Result<Self> {
-        let idx = store.next_module_instance_idx();
-        let mut addrs = imports.unwrap_or_default().link(store, module)?;
-        addrs.funcs.extend(store.init_funcs(&module.funcs, idx));
-        addrs.tables.extend(store.init_tables(&module.table_types)?);
-        match module.local_memory_allocation {
-            LocalMemoryAllocation::Skip => {
-                #[cfg(feature = "guest-debug")]
-                addrs.memories.extend(store.init_memories(&module.memory_types, MemoryInstance::new_lazy)?);
-            }
-            LocalMemoryAllocation::Lazy => {
-                addrs.memories.extend(store.init_memories(&module.memory_types, MemoryInstance::new_lazy)?)
-            }
-            LocalMemoryAllocation::Eager => {
-                addrs.memories.extend(store.init_memories(&module.memory_types, MemoryInstance::new)?)
-            }
+        let mut pending = PendingInstance::new(store, module, imports)?;
+        match pending.resume(store, &mut InitBudget::Unlimited)? {
+            Some(instance) => Ok(instance),
+            None => unreachable!("initialization without a budget can't suspend"),
         }
+    }
 
-        store.init_globals(&mut addrs.globals, &module.globals, &addrs.funcs)?;
-        let (elem_addrs, elem_trapped) =
-            store.init_elements(&addrs.tables, &addrs.funcs, &addrs.globals, &module.elements)?;
-        let (data_addrs, data_trapped) =
-            store.init_data(&addrs.memories, &addrs.globals, &addrs.funcs, &module.data)?;
-
-        let instance = ModuleInstanceInner {
-            store_id: store.id(),
-            idx,
-            types: module.func_types.clone(),
-            func_type_idxs: module.func_type_idxs.clone(),
-            func_addrs: addrs.funcs.into_boxed_slice(),
-            table_addrs: addrs.tables.into_boxed_slice(),
-            mem_addrs: addrs.memories.into_boxed_slice(),
-            global_addrs: addrs.globals.into_boxed_slice(),
-            elem_addrs,
-            data_addrs,
-            func_start: module.start_func,
-            exports: module.exports.clone(),
-        };
-
-        let instance = ModuleInstance(Rc::new(instance));
-        store.add_instance(instance.clone());
-
-        if let Some(trap) = elem_trapped.or(data_trapped) {
-            cold_path();
-            return Err(trap.into());
-        }
-        Ok(instance)
+    /// Instantiate the module in the given store and return a resumable handle
+    ///
+    /// Imports are linked and functions and memories are allocated right away. Evaluating the constant expressions
+    /// of tables and globals, initializing element and data segments and running the start function
+    /// happen when the returned [`Instantiation`] is resumed, metered with fuel or a time budget like
+    /// [`Function::call_resumable`].
+    ///
+    /// ## Example
+    /// ```rust
+    /// # fn main() -> tinywasm::Result<()> {
+    /// # use tinywasm::{ExecProgress, ModuleInstance, Store};
+    /// # let wasm = wat::parse_str(r#"
+    /// #     (module
+    /// #       (func $start (loop br 0))
+    /// #       (start $start))
+    /// # "#).expect("valid wat");
+    /// # let module = tinywasm::parse_bytes(&wasm)?;
+    /// let mut store = Store::default();
+    /// let mut instantiation = ModuleInstance::instantiate_resumable(&mut store, &module, None)?;
+    ///
+    /// // the start function never returns, so the instantiation never completes
+    /// for _ in 0..10 {
+    ///     assert!(matches!(instantiation.resume_with_fuel(1_000)?, ExecProgress::Suspended));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// See <https://webassembly.github.io/spec/core/exec/modules.html#exec-instantiation>
+    pub fn instantiate_resumable<'store>(
+        store: &'store mut Store,
+        module: &Module,
+        imports: Option<Imports>,
+    ) -> Result<Instantiation<'store>> {
+        let pending = Box::new(PendingInstance::new(store, module, imports)?);
+        Ok(Instantiation { state: InstantiationState::Initializing { store, pending } })
     }
 
     /// Get a export by name
@@ -225,6 +257,7 @@ impl ModuleInstance {
             ExternalKind::Table => self.0.table_addrs.get(export.index as usize)?,
             ExternalKind::Memory => self.0.mem_addrs.get(export.index as usize)?,
             ExternalKind::Global => self.0.global_addrs.get(export.index as usize)?,
+            ExternalKind::Tag => self.0.tag_addrs.get(export.index as usize)?,
         };
         Some(ExternVal::new(export.kind, *addr))
     }
@@ -278,6 +311,9 @@ impl ModuleInstance {
                 ExternalKind::Global => {
                     ExternItem::Global(Global(StoreItem::new(self.0.store_id, self.resolve_global_addr(export.index))))
                 }
+                ExternalKind::Tag => {
+                    ExternItem::Tag(Tag(StoreItem::new(self.0.store_id, self.resolve_tag_addr(export.index))))
+                }
             };
 
             (export.name.as_ref(), item)
@@ -337,6 +373,7 @@ impl ModuleInstance {
             ExternVal::Memory(addr) => Ok(ExternItem::Memory(Memory(StoreItem::new(self.0.store_id, addr)))),
             ExternVal::Table(addr) => Ok(ExternItem::Table(Table(StoreItem::new(self.0.store_id, addr)))),
             ExternVal::Global(addr) => Ok(ExternItem::Global(Global(StoreItem::new(self.0.store_id, addr)))),
+            ExternVal::Tag(addr) => Ok(ExternItem::Tag(Tag(StoreItem::new(self.0.store_id, addr)))),
         }
     }
 
@@ -465,7 +502,7 @@ impl ModuleInstance {
     }
 
     #[inline]
-    fn validate_typed_func<P: ToWasmTypes, R: ToWasmTypes>(func: &Function, func_name: &str) -> Result<()> {
+    pub(crate) fn validate_typed_func<P: ToWasmTypes, R: ToWasmTypes>(func: &Function, func_name: &str) -> Result<()> {
         let params = P::wasm_types();
         let results = R::wasm_types();
         if func.ty.params() != params.as_ref() || func.ty.results() != results.as_ref() {
@@ -508,6 +545,65 @@ impl ModuleInstance {
         Ok(Memory(StoreItem::new(self.0.store_id, Self::index_addr(&self.0.mem_addrs, memory_index, "memory")?)))
     }
 
+    /// Get a [`GuestAllocator`] for copying data into the guest's `memory` export.
+    ///
+    /// The guest's allocator is found by common conventions: `malloc` and `free` exports, or a
+    /// `cabi_realloc` export without a way to free memory, see [`GuestAllocator::can_free`].
+    /// Use [`Self::guest_allocator_with`] for other exports.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # fn main() -> tinywasm::Result<()> {
+    /// # use tinywasm::{ModuleInstance, Store};
+    /// # let wasm = wat::parse_str(r#"
+    /// #     (module
+    /// #       (memory (export "memory") 1)
+    /// #       (global $next (mut i32) (i32.const 16))
+    /// #       (func (export "malloc") (param i32) (result i32)
+    /// #         (global.get $next)
+    /// #         (global.set $next (i32.add (global.get $next) (local.get 0))))
+    /// #       (func (export "free") (param i32))
+    /// #       (func (export "first_byte") (param i32 i32) (result i32) (i32.load8_u (local.get 0))))
+    /// # "#).expect("valid wat");
+    /// # let module = tinywasm::parse_bytes(&wasm)?;
+    /// # let mut store = Store::default();
+    /// let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    /// let first_byte = instance.func::<(i32, i32), i32>(&store, "first_byte")?;
+    /// let allocator = instance.guest_allocator(&store)?;
+    ///
+    /// let first = allocator.with_str(&mut store, "tinywasm", |store, name| first_byte.call(store, name.args()?))?;
+    /// assert_eq!(first, i32::from(b't'));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn guest_allocator(&self, store: &Store) -> Result<GuestAllocator> {
+        if self.export_addr("malloc").is_some() {
+            let free = self.export_addr("free").map(|_| "free");
+            return GuestAllocator::new(self, store, "memory", "malloc", free);
+        }
+        if self.export_addr("cabi_realloc").is_some() {
+            return GuestAllocator::new(self, store, "memory", "cabi_realloc", None);
+        }
+
+        cold_path();
+        Err(Error::Other("no guest allocator found: expected a `malloc` or `cabi_realloc` export".into()))
+    }
+
+    /// Get a [`GuestAllocator`] using the given exports.
+    ///
+    /// `alloc` is either a `malloc`-like `(size: i32) -> i32` function or a `cabi_realloc`-like
+    /// `(old_ptr: i32, old_size: i32, align: i32, new_size: i32) -> i32` function. `free` takes a
+    /// pointer returned by `alloc`; without it, allocations can't be freed.
+    pub fn guest_allocator_with(
+        &self,
+        store: &Store,
+        memory: &str,
+        alloc: &str,
+        free: Option<&str>,
+    ) -> Result<GuestAllocator> {
+        GuestAllocator::new(self, store, memory, alloc, free)
+    }
+
     /// Get a table export by name.
     pub fn table(&self, name: &str) -> Result<Table> {
         match self.require_export(name)? {
@@ -528,6 +624,14 @@ impl ModuleInstance {
         Ok(Table(StoreItem::new(self.0.store_id, Self::index_addr(&self.0.table_addrs, table_index, "table")?)))
     }
 
+    /// Get a tag export by name.
+    pub fn tag(&self, name: &str) -> Result<Tag> {
+        match self.require_export(name)? {
+            ExternVal::Tag(tag_addr) => Ok(Tag(StoreItem::new(self.0.store_id, tag_addr))),
+            _ => Err(Error::Other(format!("Export is not a tag: {name}"))),
+        }
+    }
+
     /// Get the value of a global export by name.
     pub fn global_get(&self, store: &Store, name: &str) -> Result<WasmValue> {
         self.global(name)?.get(store)
@@ -634,3 +738,399 @@ impl ModuleInstance {
         }
     }
 }
+
+/// Resumable instantiation of a module, created by [`ModuleInstance::instantiate_resumable`].
+///
+/// The handle keeps a mutable borrow of the [`Store`] until it completes. If instantiation fails,
+/// or the handle is dropped before all segments are initialized, everything allocated for the
+/// instance is removed from the store again. Traps in the start function are not rolled back.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct Instantiation<'store> {
+    state: InstantiationState<'store>,
+}
+
+#[cfg_attr(feature = "debug", derive(Debug))]
+enum InstantiationState<'store> {
+    Initializing { store: &'store mut Store, pending: Box<PendingInstance> },
+    Starting { instance: ModuleInstance, execution: FuncExecution<'store> },
+    Completed,
+}
+
+impl Instantiation<'_> {
+    fn resume(&mut self, mut budget: InitBudget) -> Result<ExecProgress<ModuleInstance>> {
+        if let InstantiationState::Initializing { store, pending } = &mut self.state {
+            let instance = match pending.resume(store, &mut budget) {
+                Ok(Some(instance)) => instance,
+                Ok(None) => return Ok(ExecProgress::Suspended),
+                Err(err) => {
+                    self.state = InstantiationState::Completed;
+                    return Err(err);
+                }
+            };
+
+            let InstantiationState::Initializing { store, .. } =
+                core::mem::replace(&mut self.state, InstantiationState::Completed)
+            else {
+                unreachable!("invalid instantiation state")
+            };
+            let Some(func) = instance.start_func(store)? else {
+                return Ok(ExecProgress::Completed(instance));
+            };
+            let execution = func.call_resumable(store, &[])?;
+            self.state = InstantiationState::Starting { instance, execution };
+        }
+
+        let InstantiationState::Starting { execution, .. } = &mut self.state else {
+            return Err(Error::other("instantiation already completed"));
+        };
+        let progress = match budget {
+            InitBudget::Unlimited => unreachable!("instantiations are always resumed with a budget"),
+            InitBudget::Fuel(fuel) => execution.resume_with_fuel(fuel),
+            #[cfg(feature = "std")]
+            InitBudget::Time { start, budget } => {
+                execution.resume_with_time_budget(budget.saturating_sub(start.elapsed()))
+            }
+        };
+
+        match progress {
+            Ok(ExecProgress::Suspended) => Ok(ExecProgress::Suspended),
+            Ok(ExecProgress::Completed(_)) => {
+                match core::mem::replace(&mut self.state, InstantiationState::Completed) {
+                    InstantiationState::Starting { instance, .. } => Ok(ExecProgress::Completed(instance)),
+                    _ => unreachable!("invalid instantiation state"),
+                }
+            }
+            Err(err) => {
+                self.state = InstantiationState::Completed;
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns `true` once the instantiation has returned its instance or failed.
+    pub fn is_completed(&self) -> bool {
+        matches!(self.state, InstantiationState::Completed)
+    }
+
+    /// Resume instantiation with up to `fuel` units of fuel.
+    ///
+    /// Initializing a table or global costs one unit per instruction of its constant expression,
+    /// element segments one unit per element and data segments one unit per started 64 bytes.
+    /// Fuel is only checked between them, so initialization may overshoot the requested fuel.
+    /// Any fuel left afterwards is used to run the start function, see [`FuncExecution::resume_with_fuel`].
+    ///
+    /// Returns [`ExecProgress::Suspended`] when fuel is exhausted, or [`ExecProgress::Completed`]
+    /// with the new instance once the start function returns.
+    pub fn resume_with_fuel(&mut self, fuel: u32) -> Result<ExecProgress<ModuleInstance>> {
+        self.resume(InitBudget::Fuel(fuel))
+    }
+
+    #[cfg(feature = "std")]
+    /// Resume instantiation for at most `time_budget` wall-clock time.
+    ///
+    /// Time is checked between initializing tables, globals and segments, and periodically while running
+    /// the start function, see [`FuncExecution::resume_with_time_budget`].
+    ///
+    /// Returns [`ExecProgress::Suspended`] when the budget is exhausted, or [`ExecProgress::Completed`]
+    /// with the new instance once the start function returns.
+    pub fn resume_with_time_budget(
+        &mut self,
+        time_budget: crate::std::time::Duration,
+    ) -> Result<ExecProgress<ModuleInstance>> {
+        self.resume(InitBudget::Time { start: crate::std::time::Instant::now(), budget: time_budget })
+    }
+}
+
+impl Drop for Instantiation<'_> {
+    fn drop(&mut self) {
+        if let InstantiationState::Initializing { store, pending } = &mut self.state {
+            store.rollback_instantiation(&mut pending.log);
+        }
+    }
+}
+
+/// The budget for initializing an instance.
+#[derive(Clone, Copy)]
+enum InitBudget {
+    Unlimited,
+    Fuel(u32),
+    #[cfg(feature = "std")]
+    Time {
+        start: crate::std::time::Instant,
+        budget: crate::std::time::Duration,
+    },
+}
+
+impl InitBudget {
+    fn is_exhausted(&self) -> bool {
+        match self {
+            Self::Unlimited => false,
+            Self::Fuel(fuel) => *fuel == 0,
+            #[cfg(feature = "std")]
+            Self::Time { start, budget } => start.elapsed() >= *budget,
+        }
+    }
+
+    fn charge(&mut self, cost: usize) {
+        if let Self::Fuel(fuel) = self {
+            *fuel = fuel.saturating_sub(u32::try_from(cost).unwrap_or(u32::MAX));
+        }
+    }
+}
+
+/// The next table, global or segment to initialize.
+enum InitItem<'a> {
+    Table(TableType, Option<&'a [ConstInstruction]>),
+    Global(&'a tinywasm_types::Global),
+    Element(&'a Element),
+    Data(&'a Data),
+}
+
+impl<'a> InitItem<'a> {
+    /// Get the item at `index` in the order tables, globals, element segments and data segments.
+    fn get(module: &'a Module, index: usize) -> Option<Self> {
+        let mut index = index;
+        if let Some(table) = module.table_types.get(index) {
+            return Some(Self::Table(*table, module.table_inits.get(index).and_then(Option::as_deref)));
+        }
+        index -= module.table_types.len();
+        if let Some(global) = module.globals.get(index) {
+            return Some(Self::Global(global));
+        }
+        index -= module.globals.len();
+        if let Some(element) = module.elements.get(index) {
+            return Some(Self::Element(element));
+        }
+        index -= module.elements.len();
+        module.data.get(index).map(Self::Data)
+    }
+
+    /// The fuel charged for initializing the item.
+    fn cost(&self) -> usize {
+        match self {
+            Self::Table(_, init) => init.map_or(1, <[_]>::len),
+            Self::Global(global) => global.init.len(),
+            Self::Element(element) => {
+                let offset = match &element.kind {
+                    ElementKind::Active { offset, .. } => offset.len(),
+                    _ => 0,
+                };
+                offset + element.items.len()
+            }
+            Self::Data(data) => {
+                let offset = match &data.kind {
+                    DataKind::Active { offset, .. } => offset.len(),
+                    DataKind::Passive => 0,
+                };
+                offset + data.data.len().div_ceil(64)
+            }
+        }
+    }
+}
+
+/// An instance whose tables, globals and segments are still being initialized.
+#[cfg_attr(feature = "debug", derive(Debug))]
+struct PendingInstance {
+    module: Module,
+    idx: ModuleInstanceAddr,
+    type_ids: Box<[u32]>,
+    addrs: ResolvedImports,
+    elem_addrs: Vec<ElemAddr>,
+    data_addrs: Vec<DataAddr>,
+    /// The number of items initialized so far, see [`InitItem::get`].
+    initialized: usize,
+    /// Whether the active data segments of each memory were already applied when it was created.
+    applied_memories: Box<[bool]>,
+    log: InstantiationLog,
+}
+
+impl PendingInstance {
+    /// Link the imports and allocate the module's functions, memories and tags.
+    fn new(store: &mut Store, module: &Module, imports: Option<Imports>) -> Result<Self> {
+        let type_ids = store.register_types(module);
+        let addrs = imports.unwrap_or_default().link(store, module, &type_ids)?;
+
+        // the import resolver may have added items to the store that the host still refers to,
+        // so only what is allocated after linking is rolled back
+        let mut pending = Self {
+            module: module.clone(),
+            idx: store.next_module_instance_idx(),
+            type_ids,
+            addrs,
+            elem_addrs: Vec::with_capacity(module.elements.len()),
+            data_addrs: Vec::with_capacity(module.data.len()),
+            initialized: 0,
+            applied_memories: Box::default(),
+            log: store.begin_instantiation(),
+        };
+
+        if let Err(err) = pending.allocate(store) {
+            cold_path();
+            store.rollback_instantiation(&mut pending.log);
+            return Err(err);
+        }
+        Ok(pending)
+    }
+
+    fn allocate(&mut self, store: &mut Store) -> Result<()> {
+        // applying data segments ahead of time can only be observed if a segment traps and the
+        // segments written before it are kept, which atomic instantiation never does
+        let ahead_of_time = store.engine.config().atomic_instantiation || !self.segments_may_trap(store);
+        let module = &self.module;
+        let addrs = &mut self.addrs;
+        let local_func_types = &module.func_type_idxs[module.func_type_idxs.len() - module.funcs.len()..];
+        addrs.funcs.extend(store.init_funcs(
+            &module.funcs,
+            local_func_types.iter().map(|ty| self.type_ids[*ty as usize]),
+            self.idx,
+        ));
+
+        let new_memory: Option<fn(MemoryType, &MemoryBackend) -> Result<MemoryInstance>> =
+            match module.local_memory_allocation {
+                LocalMemoryAllocation::Skip if cfg!(feature = "guest-debug") => Some(MemoryInstance::new_lazy),
+                LocalMemoryAllocation::Skip => None,
+                LocalMemoryAllocation::Lazy => Some(MemoryInstance::new_lazy),
+                LocalMemoryAllocation::Eager => Some(MemoryInstance::new),
+            };
+
+        // restored file memories are used regardless of `ahead_of_time`, since their segments are never applied again
+        let imported_memories = addrs.memories.len();
+        let mut applied = vec![false; imported_memories + module.memory_types.len()].into_boxed_slice();
+        if let Some(new_memory) = new_memory {
+            let memories = store.init_memories(&module.memory_types, |idx, ty, config| {
+                let backend = config.memory_backend_for(&ty, module, (imported_memories + idx) as u32);
+                if let Some(memory) = MemoryInstance::new_initialized(module, idx, &backend, ahead_of_time) {
+                    applied[imported_memories + idx] = true;
+                    return Ok(memory);
+                }
+                new_memory(ty, &backend)
+            })?;
+            addrs.memories.extend(memories);
+        }
+        self.applied_memories = applied;
+        addrs.tags.extend(store.init_tags(&module.tags, &module.func_types, &self.type_ids)?);
+        Ok(())
+    }
+
+    /// Whether an active element or data segment of the module could be out of bounds.
+    ///
+    /// Must be called before local tables are allocated. Constant offsets are checked against the
+    /// initial size of local tables and memories and the current size of imported ones.
+    fn segments_may_trap(&self, store: &Store) -> bool {
+        fn fits(offset: &[ConstInstruction], len: usize, size: u64) -> bool {
+            let offset = match offset {
+                [ConstInstruction::I32Const(offset)] => u64::from(*offset as u32),
+                [ConstInstruction::I64Const(offset)] => *offset as u64,
+                _ => return false,
+            };
+            offset.checked_add(len as u64).is_some_and(|end| end <= size)
+        }
+
+        let module = &self.module;
+        let (imported_tables, imported_memories) = (self.addrs.tables.len(), self.addrs.memories.len());
+        let elements = module.elements.iter().any(|element| {
+            let ElementKind::Active { table, offset } = &element.kind else { return false };
+            let size = match (*table as usize).checked_sub(imported_tables) {
+                Some(idx) => module.table_types[idx].size_initial,
+                None => store.state.get_table(self.addrs.tables[*table as usize]).size() as u64,
+            };
+            !fits(offset, element.items.len(), size)
+        });
+        let data = module.data.iter().any(|data| {
+            let DataKind::Active { mem, offset } = &data.kind else { return false };
+            let size = match (*mem as usize).checked_sub(imported_memories) {
+                Some(idx) => module.memory_types[idx].initial_size(),
+                None => store.state.get_mem(self.addrs.memories[*mem as usize]).inner.len() as u64,
+            };
+            !fits(offset, data.data.len(), size)
+        });
+        elements || data
+    }
+
+    /// Continue initializing until the instance is complete or the budget is exhausted.
+    ///
+    /// On failure, everything allocated for the instance is removed from the store again.
+    fn resume(&mut self, store: &mut Store, budget: &mut InitBudget) -> Result<Option<ModuleInstance>> {
+        let result = self.initialize(store, budget);
+        if result.is_err() {
+            cold_path();
+            store.rollback_instantiation(&mut self.log);
+        }
+        result
+    }
+
+    fn initialize(&mut self, store: &mut Store, budget: &mut InitBudget) -> Result<Option<ModuleInstance>> {
+        let module = self.module.clone();
+        while let Some(item) = InitItem::get(&module, self.initialized) {
+            if budget.is_exhausted() {
+                return Ok(None);
+            }
+
+            let (addrs, type_ids) = (&mut self.addrs, &self.type_ids[..]);
+            let trap = match item {
+                InitItem::Table(table, init) => {
+                    let addr = store.init_table(table, init, &addrs.globals, &addrs.funcs, type_ids)?;
+                    addrs.tables.push(addr);
+                    None
+                }
+                InitItem::Global(global) => {
+                    let addr = store.init_global(global, &addrs.globals, &addrs.funcs, type_ids)?;
+                    addrs.globals.push(addr);
+                    None
+                }
+                InitItem::Element(element) => {
+                    store.init_element(&mut self.elem_addrs, &mut self.log, element, addrs, type_ids)?
+                }
+                InitItem::Data(Data { kind: DataKind::Active { mem, .. }, .. })
+                    if self.applied_memories.get(*mem as usize) == Some(&true) =>
+                {
+                    store.init_applied_data(&mut self.data_addrs);
+                    None
+                }
+                InitItem::Data(data) => store.init_data(&mut self.data_addrs, &mut self.log, data, addrs, type_ids)?,
+            };
+
+            if let Some(trap) = trap {
+                // the instance is added anyway, as the segments written so far may refer to its functions
+                cold_path();
+                self.finish(store);
+                return Err(trap.into());
+            }
+
+            budget.charge(item.cost());
+            self.initialized += 1;
+        }
+
+        // writes of the data segments are part of the initial image of tracked memories
+        let imported_memories = self.applied_memories.len() - module.memory_types.len();
+        for &addr in self.addrs.memories.get(imported_memories..).unwrap_or_default() {
+            let _ = store.state.get_mem_mut(addr).inner.clear_dirty();
+        }
+
+        Ok(Some(self.finish(store)))
+    }
+
+    fn finish(&mut self, store: &mut Store) -> ModuleInstance {
+        let module = &self.module;
+        let instance = ModuleInstanceInner {
+            store_id: store.id(),
+            idx: self.idx,
+            types: module.func_types.clone(),
+            type_ids: core::mem::take(&mut self.type_ids),
+            func_type_idxs: module.func_type_idxs.clone(),
+            func_addrs: core::mem::take(&mut self.addrs.funcs).into_boxed_slice(),
+            table_addrs: core::mem::take(&mut self.addrs.tables).into_boxed_slice(),
+            mem_addrs: core::mem::take(&mut self.addrs.memories).into_boxed_slice(),
+            global_addrs: core::mem::take(&mut self.addrs.globals).into_boxed_slice(),
+            tag_addrs: core::mem::take(&mut self.addrs.tags).into_boxed_slice(),
+            elem_addrs: core::mem::take(&mut self.elem_addrs).into_boxed_slice(),
+            data_addrs: core::mem::take(&mut self.data_addrs).into_boxed_slice(),
+            func_start: module.start_func,
+            exports: module.exports.clone(),
+        };
+
+        let instance = ModuleInstance(Rc::new(instance));
+        store.add_instance(instance.clone());
+        instance
+    }
+}
@@ -9,14 +9,14 @@ use alloc::rc::Rc;
 use alloc::vec::Vec;
 
 use alloc::sync::Arc;
-use interpreter::stack::{CallFrame, ValueStack};
+use interpreter::stack::{CallFrame, HandlerFrame, StackBase, Stacks, ValueStack};
 use tinywasm_types::*;
 
 use super::ExecState;
 use super::num_helpers::*;
 use super::values::*;
 use crate::engine::FuelPolicy;
-use crate::func::{FuncContext, HostFunction};
+use crate::func::{FuncContext, HostFuncInner, HostFunction, PendingHostCall};
 use crate::interpreter::Value128;
 use crate::*;
 
@@ -28,6 +28,9 @@ pub(crate) struct Executor<'store, const BUDGETED: bool> {
     module: ModuleInstance,
     store: &'store mut Store,
     call_stack_base: u32,
+    /// Handler frames below this belong to an outer invocation and are not visible to `suspend` and `switch`.
+    handler_base: usize,
+    pending_host_call: Option<PendingHostCall>,
 }
 
 impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
@@ -44,10 +47,24 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         }
     }
 
-    pub(crate) fn new(store: &'store mut Store, cf: CallFrame, call_stack_base: u32) -> Self {
+    #[inline(always)]
+    fn pop_atomic_addr<const N: usize>(
+        stack: &mut ValueStack,
+        mem: &MemoryInstance,
+        offset: u64,
+    ) -> Result<usize, Trap> {
+        let addr = Self::pop_memory_addr::<N>(stack, mem, offset)?;
+        if addr % N != 0 {
+            cold_path();
+            return Err(Trap::UnalignedAtomic { offset: addr, len: N });
+        }
+        Ok(addr)
+    }
+
+    pub(crate) fn new(store: &'store mut Store, cf: CallFrame, call_stack_base: u32, handler_base: usize) -> Self {
         let wasm_func = store.state.get_wasm_func(cf.func_addr);
         let module = store.get_module_instance_internal(wasm_func.owner);
-        Self { module, cf, func: wasm_func.func.clone(), store, call_stack_base }
+        Self { module, cf, func: wasm_func.func.clone(), store, call_stack_base, handler_base, pending_host_call: None }
     }
 
     #[inline(always)]
@@ -222,12 +239,21 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
             Select64 => Value64::stack_select(&mut self.store.value_stack)?,
             Select128 => Value128::stack_select(&mut self.store.value_stack)?,
             SelectMulti(counts) => self.store.value_stack.select_multi(*counts),
-            Call(v) => { self.exec_call_direct(*v)?; return Ok(None); }
+            Call(v) => return self.exec_call_direct(*v),
             CallSelf => { self.exec_call_self()?; return Ok(None); }
-            CallIndirect(ty, table) => { self.exec_call_indirect::<false>(*ty, *table)?; return Ok(None); }
-            ReturnCall(v) => { self.exec_return_call_direct(*v)?; return Ok(None); }
+            CallIndirect(ty, table) => return self.exec_call_indirect::<false>(*ty, *table),
+            ReturnCall(v) => return self.exec_return_call_direct(*v),
             ReturnCallSelf => { self.exec_return_call_self()?; return Ok(None); }
-            ReturnCallIndirect(ty, table) => { self.exec_call_indirect::<true>(*ty, *table)?; return Ok(None); }
+            ReturnCallIndirect(ty, table) => return self.exec_call_indirect::<true>(*ty, *table),
+            CallRef(_) => return self.exec_call_ref::<false>(),
+            ReturnCallRef(_) => return self.exec_call_ref::<true>(),
+            ContNew(_) => self.exec_cont_new()?,
+            ContBind(arg_ty, res_ty) => self.exec_cont_bind(*arg_ty, *res_ty)?,
+            Suspend(tag) => { self.exec_suspend(*tag)?; return Ok(None); }
+            Resume(ty, _, _) => return self.exec_resume(*ty),
+            Switch(ty, tag) => { self.exec_switch(*ty, *tag)?; return Ok(None); }
+            Throw(tag) => { self.exec_throw(*tag)?; return Ok(None); }
+            ThrowRef => { self.exec_throw_ref()?; return Ok(None); }
             Jump(ip) => { self.cf.instr_ptr = *ip as usize; return Ok(None); }
             JumpIfZero32(ip) => if self.exec_jump_zero_32(*ip) { return Ok(None) },
             JumpIfNonZero32(ip) => if self.exec_jump_non_zero_32(*ip) { return Ok(None) },
@@ -443,6 +469,37 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
             RefFunc(func_idx) => self.exec_const(ValueRef::from_addr(Some(self.module.resolve_func_addr(*func_idx))))?,
             RefNull(_) => self.exec_const(ValueRef::NULL)?,
             RefIsNull => self.exec_ref_is_null()?,
+            RefAsNonNull => self.exec_ref_as_non_null()?,
+            BrOnNullTest => self.exec_br_on_null_test()?,
+
+            // Garbage collection
+            StructNew(ty) => self.exec_struct_new(*ty, false)?,
+            StructNewDefault(ty) => self.exec_struct_new(*ty, true)?,
+            StructGet(ty, field) | StructGetU(ty, field) => self.exec_struct_get(*ty, *field, false)?,
+            StructGetS(ty, field) => self.exec_struct_get(*ty, *field, true)?,
+            StructSet(ty, field) => self.exec_struct_set(*ty, *field)?,
+            ArrayNew(ty) => self.exec_array_new(*ty, false)?,
+            ArrayNewDefault(ty) => self.exec_array_new(*ty, true)?,
+            ArrayNewFixed(ty, len) => self.exec_array_new_fixed(*ty, *len)?,
+            ArrayNewData(ty, data_idx) => self.exec_array_new_data(*ty, *data_idx)?,
+            ArrayNewElem(ty, elem_idx) => self.exec_array_new_elem(*ty, *elem_idx)?,
+            ArrayGet(ty) | ArrayGetU(ty) => self.exec_array_get(*ty, false)?,
+            ArrayGetS(ty) => self.exec_array_get(*ty, true)?,
+            ArraySet(ty) => self.exec_array_set(*ty)?,
+            ArrayLen => self.exec_array_len()?,
+            ArrayFill(ty) => self.exec_array_fill(*ty)?,
+            ArrayCopy(dst_ty, src_ty) => self.exec_array_copy(*dst_ty, *src_ty)?,
+            ArrayInitData(ty, data_idx) => self.exec_array_init_data(*ty, *data_idx)?,
+            ArrayInitElem(_, elem_idx) => self.exec_array_init_elem(*elem_idx)?,
+            RefEq => self.exec_ref_eq()?,
+            RefTest(ty) => self.exec_ref_test(*ty, false)?,
+            RefCast(ty) => self.exec_ref_cast(*ty)?,
+            BrOnCastTest(ty) => self.exec_ref_test(*ty, true)?,
+            RefI31 => self.exec_ref_i31()?,
+            I31GetS => self.exec_i31_get(true)?,
+            I31GetU => self.exec_i31_get(false)?,
+            AnyConvertExtern => self.exec_any_convert_extern()?,
+            ExternConvertAny => self.exec_extern_convert_any()?,
             MemorySize(addr) => self.exec_memory_size(*addr)?,
             MemoryGrow(addr) => self.exec_memory_grow(*addr)?,
 
@@ -451,6 +508,7 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
             MemoryFill(addr) => self.exec_memory_fill(*addr)?,
             MemoryFillImm(addr, val, size) => self.exec_memory_fill_imm(*addr, *val, *size)?,
             MemoryInit(data_idx, mem_idx) => self.exec_memory_init(*data_idx, *mem_idx)?,
+            MemoryDiscard(addr) => self.exec_memory_discard(*addr)?,
             DataDrop(data_index) => self.store.state.get_data_mut(self.module.resolve_data_addr(*data_index)).drop(),
             ElemDrop(elem_index) => self.store.state.get_elem_mut(self.module.resolve_elem_addr(*elem_index)).drop(),
 
@@ -490,6 +548,75 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
             I64Load32S(m) => self.exec_mem_load::<i32, 4, _>(m.mem_addr(), m.offset(), i64::from)?,
             I64Load32U(m) => self.exec_mem_load::<u32, 4, _>(m.mem_addr(), m.offset(), i64::from)?,
 
+            // Atomic memory operations
+            MemoryAtomicNotify(m) => self.exec_atomic_notify(m.mem_addr(), m.offset())?,
+            MemoryAtomicWait32(m) => self.exec_atomic_wait::<i32, 4>(m.mem_addr(), m.offset())?,
+            MemoryAtomicWait64(m) => self.exec_atomic_wait::<i64, 8>(m.mem_addr(), m.offset())?,
+            AtomicFence => {}
+            I32AtomicLoad(m) => self.exec_atomic_load::<u32, 4, _>(m.mem_addr(), m.offset(), |v| v as i32)?,
+            I64AtomicLoad(m) => self.exec_atomic_load::<u64, 8, _>(m.mem_addr(), m.offset(), |v| v as i64)?,
+            I32AtomicLoad8U(m) => self.exec_atomic_load::<u8, 1, _>(m.mem_addr(), m.offset(), i32::from)?,
+            I32AtomicLoad16U(m) => self.exec_atomic_load::<u16, 2, _>(m.mem_addr(), m.offset(), i32::from)?,
+            I64AtomicLoad8U(m) => self.exec_atomic_load::<u8, 1, _>(m.mem_addr(), m.offset(), i64::from)?,
+            I64AtomicLoad16U(m) => self.exec_atomic_load::<u16, 2, _>(m.mem_addr(), m.offset(), i64::from)?,
+            I64AtomicLoad32U(m) => self.exec_atomic_load::<u32, 4, _>(m.mem_addr(), m.offset(), i64::from)?,
+            I32AtomicStore(m) => self.exec_atomic_store::<i32, u32, 4>(m.mem_addr(), m.offset(), |v| v as u32)?,
+            I64AtomicStore(m) => self.exec_atomic_store::<i64, u64, 8>(m.mem_addr(), m.offset(), |v| v as u64)?,
+            I32AtomicStore8(m) => self.exec_atomic_store::<i32, u8, 1>(m.mem_addr(), m.offset(), |v| v as u8)?,
+            I32AtomicStore16(m) => self.exec_atomic_store::<i32, u16, 2>(m.mem_addr(), m.offset(), |v| v as u16)?,
+            I64AtomicStore8(m) => self.exec_atomic_store::<i64, u8, 1>(m.mem_addr(), m.offset(), |v| v as u8)?,
+            I64AtomicStore16(m) => self.exec_atomic_store::<i64, u16, 2>(m.mem_addr(), m.offset(), |v| v as u16)?,
+            I64AtomicStore32(m) => self.exec_atomic_store::<i64, u32, 4>(m.mem_addr(), m.offset(), |v| v as u32)?,
+            I32AtomicRmwAdd(m) => self.exec_atomic_rmw::<i32, u32, 4>(m.mem_addr(), m.offset(), u32::wrapping_add)?,
+            I64AtomicRmwAdd(m) => self.exec_atomic_rmw::<i64, u64, 8>(m.mem_addr(), m.offset(), u64::wrapping_add)?,
+            I32AtomicRmw8AddU(m) => self.exec_atomic_rmw::<i32, u8, 1>(m.mem_addr(), m.offset(), u8::wrapping_add)?,
+            I32AtomicRmw16AddU(m) => self.exec_atomic_rmw::<i32, u16, 2>(m.mem_addr(), m.offset(), u16::wrapping_add)?,
+            I64AtomicRmw8AddU(m) => self.exec_atomic_rmw::<i64, u8, 1>(m.mem_addr(), m.offset(), u8::wrapping_add)?,
+            I64AtomicRmw16AddU(m) => self.exec_atomic_rmw::<i64, u16, 2>(m.mem_addr(), m.offset(), u16::wrapping_add)?,
+            I64AtomicRmw32AddU(m) => self.exec_atomic_rmw::<i64, u32, 4>(m.mem_addr(), m.offset(), u32::wrapping_add)?,
+            I32AtomicRmwSub(m) => self.exec_atomic_rmw::<i32, u32, 4>(m.mem_addr(), m.offset(), u32::wrapping_sub)?,
+            I64AtomicRmwSub(m) => self.exec_atomic_rmw::<i64, u64, 8>(m.mem_addr(), m.offset(), u64::wrapping_sub)?,
+            I32AtomicRmw8SubU(m) => self.exec_atomic_rmw::<i32, u8, 1>(m.mem_addr(), m.offset(), u8::wrapping_sub)?,
+            I32AtomicRmw16SubU(m) => self.exec_atomic_rmw::<i32, u16, 2>(m.mem_addr(), m.offset(), u16::wrapping_sub)?,
+            I64AtomicRmw8SubU(m) => self.exec_atomic_rmw::<i64, u8, 1>(m.mem_addr(), m.offset(), u8::wrapping_sub)?,
+            I64AtomicRmw16SubU(m) => self.exec_atomic_rmw::<i64, u16, 2>(m.mem_addr(), m.offset(), u16::wrapping_sub)?,
+            I64AtomicRmw32SubU(m) => self.exec_atomic_rmw::<i64, u32, 4>(m.mem_addr(), m.offset(), u32::wrapping_sub)?,
+            I32AtomicRmwAnd(m) => self.exec_atomic_rmw::<i32, u32, 4>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I64AtomicRmwAnd(m) => self.exec_atomic_rmw::<i64, u64, 8>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I32AtomicRmw8AndU(m) => self.exec_atomic_rmw::<i32, u8, 1>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I32AtomicRmw16AndU(m) => self.exec_atomic_rmw::<i32, u16, 2>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I64AtomicRmw8AndU(m) => self.exec_atomic_rmw::<i64, u8, 1>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I64AtomicRmw16AndU(m) => self.exec_atomic_rmw::<i64, u16, 2>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I64AtomicRmw32AndU(m) => self.exec_atomic_rmw::<i64, u32, 4>(m.mem_addr(), m.offset(), |a, b| a & b)?,
+            I32AtomicRmwOr(m) => self.exec_atomic_rmw::<i32, u32, 4>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I64AtomicRmwOr(m) => self.exec_atomic_rmw::<i64, u64, 8>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I32AtomicRmw8OrU(m) => self.exec_atomic_rmw::<i32, u8, 1>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I32AtomicRmw16OrU(m) => self.exec_atomic_rmw::<i32, u16, 2>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I64AtomicRmw8OrU(m) => self.exec_atomic_rmw::<i64, u8, 1>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I64AtomicRmw16OrU(m) => self.exec_atomic_rmw::<i64, u16, 2>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I64AtomicRmw32OrU(m) => self.exec_atomic_rmw::<i64, u32, 4>(m.mem_addr(), m.offset(), |a, b| a | b)?,
+            I32AtomicRmwXor(m) => self.exec_atomic_rmw::<i32, u32, 4>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I64AtomicRmwXor(m) => self.exec_atomic_rmw::<i64, u64, 8>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I32AtomicRmw8XorU(m) => self.exec_atomic_rmw::<i32, u8, 1>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I32AtomicRmw16XorU(m) => self.exec_atomic_rmw::<i32, u16, 2>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I64AtomicRmw8XorU(m) => self.exec_atomic_rmw::<i64, u8, 1>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I64AtomicRmw16XorU(m) => self.exec_atomic_rmw::<i64, u16, 2>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I64AtomicRmw32XorU(m) => self.exec_atomic_rmw::<i64, u32, 4>(m.mem_addr(), m.offset(), |a, b| a ^ b)?,
+            I32AtomicRmwXchg(m) => self.exec_atomic_rmw::<i32, u32, 4>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I64AtomicRmwXchg(m) => self.exec_atomic_rmw::<i64, u64, 8>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I32AtomicRmw8XchgU(m) => self.exec_atomic_rmw::<i32, u8, 1>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I32AtomicRmw16XchgU(m) => self.exec_atomic_rmw::<i32, u16, 2>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I64AtomicRmw8XchgU(m) => self.exec_atomic_rmw::<i64, u8, 1>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I64AtomicRmw16XchgU(m) => self.exec_atomic_rmw::<i64, u16, 2>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I64AtomicRmw32XchgU(m) => self.exec_atomic_rmw::<i64, u32, 4>(m.mem_addr(), m.offset(), |_, b| b)?,
+            I32AtomicRmwCmpxchg(m) => self.exec_atomic_cmpxchg::<i32, u32, 4>(m.mem_addr(), m.offset())?,
+            I64AtomicRmwCmpxchg(m) => self.exec_atomic_cmpxchg::<i64, u64, 8>(m.mem_addr(), m.offset())?,
+            I32AtomicRmw8CmpxchgU(m) => self.exec_atomic_cmpxchg::<i32, u8, 1>(m.mem_addr(), m.offset())?,
+            I32AtomicRmw16CmpxchgU(m) => self.exec_atomic_cmpxchg::<i32, u16, 2>(m.mem_addr(), m.offset())?,
+            I64AtomicRmw8CmpxchgU(m) => self.exec_atomic_cmpxchg::<i64, u8, 1>(m.mem_addr(), m.offset())?,
+            I64AtomicRmw16CmpxchgU(m) => self.exec_atomic_cmpxchg::<i64, u16, 2>(m.mem_addr(), m.offset())?,
+            I64AtomicRmw32CmpxchgU(m) => self.exec_atomic_cmpxchg::<i64, u32, 4>(m.mem_addr(), m.offset())?,
+
             // Numeric conversion operations
             F32ConvertI32S => stack_op!(unary i32 => f32, |v| v as f32),
             F32ConvertI64S => stack_op!(unary i64 => f32, |v| v as f32),
@@ -960,36 +1087,58 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         Ok(())
     }
 
-    fn exec_call_host(&mut self, host_func: Rc<HostFunction>) -> Result<(), Trap> {
+    // Returns `Some(())` when an async host function was started and execution
+    // has to leave the run loop until its future resolves.
+    fn exec_call_host(&mut self, host_func: Rc<HostFunction>) -> Result<Option<()>, Trap> {
         let mut params = self.store.value_stack.pop_types(host_func.ty.params().iter().rev()).collect::<Vec<_>>();
         params.reverse();
-        let res = match host_func.call(FuncContext { store: self.store, module_addr: self.module.idx() }, &params) {
+        let res = match &host_func.func {
+            HostFuncInner::Sync(func) => {
+                // the params are no longer on the value stack, so keep referenced GC objects alive
+                let roots = self.store.pin_host_params(&params);
+                let res = func(FuncContext { store: self.store, module_addr: self.module.idx() }, &params);
+                self.store.gc.host_roots.truncate(roots);
+                res
+            }
+            HostFuncInner::Async(func) => {
+                // the params stay rooted by the execution until the future resolves
+                let roots = self.store.pin_host_params(&params);
+                let future = func(FuncContext { store: self.store, module_addr: self.module.idx() }, &params);
+                self.store.gc.host_roots.truncate(roots);
+                self.pending_host_call = Some(PendingHostCall { future, params });
+                self.cf.instr_ptr += 1;
+                return Ok(Some(()));
+            }
+        };
+
+        let res = match res {
             Ok(res) => res,
             Err(err) => {
                 cold_path();
-                return Err(Trap::HostFunction(Box::new(err)));
+                self.throw_host_error(err)?;
+                return Ok(None);
             }
         };
 
-        self.store.value_stack.extend_from_wasmvalues(&res)?;
+        self.store.push_host_values(&res)?;
         self.cf.instr_ptr += 1;
-        Ok(())
+        Ok(None)
     }
 
-    fn exec_call_direct(&mut self, v: u32) -> Result<(), Trap> {
+    fn exec_call_direct(&mut self, v: u32) -> Result<Option<()>, Trap> {
         self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
         let addr = self.module.resolve_func_addr(v);
         match self.store.state.get_func(addr) {
-            crate::FunctionInstance::Wasm(wasm_func) => self.exec_call(wasm_func.clone(), addr),
+            crate::FunctionInstance::Wasm(wasm_func) => self.exec_call(wasm_func.clone(), addr).map(|_| None),
             crate::FunctionInstance::Host(host_func) => self.exec_call_host(host_func.clone()),
         }
     }
 
-    fn exec_return_call_direct(&mut self, v: u32) -> Result<(), Trap> {
+    fn exec_return_call_direct(&mut self, v: u32) -> Result<Option<()>, Trap> {
         self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
         let addr = self.module.resolve_func_addr(v);
         match self.store.state.get_func(addr) {
-            crate::FunctionInstance::Wasm(wasm_func) => self.exec_return_call(wasm_func.clone(), addr),
+            crate::FunctionInstance::Wasm(wasm_func) => self.exec_return_call(wasm_func.clone(), addr).map(|_| None),
             crate::FunctionInstance::Host(host_func) => self.exec_call_host(host_func.clone()),
         }
     }
@@ -1019,7 +1168,11 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         Ok(())
     }
 
-    fn exec_call_indirect<const IS_RETURN_CALL: bool>(&mut self, type_addr: u32, table_addr: u32) -> Result<(), Trap> {
+    fn exec_call_indirect<const IS_RETURN_CALL: bool>(
+        &mut self,
+        type_addr: u32,
+        table_addr: u32,
+    ) -> Result<Option<()>, Trap> {
         self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
 
         // verify that the table is of the right type, this should be validated by the parser already
@@ -1041,7 +1194,7 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         let call_ty = self.module.func_type_by_type_index(type_addr);
         match self.store.state.get_func(func_ref) {
             crate::FunctionInstance::Wasm(wasm_func) => {
-                if wasm_func.ty() != call_ty {
+                if !self.store.types.is_subtype(wasm_func.type_id, self.module.resolve_type_id(type_addr)) {
                     cold_path();
                     return Err(Trap::IndirectCallTypeMismatch {
                         actual: wasm_func.ty().clone(),
@@ -1050,8 +1203,8 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
                 }
 
                 match IS_RETURN_CALL {
-                    true => self.exec_return_call(wasm_func.clone(), func_ref),
-                    false => self.exec_call(wasm_func.clone(), func_ref),
+                    true => self.exec_return_call(wasm_func.clone(), func_ref).map(|_| None),
+                    false => self.exec_call(wasm_func.clone(), func_ref).map(|_| None),
                 }
             }
             crate::FunctionInstance::Host(host_func) => {
@@ -1068,6 +1221,382 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         }
     }
 
+    /// `call_ref`, which is validated to only be used with references to functions of the right type.
+    fn exec_call_ref<const IS_RETURN_CALL: bool>(&mut self) -> Result<Option<()>, Trap> {
+        self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
+        let Some(func_ref) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullFunctionReference);
+        };
+
+        match self.store.state.get_func(func_ref) {
+            crate::FunctionInstance::Wasm(wasm_func) => match IS_RETURN_CALL {
+                true => self.exec_return_call(wasm_func.clone(), func_ref).map(|_| None),
+                false => self.exec_call(wasm_func.clone(), func_ref).map(|_| None),
+            },
+            crate::FunctionInstance::Host(host_func) => self.exec_call_host(host_func.clone()),
+        }
+    }
+
+    fn pop_cont(&mut self) -> Result<ContinuationInstance, Trap> {
+        let Some(addr) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullContinuationReference);
+        };
+
+        match core::mem::replace(self.store.state.get_cont_mut(addr), ContinuationInstance::Consumed) {
+            ContinuationInstance::Consumed | ContinuationInstance::Free => {
+                cold_path();
+                Err(Trap::ContinuationAlreadyConsumed)
+            }
+            cont => Ok(cont),
+        }
+    }
+
+    fn push_cont(&mut self, cont: ContinuationInstance) -> Result<(), Trap> {
+        let addr = self.store.add_cont(cont);
+        self.store.value_stack.push(ValueRef::from_addr(Some(addr)))
+    }
+
+    /// Makes `cf` the current frame, updating the cached function and module.
+    fn set_frame(&mut self, cf: CallFrame) {
+        let wasm_func = self.store.state.get_wasm_func(cf.func_addr);
+        if !Arc::ptr_eq(&self.func, &wasm_func.func) {
+            self.func = wasm_func.func.clone();
+        }
+        if wasm_func.owner != self.module.idx() {
+            self.module = self.store.get_module_instance_internal(wasm_func.owner);
+        }
+        self.cf = cf;
+    }
+
+    /// Finds the innermost handler for a tag, returning the index of its handler frame
+    /// and, for `suspend`, the instruction the resumer continues at.
+    fn find_handler(&self, tag_addr: TagAddr, switch: bool) -> Option<(usize, u32)> {
+        for idx in (self.handler_base..self.store.handler_stack.len()).rev() {
+            let resumer = self.store.handler_stack[idx].resumer;
+            let wasm_func = self.store.state.get_wasm_func(resumer.func_addr);
+            let Some(Instruction::Resume(_, start, len)) = wasm_func.func.instructions.get(resumer.instr_ptr) else {
+                unreachable!("handler frame does not point at a resume instruction")
+            };
+
+            let module = self.store.get_module_instance_internal(wasm_func.owner);
+            let handlers = &wasm_func.func.data.resume_handlers[*start as usize..(*start + *len) as usize];
+            for handler in handlers {
+                if module.resolve_tag_addr(handler.tag()) != tag_addr {
+                    continue;
+                }
+                match (*handler, switch) {
+                    (ResumeHandler::Label { target_ip, .. }, false) => return Some((idx, target_ip)),
+                    (ResumeHandler::Switch { .. }, true) => return Some((idx, 0)),
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+
+    fn exec_cont_new(&mut self) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let Some(func) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullFunctionReference);
+        };
+        let stacks = self.store.take_spare_stacks();
+        self.push_cont(ContinuationInstance::Fresh { func, stacks })
+    }
+
+    fn exec_cont_bind(&mut self, arg_ty: u32, res_ty: u32) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let mut cont = self.pop_cont()?;
+        let params = self.module.func_type_by_type_index(arg_ty).params();
+        let bound = params.len() - self.module.func_type_by_type_index(res_ty).params().len();
+        let counts = ValueCounts::from_iter(&params[..bound]);
+
+        let Some(stacks) = cont.stacks_mut() else { unreachable!("consumed continuations are rejected by pop_cont") };
+        // bound values are the first arguments, so they go below the ones passed to `resume`
+        self.store.value_stack.move_to(&mut stacks.value_stack, counts)?;
+        self.push_cont(cont)
+    }
+
+    /// Switches to a continuation's stacks, parking the current ones in a new handler frame.
+    fn enter_continuation(&mut self, mut stacks: Stacks, cf: CallFrame, results: ValueCounts) {
+        let inner_handlers = core::mem::take(&mut stacks.handler_stack);
+        self.store.swap_continuation_stacks(&mut stacks);
+        self.store.handler_stack.push(HandlerFrame {
+            parent: stacks,
+            resumer: self.cf,
+            call_stack_base: self.call_stack_base,
+            results,
+        });
+        self.store.handler_stack.extend(inner_handlers);
+        self.call_stack_base = 0;
+        self.set_frame(cf);
+    }
+
+    fn exec_resume(&mut self, ty: u32) -> Result<Option<()>, Trap> {
+        self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
+        let cont = self.pop_cont()?;
+        let ty = self.module.func_type_by_type_index(ty).clone();
+        let results = ValueCounts::from_iter(ty.results());
+
+        match cont {
+            ContinuationInstance::Suspended { cf, mut stacks } => {
+                self.store.value_stack.move_to(&mut stacks.value_stack, ValueCounts::from_iter(ty.params()))?;
+                self.enter_continuation(stacks, cf, results);
+            }
+            ContinuationInstance::Fresh { func, mut stacks } => {
+                self.store.value_stack.move_to(&mut stacks.value_stack, ValueCounts::from_iter(ty.params()))?;
+                match self.store.state.get_func(func).clone() {
+                    crate::FunctionInstance::Wasm(wasm_func) => {
+                        let Ok(locals_base) =
+                            stacks.value_stack.enter_locals(&wasm_func.func.params, &wasm_func.func.locals)
+                        else {
+                            cold_path();
+                            return Err(Trap::CallStackOverflow);
+                        };
+                        self.enter_continuation(
+                            stacks,
+                            CallFrame::new(func, locals_base, wasm_func.func.locals),
+                            results,
+                        );
+                    }
+                    crate::FunctionInstance::Host(host_func) => {
+                        // host functions can't suspend, so they run to completion right away
+                        let HostFuncInner::Sync(f) = &host_func.func else {
+                            cold_path();
+                            return Err(Trap::Other("async host functions can not be resumed as continuations"));
+                        };
+                        let mut params = stacks.value_stack.pop_types(ty.params().iter().rev()).collect::<Vec<_>>();
+                        params.reverse();
+                        self.store.recycle_stacks(stacks);
+
+                        let ctx = FuncContext { store: self.store, module_addr: self.module.idx() };
+                        match f(ctx, &params) {
+                            Ok(res) => {
+                                self.store.push_host_values(&res)?;
+                                self.cf.instr_ptr += 1;
+                            }
+                            Err(err) => self.throw_host_error(err)?,
+                        }
+                    }
+                }
+            }
+            ContinuationInstance::Consumed | ContinuationInstance::Free => {
+                unreachable!("consumed continuations are rejected by pop_cont")
+            }
+        }
+        Ok(None)
+    }
+
+    fn exec_suspend(&mut self, tag: u32) -> Result<(), Trap> {
+        self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
+        self.store.maybe_collect_garbage();
+        let tag_addr = self.module.resolve_tag_addr(tag);
+        let Some((idx, target_ip)) = self.find_handler(tag_addr, false) else {
+            cold_path();
+            return Err(Trap::UnhandledTag);
+        };
+
+        let inner_handlers = self.store.handler_stack.split_off(idx + 1);
+        let Some(frame) = self.store.handler_stack.pop() else { unreachable!("handler frame was just found") };
+        let mut stacks = frame.parent;
+
+        let params = ValueCounts::from_iter(self.store.state.get_tag(tag_addr).ty.params());
+        self.store.value_stack.move_to(&mut stacks.value_stack, params)?;
+        self.store.swap_continuation_stacks(&mut stacks);
+        stacks.handler_stack = inner_handlers;
+
+        let mut cf = self.cf;
+        cf.incr_instr_ptr();
+        self.push_cont(ContinuationInstance::Suspended { cf, stacks })?;
+
+        self.call_stack_base = frame.call_stack_base;
+        let mut resumer = frame.resumer;
+        resumer.instr_ptr = target_ip as usize;
+        self.set_frame(resumer);
+        Ok(())
+    }
+
+    fn exec_switch(&mut self, ty: u32, tag: u32) -> Result<(), Trap> {
+        self.charge_call_fuel(FUEL_COST_CALL_TOTAL);
+        self.store.maybe_collect_garbage();
+        let target = self.pop_cont()?;
+        let tag_addr = self.module.resolve_tag_addr(tag);
+        let Some((idx, _)) = self.find_handler(tag_addr, true) else {
+            cold_path();
+            return Err(Trap::UnhandledTag);
+        };
+
+        // the last parameter is the reference to the continuation we are switching away from
+        let params = self.module.func_type_by_type_index(ty).params();
+        let args = ValueCounts::from_iter(&params[..params.len() - 1]);
+        let (mut stacks, fresh) = match target {
+            ContinuationInstance::Suspended { cf, stacks } => (stacks, Err(cf)),
+            ContinuationInstance::Fresh { func, stacks } => match self.store.state.get_func(func) {
+                crate::FunctionInstance::Wasm(wasm_func) => (stacks, Ok((func, wasm_func.func.clone()))),
+                crate::FunctionInstance::Host(_) => {
+                    cold_path();
+                    return Err(Trap::Other("host function continuations can not be switched to"));
+                }
+            },
+            ContinuationInstance::Consumed | ContinuationInstance::Free => {
+                unreachable!("consumed continuations are rejected by pop_cont")
+            }
+        };
+
+        self.store.value_stack.move_to(&mut stacks.value_stack, args)?;
+        let target_handlers = core::mem::take(&mut stacks.handler_stack);
+        self.store.swap_continuation_stacks(&mut stacks);
+        stacks.handler_stack = self.store.handler_stack.split_off(idx + 1);
+
+        let mut cf = self.cf;
+        cf.incr_instr_ptr();
+        self.push_cont(ContinuationInstance::Suspended { cf, stacks })?;
+        self.store.handler_stack.extend(target_handlers);
+
+        let target_cf = match fresh {
+            Err(cf) => cf,
+            Ok((func_addr, func)) => {
+                let Ok(locals_base) = self.store.value_stack.enter_locals(&func.params, &func.locals) else {
+                    cold_path();
+                    return Err(Trap::CallStackOverflow);
+                };
+                CallFrame::new(func_addr, locals_base, func.locals)
+            }
+        };
+        self.set_frame(target_cf);
+        Ok(())
+    }
+
+    pub(crate) fn throw_from_host(mut self, err: Error) -> Result<CallFrame, Trap> {
+        self.throw_host_error(err)?;
+        Ok(self.cf)
+    }
+
+    fn exec_throw(&mut self, tag: u32) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let tag_addr = self.module.resolve_tag_addr(tag);
+        let params = self.store.state.get_tag(tag_addr).ty.clone();
+        let mut payload = self.store.value_stack.pop_types(params.params().iter().rev()).collect::<Vec<_>>();
+        payload.reverse();
+        let exn = self.store.add_exception(ExceptionInstance { tag: tag_addr, payload: payload.into() });
+        self.throw(exn)
+    }
+
+    fn exec_throw_ref(&mut self) -> Result<(), Trap> {
+        let Some(exn) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullExceptionReference);
+        };
+        self.throw(exn)
+    }
+
+    /// Throws an error returned by a host function if it is an [`Exception`], otherwise traps.
+    fn throw_host_error(&mut self, err: Error) -> Result<(), Trap> {
+        let Error::Trap(Trap::Exception(exception)) = err else {
+            return Err(Trap::HostFunction(Box::new(err)));
+        };
+        exception.tag.0.validate_store(self.store)?;
+        let Exception { tag, payload } = *exception;
+        let exn = self.store.add_exception(ExceptionInstance { tag: tag.0.addr, payload });
+        self.throw(exn)
+    }
+
+    /// Finds the innermost catch clause of `cf`'s function around its current instruction that catches `tag_addr`.
+    fn find_catch(&self, cf: &CallFrame, tag_addr: TagAddr) -> Option<(TryRange, CatchHandler)> {
+        let wasm_func = self.store.state.get_wasm_func(cf.func_addr);
+        let data = &wasm_func.func.data;
+        if data.try_ranges.is_empty() {
+            return None;
+        }
+
+        let module = self.store.get_module_instance_internal(wasm_func.owner);
+        // ranges are ordered by their start, so inner blocks come after the blocks around them
+        for range in data.try_ranges.iter().rev().filter(|range| range.contains(cf.instr_ptr as u32)) {
+            let start = range.handlers_start as usize;
+            let handlers = &data.catch_handlers[start..start + range.handler_count as usize];
+            if let Some(handler) =
+                handlers.iter().find(|h| h.tag.is_none_or(|tag| module.resolve_tag_addr(tag) == tag_addr))
+            {
+                return Some((*range, *handler));
+            }
+        }
+        None
+    }
+
+    /// Unwinds the stacks to the innermost `try_table` that catches the exception and continues at its catch clause.
+    ///
+    /// Exceptions propagate out of continuations to the `resume` that entered them. If nothing catches
+    /// the exception before the current invocation is left, it surfaces as [`Trap::Exception`].
+    fn throw(&mut self, exn_addr: ExnAddr) -> Result<(), Trap> {
+        let tag_addr = self.store.state.get_exception(exn_addr).tag;
+        let mut cf = self.cf;
+        loop {
+            if let Some((range, handler)) = self.find_catch(&cf, tag_addr) {
+                let base = cf.stack_base();
+                self.store.value_stack.truncate_to_base(StackBase {
+                    s32: base.s32 + range.base.c32 as u32,
+                    s64: base.s64 + range.base.c64 as u32,
+                    s128: base.s128 + range.base.c128 as u32,
+                });
+                if handler.tag.is_some() {
+                    let payload = self.store.state.get_exception(exn_addr).payload.clone();
+                    self.store.value_stack.extend_from_wasmvalues(&payload)?;
+                }
+                if handler.with_ref {
+                    self.store.value_stack.push(ValueRef::from_addr(Some(exn_addr)))?;
+                }
+                cf.instr_ptr = handler.target_ip as usize;
+                self.set_frame(cf);
+                return Ok(());
+            }
+
+            if let Some(caller) = self.store.call_stack.pop_frame(self.call_stack_base) {
+                // callers were saved pointing after their call instruction
+                cf = caller;
+                cf.instr_ptr -= 1;
+                continue;
+            }
+
+            if self.store.handler_stack.len() > self.handler_base {
+                // the continuation is abandoned and the exception is rethrown at its `resume`
+                let Some(mut frame) = self.store.handler_stack.pop() else {
+                    unreachable!("handler stack is not empty")
+                };
+                self.store.swap_continuation_stacks(&mut frame.parent);
+                self.store.recycle_stacks(frame.parent);
+                self.call_stack_base = frame.call_stack_base;
+                cf = frame.resumer;
+                continue;
+            }
+
+            cold_path();
+            let exn = self.store.state.get_exception(exn_addr);
+            let tag = Tag(StoreItem::new(self.store.id(), exn.tag));
+            return Err(Trap::Exception(Box::new(Exception { tag, payload: exn.payload.clone() })));
+        }
+    }
+
+    /// Returns from a continuation that ran to completion to the `resume` that entered it.
+    /// Returns `false` if the call stack was exhausted outside of any continuation.
+    fn resume_parent(&mut self) -> Result<bool, Trap> {
+        if self.pending_host_call.is_some() || self.store.handler_stack.len() <= self.handler_base {
+            return Ok(false);
+        }
+
+        let Some(mut frame) = self.store.handler_stack.pop() else { unreachable!("handler stack is not empty") };
+        self.store.value_stack.move_to(&mut frame.parent.value_stack, frame.results)?;
+        self.store.swap_continuation_stacks(&mut frame.parent);
+        self.store.recycle_stacks(frame.parent);
+
+        self.call_stack_base = frame.call_stack_base;
+        let mut resumer = frame.resumer;
+        resumer.incr_instr_ptr();
+        self.set_frame(resumer);
+        Ok(true)
+    }
+
     fn exec_return(&mut self) -> bool {
         self.store.value_stack.truncate_keep_counts(self.cf.locals_base, self.func.results);
         self.finish_return()
@@ -1250,7 +1779,9 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         let raw = <Value32>::stack_pop(&mut self.store.value_stack);
         let value = match self.store.state.get_global(global_addr).ty.ty {
             WasmType::I32 | WasmType::F32 => TinyWasmValue::Value32(raw),
-            WasmType::RefExtern | WasmType::RefFunc => TinyWasmValue::ValueRef(ValueRef::from_raw(raw)),
+            WasmType::RefExtern | WasmType::RefFunc | WasmType::RefCont | WasmType::RefExn | WasmType::RefAny => {
+                TinyWasmValue::ValueRef(ValueRef::from_raw(raw))
+            }
             WasmType::I64 | WasmType::F64 | WasmType::V128 => unreachable!("invalid global.set.32 target type"),
         };
         self.store.state.set_global_val(global_addr, value);
@@ -1265,23 +1796,38 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         self.store.value_stack.push::<i32>(is_null)
     }
 
+    fn exec_ref_as_non_null(&mut self) -> Result<(), Trap> {
+        if <ValueRef>::stack_peek(&self.store.value_stack).is_null() {
+            cold_path();
+            return Err(Trap::NullReference);
+        }
+        Ok(())
+    }
+
+    fn exec_br_on_null_test(&mut self) -> Result<(), Trap> {
+        let is_null = i32::from(<ValueRef>::stack_peek(&self.store.value_stack).is_null());
+        self.store.value_stack.push::<i32>(is_null)
+    }
+
     fn exec_memory_size(&mut self, addr: u32) -> Result<(), Trap> {
         let mem = self.store.state.get_mem(self.module.resolve_mem_addr(addr));
         match mem.is_64bit() {
-            true => self.store.value_stack.push::<i64>(mem.page_count as i64),
-            false => self.store.value_stack.push::<i32>(mem.page_count as i32),
+            true => self.store.value_stack.push::<i64>(mem.page_count() as i64),
+            false => self.store.value_stack.push::<i32>(mem.page_count() as i32),
         }
     }
 
     fn exec_memory_grow(&mut self, addr: u32) -> Result<(), Trap> {
-        let mem = self.store.state.get_mem_mut(self.module.resolve_mem_addr(addr));
-        let is_64bit = mem.is_64bit();
+        let mem_addr = self.module.resolve_mem_addr(addr);
+        let is_64bit = self.store.state.get_mem(mem_addr).is_64bit();
         let pages_delta = match is_64bit {
             true => <i64>::stack_pop(&mut self.store.value_stack),
             false => i64::from(<i32>::stack_pop(&mut self.store.value_stack)),
         };
 
-        let size = mem.grow(pages_delta, self.store.engine.config().trap_on_oom())?.unwrap_or(-1);
+        let trap_on_oom = self.store.engine.config().trap_on_oom();
+        let size =
+            self.store.grow_memory(mem_addr, pages_delta, trap_on_oom, Some((&self.module, addr)))?.unwrap_or(-1);
         match is_64bit {
             true => self.store.value_stack.push::<i64>(size)?,
             false => self.store.value_stack.push::<i32>(size as i32)?,
@@ -1325,15 +1871,36 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         let mem = self.store.state.get_mem_mut(self.module.resolve_mem_addr(addr));
         if mem.inner.fill(dst as usize, size as usize, val).is_none() {
             cold_path();
-            return Err(Trap::MemoryOutOfBounds {
-                offset: dst as usize,
-                len: size as usize,
-                max: self.store.state.get_mem(self.module.resolve_mem_addr(addr)).inner.len(),
-            });
+            return Err(self.store.state.get_mem(self.module.resolve_mem_addr(addr)).access_trap(
+                dst as usize,
+                size as usize,
+                true,
+            ));
         }
         Ok(())
     }
 
+    fn exec_memory_discard(&mut self, addr: u32) -> Result<(), Trap> {
+        let mem = self.store.state.get_mem_mut(self.module.resolve_mem_addr(addr));
+        let (dst, size) = match mem.is_64bit() {
+            true => {
+                let size = <i64>::stack_pop(&mut self.store.value_stack) as u64;
+                (<i64>::stack_pop(&mut self.store.value_stack) as u64, size)
+            }
+            false => {
+                let size = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+                (u64::from(<i32>::stack_pop(&mut self.store.value_stack) as u32), u64::from(size))
+            }
+        };
+
+        let page_size = mem.kind.page_size();
+        if dst % page_size != 0 || size % page_size != 0 {
+            cold_path();
+            return Err(Trap::UnalignedDiscard { offset: dst, len: size });
+        }
+        mem.discard(usize::try_from(dst).unwrap_or(usize::MAX), usize::try_from(size).unwrap_or(usize::MAX))
+    }
+
     fn exec_memory_init(&mut self, data_index: u32, mem_index: u32) -> Result<(), Trap> {
         let size = i32::stack_pop(&mut self.store.value_stack);
         let offset = i32::stack_pop(&mut self.store.value_stack);
@@ -1358,7 +1925,7 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
 
         if mem.inner.write_all(dst as usize, &data[offset as usize..((offset + size) as usize)]).is_none() {
             cold_path();
-            return Err(Trap::MemoryOutOfBounds { offset: dst as usize, len: size as usize, max: mem.inner.len() });
+            return Err(mem.access_trap(dst as usize, size as usize, true));
         }
         Ok(())
     }
@@ -1466,6 +2033,80 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         }
     }
 
+    fn exec_atomic_load<LOAD: MemValue<N>, const N: usize, TARGET: InternalValue>(
+        &mut self,
+        mem_addr: tinywasm_types::MemAddr,
+        offset: u64,
+        cast: impl Fn(LOAD) -> TARGET,
+    ) -> Result<(), Trap> {
+        let mem = self.store.state.get_mem(self.module.resolve_mem_addr(mem_addr));
+        let addr = Self::pop_atomic_addr::<N>(&mut self.store.value_stack, mem, offset)?;
+        let val = LOAD::load_at(&*mem.inner, addr)?;
+        self.store.value_stack.push(cast(val))
+    }
+
+    fn exec_atomic_store<T: InternalValue, U: MemValue<N>, const N: usize>(
+        &mut self,
+        mem_addr: tinywasm_types::MemAddr,
+        offset: u64,
+        cast: impl Fn(T) -> U,
+    ) -> Result<(), Trap> {
+        let val = cast(<T>::stack_pop(&mut self.store.value_stack));
+        let mem = self.store.state.get_mem_mut(self.module.resolve_mem_addr(mem_addr));
+        let addr = Self::pop_atomic_addr::<N>(&mut self.store.value_stack, mem, offset)?;
+        val.store_at(&mut *mem.inner, addr)
+    }
+
+    fn exec_atomic_rmw<T: InternalValue + AtomicOperand<U>, U: MemValue<N>, const N: usize>(
+        &mut self,
+        mem_addr: tinywasm_types::MemAddr,
+        offset: u64,
+        op: impl Fn(U, U) -> U,
+    ) -> Result<(), Trap> {
+        let operand = <T>::stack_pop(&mut self.store.value_stack).wrap();
+        let mem = self.store.state.get_mem_mut(self.module.resolve_mem_addr(mem_addr));
+        let addr = Self::pop_atomic_addr::<N>(&mut self.store.value_stack, mem, offset)?;
+        let old = mem.atomic_rmw::<N>(addr, |old| op(U::from_mem_bytes(old), operand).to_mem_bytes())?;
+        self.store.value_stack.push(T::extend(U::from_mem_bytes(old)))
+    }
+
+    fn exec_atomic_cmpxchg<T: InternalValue + AtomicOperand<U>, U: MemValue<N> + PartialEq, const N: usize>(
+        &mut self,
+        mem_addr: tinywasm_types::MemAddr,
+        offset: u64,
+    ) -> Result<(), Trap> {
+        let replacement = <T>::stack_pop(&mut self.store.value_stack).wrap();
+        let expected = <T>::stack_pop(&mut self.store.value_stack).wrap();
+        let mem = self.store.state.get_mem_mut(self.module.resolve_mem_addr(mem_addr));
+        let addr = Self::pop_atomic_addr::<N>(&mut self.store.value_stack, mem, offset)?;
+        let old = mem.atomic_rmw::<N>(addr, |old| match U::from_mem_bytes(old) == expected {
+            true => replacement.to_mem_bytes(),
+            false => old,
+        })?;
+        self.store.value_stack.push(T::extend(U::from_mem_bytes(old)))
+    }
+
+    fn exec_atomic_wait<T: InternalValue + MemValue<N>, const N: usize>(
+        &mut self,
+        mem_addr: tinywasm_types::MemAddr,
+        offset: u64,
+    ) -> Result<(), Trap> {
+        let timeout = <i64>::stack_pop(&mut self.store.value_stack);
+        let expected = <T>::stack_pop(&mut self.store.value_stack);
+        let mem = self.store.state.get_mem(self.module.resolve_mem_addr(mem_addr));
+        let addr = Self::pop_atomic_addr::<N>(&mut self.store.value_stack, mem, offset)?;
+        let result = mem.atomic_wait(addr, expected.to_mem_bytes(), timeout)?;
+        self.store.value_stack.push::<i32>(result)
+    }
+
+    fn exec_atomic_notify(&mut self, mem_addr: tinywasm_types::MemAddr, offset: u64) -> Result<(), Trap> {
+        let count = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let mem = self.store.state.get_mem(self.module.resolve_mem_addr(mem_addr));
+        let addr = Self::pop_atomic_addr::<4>(&mut self.store.value_stack, mem, offset)?;
+        let woken = mem.atomic_notify(addr, count)?;
+        self.store.value_stack.push::<i32>(woken as i32)
+    }
+
     fn exec_table_get(&mut self, table_index: u32) -> Result<(), Trap> {
         let table_addr = self.module.resolve_table_addr(table_index);
         let idx = self.pop_table_operand(self.store.state.get_table(table_addr).kind.arch())?;
@@ -1516,9 +2157,8 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         let arch = self.store.state.get_table(table_addr).kind.arch();
         let n = self.pop_table_operand(arch)?;
         let val = <ValueRef>::stack_pop(&mut self.store.value_stack);
-        let table = self.store.state.get_table_mut(table_addr);
-        let sz = table.size();
-        let result = table.grow(n, val.addr().into());
+        let sz = self.store.state.get_table(table_addr).size();
+        let result = self.store.grow_table(table_addr, n, val.addr().into(), Some((&self.module, table_index)));
         match (arch, result) {
             (MemoryArch::I32, Ok(())) => self.store.value_stack.push(sz as i32),
             (MemoryArch::I32, Err(_)) => self.store.value_stack.push(-1_i32),
@@ -1536,6 +2176,290 @@ impl<'store, const BUDGETED: bool> Executor<'store, BUDGETED> {
         self.store.state.get_table_mut(table_addr).fill(self.module.func_addrs(), i, n, val.addr().into())
     }
 
+    fn exec_struct_new(&mut self, ty: u32, default: bool) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let ty = self.module.resolve_type_id(ty);
+        let idx = self.store.gc.alloc_struct(&self.store.types, ty)?;
+        if !default {
+            let (_, data) = self.store.gc.struct_data_mut(idx);
+            for field in self.store.types.struct_fields(ty).iter().rev() {
+                field.kind.write(&mut data[field.range()], pop_field(&mut self.store.value_stack, field.kind));
+            }
+        }
+        self.store.value_stack.push(ValueRef::from_raw(idx))
+    }
+
+    fn exec_struct_get(&mut self, ty: u32, field: u32, signed: bool) -> Result<(), Trap> {
+        let Some(idx) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullStructReference);
+        };
+        let field = &self.store.types.struct_fields(self.module.resolve_type_id(ty))[field as usize];
+        let (_, data) = self.store.gc.struct_data_mut(idx);
+        let value = unpack_field(field.kind.read(&data[field.range()]), field.kind, signed);
+        self.store.value_stack.push_dyn(value)
+    }
+
+    fn exec_struct_set(&mut self, ty: u32, field: u32) -> Result<(), Trap> {
+        let field = &self.store.types.struct_fields(self.module.resolve_type_id(ty))[field as usize];
+        let value = pop_field(&mut self.store.value_stack, field.kind);
+        let Some(idx) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullStructReference);
+        };
+        let (_, data) = self.store.gc.struct_data_mut(idx);
+        field.kind.write(&mut data[field.range()], value);
+        Ok(())
+    }
+
+    fn exec_array_new(&mut self, ty: u32, default: bool) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let ty = self.module.resolve_type_id(ty);
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let init = match default {
+            true => None,
+            false => Some(pop_field(&mut self.store.value_stack, self.store.types.array_element(ty).kind)),
+        };
+        let idx = self.store.gc.alloc_array(&self.store.types, ty, len, init)?;
+        self.store.value_stack.push(ValueRef::from_raw(idx))
+    }
+
+    fn exec_array_new_fixed(&mut self, ty: u32, len: u32) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let ty = self.module.resolve_type_id(ty);
+        let idx = self.store.gc.alloc_array(&self.store.types, ty, len, None)?;
+        let kind = self.store.types.array_element(ty).kind;
+        let (_, _, data) = self.store.gc.array_data_mut(idx);
+        for element in data.chunks_exact_mut(kind.size()).rev() {
+            kind.write(element, pop_field(&mut self.store.value_stack, kind));
+        }
+        self.store.value_stack.push(ValueRef::from_raw(idx))
+    }
+
+    fn exec_array_new_data(&mut self, ty: u32, data_index: u32) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let ty = self.module.resolve_type_id(ty);
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let offset = <i32>::stack_pop(&mut self.store.value_stack) as u32 as usize;
+
+        let kind = self.store.types.array_element(ty).kind;
+        let data = &self.store.state.data[self.module.resolve_data_addr(data_index) as usize];
+        let data = data.data.as_deref().unwrap_or(&[]);
+        let size = len as usize * kind.size();
+        if offset.checked_add(size).is_none_or(|end| end > data.len()) {
+            cold_path();
+            return Err(Trap::MemoryOutOfBounds { offset, len: size, max: data.len() });
+        }
+
+        let idx = self.store.gc.alloc_array(&self.store.types, ty, len, None)?;
+        let (_, _, array) = self.store.gc.array_data_mut(idx);
+        array.copy_from_slice(&data[offset..offset + size]);
+        self.store.value_stack.push(ValueRef::from_raw(idx))
+    }
+
+    fn exec_array_new_elem(&mut self, ty: u32, elem_index: u32) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let ty = self.module.resolve_type_id(ty);
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let offset = <i32>::stack_pop(&mut self.store.value_stack) as u32 as usize;
+
+        let elem = &self.store.state.elements[self.module.resolve_elem_addr(elem_index) as usize];
+        let items = elem.items.as_deref().unwrap_or(&[]);
+        if offset.checked_add(len as usize).is_none_or(|end| end > items.len()) {
+            cold_path();
+            return Err(Trap::TableOutOfBounds { offset, len: len as usize, max: items.len() });
+        }
+
+        let idx = self.store.gc.alloc_array(&self.store.types, ty, len, None)?;
+        let (_, _, array) = self.store.gc.array_data_mut(idx);
+        for (element, item) in array.chunks_exact_mut(4).zip(&items[offset..]) {
+            element.copy_from_slice(&ValueRef::from_addr(item.addr()).raw().to_le_bytes());
+        }
+        self.store.value_stack.push(ValueRef::from_raw(idx))
+    }
+
+    /// Pop an array reference and check that `len` elements starting at `offset` are in bounds.
+    fn pop_array_range(&mut self, offset: u32, len: u32) -> Result<u32, Trap> {
+        let Some(idx) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullArrayReference);
+        };
+        let (_, array_len, _) = self.store.gc.array_data_mut(idx);
+        if offset.checked_add(len).is_none_or(|end| end > array_len) {
+            cold_path();
+            return Err(Trap::ArrayOutOfBounds { offset: offset as usize, len: len as usize, max: array_len as usize });
+        }
+        Ok(idx)
+    }
+
+    fn exec_array_get(&mut self, ty: u32, signed: bool) -> Result<(), Trap> {
+        let index = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let idx = self.pop_array_range(index, 1)?;
+        let kind = self.store.types.array_element(self.module.resolve_type_id(ty)).kind;
+        let (_, _, data) = self.store.gc.array_data_mut(idx);
+        let value = unpack_field(kind.read(&data[index as usize * kind.size()..]), kind, signed);
+        self.store.value_stack.push_dyn(value)
+    }
+
+    fn exec_array_set(&mut self, ty: u32) -> Result<(), Trap> {
+        let kind = self.store.types.array_element(self.module.resolve_type_id(ty)).kind;
+        let value = pop_field(&mut self.store.value_stack, kind);
+        let index = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let idx = self.pop_array_range(index, 1)?;
+        let (_, _, data) = self.store.gc.array_data_mut(idx);
+        kind.write(&mut data[index as usize * kind.size()..], value);
+        Ok(())
+    }
+
+    fn exec_array_len(&mut self) -> Result<(), Trap> {
+        let Some(idx) = <ValueRef>::stack_pop(&mut self.store.value_stack).addr() else {
+            cold_path();
+            return Err(Trap::NullArrayReference);
+        };
+        let (_, len, _) = self.store.gc.array_data_mut(idx);
+        self.store.value_stack.push(len as i32)
+    }
+
+    fn exec_array_fill(&mut self, ty: u32) -> Result<(), Trap> {
+        let kind = self.store.types.array_element(self.module.resolve_type_id(ty)).kind;
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let value = pop_field(&mut self.store.value_stack, kind);
+        let offset = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let idx = self.pop_array_range(offset, len)?;
+        let (_, _, data) = self.store.gc.array_data_mut(idx);
+        let start = offset as usize * kind.size();
+        for element in data[start..start + len as usize * kind.size()].chunks_exact_mut(kind.size()) {
+            kind.write(element, value);
+        }
+        Ok(())
+    }
+
+    fn exec_array_copy(&mut self, dst_ty: u32, _src_ty: u32) -> Result<(), Trap> {
+        let size = self.store.types.array_element(self.module.resolve_type_id(dst_ty)).kind.size();
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let src_offset = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let src = self.pop_array_range(src_offset, len)?;
+        let dst_offset = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let dst = self.pop_array_range(dst_offset, len)?;
+        self.store.gc.copy_array_data(
+            dst,
+            dst_offset as usize * size,
+            src,
+            src_offset as usize * size,
+            len as usize * size,
+        );
+        Ok(())
+    }
+
+    fn exec_array_init_data(&mut self, ty: u32, data_index: u32) -> Result<(), Trap> {
+        let kind = self.store.types.array_element(self.module.resolve_type_id(ty)).kind;
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let src = <i32>::stack_pop(&mut self.store.value_stack) as u32 as usize;
+        let dst = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let idx = self.pop_array_range(dst, len)?;
+
+        let data = &self.store.state.data[self.module.resolve_data_addr(data_index) as usize];
+        let data = data.data.as_deref().unwrap_or(&[]);
+        let size = len as usize * kind.size();
+        if src.checked_add(size).is_none_or(|end| end > data.len()) {
+            cold_path();
+            return Err(Trap::MemoryOutOfBounds { offset: src, len: size, max: data.len() });
+        }
+
+        let (_, _, array) = self.store.gc.array_data_mut(idx);
+        let dst = dst as usize * kind.size();
+        array[dst..dst + size].copy_from_slice(&data[src..src + size]);
+        Ok(())
+    }
+
+    fn exec_array_init_elem(&mut self, elem_index: u32) -> Result<(), Trap> {
+        let len = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let src = <i32>::stack_pop(&mut self.store.value_stack) as u32 as usize;
+        let dst = <i32>::stack_pop(&mut self.store.value_stack) as u32;
+        let idx = self.pop_array_range(dst, len)?;
+
+        let elem = &self.store.state.elements[self.module.resolve_elem_addr(elem_index) as usize];
+        let items = elem.items.as_deref().unwrap_or(&[]);
+        if src.checked_add(len as usize).is_none_or(|end| end > items.len()) {
+            cold_path();
+            return Err(Trap::TableOutOfBounds { offset: src, len: len as usize, max: items.len() });
+        }
+
+        let (_, _, array) = self.store.gc.array_data_mut(idx);
+        let elements = array[dst as usize * 4..].chunks_exact_mut(4);
+        for (element, item) in elements.zip(&items[src..src + len as usize]) {
+            element.copy_from_slice(&ValueRef::from_addr(item.addr()).raw().to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn exec_ref_eq(&mut self) -> Result<(), Trap> {
+        let rhs = <ValueRef>::stack_pop(&mut self.store.value_stack);
+        let lhs = <ValueRef>::stack_pop(&mut self.store.value_stack);
+        self.store.value_stack.push(i32::from(lhs == rhs))
+    }
+
+    fn ref_matches(&self, value: ValueRef, ty: RefType) -> bool {
+        self.store.ref_matches(value.raw(), map_ref_type(ty, |idx| self.module.resolve_type_id(idx)))
+    }
+
+    /// `ref.test`, or the test of `br_on_cast` if `keep` is set, which leaves the reference on the stack.
+    fn exec_ref_test(&mut self, ty: RefType, keep: bool) -> Result<(), Trap> {
+        let value = match keep {
+            true => <ValueRef>::stack_peek(&self.store.value_stack),
+            false => <ValueRef>::stack_pop(&mut self.store.value_stack),
+        };
+        let matches = self.ref_matches(value, ty);
+        self.store.value_stack.push(i32::from(matches))
+    }
+
+    fn exec_ref_cast(&mut self, ty: RefType) -> Result<(), Trap> {
+        if !self.ref_matches(<ValueRef>::stack_peek(&self.store.value_stack), ty) {
+            cold_path();
+            return Err(Trap::CastFailure);
+        }
+        Ok(())
+    }
+
+    fn exec_ref_i31(&mut self) -> Result<(), Trap> {
+        let value = <i32>::stack_pop(&mut self.store.value_stack);
+        self.store.value_stack.push(ValueRef::from_raw(AnyRef::i31(value).raw()))
+    }
+
+    fn exec_i31_get(&mut self, signed: bool) -> Result<(), Trap> {
+        let value = AnyRef::from_raw(<ValueRef>::stack_pop(&mut self.store.value_stack).raw());
+        let value = match signed {
+            true => value.as_i31_s(),
+            false => value.as_i31_u().map(|v| v as i32),
+        };
+        let Some(value) = value else {
+            cold_path();
+            return Err(Trap::NullI31Reference);
+        };
+        self.store.value_stack.push(value)
+    }
+
+    fn exec_any_convert_extern(&mut self) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let value = <ValueRef>::stack_pop(&mut self.store.value_stack);
+        let value = self.store.gc.internalize(value.raw())?;
+        self.store.value_stack.push(ValueRef::from_raw(value))
+    }
+
+    fn exec_extern_convert_any(&mut self) -> Result<(), Trap> {
+        self.store.maybe_collect_garbage();
+        let value = <ValueRef>::stack_pop(&mut self.store.value_stack);
+        let value = self.store.gc.externalize(value.raw())?;
+        self.store.value_stack.push(ValueRef::from_raw(value))
+    }
+
+    fn exit_state(&mut self) -> ExecState {
+        match self.pending_host_call.take() {
+            Some(call) => ExecState::AwaitingHost(self.cf, call),
+            None => ExecState::Completed,
+        }
+    }
+
     fn pop_table_operand(&mut self, arch: MemoryArch) -> Result<usize, Trap> {
         let value = match arch {
             MemoryArch::I32 => <i32>::stack_pop(&mut self.store.value_stack) as u32 as u64,
@@ -1550,7 +2474,11 @@ impl<'store> Executor<'store, false> {
     pub(crate) fn run_to_completion(&mut self) -> Result<(), Trap> {
         // ideally we use `loop_match` / `become` once thats stabilized
         loop {
-            if self.exec()?.is_some() {
+            if self.exec()?.is_some() && !self.resume_parent()? {
+                if self.pending_host_call.take().is_some() {
+                    cold_path();
+                    return Err(Trap::Other("async host functions require resumable or async execution"));
+                }
                 return Ok(());
             }
         }
@@ -1567,8 +2495,8 @@ impl<'store> Executor<'store, false> {
 
         loop {
             for _ in 0..128 {
-                if self.exec()?.is_some() {
-                    return Ok(ExecState::Completed);
+                if self.exec()?.is_some() && !self.resume_parent()? {
+                    return Ok(self.exit_state());
                 }
             }
 
@@ -1589,8 +2517,8 @@ impl<'store> Executor<'store, true> {
 
         loop {
             for _ in 0..128 {
-                if self.exec()?.is_some() {
-                    return Ok(ExecState::Completed);
+                if self.exec()?.is_some() && !self.resume_parent()? {
+                    return Ok(self.exit_state());
                 }
             }
 
@@ -1602,6 +2530,27 @@ impl<'store> Executor<'store, true> {
     }
 }
 
+/// Pop a struct field or array element from the stack.
+#[inline]
+fn pop_field(stack: &mut ValueStack, kind: FieldKind) -> TinyWasmValue {
+    match kind {
+        FieldKind::I8 | FieldKind::I16 | FieldKind::I32 => TinyWasmValue::Value32(<Value32>::stack_pop(stack)),
+        FieldKind::I64 => TinyWasmValue::Value64(<Value64>::stack_pop(stack)),
+        FieldKind::V128 => TinyWasmValue::Value128(<Value128>::stack_pop(stack)),
+        FieldKind::Ref => TinyWasmValue::ValueRef(<ValueRef>::stack_pop(stack)),
+    }
+}
+
+/// Sign-extend a packed field that was read with [`FieldKind::read`] if `signed` is set.
+#[inline]
+fn unpack_field(value: TinyWasmValue, kind: FieldKind, signed: bool) -> TinyWasmValue {
+    match (value, kind, signed) {
+        (TinyWasmValue::Value32(v), FieldKind::I8, true) => TinyWasmValue::Value32(v as u8 as i8 as i32 as u32),
+        (TinyWasmValue::Value32(v), FieldKind::I16, true) => TinyWasmValue::Value32(v as u16 as i16 as i32 as u32),
+        (value, _, _) => value,
+    }
+}
+
 #[inline(always)]
 fn cmp_i32(lhs: i32, rhs: i32, op: CmpOp) -> bool {
     match op {
@@ -7,15 +7,16 @@ pub(crate) mod values;
 #[cfg(not(feature = "std"))]
 mod no_std_floats;
 
-use crate::{Result, Store, Trap, interpreter::stack::CallFrame};
+use crate::{Error, Result, Store, Trap, func::PendingHostCall, interpreter::stack::CallFrame};
 pub(crate) use simd::*;
 pub(crate) use values::*;
 
-#[derive(Clone)]
 #[cfg_attr(feature = "debug", derive(Debug))]
 pub(crate) enum ExecState {
     Completed,
     Suspended(CallFrame),
+    /// An async host function was called, execution continues at the frame once its future resolves.
+    AwaitingHost(CallFrame, PendingHostCall),
 }
 
 /// The main `TinyWasm` runtime.
@@ -27,11 +28,33 @@ pub(crate) struct InterpreterRuntime;
 
 impl InterpreterRuntime {
     pub(crate) fn exec(store: &mut Store, cf: CallFrame, call_stack_base: u32) -> Result<(), Trap> {
-        executor::Executor::<false>::new(store, cf, call_stack_base).run_to_completion()
+        // continuations resumed outside of this call can't be suspended from within it
+        let handler_base = store.handler_stack.len();
+        let result = executor::Executor::<false>::new(store, cf, call_stack_base, handler_base).run_to_completion();
+        if result.is_err() {
+            store.unwind_handlers(handler_base);
+        }
+        result
     }
 
     pub(crate) fn exec_with_fuel(store: &mut Store, cf: CallFrame, fuel: u32) -> Result<ExecState, Trap> {
-        executor::Executor::<true>::new(store, cf, 0).run_with_fuel(fuel)
+        let result = executor::Executor::<true>::new(store, cf, 0, 0).run_with_fuel(fuel);
+        if result.is_err() {
+            store.unwind_handlers(0);
+        }
+        result
+    }
+
+    /// Throws the error an async host function resolved to at the call that awaited it,
+    /// returning the frame execution continues at.
+    pub(crate) fn throw_host_error(store: &mut Store, mut cf: CallFrame, err: Error) -> Result<CallFrame, Trap> {
+        // the frame was saved pointing after the call instruction
+        cf.instr_ptr -= 1;
+        let result = executor::Executor::<false>::new(store, cf, 0, 0).throw_from_host(err);
+        if result.is_err() {
+            store.unwind_handlers(0);
+        }
+        result
     }
 
     #[cfg(feature = "std")]
@@ -40,6 +63,10 @@ impl InterpreterRuntime {
         cf: CallFrame,
         time_budget: core::time::Duration,
     ) -> Result<ExecState, Trap> {
-        executor::Executor::<false>::new(store, cf, 0).run_with_time_budget(time_budget)
+        let result = executor::Executor::<false>::new(store, cf, 0, 0).run_with_time_budget(time_budget);
+        if result.is_err() {
+            store.unwind_handlers(0);
+        }
+        result
     }
 }
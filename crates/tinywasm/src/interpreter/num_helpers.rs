@@ -141,3 +141,27 @@ macro_rules! impl_checked_wrapping_rem {
 }
 
 impl_checked_wrapping_rem! { i32 i64 u32 u64 }
+
+/// Converts between a stack operand and the unsigned value accessed by a (possibly narrow) atomic instruction.
+pub(crate) trait AtomicOperand<U> {
+    fn wrap(self) -> U;
+    fn extend(value: U) -> Self;
+}
+
+macro_rules! impl_atomic_operand {
+    ($($t:ty => $($u:ty),*);*) => ($($(
+        impl AtomicOperand<$u> for $t {
+            #[inline(always)]
+            fn wrap(self) -> $u {
+                self as $u
+            }
+
+            #[inline(always)]
+            fn extend(value: $u) -> Self {
+                value as $t
+            }
+        }
+    )*)*)
+}
+
+impl_atomic_operand! { i32 => u8, u16, u32; i64 => u8, u16, u32, u64 }
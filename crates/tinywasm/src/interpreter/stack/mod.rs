@@ -1,5 +1,52 @@
 mod call_stack;
 mod value_stack;
 
+use alloc::vec::Vec;
+use tinywasm_types::ValueCounts;
+
 pub(crate) use call_stack::{CallFrame, CallStack, StackBase};
 pub(crate) use value_stack::ValueStack;
+
+/// The call and value stacks of an execution that is not using the store's own stacks.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct Stacks {
+    pub(crate) call_stack: CallStack,
+    pub(crate) value_stack: ValueStack,
+    /// Handlers installed by active `resume` instructions, innermost last.
+    pub(crate) handler_stack: Vec<HandlerFrame>,
+}
+
+impl Stacks {
+    pub(crate) fn new(config: &crate::engine::Config) -> Self {
+        Self { call_stack: CallStack::new(config), value_stack: ValueStack::new(config), handler_stack: Vec::new() }
+    }
+
+    /// The number of bytes [`Stacks::new`] reserves up front with the given config.
+    pub(crate) fn initial_size(config: &crate::engine::Config) -> usize {
+        config.call_stack.initial_size * size_of::<CallFrame>()
+            + config.value_stack_32.initial_size * size_of::<crate::interpreter::Value32>()
+            + config.value_stack_64.initial_size * size_of::<crate::interpreter::Value64>()
+            + config.value_stack_128.initial_size * size_of::<crate::interpreter::Value128>()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.call_stack.clear();
+        self.value_stack.clear();
+        self.handler_stack.clear();
+    }
+}
+
+/// A continuation that is currently running, entered through a `resume` instruction.
+///
+/// The resumer's stacks are parked here while the continuation runs on its own.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct HandlerFrame {
+    /// The call and value stacks of the resumer.
+    pub(crate) parent: Stacks,
+    /// The frame executing the `resume` instruction, pointing at it.
+    pub(crate) resumer: CallFrame,
+    /// The call stack base of the executor when the `resume` was executed.
+    pub(crate) call_stack_base: u32,
+    /// The values the continuation returns to the resumer.
+    pub(crate) results: ValueCounts,
+}
@@ -1,6 +1,6 @@
 use alloc::vec::Vec;
 use core::hint::cold_path;
-use tinywasm_types::{ExternRef, FuncRef, ValueCounts, WasmType, WasmValue};
+use tinywasm_types::{AnyRef, ContRef, ExnRef, ExternRef, FuncRef, ValueCounts, WasmType, WasmValue};
 
 use super::StackBase;
 use crate::engine::{Config, StackConfig};
@@ -35,6 +35,10 @@ impl<T: Copy + Default> Stack<T> {
         self.data.len()
     }
 
+    pub(crate) fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
     #[inline(always)]
     pub(crate) fn push(&mut self, value: T) -> Result<(), Trap> {
         if !self.ensure_capacity_for(self.data.len() + 1) {
@@ -90,6 +94,17 @@ impl<T: Copy + Default> Stack<T> {
         self.data.truncate(n);
     }
 
+    /// Moves the topmost `n` values onto another stack, keeping their order.
+    pub(crate) fn move_to(&mut self, other: &mut Self, n: usize) -> Result<(), Trap> {
+        debug_assert!(n <= self.data.len());
+        if !other.ensure_capacity_for(other.data.len() + n) {
+            cold_path();
+            return Err(Trap::ValueStackOverflow);
+        }
+        other.data.extend(self.data.drain(self.data.len() - n..));
+        Ok(())
+    }
+
     #[inline(always)]
     pub(crate) fn truncate_to_one_tail(&mut self, n: usize) {
         debug_assert!(n < self.data.len());
@@ -180,6 +195,13 @@ impl ValueStack {
         self.stack_128.clear();
     }
 
+    /// Moves the topmost values onto another stack, e.g. to pass arguments between continuations.
+    pub(crate) fn move_to(&mut self, other: &mut Self, counts: ValueCounts) -> Result<(), Trap> {
+        self.stack_32.move_to(&mut other.stack_32, counts.c32 as usize)?;
+        self.stack_64.move_to(&mut other.stack_64, counts.c64 as usize)?;
+        self.stack_128.move_to(&mut other.stack_128, counts.c128 as usize)
+    }
+
     #[inline(always)]
     pub(crate) fn base(&self) -> StackBase {
         StackBase {
@@ -254,6 +276,9 @@ impl ValueStack {
             WasmType::F64 => WasmValue::F64(f64::stack_pop(self)),
             WasmType::RefExtern => WasmValue::RefExtern(ExternRef::from_raw(ValueRef::stack_pop(self).raw())),
             WasmType::RefFunc => WasmValue::RefFunc(FuncRef::from_raw(ValueRef::stack_pop(self).raw())),
+            WasmType::RefCont => WasmValue::RefCont(ContRef::from_raw(ValueRef::stack_pop(self).raw())),
+            WasmType::RefExn => WasmValue::RefExn(ExnRef::from_raw(ValueRef::stack_pop(self).raw())),
+            WasmType::RefAny => WasmValue::RefAny(AnyRef::from_raw(ValueRef::stack_pop(self).raw())),
             WasmType::V128 => WasmValue::V128(Value128::stack_pop(self).0),
         }
     }
@@ -267,6 +292,9 @@ impl ValueStack {
                 WasmValue::F64(v) => self.stack_64.push(v.to_bits())?,
                 WasmValue::RefExtern(v) => self.stack_32.push(v.raw())?,
                 WasmValue::RefFunc(v) => self.stack_32.push(v.raw())?,
+                WasmValue::RefCont(v) => self.stack_32.push(v.raw())?,
+                WasmValue::RefExn(v) => self.stack_32.push(v.raw())?,
+                WasmValue::RefAny(v) => self.stack_32.push(v.raw())?,
                 WasmValue::V128(v) => self.stack_128.push((*v).into())?,
             }
         }
@@ -1,6 +1,6 @@
 use super::stack::{CallFrame, ValueStack};
 use crate::{Result, interpreter::simd::Value128};
-use tinywasm_types::{ExternRef, FuncRef, LocalAddr, WasmType, WasmValue};
+use tinywasm_types::{AnyRef, ContRef, ExnRef, ExternRef, FuncRef, LocalAddr, WasmType, WasmValue};
 
 pub(crate) type Value32 = u32;
 pub(crate) type Value64 = u64;
@@ -103,10 +103,15 @@ impl TinyWasmValue {
             (Self::Value64(v), WasmType::F64) => Some(WasmValue::F64(f64::from_bits(v))),
             (Self::ValueRef(v), WasmType::RefExtern) => Some(WasmValue::RefExtern(ExternRef::from_raw(v.raw()))),
             (Self::ValueRef(v), WasmType::RefFunc) => Some(WasmValue::RefFunc(FuncRef::from_raw(v.raw()))),
+            (Self::ValueRef(v), WasmType::RefCont) => Some(WasmValue::RefCont(ContRef::from_raw(v.raw()))),
+            (Self::ValueRef(v), WasmType::RefExn) => Some(WasmValue::RefExn(ExnRef::from_raw(v.raw()))),
+            (Self::ValueRef(v), WasmType::RefAny) => Some(WasmValue::RefAny(AnyRef::from_raw(v.raw()))),
             (Self::Value128(v), WasmType::V128) => Some(WasmValue::V128(v.0)),
             (_, WasmType::I32 | WasmType::F32) => None,
             (_, WasmType::I64 | WasmType::F64) => None,
-            (_, WasmType::RefExtern | WasmType::RefFunc) => None,
+            (_, WasmType::RefExtern | WasmType::RefFunc | WasmType::RefCont | WasmType::RefExn | WasmType::RefAny) => {
+                None
+            }
             (_, WasmType::V128) => None,
         }
     }
@@ -121,6 +126,9 @@ impl From<&WasmValue> for TinyWasmValue {
             WasmValue::F64(v) => Self::Value64(v.to_bits()),
             WasmValue::RefExtern(v) => Self::ValueRef(ValueRef::from_addr(v.addr())),
             WasmValue::RefFunc(v) => Self::ValueRef(ValueRef::from_addr(v.addr())),
+            WasmValue::RefCont(v) => Self::ValueRef(ValueRef::from_addr(v.addr())),
+            WasmValue::RefExn(v) => Self::ValueRef(ValueRef::from_addr(v.addr())),
+            WasmValue::RefAny(v) => Self::ValueRef(ValueRef::from_raw(v.raw())),
             WasmValue::V128(v) => Self::Value128((*v).into()),
         }
     }
@@ -29,6 +29,8 @@
 //!   Parallelizes function parsing and validation across threads when `std` is enabled. Enabled by default.
 //! - **`guest-debug`**\
 //!   Exposes module-internal by-index inspection APIs (`*_by_index`).
+//! - **`macros`**\
+//!   Enables the `#[host_module]` attribute for defining a [`HostModule`] from an `impl` block.
 //! - **`simd-x86`**\
 //!   Enables x86-specific SIMD intrinsics for selected operations and uses `unsafe` internally.
 //!
@@ -107,26 +109,44 @@ pub(crate) mod log {
 mod error;
 pub use error::*;
 pub use func::{
-    ExecProgress, FuncContext, FuncExecution, FuncExecutionTyped, Function, FunctionTyped, HostFunction, ToWasmTypes,
-    WasmTupleChain,
+    CallFuture, CallFutureTyped, DetachedExecution, DetachedExecutionTyped, ExecProgress, FuncContext, FuncExecution,
+    FuncExecutionTyped, Function, FunctionTyped, HostFunction, ToWasmTypes, WasmTupleChain,
 };
+pub use guest_alloc::{GuestAllocator, GuestBuffer};
 pub use imports::*;
-pub use instance::{ExternItem, ModuleInstance};
+pub use instance::{ExternItem, Instantiation, ModuleInstance};
+pub use memory_view::{MemoryView, WasmAddress, WasmPtr, WasmPtr64, WasmRepr};
 pub use reference::*;
 pub use store::*;
 
 mod func;
+mod guest_alloc;
 mod imports;
 mod instance;
+mod memory_view;
 mod reference;
 mod store;
 
 mod interpreter;
 use interpreter::InterpreterRuntime;
 
+#[cfg(feature = "macros")]
+pub use tinywasm_macros::host_module;
+
+// used by the code generated by `host_module`
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::string::String;
+    pub use alloc::vec;
+    pub use alloc::vec::Vec;
+}
+
 /// Global configuration for the WebAssembly interpreter
 pub mod engine;
-pub use engine::{Engine, LazyLinearMemory, LinearMemory, MemoryBackend, PagedMemory, StackConfig, VecMemory};
+pub use engine::{
+    DirtyTrackingMemory, Engine, LazyLinearMemory, LinearMemory, MemoryBackend, PagedMemory, StackConfig, VecMemory,
+};
 
 #[cfg(feature = "parser")]
 /// Re-export of [`tinywasm_parser`]. Requires `parser` feature.
@@ -0,0 +1,418 @@
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+
+use tinywasm_types::{WasmType, WasmValue};
+
+use crate::func::{FromWasmValues, IntoWasmValues, ToWasmType, ToWasmTypes};
+use crate::{Error, MemoryInstance, Result};
+
+/// A value with a fixed-size little-endian encoding in linear memory.
+///
+/// Values are read and written byte by byte, so they don't need to be aligned in guest memory.
+/// Implemented for the primitive integer and float types, arrays and [`WasmPtr`]s. Structs can
+/// implement it by encoding their fields one after another:
+///
+/// ```rust
+/// use tinywasm::WasmRepr;
+///
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// impl WasmRepr for Point {
+///     const SIZE: usize = 8;
+///
+///     fn read_le(bytes: &[u8]) -> Self {
+///         Self { x: i32::read_le(&bytes[..4]), y: i32::read_le(&bytes[4..]) }
+///     }
+///
+///     fn write_le(&self, bytes: &mut [u8]) {
+///         self.x.write_le(&mut bytes[..4]);
+///         self.y.write_le(&mut bytes[4..]);
+///     }
+/// }
+/// ```
+pub trait WasmRepr: Sized {
+    /// The size of the encoded value in bytes.
+    const SIZE: usize;
+
+    /// Decodes a value from exactly [`Self::SIZE`] bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+
+    /// Encodes the value into exactly [`Self::SIZE`] bytes.
+    fn write_le(&self, bytes: &mut [u8]);
+}
+
+macro_rules! impl_wasm_repr {
+    ($($T:ty),* $(,)?) => {
+        $(
+            impl WasmRepr for $T {
+                const SIZE: usize = core::mem::size_of::<$T>();
+
+                #[inline]
+                fn read_le(bytes: &[u8]) -> Self {
+                    let mut buf = [0; core::mem::size_of::<$T>()];
+                    buf.copy_from_slice(bytes);
+                    <$T>::from_le_bytes(buf)
+                }
+
+                #[inline]
+                fn write_le(&self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_wasm_repr!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f32, f64);
+
+impl<T: WasmRepr, const N: usize> WasmRepr for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn read_le(bytes: &[u8]) -> Self {
+        core::array::from_fn(|i| T::read_le(&bytes[i * T::SIZE..(i + 1) * T::SIZE]))
+    }
+
+    fn write_le(&self, bytes: &mut [u8]) {
+        for (i, value) in self.iter().enumerate() {
+            value.write_le(&mut bytes[i * T::SIZE..(i + 1) * T::SIZE]);
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// The address type of a [`WasmPtr`]: `u32` for 32-bit memories and `u64` for memory64.
+///
+/// This trait is sealed and can't be implemented outside of `tinywasm`.
+pub trait WasmAddress: sealed::Sealed + WasmRepr + Copy + Eq + Hash + Default {
+    /// The WebAssembly value type guests pass the address as.
+    const WASM_TYPE: WasmType;
+
+    #[doc(hidden)]
+    fn to_u64(self) -> u64;
+    #[doc(hidden)]
+    fn from_u64(value: u64) -> Option<Self>;
+    #[doc(hidden)]
+    fn to_wasm_value(self) -> WasmValue;
+    #[doc(hidden)]
+    fn from_wasm_value(value: WasmValue) -> Option<Self>;
+}
+
+impl WasmAddress for u32 {
+    const WASM_TYPE: WasmType = WasmType::I32;
+
+    fn to_u64(self) -> u64 {
+        u64::from(self)
+    }
+
+    fn from_u64(value: u64) -> Option<Self> {
+        u32::try_from(value).ok()
+    }
+
+    fn to_wasm_value(self) -> WasmValue {
+        WasmValue::I32(self as i32)
+    }
+
+    fn from_wasm_value(value: WasmValue) -> Option<Self> {
+        value.as_i32().map(|value| value as u32)
+    }
+}
+
+impl WasmAddress for u64 {
+    const WASM_TYPE: WasmType = WasmType::I64;
+
+    fn to_u64(self) -> u64 {
+        self
+    }
+
+    fn from_u64(value: u64) -> Option<Self> {
+        Some(value)
+    }
+
+    fn to_wasm_value(self) -> WasmValue {
+        WasmValue::I64(self as i64)
+    }
+
+    fn from_wasm_value(value: WasmValue) -> Option<Self> {
+        value.as_i64().map(|value| value as u64)
+    }
+}
+
+/// A typed pointer into the linear memory of a guest.
+///
+/// `A` is the address type, `u32` by default and `u64` for memory64 modules (see [`WasmPtr64`]).
+/// Pointers can be used as parameters and results of typed host functions, where the guest passes
+/// them as an `i32` or `i64`, and are dereferenced through a [`MemoryView`].
+///
+/// ## Example
+/// ```rust
+/// # fn main() -> tinywasm::Result<()> {
+/// use tinywasm::{FuncContext, HostFunction, Imports, ModuleInstance, Store, WasmPtr};
+/// # let wasm = wat::parse_str(r#"
+/// #     (module
+/// #       (import "env" "sum" (func $sum (param i32 i32) (result i64)))
+/// #       (memory (export "memory") 1)
+/// #       (data (i32.const 3) "\01\00\00\00\02\00\00\00\03\00\00\00")
+/// #       (func (export "run") (result i64) (call $sum (i32.const 3) (i32.const 3))))
+/// # "#).expect("valid wat");
+/// # let module = tinywasm::parse_bytes(&wasm)?;
+///
+/// let mut store = Store::default();
+/// let sum = HostFunction::from(
+///     &mut store,
+///     |mut ctx: FuncContext<'_>, (values, len): (WasmPtr<u32>, i32)| {
+///         let view = ctx.memory("memory")?.view(&mut ctx)?;
+///         Ok(view.read_array(values, len as usize)?.into_iter().map(i64::from).sum::<i64>())
+///     },
+/// );
+///
+/// let mut imports = Imports::new();
+/// imports.define("env", "sum", sum);
+/// let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+/// assert_eq!(instance.func::<(), i64>(&store, "run")?.call(&mut store, ())?, 6);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WasmPtr<T, A: WasmAddress = u32> {
+    offset: A,
+    marker: PhantomData<fn() -> T>,
+}
+
+/// A [`WasmPtr`] into a 64-bit memory.
+pub type WasmPtr64<T> = WasmPtr<T, u64>;
+
+impl<T, A: WasmAddress> WasmPtr<T, A> {
+    /// Creates a pointer to the byte offset `offset`.
+    pub const fn new(offset: A) -> Self {
+        Self { offset, marker: PhantomData }
+    }
+
+    /// Returns the byte offset this pointer points to.
+    pub const fn offset(self) -> A {
+        self.offset
+    }
+
+    /// Returns true if this is a null pointer.
+    pub fn is_null(self) -> bool {
+        self.offset == A::default()
+    }
+
+    /// Reinterprets the pointer as a pointer to a `U`.
+    pub const fn cast<U>(self) -> WasmPtr<U, A> {
+        WasmPtr::new(self.offset)
+    }
+}
+
+impl<T: WasmRepr, A: WasmAddress> WasmPtr<T, A> {
+    /// Returns a pointer to the `count`th `T` after this one, or `None` if the address overflows.
+    pub fn checked_add(self, count: u64) -> Option<Self> {
+        let offset = (T::SIZE as u64).checked_mul(count)?.checked_add(self.offset.to_u64())?;
+        A::from_u64(offset).map(Self::new)
+    }
+
+    /// Reads the value this pointer points to.
+    pub fn read(self, view: &MemoryView<'_>) -> Result<T> {
+        view.read(self)
+    }
+
+    /// Writes `value` to the address this pointer points to.
+    pub fn write(self, view: &mut MemoryView<'_>, value: &T) -> Result<()> {
+        view.write(self, value)
+    }
+}
+
+impl<T, A: WasmAddress> Clone for WasmPtr<T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, A: WasmAddress> Copy for WasmPtr<T, A> {}
+
+impl<T, A: WasmAddress> PartialEq for WasmPtr<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T, A: WasmAddress> Eq for WasmPtr<T, A> {}
+
+impl<T, A: WasmAddress> Hash for WasmPtr<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.offset.hash(state);
+    }
+}
+
+impl<T, A: WasmAddress> Default for WasmPtr<T, A> {
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+#[cfg(feature = "debug")]
+impl<T, A: WasmAddress + core::fmt::Debug> core::fmt::Debug for WasmPtr<T, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("WasmPtr").field(&self.offset).finish()
+    }
+}
+
+impl<T, A: WasmAddress> WasmRepr for WasmPtr<T, A> {
+    const SIZE: usize = A::SIZE;
+
+    fn read_le(bytes: &[u8]) -> Self {
+        Self::new(A::read_le(bytes))
+    }
+
+    fn write_le(&self, bytes: &mut [u8]) {
+        self.offset.write_le(bytes);
+    }
+}
+
+impl<T, A: WasmAddress> ToWasmType for WasmPtr<T, A> {
+    const WASM_TYPE: WasmType = A::WASM_TYPE;
+}
+
+impl<T, A: WasmAddress> ToWasmTypes for WasmPtr<T, A> {
+    const WASM_TYPES: Option<&'static [WasmType]> = Some(&[A::WASM_TYPE]);
+}
+
+impl<T, A: WasmAddress> From<WasmPtr<T, A>> for WasmValue {
+    fn from(ptr: WasmPtr<T, A>) -> Self {
+        ptr.offset.to_wasm_value()
+    }
+}
+
+impl<T, A: WasmAddress> TryFrom<WasmValue> for WasmPtr<T, A> {
+    type Error = ();
+
+    fn try_from(value: WasmValue) -> core::result::Result<Self, Self::Error> {
+        A::from_wasm_value(value).map(Self::new).ok_or(())
+    }
+}
+
+impl<T, A: WasmAddress> IntoWasmValues for WasmPtr<T, A> {
+    fn into_wasm_values(self) -> Vec<WasmValue> {
+        vec![self.into()]
+    }
+}
+
+impl<T, A: WasmAddress> FromWasmValues for WasmPtr<T, A> {
+    fn from_wasm_values(values: &[WasmValue]) -> Result<Self> {
+        let value = *values.first().ok_or_else(|| Error::other("Not enough elements in &[WasmValue]"))?;
+        Self::try_from(value).map_err(|_| {
+            Error::Other(format!("FromWasmValues: Could not convert {value:?} to a pointer of type {}", A::WASM_TYPE))
+        })
+    }
+}
+
+/// Typed access to the contents of a [`crate::Memory`].
+///
+/// Created with [`crate::Memory::view`]. All accesses are bounds checked and fail with
+/// [`crate::Trap::MemoryOutOfBounds`], or [`crate::Trap::MemoryProtectionViolation`] for ranges protected with
+/// [`crate::Memory::protect`].
+pub struct MemoryView<'a> {
+    memory: &'a mut MemoryInstance,
+}
+
+impl<'a> MemoryView<'a> {
+    pub(crate) fn new(memory: &'a mut MemoryInstance) -> Self {
+        Self { memory }
+    }
+
+    /// Returns the memory length in bytes.
+    pub fn len(&self) -> usize {
+        self.memory.inner.len()
+    }
+
+    /// Returns true if the memory is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the `T` at `ptr`.
+    pub fn read<T: WasmRepr, A: WasmAddress>(&self, ptr: WasmPtr<T, A>) -> Result<T> {
+        self.with_bytes(ptr, 1, T::read_le)
+    }
+
+    /// Reads `len` consecutive `T`s starting at `ptr`.
+    pub fn read_array<T: WasmRepr, A: WasmAddress>(&self, ptr: WasmPtr<T, A>, len: usize) -> Result<Vec<T>> {
+        self.with_bytes(ptr, len, |bytes| {
+            (0..len).map(|i| T::read_le(&bytes[i * T::SIZE..(i + 1) * T::SIZE])).collect()
+        })
+    }
+
+    /// Writes `value` to `ptr`.
+    pub fn write<T: WasmRepr, A: WasmAddress>(&mut self, ptr: WasmPtr<T, A>, value: &T) -> Result<()> {
+        self.write_array(ptr, core::slice::from_ref(value))
+    }
+
+    /// Writes `values` to consecutive `T`s starting at `ptr`.
+    pub fn write_array<T: WasmRepr, A: WasmAddress>(&mut self, ptr: WasmPtr<T, A>, values: &[T]) -> Result<()> {
+        let (offset, len) = self.range(ptr, values.len(), true)?;
+        let encode = |bytes: &mut [u8]| {
+            for (i, value) in values.iter().enumerate() {
+                value.write_le(&mut bytes[i * T::SIZE..(i + 1) * T::SIZE]);
+            }
+        };
+
+        if let Some(bytes) = self.memory.inner.as_mut_slice(offset, len) {
+            encode(bytes);
+            return Ok(());
+        }
+
+        let mut bytes = vec![0; len];
+        encode(&mut bytes);
+        self.memory.inner.write_all(offset, &bytes).ok_or_else(|| self.access_trap(offset, len, true))
+    }
+
+    fn with_bytes<T: WasmRepr, A: WasmAddress, R>(
+        &self,
+        ptr: WasmPtr<T, A>,
+        count: usize,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R> {
+        let (offset, len) = self.range(ptr, count, false)?;
+        if let Some(bytes) = self.memory.inner.as_slice(offset, len) {
+            return Ok(f(bytes));
+        }
+
+        let bytes = self.memory.inner.read_vec(offset, len).ok_or_else(|| self.access_trap(offset, len, false))?;
+        Ok(f(&bytes))
+    }
+
+    /// Returns the byte offset and length of `count` `T`s at `ptr`.
+    fn range<T: WasmRepr, A: WasmAddress>(
+        &self,
+        ptr: WasmPtr<T, A>,
+        count: usize,
+        write: bool,
+    ) -> Result<(usize, usize)> {
+        let offset = usize::try_from(ptr.offset.to_u64()).unwrap_or(usize::MAX);
+        let len = T::SIZE.checked_mul(count).ok_or_else(|| self.access_trap(offset, usize::MAX, write))?;
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => Ok((offset, len)),
+            _ => Err(self.access_trap(offset, len, write)),
+        }
+    }
+
+    fn access_trap(&self, offset: usize, len: usize, write: bool) -> Error {
+        Error::Trap(self.memory.access_trap(offset, len, write))
+    }
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for MemoryView<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryView").field("memory", &self.memory).finish()
+    }
+}
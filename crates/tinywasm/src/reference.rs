@@ -1,16 +1,23 @@
 use core::hint::cold_path;
 
+use alloc::boxed::Box;
 use alloc::ffi::CString;
 use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
-use crate::store::{GlobalInstance, TableElement, TableInstance};
-use crate::{Error, MemoryInstance, Result, Store, Trap};
+use crate::func::{FromWasmValues, IntoWasmValues, ToWasmTypes};
+use crate::interpreter::ValueRef;
+use crate::store::{FunctionInstance, GlobalInstance, TableElement, TableInstance, TagInstance};
+use crate::{Error, Function, FunctionTyped, MemoryInstance, MemoryProtection, MemoryView, Result, Store, Trap};
 use tinywasm_types::{
-    Addr, ExternRef, FuncRef, GlobalAddr, GlobalType, MemAddr, MemoryType, TableAddr, TableType, WasmType, WasmValue,
+    Addr, AnyRef, ExternRef, FuncRef, FuncType, GlobalAddr, GlobalType, HeapType, MemAddr, MemoryType, TableAddr,
+    TableType, WasmType, WasmValue,
 };
 
+const UNTRACKED_MEMORY: &str = "dirty page tracking for a memory backend that doesn't track writes";
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "debug", derive(Debug))]
 pub(crate) struct StoreItem {
@@ -66,6 +73,43 @@ pub struct Table(pub(crate) StoreItem);
 #[cfg_attr(feature = "debug", derive(Debug))]
 pub struct Global(pub(crate) StoreItem);
 
+/// An exception tag in a store.
+///
+/// Tags identify the kind of an [`Exception`]. Two tags are only equal if they
+/// refer to the same tag instance, even if their types match.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct Tag(pub(crate) StoreItem);
+
+/// A WebAssembly exception, thrown by `throw` or by a host function.
+///
+/// Host functions throw an exception by returning it as an error, which WebAssembly
+/// code can catch with `try_table`. Exceptions that are not caught surface as
+/// [`Trap::Exception`].
+///
+/// ## Example
+/// ```rust
+/// # fn main() -> tinywasm::Result<()> {
+/// use tinywasm::types::{FuncType, WasmType, WasmValue};
+/// use tinywasm::{Error, Exception, Store, Tag, Trap};
+///
+/// let mut store = Store::default();
+/// let tag = Tag::new(&mut store, FuncType::new(&[WasmType::I32], &[]));
+/// let exception = Exception::new(&store, tag, &[WasmValue::I32(42)])?;
+///
+/// let Error::Trap(Trap::Exception(exception)) = Error::from(exception) else { unreachable!() };
+/// assert_eq!(exception.tag(), tag);
+/// assert_eq!(exception.payload(), &[WasmValue::I32(42)]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct Exception {
+    pub(crate) tag: Tag,
+    pub(crate) payload: Box<[WasmValue]>,
+}
+
 /// A cursor over a [`Memory`] instance.
 ///
 /// Available with the `std` feature enabled.
@@ -180,6 +224,11 @@ impl Memory {
         Ok(MemoryCursor::new(self.instance_mut(store)?, position))
     }
 
+    /// Creates a [`MemoryView`] for typed access to this memory through [`crate::WasmPtr`]s.
+    pub fn view<'a>(&self, store: &'a mut Store) -> Result<MemoryView<'a>> {
+        Ok(MemoryView::new(self.instance_mut(store)?))
+    }
+
     #[inline]
     fn instance<'a>(&self, store: &'a Store) -> Result<&'a MemoryInstance> {
         self.0.validate_store(store)?;
@@ -221,30 +270,130 @@ impl Memory {
 
     /// Reads exactly `dst.len()` bytes from memory.
     pub fn read_exact(&self, store: &Store, offset: usize, dst: &mut [u8]) -> Result<()> {
-        self.instance(store)?.inner.read_exact(offset, dst).ok_or_else(|| {
-            Error::Trap(crate::Trap::MemoryOutOfBounds {
-                offset,
-                len: dst.len(),
-                max: self.instance(store).unwrap().inner.len(),
-            })
-        })
+        let memory = self.instance(store)?;
+        memory.inner.read_exact(offset, dst).ok_or_else(|| memory.access_trap(offset, dst.len(), false).into())
     }
 
     /// Reads `len` bytes from memory into a newly allocated buffer.
     pub fn read_vec(&self, store: &Store, offset: usize, len: usize) -> Result<Vec<u8>> {
-        self.instance(store)?.inner.read_vec(offset, len).ok_or_else(|| {
-            Error::Trap(crate::Trap::MemoryOutOfBounds { offset, len, max: self.instance(store).unwrap().inner.len() })
-        })
+        let memory = self.instance(store)?;
+        memory.inner.read_vec(offset, len).ok_or_else(|| memory.access_trap(offset, len, false).into())
+    }
+
+    /// Calls `f` with a view of the `len` bytes at `offset`.
+    ///
+    /// The bytes are borrowed without copying when the backend stores the range contiguously (see
+    /// [`crate::LinearMemory::as_slice`]), which [`crate::VecMemory`] always does. Otherwise, they are
+    /// copied into a temporary buffer first.
+    pub fn with_slice<R>(&self, store: &Store, offset: usize, len: usize, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        let memory = self.instance(store)?;
+        if let Some(slice) = memory.inner.as_slice(offset, len) {
+            return Ok(f(slice));
+        }
+
+        let bytes = memory.inner.read_vec(offset, len).ok_or_else(|| memory.access_trap(offset, len, false))?;
+        Ok(f(&bytes))
+    }
+
+    /// Calls `f` with a mutable view of the `len` bytes at `offset`.
+    ///
+    /// Like [`Self::with_slice`], this borrows the memory directly when possible. Otherwise, `f` gets
+    /// a temporary copy of the range, which is written back to memory after `f` returns.
+    pub fn with_slice_mut<R>(
+        &self,
+        store: &mut Store,
+        offset: usize,
+        len: usize,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Result<R> {
+        let memory = self.instance_mut(store)?;
+        if let Some(slice) = memory.inner.as_mut_slice(offset, len) {
+            return Ok(f(slice));
+        }
+
+        // check write access before `f` runs, so a protected range isn't read for nothing
+        let Some(mut bytes) = memory.inner.read_vec(offset, len).filter(|_| memory.writable(offset, len)) else {
+            return Err(memory.access_trap(offset, len, true).into());
+        };
+        let result = f(&mut bytes);
+        memory.inner.write_all(offset, &bytes).ok_or_else(|| memory.access_trap(offset, len, true))?;
+        Ok(result)
+    }
+
+    /// Returns the indices of the pages written since the last [`Self::clear_dirty`], in ascending order.
+    ///
+    /// Requires a backend that tracks writes, see [`crate::MemoryBackend::track_dirty_pages`].
+    pub fn dirty_pages(&self, store: &Store) -> Result<Vec<usize>> {
+        self.instance(store)?.inner.dirty_pages().ok_or(Error::UnsupportedFeature(UNTRACKED_MEMORY))
+    }
+
+    /// Marks all pages as clean, making the current contents the ones restored by [`Self::reset_dirty`].
+    pub fn clear_dirty(&self, store: &mut Store) -> Result<()> {
+        self.instance_mut(store)?.inner.clear_dirty().ok_or(Error::UnsupportedFeature(UNTRACKED_MEMORY))
+    }
+
+    /// Restores the contents of the pages written since the last [`Self::clear_dirty`], leaving other pages untouched.
+    ///
+    /// The memory keeps its current size. For memories of a module instance, this resets them to their
+    /// contents after instantiation unless [`Self::clear_dirty`] was called since.
+    pub fn reset_dirty(&self, store: &mut Store) -> Result<()> {
+        self.instance_mut(store)?.inner.reset_dirty().ok_or(Error::UnsupportedFeature(UNTRACKED_MEMORY))
+    }
+
+    /// Writes changes to the backend's persistent storage, see [`crate::LinearMemory::flush`].
+    ///
+    /// Memories without persistent storage have nothing to write.
+    pub fn flush(&self, store: &mut Store) -> Result<()> {
+        self.instance_mut(store)?.inner.flush()
+    }
+
+    /// Writes changes to the backend's persistent storage and waits until they reach the storage device.
+    pub fn sync(&self, store: &mut Store) -> Result<()> {
+        self.instance_mut(store)?.inner.sync()
+    }
+
+    /// Zeroes `len` bytes at `offset` and releases the storage backing them where the backend can.
+    ///
+    /// This is the host side of `memory.discard`, but the range doesn't have to be page-aligned. See
+    /// [`crate::LinearMemory::discard`] for which backends free memory.
+    pub fn discard(&self, store: &mut Store, offset: usize, len: usize) -> Result<()> {
+        self.instance_mut(store)?.discard(offset, len)?;
+        Ok(())
+    }
+
+    /// Marks `len` bytes at `offset` as read-only or inaccessible.
+    ///
+    /// Guest loads, stores and bulk memory operations touching the range trap with
+    /// [`crate::Trap::MemoryProtectionViolation`], and host accesses through this handle fail the same way.
+    /// Protecting a range replaces any previous protection of it. Memories without protected ranges
+    /// are accessed without any additional checks. Shared memories can't be protected.
+    pub fn protect(&self, store: &mut Store, offset: usize, len: usize, protection: MemoryProtection) -> Result<()> {
+        self.instance_mut(store)?.protect(offset, len, Some(protection))
+    }
+
+    /// Removes the protections of `len` bytes at `offset`, see [`Self::protect`].
+    pub fn unprotect(&self, store: &mut Store, offset: usize, len: usize) -> Result<()> {
+        self.instance_mut(store)?.protect(offset, len, None)
     }
 
     /// Grow the memory by the given number of pages.
     pub fn grow(&self, store: &mut Store, delta_pages: i64) -> Result<Option<i64>> {
-        self.instance_mut(store)?.grow(delta_pages, true).map_err(Into::into)
+        self.0.validate_store(store)?;
+        store.grow_memory(self.0.addr, delta_pages, true, None).map_err(Into::into)
+    }
+
+    /// Returns a [`crate::SharedMemory`] handle for this memory, or `None` if it is not shared.
+    ///
+    /// The handle can be imported into other stores, including stores on other threads.
+    /// Available with the `std` feature enabled.
+    #[cfg(feature = "std")]
+    pub fn shared(&self, store: &Store) -> Result<Option<crate::SharedMemory>> {
+        Ok(self.instance(store)?.shared.clone())
     }
 
     /// Get the current size of the memory in pages.
     pub fn page_count(&self, store: &Store) -> Result<usize> {
-        Ok(self.instance(store)?.page_count)
+        Ok(self.instance(store)?.page_count())
     }
 
     /// Copy a slice of memory to another place in memory.
@@ -255,20 +404,14 @@ impl Memory {
 
     /// Fill a slice of memory with a value.
     pub fn fill(&self, store: &mut Store, offset: usize, len: usize, val: u8) -> Result<()> {
-        self.instance_mut(store)?.inner.fill(offset, len, val).ok_or_else(|| {
-            Error::Trap(crate::Trap::MemoryOutOfBounds { offset, len, max: self.instance(store).unwrap().inner.len() })
-        })
+        let memory = self.instance_mut(store)?;
+        memory.inner.fill(offset, len, val).ok_or_else(|| memory.access_trap(offset, len, true).into())
     }
 
     /// Copies a full slice into memory.
     pub fn copy_from_slice(&self, store: &mut Store, offset: usize, data: &[u8]) -> Result<()> {
-        self.instance_mut(store)?.inner.write_all(offset, data).ok_or_else(|| {
-            Error::Trap(crate::Trap::MemoryOutOfBounds {
-                offset,
-                len: data.len(),
-                max: self.instance(store).unwrap().inner.len(),
-            })
-        })
+        let memory = self.instance_mut(store)?;
+        memory.inner.write_all(offset, data).ok_or_else(|| memory.access_trap(offset, data.len(), true).into())
     }
 
     /// Copies a nul-terminated C string into memory.
@@ -324,7 +467,8 @@ impl Memory {
 fn table_element_to_value(element_type: WasmType, element: TableElement) -> WasmValue {
     match element_type {
         WasmType::RefFunc => WasmValue::RefFunc(FuncRef::new(element.addr())),
-        WasmType::RefExtern => WasmValue::RefExtern(ExternRef::new(element.addr())),
+        WasmType::RefExtern => WasmValue::RefExtern(ExternRef::from_raw(ValueRef::from_addr(element.addr()).raw())),
+        WasmType::RefAny => WasmValue::RefAny(AnyRef::from_raw(ValueRef::from_addr(element.addr()).raw())),
         _ => unreachable!("table element type must be a reference type"),
     }
 }
@@ -333,18 +477,25 @@ fn table_value_to_element(element_type: WasmType, value: WasmValue) -> Result<Ta
     match (element_type, value) {
         (WasmType::RefFunc, WasmValue::RefFunc(func_ref)) => Ok(TableElement::from(func_ref.addr())),
         (WasmType::RefExtern, WasmValue::RefExtern(extern_ref)) => Ok(TableElement::from(extern_ref.addr())),
+        (WasmType::RefAny, WasmValue::RefAny(any_ref)) => {
+            Ok(TableElement::from(ValueRef::from_raw(any_ref.raw()).addr()))
+        }
         _ => Err(Trap::Other("invalid table value type")),
     }
 }
 
 impl Table {
     /// Create a new table in the given store.
-    pub fn new(store: &mut Store, ty: TableType, init: WasmValue) -> Result<Self> {
-        let init = match (ty.element_type, init) {
+    pub fn new(store: &mut Store, ty: TableType, init_value: WasmValue) -> Result<Self> {
+        let init = match (ty.element_type, init_value) {
             (WasmType::RefFunc, WasmValue::RefFunc(func_ref)) => TableElement::from(func_ref.addr()),
             (WasmType::RefExtern, WasmValue::RefExtern(extern_ref)) => TableElement::from(extern_ref.addr()),
+            (WasmType::RefAny, WasmValue::RefAny(any_ref)) => {
+                TableElement::from(ValueRef::from_raw(any_ref.raw()).addr())
+            }
             _ => return Err(Error::other("invalid table init value")),
         };
+        store.gc.hand_over([&init_value]);
         let addr = store.state.tables.len() as TableAddr;
         store.state.tables.push(TableInstance::new_with_init(ty, init)?);
         Ok(Self(StoreItem::new(store.id(), addr)))
@@ -377,6 +528,51 @@ impl Table {
         Ok(self.instance(store)?.get_wasm_val(index as usize)?)
     }
 
+    /// Get a function handle for a `funcref` table element, or `None` for a null element.
+    pub fn get_func(&self, store: &Store, index: TableAddr) -> Result<Option<Function>> {
+        match self.get(store, index)? {
+            WasmValue::RefFunc(func_ref) => Function::from_funcref(store, func_ref),
+            _ => {
+                cold_path();
+                Err(Error::other("table does not contain function references"))
+            }
+        }
+    }
+
+    /// Get a typed function handle for a `funcref` table element, or `None` for a null element.
+    ///
+    /// Returns an error if the function's signature does not match `P` and `R`.
+    pub fn get_typed_func<P: IntoWasmValues + ToWasmTypes, R: FromWasmValues + ToWasmTypes>(
+        &self,
+        store: &Store,
+        index: TableAddr,
+    ) -> Result<Option<FunctionTyped<P, R>>> {
+        self.get_func(store, index)?.map(Function::typed).transpose()
+    }
+
+    /// Set a table element to a function.
+    ///
+    /// Unlike [`Table::set`] with a [`WasmValue::RefFunc`], this checks that the function
+    /// belongs to the same store and matches the table's precise element type.
+    pub fn set_func(&self, store: &mut Store, index: TableAddr, func: &Function) -> Result<()> {
+        func.item.validate_store(store)?;
+        let element_type = self.instance(store)?.kind.precise_element_type();
+        let matches = match element_type.map(|ty| ty.heap_type) {
+            Some(HeapType::Func) => true,
+            Some(HeapType::Concrete(id)) => match store.state.get_func(func.addr) {
+                FunctionInstance::Wasm(wasm_func) => store.types.is_subtype(wasm_func.type_id, id),
+                FunctionInstance::Host(host_func) => store.types.matches_func_type(id, &host_func.ty),
+            },
+            _ => false,
+        };
+
+        if !matches {
+            cold_path();
+            return Err(Error::other("function does not match the table's element type"));
+        }
+        Ok(self.instance_mut(store)?.set(index as usize, TableElement::from(Some(func.addr)))?)
+    }
+
     /// Load a range of table elements and iterate over wasm reference values.
     pub fn load(&self, store: &Store, offset: usize, len: usize) -> Result<alloc::vec::IntoIter<WasmValue>> {
         let table = self.instance(store)?;
@@ -391,10 +587,24 @@ impl Table {
     }
 
     /// Set a table element.
+    ///
+    /// Returns an error if the value does not match the table's precise element type.
     pub fn set(&self, store: &mut Store, index: TableAddr, value: WasmValue) -> Result<(), Trap> {
-        let table = self.instance_mut(store)?;
-        let value = table_value_to_element(table.kind.element_type, value)?;
-        table.set(index as usize, value)
+        let element = self.check_value(store, value)?;
+        store.gc.hand_over([&value]);
+        self.instance_mut(store)?.set(index as usize, element)
+    }
+
+    /// Convert a value from the host to a table element, checking it against the precise element type.
+    fn check_value(&self, store: &Store, value: WasmValue) -> Result<TableElement, Trap> {
+        self.0.validate_store(store)?;
+        let kind = store.state.get_table(self.0.addr).kind;
+        let element = table_value_to_element(kind.element_type, value)?;
+        if !store.host_ref_matches(&value, kind.precise_element_type()) {
+            cold_path();
+            return Err(Trap::Other("table value does not match the table's element type"));
+        }
+        Ok(element)
     }
 
     /// Copy elements within the same table.
@@ -404,12 +614,11 @@ impl Table {
 
     /// Grow the table and return the previous size.
     pub fn grow(&self, store: &mut Store, delta: i32, init: WasmValue) -> Result<usize> {
-        self.0.validate_store(store)?;
-        let table = store.state.get_table_mut(self.0.addr);
-        let old_size = table.size();
-        let init = table_value_to_element(table.kind.element_type, init)?;
+        let element = self.check_value(store, init)?;
+        store.gc.hand_over([&init]);
+        let old_size = store.state.get_table(self.0.addr).size();
         let delta = usize::try_from(delta).map_err(|_| Trap::TableOutOfBounds { offset: 0, len: 1, max: old_size })?;
-        table.grow(delta, init)?;
+        store.grow_table(self.0.addr, delta, element, None)?;
         Ok(old_size)
     }
 }
@@ -421,6 +630,7 @@ impl Global {
             cold_path();
             return Err(Error::Other("invalid global value type".to_string()));
         }
+        store.gc.hand_over([&value]);
         let addr = store.state.globals.len() as GlobalAddr;
         store.state.globals.push(GlobalInstance::new(ty, value.into()));
         Ok(Self(StoreItem::new(store.id(), addr)))
@@ -452,7 +662,7 @@ impl Global {
 
     /// Set the current value of the global.
     pub fn set(&self, store: &mut Store, value: WasmValue) -> Result<()> {
-        let global = self.instance_mut(store)?;
+        let global = self.instance(store)?;
         if !global.ty.mutable {
             cold_path();
             return Err(Error::Other("global is immutable".to_string()));
@@ -461,7 +671,66 @@ impl Global {
             cold_path();
             return Err(Error::Other("invalid global value type".to_string()));
         }
-        global.value = value.into();
+        if !store.host_ref_matches(&value, global.ty.precise_ref_type()) {
+            cold_path();
+            return Err(Error::Other("global value does not match the global's reference type".to_string()));
+        }
+        store.gc.hand_over([&value]);
+        self.instance_mut(store)?.value = value.into();
         Ok(())
     }
 }
+
+impl Tag {
+    /// Create a new tag in the given store.
+    ///
+    /// The parameters of `ty` are the payload of exceptions with this tag. Tags used
+    /// for exceptions have no results.
+    pub fn new(store: &mut Store, ty: FuncType) -> Self {
+        let addr = store.state.add_tag(TagInstance { ty: Arc::new(ty), type_id: None });
+        Self(StoreItem::new(store.id(), addr))
+    }
+
+    #[inline]
+    fn instance<'a>(&self, store: &'a Store) -> Result<&'a TagInstance> {
+        self.0.validate_store(store)?;
+        Ok(store.state.get_tag(self.0.addr))
+    }
+
+    /// Get the type of the tag.
+    pub fn ty(&self, store: &Store) -> Result<Arc<FuncType>> {
+        Ok(self.instance(store)?.ty.clone())
+    }
+}
+
+impl Exception {
+    /// Create a new exception with the given tag and payload.
+    ///
+    /// Returns an error if the payload does not match the parameters of the tag's type.
+    pub fn new(store: &Store, tag: Tag, payload: &[WasmValue]) -> Result<Self> {
+        let ty = &tag.instance(store)?.ty;
+        if ty.params().len() != payload.len()
+            || ty.params().iter().zip(payload).any(|(ty, value)| WasmType::from(*value) != *ty)
+        {
+            cold_path();
+            return Err(Error::Other("exception payload does not match tag type".to_string()));
+        }
+        Ok(Self { tag, payload: payload.into() })
+    }
+
+    /// Get the tag of the exception.
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    /// Get the payload of the exception.
+    pub fn payload(&self) -> &[WasmValue] {
+        &self.payload
+    }
+}
+
+impl From<Exception> for Error {
+    fn from(value: Exception) -> Self {
+        Self::Trap(Trap::Exception(Box::new(value)))
+    }
+}
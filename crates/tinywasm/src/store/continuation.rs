@@ -0,0 +1,88 @@
+use tinywasm_types::FuncAddr;
+
+use crate::interpreter::stack::{CallFrame, Stacks};
+
+/// A continuation created by `cont.new` or by suspending a running continuation.
+///
+/// Each continuation can only be resumed once, after which it is [`ContinuationInstance::Consumed`].
+/// Continuations are collected together with the GC heap, freeing their slot once no reference to them is left.
+///
+/// See <https://github.com/WebAssembly/stack-switching/blob/main/proposals/stack-switching/Explainer.md>
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) enum ContinuationInstance {
+    /// A continuation that has not started yet. Its value stack holds the arguments bound with `cont.bind`.
+    Fresh {
+        func: FuncAddr,
+        stacks: Stacks,
+    },
+    /// A suspended continuation that continues at `cf`. Its handler stack holds the handlers
+    /// of `resume` instructions that were active inside of it when it was suspended.
+    Suspended {
+        cf: CallFrame,
+        stacks: Stacks,
+    },
+    Consumed,
+    /// A slot freed by the garbage collector, reused by the next continuation.
+    Free,
+}
+
+impl ContinuationInstance {
+    pub(crate) fn stacks_mut(&mut self) -> Option<&mut Stacks> {
+        match self {
+            Self::Fresh { stacks, .. } | Self::Suspended { stacks, .. } => Some(stacks),
+            Self::Consumed | Self::Free => None,
+        }
+    }
+
+    pub(crate) fn stacks(&self) -> Option<&Stacks> {
+        match self {
+            Self::Fresh { stacks, .. } | Self::Suspended { stacks, .. } => Some(stacks),
+            Self::Consumed | Self::Free => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModuleInstance, Store};
+
+    const WASM: &str = r#"
+        (module
+          (type $fv (func))
+          (type $cv (cont $fv))
+          (tag $yield)
+
+          (func $gen (suspend $yield) (suspend $yield))
+          (elem declare func $gen)
+
+          (func (export "abandon-fresh") (param $n i32)
+            (loop $next
+              (drop (cont.new $cv (ref.func $gen)))
+              (br_if $next (local.tee $n (i32.sub (local.get $n) (i32.const 1))))))
+
+          (func (export "abandon-suspended") (param $n i32)
+            (loop $next
+              (block $on_yield (result (ref $cv))
+                (resume $cv (on $yield $on_yield) (cont.new $cv (ref.func $gen)))
+                (unreachable))
+              (drop)
+              (br_if $next (local.tee $n (i32.sub (local.get $n) (i32.const 1)))))))
+    "#;
+
+    #[test]
+    fn unreachable_continuations_are_freed() {
+        let module = crate::parse_bytes(&wat::parse_str(WASM).unwrap()).unwrap();
+        let mut store = Store::default();
+        let instance = ModuleInstance::instantiate(&mut store, &module, None).unwrap();
+
+        for name in ["abandon-fresh", "abandon-suspended"] {
+            instance.func::<i32, ()>(&store, name).unwrap().call(&mut store, 10_000).unwrap();
+            assert!(store.state.continuations.len() < 100, "{name} kept {} slots", store.state.continuations.len());
+            assert!(store.spare_stacks.len() <= super::super::MAX_SPARE_STACKS);
+        }
+
+        store.collect_garbage();
+        assert!(store.state.continuations.iter().all(|cont| matches!(cont, ContinuationInstance::Free)));
+    }
+}
@@ -0,0 +1,59 @@
+use alloc::boxed::Box;
+use tinywasm_types::{TagAddr, WasmValue};
+
+/// A WebAssembly Exception Instance
+///
+/// Created by `throw` or by a host function throwing an [`crate::Exception`].
+/// Exceptions are collected together with the GC heap once no `exnref` to them is left.
+///
+/// See <https://webassembly.github.io/spec/core/exec/runtime.html#exception-instances>
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct ExceptionInstance {
+    pub(crate) tag: TagAddr,
+    pub(crate) payload: Box<[WasmValue]>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ModuleInstance, Store};
+
+    const WASM: &str = r#"
+        (module
+          (tag $e (param i32))
+
+          (func (export "throw-and-catch") (param $n i32) (result i32)
+            (local $sum i32)
+            (loop $next
+              (block $caught (result i32)
+                (try_table (catch $e $caught) (throw $e (local.get $n)))
+                (unreachable))
+              (local.set $sum (i32.add (local.get $sum)))
+              (br_if $next (local.tee $n (i32.sub (local.get $n) (i32.const 1)))))
+            (local.get $sum))
+
+          (func (export "keep-ref") (result exnref)
+            (block $caught (result exnref)
+              (try_table (catch_all_ref $caught) (throw $e (i32.const 7)))
+              (unreachable))))
+    "#;
+
+    #[test]
+    fn caught_exceptions_are_freed() {
+        let module = crate::parse_bytes(&wat::parse_str(WASM).unwrap()).unwrap();
+        let mut store = Store::default();
+        let instance = ModuleInstance::instantiate(&mut store, &module, None).unwrap();
+
+        let sum = instance.func::<i32, i32>(&store, "throw-and-catch").unwrap().call(&mut store, 100_000).unwrap();
+        assert_eq!(sum, (1..=100_000).fold(0i32, |sum, n| sum.wrapping_add(n)));
+        assert!(store.state.exceptions.len() < 100_000);
+
+        let exn = instance.func_untyped(&store, "keep-ref").unwrap().call(&mut store, &[]).unwrap();
+        store.pin(exn[0]);
+        store.collect_garbage();
+        assert_eq!(store.state.exceptions.iter().flatten().count(), 1);
+
+        store.unpin(exn[0]);
+        store.collect_garbage();
+        assert_eq!(store.state.exceptions.iter().flatten().count(), 0);
+    }
+}
@@ -31,6 +31,8 @@ impl FunctionInstance {
 pub(crate) struct WasmFunctionInstance {
     pub(crate) func: Arc<WasmFunction>,
     pub(crate) owner: ModuleInstanceAddr,
+    /// The canonical id of the function's type.
+    pub(crate) type_id: u32,
 }
 
 impl WasmFunctionInstance {
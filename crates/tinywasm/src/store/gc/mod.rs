@@ -0,0 +1,1022 @@
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, vec::Vec};
+use core::any::Any;
+use core::hint::cold_path;
+use tinywasm_types::*;
+
+use crate::interpreter::stack::{Stacks, ValueStack};
+use crate::interpreter::{TinyWasmValue, ValueRef};
+use crate::{
+    ContinuationInstance, Error, ExceptionInstance, FunctionInstance, ModuleInstance, Result, Store, StoreItem, Trap,
+};
+
+mod types;
+pub(crate) use types::*;
+
+/// `externref`s at or above this value refer to an externalized `anyref` in the GC heap,
+/// or from [`HOST_BASE`] on to a host value created with [`Store::new_extern_ref`].
+///
+/// References created by the host with [`ExternRef::new`] stay below it.
+pub(crate) const EXTERNALIZED_BASE: u32 = ExternRef::MAX_ADDR + 1;
+
+/// `externref`s at or above this value refer to a host value: the low [`HOST_SLOT_BITS`] are the index
+/// of its slot, and the bits above the generation of the slot when the value was created.
+const HOST_BASE: u32 = 0xC000_0000;
+const HOST_SLOT_BITS: u32 = 24;
+/// Slots are retired once their generations are used up, so a stale reference never refers to a newer value.
+const HOST_GENERATIONS: u32 = 1 << (30 - HOST_SLOT_BITS);
+/// The last slot is never used, as its last generation would be the null reference.
+const MAX_HOST_SLOTS: usize = (1 << HOST_SLOT_BITS) - 1;
+
+/// Bookkeeping cost of an object that is counted on top of its data.
+const OBJECT_OVERHEAD: usize = 32;
+
+/// Get the index of the GC object a raw reference could point to.
+///
+/// This is conservative: it is used for values of unknown type, so it also returns
+/// indices for references of other kinds (e.g. function references) and `i31ref`s.
+#[inline]
+const fn heap_index(raw: u32) -> Option<u32> {
+    if AnyRef::from_raw(raw).heap_index().is_some() {
+        Some(raw)
+    } else if raw >= EXTERNALIZED_BASE && raw < HOST_BASE {
+        Some(raw - EXTERNALIZED_BASE)
+    } else {
+        None
+    }
+}
+
+/// Decode a raw reference that could point to a host value into its slot index and generation.
+#[inline]
+const fn host_slot(raw: u32) -> Option<(usize, u32)> {
+    if raw >= HOST_BASE && raw != ValueRef::NULL.raw() {
+        let bits = raw - HOST_BASE;
+        Some(((bits & ((1 << HOST_SLOT_BITS) - 1)) as usize, bits >> HOST_SLOT_BITS))
+    } else {
+        None
+    }
+}
+
+/// Whether a raw reference could keep an object or host value alive.
+#[inline]
+const fn is_root(raw: u32) -> bool {
+    heap_index(raw).is_some() || host_slot(raw).is_some()
+}
+
+/// A slot for a value owned by the host, see [`Store::new_extern_ref`].
+#[derive(Default)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+struct HostSlot {
+    /// Incremented whenever the slot is freed, so references to its earlier values no longer match.
+    generation: u32,
+    live: bool,
+    /// Whether the reference returned by [`Store::new_extern_ref`] keeps the value alive,
+    /// until it is handed to WebAssembly or released.
+    held: bool,
+    /// The value, or `None` once it was released with [`Store::release_extern_ref`].
+    value: Option<Box<dyn Any>>,
+}
+
+impl HostSlot {
+    fn size(&self) -> usize {
+        OBJECT_OVERHEAD + self.value.as_ref().map_or(0, |value| size_of_val(&**value))
+    }
+}
+
+/// An object in the GC heap.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) enum GcObject {
+    Free,
+    /// A struct, with its fields stored as little-endian bytes at the offsets of its [`Layout`].
+    Struct {
+        ty: u32,
+        data: Box<[u8]>,
+    },
+    /// An array, with its elements stored as little-endian bytes.
+    Array {
+        ty: u32,
+        len: u32,
+        data: Box<[u8]>,
+    },
+    /// An `externref` converted to an `anyref` by `any.convert_extern`.
+    Internalized(u32),
+    /// An `anyref` converted to an `externref` by `extern.convert_any`.
+    Externalized(u32),
+}
+
+impl GcObject {
+    fn size(&self) -> usize {
+        match self {
+            Self::Free => 0,
+            Self::Struct { data, .. } | Self::Array { data, .. } => OBJECT_OVERHEAD + data.len(),
+            Self::Internalized(_) | Self::Externalized(_) => OBJECT_OVERHEAD,
+        }
+    }
+}
+
+/// The store's garbage collected heap, holding structs, arrays, converted references and host values.
+///
+/// Objects are never moved, so a reference to an object is its index in the heap.
+/// Memory is reclaimed by a mark and sweep collector that treats the value stacks as
+/// conservative roots, and globals, tables and element segments as precise ones.
+///
+/// Continuations and exceptions live in the store, but are traced and freed by the same collections.
+/// Their references are small addresses, so every traced value is also treated as a
+/// potential reference to one of them.
+#[derive(Default)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct GcHeap {
+    objects: Vec<GcObject>,
+    free: Vec<u32>,
+    marks: Vec<bool>,
+    hosts: Vec<HostSlot>,
+    free_hosts: Vec<u32>,
+    host_marks: Vec<bool>,
+    live_objects: usize,
+    /// Continuations and exceptions that are tracked by the heap, see [`GcHeap::track`].
+    tracked_objects: usize,
+    live_bytes: usize,
+    /// Bytes allocated since the last collection.
+    allocated_bytes: usize,
+    /// References pinned by the host.
+    pins: Vec<u32>,
+    /// References passed to host functions that are currently running.
+    pub(crate) host_roots: Vec<u32>,
+    /// The number of host values that are still held by the host, see [`HostSlot::held`].
+    held_hosts: usize,
+    /// The stacks of suspended detached executions, which are not part of the store.
+    suspended: Vec<(Weak<()>, Box<[u32]>)>,
+}
+
+impl GcHeap {
+    /// Add an object to the heap, returning its index.
+    pub(crate) fn alloc(&mut self, object: GcObject) -> Result<u32, Trap> {
+        let size = object.size();
+        self.live_objects += 1;
+        self.live_bytes += size;
+        self.allocated_bytes += size;
+
+        if let Some(idx) = self.free.pop() {
+            self.objects[idx as usize] = object;
+            return Ok(idx);
+        }
+
+        // every object has to fit below the host values when it is externalized
+        let idx = self.objects.len() as u32;
+        if idx >= HOST_BASE - EXTERNALIZED_BASE {
+            cold_path();
+            return Err(Trap::OutOfMemory);
+        }
+        self.objects.push(object);
+        Ok(idx)
+    }
+
+    /// Add a value owned by the host to the heap, returning the raw `externref` to it.
+    fn alloc_host(&mut self, value: Box<dyn Any>) -> Result<u32, Trap> {
+        let slot = match self.free_hosts.pop() {
+            Some(slot) => slot as usize,
+            None if self.hosts.len() < MAX_HOST_SLOTS => {
+                self.hosts.push(HostSlot::default());
+                self.hosts.len() - 1
+            }
+            None => {
+                cold_path();
+                return Err(Trap::OutOfMemory);
+            }
+        };
+
+        let host = &mut self.hosts[slot];
+        host.live = true;
+        host.held = true;
+        host.value = Some(value);
+        self.held_hosts += 1;
+        let size = host.size();
+        self.live_objects += 1;
+        self.live_bytes += size;
+        self.allocated_bytes += size;
+        Ok(HOST_BASE + (host.generation << HOST_SLOT_BITS) + slot as u32)
+    }
+
+    /// Get the live host value slot a raw `externref` refers to.
+    fn host(&self, raw: u32) -> Option<&HostSlot> {
+        let (slot, generation) = host_slot(raw)?;
+        self.hosts.get(slot).filter(|host| host.live && host.generation == generation)
+    }
+
+    fn host_mut(&mut self, raw: u32) -> Option<&mut HostSlot> {
+        let (slot, generation) = host_slot(raw)?;
+        self.hosts.get_mut(slot).filter(|host| host.live && host.generation == generation)
+    }
+
+    /// Stop keeping the host values `values` refer to alive, as WebAssembly now holds references to them.
+    pub(crate) fn hand_over<'a>(&mut self, values: impl IntoIterator<Item = &'a WasmValue>) {
+        if self.held_hosts == 0 {
+            return;
+        }
+        for value in values {
+            if let WasmValue::RefExtern(value) = value {
+                self.unhold(value.raw());
+            }
+        }
+    }
+
+    fn unhold(&mut self, raw: u32) {
+        if let Some(host) = self.host_mut(raw).filter(|host| host.held) {
+            host.held = false;
+            self.held_hosts -= 1;
+        }
+    }
+
+    /// Count an object that lives outside of the heap, like a continuation, towards the next collection.
+    pub(crate) fn track(&mut self, size: usize) {
+        self.tracked_objects += 1;
+        self.live_bytes += size;
+        self.allocated_bytes += size;
+    }
+
+    /// Release an object counted with [`GcHeap::track`] that was found to be unreachable.
+    fn untrack(&mut self, size: usize) {
+        self.tracked_objects -= 1;
+        self.live_bytes -= size;
+    }
+
+    /// Whether there is nothing to collect, so roots don't need to be recorded.
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.live_objects == 0 && self.tracked_objects == 0
+    }
+
+    /// Allocate a struct with all fields set to their default value.
+    pub(crate) fn alloc_struct(&mut self, types: &TypeRegistry, ty: u32) -> Result<u32, Trap> {
+        let Layout::Struct { fields, size } = &types.get(ty).layout else {
+            unreachable!("type {ty} is not a struct type, this should have been validated")
+        };
+
+        let mut data = alloc::vec![0; *size].into_boxed_slice();
+        for field in fields.iter().filter(|field| field.kind == FieldKind::Ref) {
+            data[field.range()].fill(FieldKind::Ref.default_byte());
+        }
+        self.alloc(GcObject::Struct { ty, data })
+    }
+
+    /// Allocate an array of `len` elements, set to `init` or their default value.
+    pub(crate) fn alloc_array(
+        &mut self,
+        types: &TypeRegistry,
+        ty: u32,
+        len: u32,
+        init: Option<TinyWasmValue>,
+    ) -> Result<u32, Trap> {
+        let kind = types.array_element(ty).kind;
+        let Some(size) = (len as usize).checked_mul(kind.size()) else {
+            cold_path();
+            return Err(Trap::OutOfMemory);
+        };
+
+        let mut data = Vec::new();
+        if data.try_reserve_exact(size).is_err() {
+            cold_path();
+            return Err(Trap::OutOfMemory);
+        }
+        data.resize(size, kind.default_byte());
+        if let Some(init) = init {
+            for element in data.chunks_exact_mut(kind.size()) {
+                kind.write(element, init);
+            }
+        }
+        self.alloc(GcObject::Array { ty, len, data: data.into_boxed_slice() })
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, idx: u32) -> &GcObject {
+        self.objects.get(idx as usize).unwrap_or_else(|| unreachable!("invalid GC object: {idx}"))
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self, idx: u32) -> &mut GcObject {
+        self.objects.get_mut(idx as usize).unwrap_or_else(|| unreachable!("invalid GC object: {idx}"))
+    }
+
+    /// Get the type and data of the struct a non-null `anyref` points to.
+    #[inline]
+    pub(crate) fn struct_data_mut(&mut self, raw: u32) -> (u32, &mut [u8]) {
+        match self.get_mut(raw) {
+            GcObject::Struct { ty, data } => (*ty, data),
+            _ => unreachable!("reference is not a struct, this should have been validated"),
+        }
+    }
+
+    /// Get the type, length and data of the array a non-null `anyref` points to.
+    #[inline]
+    pub(crate) fn array_data_mut(&mut self, raw: u32) -> (u32, u32, &mut [u8]) {
+        match self.get_mut(raw) {
+            GcObject::Array { ty, len, data } => (*ty, *len, data),
+            _ => unreachable!("reference is not an array, this should have been validated"),
+        }
+    }
+
+    /// Copy `len` bytes between the data of two arrays, which may be the same.
+    pub(crate) fn copy_array_data(&mut self, dst: u32, dst_offset: usize, src: u32, src_offset: usize, len: usize) {
+        if dst == src {
+            let (_, _, data) = self.array_data_mut(dst);
+            data.copy_within(src_offset..src_offset + len, dst_offset);
+            return;
+        }
+
+        let Ok([GcObject::Array { data: dst, .. }, GcObject::Array { data: src, .. }]) =
+            self.objects.get_disjoint_mut([dst as usize, src as usize])
+        else {
+            unreachable!("references are not arrays, this should have been validated")
+        };
+        dst[dst_offset..dst_offset + len].copy_from_slice(&src[src_offset..src_offset + len]);
+    }
+
+    /// Convert an `externref` to an `anyref` (`any.convert_extern`).
+    pub(crate) fn internalize(&mut self, raw: u32) -> Result<u32, Trap> {
+        if raw == ValueRef::NULL.raw() {
+            return Ok(raw);
+        }
+        if let Some(idx) = heap_index(raw).filter(|_| raw >= EXTERNALIZED_BASE)
+            && let Some(GcObject::Externalized(any)) = self.objects.get(idx as usize)
+        {
+            return Ok(*any);
+        }
+        self.alloc(GcObject::Internalized(raw))
+    }
+
+    /// Convert an `anyref` to an `externref` (`extern.convert_any`).
+    pub(crate) fn externalize(&mut self, raw: u32) -> Result<u32, Trap> {
+        if raw == ValueRef::NULL.raw() {
+            return Ok(raw);
+        }
+        if AnyRef::from_raw(raw).heap_index().is_some()
+            && let GcObject::Internalized(host) = self.get(raw)
+        {
+            return Ok(*host);
+        }
+        Ok(self.alloc(GcObject::Externalized(raw))? + EXTERNALIZED_BASE)
+    }
+
+    /// Whether enough memory was allocated since the last collection to collect again.
+    ///
+    /// The heap may grow by at least its live size before the next collection,
+    /// so the cost of collecting stays proportional to the allocation rate.
+    #[inline]
+    pub(crate) fn should_collect(&self, threshold: usize) -> bool {
+        // `live_bytes` includes everything allocated since, so only compare against what survived
+        self.allocated_bytes >= threshold.max(self.live_bytes.saturating_sub(self.allocated_bytes))
+    }
+
+    /// Keep the references on suspended `stacks` and in `params` alive until they are resumed or `token` is dropped.
+    pub(crate) fn suspend(&mut self, token: &Arc<()>, stacks: &Stacks, params: &[WasmValue]) {
+        self.resume(token);
+        if self.is_empty() {
+            return;
+        }
+
+        let mut roots = Vec::new();
+        stack_roots(&mut roots, stacks);
+        roots.extend(params.iter().filter_map(wasm_value_root));
+        self.suspended.push((Arc::downgrade(token), roots.into_boxed_slice()));
+    }
+
+    /// Release the references kept alive by [`GcHeap::suspend`].
+    pub(crate) fn resume(&mut self, token: &Arc<()>) {
+        let token = Arc::downgrade(token);
+        self.suspended.retain(|(suspended, _)| !suspended.ptr_eq(&token));
+    }
+
+    /// Mark everything reachable from `worklist` and free all unreachable objects.
+    ///
+    /// `trace` is called with every reachable value, so objects outside of the heap can be marked as well.
+    fn collect(&mut self, types: &TypeRegistry, mut worklist: Vec<u32>, mut trace: impl FnMut(u32, &mut Vec<u32>)) {
+        self.suspended.retain(|(token, _)| token.strong_count() > 0);
+        worklist.extend_from_slice(&self.pins);
+        worklist.extend_from_slice(&self.host_roots);
+        for (_, roots) in &self.suspended {
+            worklist.extend_from_slice(roots);
+        }
+
+        self.marks.clear();
+        self.marks.resize(self.objects.len(), false);
+        self.host_marks.clear();
+        self.host_marks.resize(self.hosts.len(), false);
+        while let Some(raw) = worklist.pop() {
+            trace(raw, &mut worklist);
+            if let Some((slot, generation)) = host_slot(raw) {
+                if self.hosts.get(slot).is_some_and(|host| host.live && host.generation == generation) {
+                    self.host_marks[slot] = true;
+                }
+                continue;
+            }
+            let Some(idx) = heap_index(raw) else { continue };
+            let (Some(mark), Some(object)) = (self.marks.get_mut(idx as usize), self.objects.get(idx as usize)) else {
+                continue;
+            };
+            if *mark || matches!(object, GcObject::Free) {
+                continue;
+            }
+
+            *mark = true;
+            match object {
+                GcObject::Struct { ty, data } => {
+                    for field in types.struct_fields(*ty).iter().filter(|field| field.kind == FieldKind::Ref) {
+                        worklist.push(read_ref(&data[field.range()]));
+                    }
+                }
+                GcObject::Array { ty, data, .. } => {
+                    if types.array_element(*ty).kind == FieldKind::Ref {
+                        worklist.extend(data.chunks_exact(4).map(read_ref));
+                    }
+                }
+                GcObject::Externalized(any) => worklist.push(*any),
+                GcObject::Internalized(host) => worklist.push(*host),
+                GcObject::Free => {}
+            }
+        }
+
+        for (idx, object) in self.objects.iter_mut().enumerate() {
+            if self.marks[idx] || matches!(object, GcObject::Free) {
+                continue;
+            }
+            self.live_objects -= 1;
+            self.live_bytes -= object.size();
+            *object = GcObject::Free;
+            self.free.push(idx as u32);
+        }
+
+        for (slot, host) in self.hosts.iter_mut().enumerate() {
+            if self.host_marks[slot] || host.held || !host.live {
+                continue;
+            }
+            self.live_objects -= 1;
+            self.live_bytes -= host.size();
+            host.live = false;
+            host.value = None;
+            host.generation += 1;
+            if host.generation < HOST_GENERATIONS {
+                self.free_hosts.push(slot as u32);
+            }
+        }
+        self.allocated_bytes = 0;
+    }
+}
+
+#[inline]
+fn read_ref(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap_or_default())
+}
+
+fn stack_roots(roots: &mut Vec<u32>, stacks: &Stacks) {
+    value_stack_roots(roots, &stacks.value_stack);
+    for frame in &stacks.handler_stack {
+        stack_roots(roots, &frame.parent);
+    }
+}
+
+fn value_stack_roots(roots: &mut Vec<u32>, stack: &ValueStack) {
+    roots.extend(stack.stack_32.as_slice().iter().copied().filter(|raw| is_root(*raw)));
+}
+
+fn exception_size(exn: &ExceptionInstance) -> usize {
+    OBJECT_OVERHEAD + size_of_val(&*exn.payload)
+}
+
+fn wasm_value_root(value: &WasmValue) -> Option<u32> {
+    match value {
+        WasmValue::RefAny(any) => Some(any.raw()),
+        WasmValue::RefExtern(ext) => Some(ext.raw()),
+        WasmValue::RefCont(cont) => Some(cont.raw()),
+        WasmValue::RefExn(exn) => Some(exn.raw()),
+        _ => None,
+    }
+}
+
+impl Store {
+    /// Register the types of a module, returning the canonical id of every type index.
+    pub(crate) fn register_types(&mut self, module: &Module) -> Box<[u32]> {
+        self.types.register(&module.types, &module.rec_groups)
+    }
+
+    /// Collect unreachable objects in the GC heap if enough memory was allocated since the last collection.
+    #[inline]
+    pub(crate) fn maybe_collect_garbage(&mut self) {
+        if self.gc.should_collect(self.engine.config().gc_threshold) {
+            cold_path();
+            self.collect_garbage();
+        }
+    }
+
+    /// Pin the arguments of a host function call, returning the number of roots to restore afterwards.
+    #[inline]
+    pub(crate) fn pin_host_params(&mut self, params: &[WasmValue]) -> usize {
+        let len = self.gc.host_roots.len();
+        if !self.gc.is_empty() {
+            self.gc.host_roots.extend(params.iter().filter_map(wasm_value_root));
+        }
+        len
+    }
+
+    /// Push values from the host onto the value stack, handing the host values they refer to over to WebAssembly.
+    #[inline]
+    pub(crate) fn push_host_values(&mut self, values: &[WasmValue]) -> Result<(), Trap> {
+        self.gc.hand_over(values);
+        self.value_stack.extend_from_wasmvalues(values)
+    }
+
+    /// Check if a non-null reference points to a value of the given canonical heap type.
+    pub(crate) fn ref_matches(&self, raw: u32, ty: RefType) -> bool {
+        if raw == ValueRef::NULL.raw() {
+            return ty.nullable;
+        }
+
+        // references from the host may point past the heap, which doesn't match any type
+        let object = || AnyRef::from_raw(raw).heap_index().and_then(|idx| self.gc.objects.get(idx as usize));
+
+        // function references from the host may not point to a function
+        let func = || self.state.funcs.get(raw as usize);
+
+        match ty.heap_type {
+            HeapType::Func => func().is_some(),
+            HeapType::Extern | HeapType::Any | HeapType::Exn | HeapType::Cont => true,
+            HeapType::NoFunc | HeapType::NoExtern | HeapType::None | HeapType::NoExn | HeapType::NoCont => false,
+            HeapType::I31 => AnyRef::from_raw(raw).is_i31(),
+            HeapType::Eq => {
+                AnyRef::from_raw(raw).is_i31()
+                    || matches!(object(), Some(GcObject::Struct { .. } | GcObject::Array { .. }))
+            }
+            HeapType::Struct => matches!(object(), Some(GcObject::Struct { .. })),
+            HeapType::Array => matches!(object(), Some(GcObject::Array { .. })),
+            HeapType::Concrete(id) => match &self.types.get(id).layout {
+                Layout::Func(func_ty) => match func() {
+                    Some(FunctionInstance::Wasm(func)) => self.types.is_subtype(func.type_id, id),
+                    Some(FunctionInstance::Host(func)) => func.ty == *func_ty,
+                    None => false,
+                },
+                Layout::Struct { .. } | Layout::Array(_) => match object() {
+                    Some(GcObject::Struct { ty, .. } | GcObject::Array { ty, .. }) => self.types.is_subtype(*ty, id),
+                    _ => false,
+                },
+                Layout::Cont => true,
+            },
+        }
+    }
+
+    /// Add a continuation to the store, reusing the slot of a collected one if possible.
+    pub(crate) fn add_cont(&mut self, cont: ContinuationInstance) -> ContAddr {
+        self.gc.track(Stacks::initial_size(self.engine.config()));
+        self.state.add_cont(cont)
+    }
+
+    /// Add an exception to the store, reusing the slot of a collected one if possible.
+    pub(crate) fn add_exception(&mut self, exn: ExceptionInstance) -> ExnAddr {
+        self.gc.hand_over(&*exn.payload);
+        self.gc.track(exception_size(&exn));
+        self.state.add_exception(exn)
+    }
+
+    /// Free all exceptions that were not marked by the last collection.
+    fn sweep_exceptions(&mut self, marks: &[bool]) {
+        for (addr, marked) in marks.iter().enumerate() {
+            if *marked {
+                continue;
+            }
+            if let Some(exn) = self.state.exceptions[addr].take() {
+                self.state.free_exceptions.push(addr as ExnAddr);
+                self.gc.untrack(exception_size(&exn));
+            }
+        }
+    }
+
+    /// Free all continuations that were not marked by the last collection.
+    fn sweep_continuations(&mut self, marks: &[bool]) {
+        let size = Stacks::initial_size(self.engine.config());
+        for (addr, marked) in marks.iter().enumerate() {
+            let cont = &mut self.state.continuations[addr];
+            if *marked || matches!(cont, ContinuationInstance::Free) {
+                continue;
+            }
+
+            let cont = core::mem::replace(cont, ContinuationInstance::Free);
+            self.state.free_continuations.push(addr as ContAddr);
+            self.gc.untrack(size);
+            if let ContinuationInstance::Fresh { stacks, .. } | ContinuationInstance::Suspended { stacks, .. } = cont {
+                self.recycle_stacks(stacks);
+            }
+        }
+    }
+
+    /// Run the garbage collector, freeing all GC objects that are no longer reachable.
+    ///
+    /// Collections also happen automatically while WebAssembly code allocates, see
+    /// [`Config::with_gc_threshold`](crate::engine::Config::with_gc_threshold).
+    ///
+    /// Continuations and exceptions are collected as well, once no reference to them is left.
+    ///
+    /// Objects are reachable from globals, tables, element segments, reachable exceptions and continuations,
+    /// the stacks of running and suspended executions, and references pinned with [`Store::pin`].
+    /// References the host keeps elsewhere, e.g. the results of a call, are not reachable
+    /// and have to be pinned to stay valid across calls.
+    ///
+    /// Host values created with [`Store::new_extern_ref`] are kept alive until they are handed to WebAssembly,
+    /// and are collected like other objects from then on.
+    pub fn collect_garbage(&mut self) {
+        if self.gc.is_empty() {
+            return;
+        }
+
+        let mut roots = Vec::new();
+        value_stack_roots(&mut roots, &self.value_stack);
+        for frame in &self.handler_stack {
+            stack_roots(&mut roots, &frame.parent);
+        }
+
+        for global in &self.state.globals {
+            if let TinyWasmValue::ValueRef(value) = global.value {
+                roots.push(value.raw());
+            }
+        }
+        for table in self.state.tables.iter().filter(|table| table.kind.element_type != WasmType::RefFunc) {
+            roots.extend(table.elements.iter().filter_map(|elem| elem.addr()));
+        }
+        for elem in &self.state.elements {
+            roots.extend(elem.items.iter().flatten().filter_map(|item| item.addr()));
+        }
+        let (continuations, exceptions) = (&self.state.continuations, &self.state.exceptions);
+        let mut cont_marks = alloc::vec![false; continuations.len()];
+        let mut exn_marks = alloc::vec![false; exceptions.len()];
+        self.gc.collect(&self.types, roots, |raw, worklist| {
+            if let Some(mark) = cont_marks.get_mut(raw as usize).filter(|mark| !**mark) {
+                *mark = true;
+                if let Some(stacks) = continuations[raw as usize].stacks() {
+                    stack_roots(worklist, stacks);
+                }
+            }
+            if let Some(mark) = exn_marks.get_mut(raw as usize).filter(|mark| !**mark) {
+                *mark = true;
+                if let Some(exn) = &exceptions[raw as usize] {
+                    worklist.extend(exn.payload.iter().filter_map(wasm_value_root));
+                }
+            }
+        });
+        self.sweep_continuations(&cont_marks);
+        self.sweep_exceptions(&exn_marks);
+    }
+
+    /// Keep a GC object, continuation or exception alive, even if it is not reachable from WebAssembly.
+    ///
+    /// Pins are counted, so a value pinned twice has to be unpinned twice.
+    pub fn pin(&mut self, value: impl Into<WasmValue>) {
+        if let Some(raw) = wasm_value_root(&value.into()) {
+            self.gc.pins.push(raw);
+        }
+    }
+
+    /// Release a value pinned with [`Store::pin`].
+    pub fn unpin(&mut self, value: impl Into<WasmValue>) {
+        let Some(raw) = wasm_value_root(&value.into()) else { return };
+        if let Some(pos) = self.gc.pins.iter().rposition(|pinned| *pinned == raw) {
+            self.gc.pins.swap_remove(pos);
+        }
+    }
+
+    /// Get the number of live objects in the GC heap.
+    pub fn gc_object_count(&self) -> usize {
+        self.gc.live_objects
+    }
+
+    /// Create a struct with the given field values.
+    ///
+    /// Packed fields take an `i32` and keep its low bits.
+    pub fn new_struct(&mut self, ty: GcType, fields: &[WasmValue]) -> Result<AnyRef> {
+        ty.0.validate_store(self)?;
+        let Layout::Struct { fields: types, .. } = &self.types.get(ty.0.addr).layout else {
+            cold_path();
+            return Err(Error::other("type is not a struct type"));
+        };
+        if types.len() != fields.len() {
+            cold_path();
+            return Err(Error::other("wrong number of struct fields"));
+        }
+
+        let mut values = Vec::with_capacity(fields.len());
+        for (idx, value) in fields.iter().enumerate() {
+            values.push(self.check_field_value(ty.0.addr, Some(idx), value)?);
+        }
+
+        let idx = self.gc.alloc_struct(&self.types, ty.0.addr)?;
+        let (_, data) = self.gc.struct_data_mut(idx);
+        for (field, value) in self.types.struct_fields(ty.0.addr).iter().zip(values) {
+            field.kind.write(&mut data[field.range()], value);
+        }
+        Ok(AnyRef::from_raw(idx))
+    }
+
+    /// Create an array of `len` elements set to `init`.
+    pub fn new_array(&mut self, ty: GcType, len: u32, init: WasmValue) -> Result<AnyRef> {
+        ty.0.validate_store(self)?;
+        if !matches!(self.types.get(ty.0.addr).layout, Layout::Array(_)) {
+            cold_path();
+            return Err(Error::other("type is not an array type"));
+        }
+        let init = self.check_field_value(ty.0.addr, None, &init)?;
+        Ok(AnyRef::from_raw(self.gc.alloc_array(&self.types, ty.0.addr, len, Some(init))?))
+    }
+
+    /// Find out what an `anyref` refers to, or `None` if it is null.
+    pub fn inspect(&self, value: AnyRef) -> Result<Option<GcValue>> {
+        if value.is_null() {
+            return Ok(None);
+        }
+        if let Some(v) = value.as_i31_s() {
+            return Ok(Some(GcValue::I31(v)));
+        }
+
+        Ok(Some(match self.object(value)? {
+            GcObject::Struct { ty, .. } => GcValue::Struct(GcType(StoreItem::new(self.id(), *ty))),
+            GcObject::Array { ty, .. } => GcValue::Array(GcType(StoreItem::new(self.id(), *ty))),
+            GcObject::Internalized(host) => GcValue::Extern(ExternRef::from_raw(*host)),
+            GcObject::Externalized(_) | GcObject::Free => unreachable!("checked by Store::object"),
+        }))
+    }
+
+    /// Get a struct field. Packed fields are zero-extended.
+    pub fn struct_get(&self, value: AnyRef, field: u32) -> Result<WasmValue> {
+        let GcObject::Struct { ty, data } = self.object(value)? else {
+            cold_path();
+            return Err(Error::other("reference is not a struct"));
+        };
+        let Some(field) = self.types.struct_fields(*ty).get(field as usize) else {
+            cold_path();
+            return Err(Error::other("struct field index out of bounds"));
+        };
+        Ok(self.to_wasm_value(field.kind.read(&data[field.range()]), field.ty))
+    }
+
+    /// Set a mutable struct field.
+    pub fn struct_set(&mut self, value: AnyRef, field: u32, new: WasmValue) -> Result<()> {
+        let GcObject::Struct { ty, .. } = self.object(value)? else {
+            cold_path();
+            return Err(Error::other("reference is not a struct"));
+        };
+        let ty = *ty;
+        let Some(layout) = self.types.struct_fields(ty).get(field as usize) else {
+            cold_path();
+            return Err(Error::other("struct field index out of bounds"));
+        };
+        if !layout.mutable {
+            cold_path();
+            return Err(Error::other("struct field is immutable"));
+        }
+
+        let new = self.check_field_value(ty, Some(field as usize), &new)?;
+        let layout = &self.types.struct_fields(ty)[field as usize];
+        let (_, data) = self.gc.struct_data_mut(value.raw());
+        layout.kind.write(&mut data[layout.range()], new);
+        Ok(())
+    }
+
+    /// Get the length of an array.
+    pub fn array_len(&self, value: AnyRef) -> Result<u32> {
+        match self.object(value)? {
+            GcObject::Array { len, .. } => Ok(*len),
+            _ => Err(Error::other("reference is not an array")),
+        }
+    }
+
+    /// Get an array element. Packed elements are zero-extended.
+    pub fn array_get(&self, value: AnyRef, index: u32) -> Result<WasmValue> {
+        let GcObject::Array { ty, len, data } = self.object(value)? else {
+            cold_path();
+            return Err(Error::other("reference is not an array"));
+        };
+        if index >= *len {
+            cold_path();
+            return Err(Trap::ArrayOutOfBounds { offset: index as usize, len: 1, max: *len as usize }.into());
+        }
+        let element = self.types.array_element(*ty);
+        let offset = index as usize * element.kind.size();
+        Ok(self.to_wasm_value(element.kind.read(&data[offset..]), element.ty))
+    }
+
+    /// Set an element of a mutable array.
+    pub fn array_set(&mut self, value: AnyRef, index: u32, new: WasmValue) -> Result<()> {
+        let GcObject::Array { ty, len, .. } = self.object(value)? else {
+            cold_path();
+            return Err(Error::other("reference is not an array"));
+        };
+        let (ty, len) = (*ty, *len);
+        if !self.types.array_element(ty).mutable {
+            cold_path();
+            return Err(Error::other("array is immutable"));
+        }
+        if index >= len {
+            cold_path();
+            return Err(Trap::ArrayOutOfBounds { offset: index as usize, len: 1, max: len as usize }.into());
+        }
+
+        let new = self.check_field_value(ty, None, &new)?;
+        let kind = self.types.array_element(ty).kind;
+        let (_, _, data) = self.gc.array_data_mut(value.raw());
+        kind.write(&mut data[index as usize * kind.size()..], new);
+        Ok(())
+    }
+
+    /// Convert an `externref` to an `anyref`, like `any.convert_extern`.
+    pub fn extern_to_any(&mut self, value: ExternRef) -> Result<AnyRef> {
+        Ok(AnyRef::from_raw(self.gc.internalize(value.raw())?))
+    }
+
+    /// Convert an `anyref` to an `externref`, like `extern.convert_any`.
+    pub fn any_to_extern(&mut self, value: AnyRef) -> Result<ExternRef> {
+        if value.heap_index().is_some() {
+            self.object(value)?;
+        }
+        Ok(ExternRef::from_raw(self.gc.externalize(value.raw())?))
+    }
+
+    /// Create an `externref` that refers to a value owned by the host.
+    ///
+    /// The value lives in the GC heap and stays alive until the reference is handed to WebAssembly, i.e.
+    /// passed as an argument to or returned from a function, or stored in a table or global by the host.
+    /// From then on it is dropped once it is no longer reachable, just like structs and arrays, so it has
+    /// to be pinned with [`Store::pin`] if the host keeps using it. Use [`Store::downcast_ref`] to access
+    /// the value again, and [`Store::release_extern_ref`] to drop a value that was never handed over.
+    ///
+    /// Host values count towards [`Config::gc_threshold`](crate::engine::Config::gc_threshold), so creating
+    /// one may collect garbage first. References to a collected value never refer to a newer one.
+    pub fn new_extern_ref(&mut self, value: impl Any) -> Result<ExternRef> {
+        self.maybe_collect_garbage();
+        Ok(ExternRef::from_raw(self.gc.alloc_host(Box::new(value))?))
+    }
+
+    /// Get the host value an `externref` created with [`Store::new_extern_ref`] refers to.
+    ///
+    /// Returns `None` if the reference doesn't refer to a host value of type `T` or the value was released.
+    pub fn downcast_ref<T: Any>(&self, value: ExternRef) -> Option<&T> {
+        self.gc.host(value.raw())?.value.as_ref()?.downcast_ref()
+    }
+
+    /// Mutable version of [`Store::downcast_ref`].
+    pub fn downcast_mut<T: Any>(&mut self, value: ExternRef) -> Option<&mut T> {
+        self.gc.host_mut(value.raw())?.value.as_mut()?.downcast_mut()
+    }
+
+    /// Drop the host value an `externref` refers to without waiting for it to become unreachable,
+    /// returning it to the caller.
+    ///
+    /// References to a released value stay valid, but [`Store::downcast_ref`] returns `None` for them.
+    pub fn release_extern_ref(&mut self, value: ExternRef) -> Option<Box<dyn Any>> {
+        self.gc.unhold(value.raw());
+        let released = self.gc.host_mut(value.raw())?.value.take()?;
+        self.gc.live_bytes -= size_of_val(&*released);
+        Some(released)
+    }
+
+    fn object(&self, value: AnyRef) -> Result<&GcObject> {
+        let object = value.heap_index().and_then(|idx| self.gc.objects.get(idx as usize));
+        match object {
+            Some(object @ (GcObject::Struct { .. } | GcObject::Array { .. } | GcObject::Internalized(_))) => Ok(object),
+            _ => {
+                cold_path();
+                Err(Error::other("reference does not point to a live GC object"))
+            }
+        }
+    }
+
+    fn to_wasm_value(&self, value: TinyWasmValue, ty: WasmType) -> WasmValue {
+        value.attach_type(ty).unwrap_or_else(|| unreachable!("GC field value does not match its type"))
+    }
+
+    /// Check a value from the host against a struct field (or the element of an array if `field` is `None`).
+    fn check_field_value(&self, ty: u32, field: Option<usize>, value: &WasmValue) -> Result<TinyWasmValue> {
+        let field_type = match (&self.types.get(ty).sub.composite, field) {
+            (CompositeType::Struct(fields), Some(idx)) => fields[idx],
+            (CompositeType::Array(element), None) => *element,
+            _ => unreachable!("invalid GC field"),
+        };
+        let layout = match field {
+            Some(idx) => &self.types.struct_fields(ty)[idx],
+            None => self.types.array_element(ty),
+        };
+
+        if WasmType::from(value) != layout.ty {
+            cold_path();
+            return Err(Error::other("GC field value has the wrong type"));
+        }
+        if let StorageType::Val(ValType::Ref(ref_ty)) = field_type.storage
+            && !self.host_ref_matches(value, Some(ref_ty))
+        {
+            cold_path();
+            return Err(Error::other("GC field value has the wrong reference type"));
+        }
+        Ok(TinyWasmValue::from(value))
+    }
+
+    /// Check a value from the host against a precise reference type with canonical type ids.
+    ///
+    /// [`WasmType`] only tells the hierarchy of a reference apart, so e.g. an array passed for a
+    /// `(ref $struct)` has to be caught here before WebAssembly code relies on its type.
+    /// Values that aren't references, or have no precise type to check, always match.
+    pub(crate) fn host_ref_matches(&self, value: &WasmValue, ty: Option<RefType>) -> bool {
+        match (wasm_ref_raw(value), ty) {
+            (Some(raw), Some(ty)) => self.ref_matches(raw, ty),
+            _ => true,
+        }
+    }
+
+    /// Check the arguments of a call from the host against the precise parameter types of a function.
+    ///
+    /// Host functions only have a [`FuncType`] signature, which [`Function::call`](crate::Function::call)
+    /// already checks.
+    pub(crate) fn check_call_params(&self, func_addr: FuncAddr, params: &[WasmValue]) -> Result<()> {
+        let FunctionInstance::Wasm(func) = self.state.get_func(func_addr) else { return Ok(()) };
+        let CompositeType::Func { params: types, .. } = &self.types.get(func.type_id).sub.composite else {
+            unreachable!("type {} is not a function type, this should have been validated", func.type_id)
+        };
+
+        for (ty, value) in types.iter().zip(params) {
+            if let ValType::Ref(ty) = ty
+                && !self.host_ref_matches(value, Some(*ty))
+            {
+                cold_path();
+                return Err(Error::other("argument does not match the parameter's reference type"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn wasm_ref_raw(value: &WasmValue) -> Option<u32> {
+    match value {
+        WasmValue::RefAny(v) => Some(v.raw()),
+        WasmValue::RefExtern(v) => Some(v.raw()),
+        WasmValue::RefFunc(v) => Some(v.raw()),
+        WasmValue::RefCont(v) => Some(v.raw()),
+        WasmValue::RefExn(v) => Some(v.raw()),
+        _ => None,
+    }
+}
+
+/// A struct or array type, used to create GC objects from the host.
+///
+/// Get one with [`ModuleInstance::gc_type`]. Types are canonicalized, so the same type
+/// defined by different modules gives equal handles.
+///
+/// ## Example
+/// ```rust
+/// # fn main() -> tinywasm::Result<()> {
+/// # use tinywasm::{GcValue, ModuleInstance, Store};
+/// # use tinywasm::types::WasmValue;
+/// # let wasm = wat::parse_str(r#"
+/// #     (module
+/// #       (type $point (struct (field $x (mut i32)) (field $y (mut i32))))
+/// #       (func (export "sum") (param (ref $point)) (result i32)
+/// #         (i32.add (struct.get $point $x (local.get 0)) (struct.get $point $y (local.get 0)))))
+/// # "#).expect("valid wat");
+/// # let module = tinywasm::parse_bytes(&wasm)?;
+/// let mut store = Store::default();
+/// let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+///
+/// let point_type = instance.gc_type(&store, 0)?;
+/// let point = store.new_struct(point_type, &[WasmValue::I32(1), WasmValue::I32(2)])?;
+/// assert_eq!(store.inspect(point)?, Some(GcValue::Struct(point_type)));
+///
+/// store.struct_set(point, 1, WasmValue::I32(41))?;
+/// let sum = instance.func_untyped(&store, "sum")?.call(&mut store, &[point.into()])?;
+/// assert_eq!(sum, vec![WasmValue::I32(42)]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct GcType(pub(crate) StoreItem);
+
+/// What an `anyref` refers to, see [`Store::inspect`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub enum GcValue {
+    /// An `i31ref` with its sign-extended value.
+    I31(i32),
+    /// A struct of the given type.
+    Struct(GcType),
+    /// An array of the given type.
+    Array(GcType),
+    /// An `externref` converted with `any.convert_extern`.
+    Extern(ExternRef),
+}
+
+impl ModuleInstance {
+    /// Get a struct or array type defined in the module's type section.
+    pub fn gc_type(&self, store: &Store, type_idx: u32) -> Result<GcType> {
+        self.validate_store(store)?;
+        let Some(id) = self.type_ids().get(type_idx as usize).copied() else {
+            cold_path();
+            return Err(Error::Other(alloc::format!("type {type_idx} not found")));
+        };
+        match store.types.get(id).layout {
+            Layout::Struct { .. } | Layout::Array(_) => Ok(GcType(StoreItem::new(store.id(), id))),
+            _ => Err(Error::Other(alloc::format!("type {type_idx} is not a struct or array type"))),
+        }
+    }
+}
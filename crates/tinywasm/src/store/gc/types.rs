@@ -0,0 +1,437 @@
+use alloc::collections::BTreeMap;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::ops::Range;
+use tinywasm_types::*;
+
+use crate::interpreter::{TinyWasmValue, Value128, ValueRef};
+
+/// Marks a type index as relative to the start of its recursion group in a canonicalization key.
+const REC_GROUP_RELATIVE: u32 = 0x8000_0000;
+
+/// How a struct field or array element is stored in a GC object.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) enum FieldKind {
+    I8,
+    I16,
+    I32,
+    I64,
+    V128,
+    Ref,
+}
+
+impl FieldKind {
+    fn new(storage: &StorageType) -> Self {
+        match storage {
+            StorageType::I8 => Self::I8,
+            StorageType::I16 => Self::I16,
+            StorageType::Val(ValType::I32 | ValType::F32) => Self::I32,
+            StorageType::Val(ValType::I64 | ValType::F64) => Self::I64,
+            StorageType::Val(ValType::V128) => Self::V128,
+            StorageType::Val(ValType::Ref(_)) => Self::Ref,
+        }
+    }
+
+    #[inline]
+    pub(crate) const fn size(self) -> usize {
+        match self {
+            Self::I8 => 1,
+            Self::I16 => 2,
+            Self::I32 | Self::Ref => 4,
+            Self::I64 => 8,
+            Self::V128 => 16,
+        }
+    }
+
+    /// The bytes of a freshly allocated field: zero, or null for references.
+    #[inline]
+    pub(crate) const fn default_byte(self) -> u8 {
+        match self {
+            Self::Ref => 0xFF,
+            _ => 0,
+        }
+    }
+
+    /// Read a value, zero-extending packed integers.
+    #[inline]
+    pub(crate) fn read(self, bytes: &[u8]) -> TinyWasmValue {
+        match self {
+            Self::I8 => TinyWasmValue::Value32(u32::from(bytes[0])),
+            Self::I16 => TinyWasmValue::Value32(u32::from(u16::from_le_bytes([bytes[0], bytes[1]]))),
+            Self::I32 => TinyWasmValue::Value32(u32::from_le_bytes(bytes[..4].try_into().unwrap_or_default())),
+            Self::I64 => TinyWasmValue::Value64(u64::from_le_bytes(bytes[..8].try_into().unwrap_or_default())),
+            Self::V128 => TinyWasmValue::Value128(Value128::from_le_bytes(bytes[..16].try_into().unwrap_or_default())),
+            Self::Ref => TinyWasmValue::ValueRef(ValueRef::from_raw(u32::from_le_bytes(
+                bytes[..4].try_into().unwrap_or_default(),
+            ))),
+        }
+    }
+
+    /// Write a value, wrapping packed integers.
+    #[inline]
+    pub(crate) fn write(self, bytes: &mut [u8], value: TinyWasmValue) {
+        match (self, value) {
+            (Self::I8, TinyWasmValue::Value32(v)) => bytes[0] = v as u8,
+            (Self::I16, TinyWasmValue::Value32(v)) => bytes[..2].copy_from_slice(&(v as u16).to_le_bytes()),
+            (Self::I32, TinyWasmValue::Value32(v)) => bytes[..4].copy_from_slice(&v.to_le_bytes()),
+            (Self::I64, TinyWasmValue::Value64(v)) => bytes[..8].copy_from_slice(&v.to_le_bytes()),
+            (Self::V128, TinyWasmValue::Value128(v)) => bytes[..16].copy_from_slice(&v.to_le_bytes()),
+            (Self::Ref, TinyWasmValue::ValueRef(v)) => bytes[..4].copy_from_slice(&v.raw().to_le_bytes()),
+            (Self::Ref, TinyWasmValue::Value32(v)) => bytes[..4].copy_from_slice(&v.to_le_bytes()),
+            _ => unreachable!("GC field value does not match its storage type, this should have been validated"),
+        }
+    }
+}
+
+/// A struct field, placed at a fixed offset in the object's data.
+#[derive(Clone)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct Field {
+    pub(crate) offset: usize,
+    pub(crate) kind: FieldKind,
+    /// The type of the field's value after unpacking, used by the host API.
+    pub(crate) ty: WasmType,
+    pub(crate) mutable: bool,
+}
+
+impl Field {
+    #[inline]
+    pub(crate) fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.kind.size()
+    }
+}
+
+/// How the values of a type are represented at runtime.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) enum Layout {
+    /// A function type, with its parameters and results as [`WasmType`]s.
+    Func(Arc<FuncType>),
+    Struct {
+        fields: Box<[Field]>,
+        size: usize,
+    },
+    Array(Field),
+    Cont,
+}
+
+/// A type registered in a [`TypeRegistry`].
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct RuntimeType {
+    /// The type, with all type indices replaced by canonical ids.
+    pub(crate) sub: SubType,
+    /// The canonical ids of this type and all of its declared supertypes, starting with itself.
+    pub(crate) supertypes: Box<[u32]>,
+    pub(crate) layout: Layout,
+}
+
+/// Canonicalizes the types of all module instances in a store.
+///
+/// Types are compared iso-recursively: two recursion groups define the same types if they
+/// are structurally identical after replacing references to earlier types with canonical ids.
+/// Each distinct type gets a canonical id, so type equality becomes an integer comparison
+/// and subtyping a lookup in the declared supertype chain.
+///
+/// See <https://webassembly.github.io/gc/core/valid/conventions.html#rolling-and-unrolling>
+#[derive(Default)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct TypeRegistry {
+    types: Vec<RuntimeType>,
+    groups: BTreeMap<Box<[SubType]>, u32>,
+}
+
+impl TypeRegistry {
+    /// Register the types of a module, returning the canonical id of every type index.
+    pub(crate) fn register(&mut self, types: &[SubType], rec_groups: &[Range<TypeAddr>]) -> Box<[u32]> {
+        let mut ids: Vec<u32> = Vec::with_capacity(types.len());
+        for group in rec_groups {
+            let key = types[group.start as usize..group.end as usize]
+                .iter()
+                .map(|ty| {
+                    map_sub_type(ty, &|idx| match group.contains(&idx) {
+                        true => REC_GROUP_RELATIVE | (idx - group.start),
+                        false => ids[idx as usize],
+                    })
+                })
+                .collect::<Box<[SubType]>>();
+
+            let first = match self.groups.get(&key) {
+                Some(first) => *first,
+                None => {
+                    let first = self.types.len() as u32;
+                    let group = key
+                        .iter()
+                        .map(|ty| {
+                            map_sub_type(ty, &|idx| match idx & REC_GROUP_RELATIVE {
+                                0 => idx,
+                                _ => first + (idx & !REC_GROUP_RELATIVE),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    for ty in &group {
+                        self.push(ty.clone(), first, &group);
+                    }
+                    self.groups.insert(key, first);
+                    first
+                }
+            };
+            ids.extend(first..first + group.len() as u32);
+        }
+        ids.into_boxed_slice()
+    }
+
+    /// Register a type of the group starting at id `first`, whose members may not be registered yet.
+    fn push(&mut self, sub: SubType, first: u32, group: &[SubType]) {
+        let id = self.types.len() as u32;
+        let mut supertypes = alloc::vec![id];
+        if let Some(supertype) = sub.supertype {
+            supertypes.extend_from_slice(&self.get(supertype).supertypes);
+        }
+
+        let layout = match &sub.composite {
+            CompositeType::Func { params, results } => {
+                let params = params.iter().map(|ty| self.wasm_type(ty, first, group)).collect::<Vec<_>>();
+                let results = results.iter().map(|ty| self.wasm_type(ty, first, group)).collect::<Vec<_>>();
+                Layout::Func(Arc::new(FuncType::new(&params, &results)))
+            }
+            CompositeType::Struct(field_types) => {
+                let mut fields = Vec::with_capacity(field_types.len());
+                let mut size = 0;
+                for field in field_types {
+                    let field = self.field(field, size, first, group);
+                    size += field.kind.size();
+                    fields.push(field);
+                }
+                Layout::Struct { fields: fields.into_boxed_slice(), size }
+            }
+            CompositeType::Array(element) => Layout::Array(self.field(element, 0, first, group)),
+            CompositeType::Cont(_) => Layout::Cont,
+        };
+
+        self.types.push(RuntimeType { sub, supertypes: supertypes.into_boxed_slice(), layout });
+    }
+
+    fn field(&self, field: &FieldType, offset: usize, first: u32, group: &[SubType]) -> Field {
+        let ty = match field.storage {
+            StorageType::I8 | StorageType::I16 => WasmType::I32,
+            StorageType::Val(ty) => self.wasm_type(&ty, first, group),
+        };
+        Field { offset, kind: FieldKind::new(&field.storage), ty, mutable: field.mutable }
+    }
+
+    /// The [`WasmType`] of a value of a canonical [`ValType`].
+    fn wasm_type(&self, ty: &ValType, first: u32, group: &[SubType]) -> WasmType {
+        match ty {
+            ValType::I32 => WasmType::I32,
+            ValType::I64 => WasmType::I64,
+            ValType::F32 => WasmType::F32,
+            ValType::F64 => WasmType::F64,
+            ValType::V128 => WasmType::V128,
+            ValType::Ref(ty) => match ty.heap_type {
+                HeapType::Func | HeapType::NoFunc => WasmType::RefFunc,
+                HeapType::Extern | HeapType::NoExtern => WasmType::RefExtern,
+                HeapType::Any | HeapType::Eq | HeapType::I31 | HeapType::Struct | HeapType::Array | HeapType::None => {
+                    WasmType::RefAny
+                }
+                HeapType::Exn | HeapType::NoExn => WasmType::RefExn,
+                HeapType::Cont | HeapType::NoCont => WasmType::RefCont,
+                HeapType::Concrete(id) => match id.checked_sub(first) {
+                    Some(idx) => group[idx as usize].composite.ref_type(),
+                    None => self.get(id).sub.composite.ref_type(),
+                },
+            },
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, id: u32) -> &RuntimeType {
+        self.types.get(id as usize).unwrap_or_else(|| unreachable!("invalid canonical type id: {id}"))
+    }
+
+    /// Check if `sub` is `sup` or declares it as a (transitive) supertype.
+    #[inline]
+    pub(crate) fn is_subtype(&self, sub: u32, sup: u32) -> bool {
+        sub == sup || self.get(sub).supertypes.contains(&sup)
+    }
+
+    /// Check if a host function type matches a canonical function type.
+    ///
+    /// Host functions have no canonical type, so they are compared by their [`WasmType`] signature,
+    /// the same way `call_indirect` checks them.
+    pub(crate) fn matches_func_type(&self, id: u32, ty: &FuncType) -> bool {
+        let CompositeType::Func { params, results } = &self.get(id).sub.composite else { return false };
+        let coarse = |types: &[ValType], expected: &[WasmType]| {
+            types.len() == expected.len()
+                && types.iter().zip(expected).all(|(ty, expected)| self.wasm_type(ty, u32::MAX, &[]) == *expected)
+        };
+        coarse(params, ty.params()) && coarse(results, ty.results())
+    }
+
+    /// Check if a reference type with canonical type ids matches another one.
+    pub(crate) fn is_ref_subtype(&self, sub: RefType, sup: RefType) -> bool {
+        (sup.nullable || !sub.nullable) && self.is_heap_subtype(sub.heap_type, sup.heap_type)
+    }
+
+    fn is_heap_subtype(&self, sub: HeapType, sup: HeapType) -> bool {
+        use HeapType::*;
+        match (sub, sup) {
+            _ if sub == sup => true,
+            (Concrete(sub), Concrete(sup)) => self.is_subtype(sub, sup),
+            (Concrete(id), sup) => match self.get(id).sub.composite {
+                CompositeType::Func { .. } => sup == Func,
+                CompositeType::Struct(_) => matches!(sup, Struct | Eq | Any),
+                CompositeType::Array(_) => matches!(sup, Array | Eq | Any),
+                CompositeType::Cont(_) => sup == Cont,
+            },
+            _ if sub.is_bottom() => self.top_type(sub) == self.top_type(sup),
+            (I31 | Struct | Array, Eq | Any) | (Eq, Any) => true,
+            _ => false,
+        }
+    }
+
+    /// The top type of the hierarchy a heap type belongs to.
+    fn top_type(&self, ty: HeapType) -> HeapType {
+        use HeapType::*;
+        match ty {
+            Func | NoFunc => Func,
+            Extern | NoExtern => Extern,
+            Any | Eq | I31 | Struct | Array | None => Any,
+            Exn | NoExn => Exn,
+            Cont | NoCont => Cont,
+            Concrete(id) => match self.get(id).sub.composite {
+                CompositeType::Func { .. } => Func,
+                CompositeType::Struct(_) | CompositeType::Array(_) => Any,
+                CompositeType::Cont(_) => Cont,
+            },
+        }
+    }
+
+    /// The struct fields of a type.
+    #[inline]
+    pub(crate) fn struct_fields(&self, id: u32) -> &[Field] {
+        match &self.get(id).layout {
+            Layout::Struct { fields, .. } => fields,
+            _ => unreachable!("type {id} is not a struct type, this should have been validated"),
+        }
+    }
+
+    /// The element of an array type.
+    #[inline]
+    pub(crate) fn array_element(&self, id: u32) -> &Field {
+        match &self.get(id).layout {
+            Layout::Array(element) => element,
+            _ => unreachable!("type {id} is not an array type, this should have been validated"),
+        }
+    }
+}
+
+fn map_sub_type(ty: &SubType, f: &impl Fn(u32) -> u32) -> SubType {
+    let composite = match &ty.composite {
+        CompositeType::Func { params, results } => CompositeType::Func {
+            params: params.iter().map(|ty| map_val_type(*ty, f)).collect(),
+            results: results.iter().map(|ty| map_val_type(*ty, f)).collect(),
+        },
+        CompositeType::Struct(fields) => {
+            CompositeType::Struct(fields.iter().map(|field| map_field(*field, f)).collect())
+        }
+        CompositeType::Array(element) => CompositeType::Array(map_field(*element, f)),
+        CompositeType::Cont(idx) => CompositeType::Cont(f(*idx)),
+    };
+    SubType { is_final: ty.is_final, supertype: ty.supertype.map(f), composite }
+}
+
+fn map_field(field: FieldType, f: &impl Fn(u32) -> u32) -> FieldType {
+    let storage = match field.storage {
+        StorageType::Val(ty) => StorageType::Val(map_val_type(ty, f)),
+        packed => packed,
+    };
+    FieldType { storage, mutable: field.mutable }
+}
+
+fn map_val_type(ty: ValType, f: &impl Fn(u32) -> u32) -> ValType {
+    match ty {
+        ValType::Ref(ty) => ValType::Ref(map_ref_type(ty, f)),
+        ty => ty,
+    }
+}
+
+/// Replace the type index of a concrete heap type.
+pub(crate) fn map_ref_type(ty: RefType, f: impl Fn(u32) -> u32) -> RefType {
+    match ty.heap_type {
+        HeapType::Concrete(idx) => RefType::new(ty.nullable, HeapType::Concrete(f(idx))),
+        _ => ty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn struct_type(fields: &[StorageType], supertype: Option<u32>) -> SubType {
+        let fields = fields.iter().map(|&storage| FieldType { storage, mutable: true }).collect();
+        SubType { is_final: false, supertype, composite: CompositeType::Struct(fields) }
+    }
+
+    fn single_group(len: u32) -> Vec<Range<TypeAddr>> {
+        vec![0..len; 1]
+    }
+
+    fn self_ref(idx: u32) -> StorageType {
+        StorageType::Val(ValType::Ref(RefType::new(true, HeapType::Concrete(idx))))
+    }
+
+    #[test]
+    fn test_identical_rec_groups_are_canonicalized() {
+        let mut registry = TypeRegistry::default();
+        let a = registry.register(&[struct_type(&[self_ref(0)], None)], &single_group(1));
+        let b = registry
+            .register(&[struct_type(&[StorageType::I8], None), struct_type(&[self_ref(1)], None)], &[0..1, 1..2]);
+        assert_eq!(a[0], b[1]);
+        assert_ne!(b[0], b[1]);
+    }
+
+    #[test]
+    fn test_rec_group_position_matters() {
+        let mut registry = TypeRegistry::default();
+        let types = vec![struct_type(&[StorageType::I8], None), struct_type(&[StorageType::I8], None)];
+        let ids = registry.register(&types, &single_group(2));
+        assert_ne!(ids[0], ids[1]);
+        let single = registry.register(&types[..1], &single_group(1));
+        assert_ne!(single[0], ids[0]);
+    }
+
+    #[test]
+    fn test_subtyping_and_layout() {
+        let mut registry = TypeRegistry::default();
+        let types = vec![
+            struct_type(&[StorageType::I8], None),
+            struct_type(&[StorageType::I8, StorageType::Val(ValType::I64)], Some(0)),
+        ];
+        let ids = registry.register(&types, &[0..1, 1..2]);
+        assert!(registry.is_subtype(ids[1], ids[0]));
+        assert!(!registry.is_subtype(ids[0], ids[1]));
+
+        let fields = registry.struct_fields(ids[1]);
+        assert_eq!(fields[1].offset, 1);
+        assert_eq!(fields[1].ty, WasmType::I64);
+    }
+
+    #[test]
+    fn test_ref_subtyping() {
+        let mut registry = TypeRegistry::default();
+        let func = SubType {
+            is_final: true,
+            supertype: None,
+            composite: CompositeType::Func { params: [].into(), results: [].into() },
+        };
+        let ids = registry.register(&[struct_type(&[StorageType::I8], None), func], &[0..1, 1..2]);
+        let (point, func) = (HeapType::Concrete(ids[0]), HeapType::Concrete(ids[1]));
+
+        assert!(registry.is_ref_subtype(RefType::new(false, point), RefType::new(true, HeapType::Eq)));
+        assert!(registry.is_ref_subtype(RefType::new(false, func), RefType::new(false, HeapType::Func)));
+        assert!(registry.is_ref_subtype(RefType::new(true, HeapType::NoFunc), RefType::new(true, func)));
+        assert!(!registry.is_ref_subtype(RefType::new(true, func), RefType::new(false, func)));
+        assert!(!registry.is_ref_subtype(RefType::new(false, func), RefType::new(false, HeapType::Any)));
+        assert!(!registry.is_ref_subtype(RefType::new(true, HeapType::None), RefType::new(true, func)));
+    }
+}
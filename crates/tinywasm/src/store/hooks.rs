@@ -0,0 +1,102 @@
+use alloc::boxed::Box;
+use tinywasm_types::{MemAddr, TableAddr};
+
+use super::TableElement;
+use crate::reference::StoreItem;
+use crate::{Memory, ModuleInstance, Store, Table, Trap};
+
+type GrowHook<T> = Box<dyn FnMut(&GrowEvent<T>)>;
+
+/// A growth of a memory or table, passed to the hooks set with [`Store::on_memory_grow`] and [`Store::on_table_grow`].
+#[derive(Clone)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub struct GrowEvent<T> {
+    /// The grown [`Memory`] or [`Table`].
+    pub target: T,
+    /// The instance whose `memory.grow` or `table.grow` grew the target and the target's index in that
+    /// instance, or `None` if the host grew it.
+    pub instance: Option<(ModuleInstance, u32)>,
+    /// The size before growing, in pages for memories and in elements for tables.
+    pub old_size: u64,
+    /// The requested size. The size is unchanged if the growth failed.
+    pub new_size: u64,
+    /// Whether the growth succeeded.
+    pub succeeded: bool,
+}
+
+/// The hooks registered on a [`Store`].
+#[derive(Default)]
+pub(crate) struct StoreHooks {
+    memory_grow: Option<GrowHook<Memory>>,
+    table_grow: Option<GrowHook<Table>>,
+}
+
+impl Store {
+    /// Sets a hook called after every attempt to grow a memory of this store, including failed ones.
+    ///
+    /// The hook sees growth by `memory.grow` and by [`Memory::grow`], and replaces any previous hook.
+    /// Hosts can use it to log memory usage, emit metrics or refresh pointers into memory.
+    ///
+    /// A shared memory can also grow without this store: through [`SharedMemory::grow`](crate::SharedMemory::grow)
+    /// or through another store it is imported into. Such growth is not reported here.
+    pub fn on_memory_grow(&mut self, hook: impl FnMut(&GrowEvent<Memory>) + 'static) -> &mut Self {
+        self.hooks.memory_grow = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets a hook called after every attempt to grow a table of this store, including failed ones.
+    ///
+    /// See [`Self::on_memory_grow`].
+    pub fn on_table_grow(&mut self, hook: impl FnMut(&GrowEvent<Table>) + 'static) -> &mut Self {
+        self.hooks.table_grow = Some(Box::new(hook));
+        self
+    }
+
+    /// Grows the memory at `addr` by `pages_delta` pages, reporting the growth to the hook.
+    pub(crate) fn grow_memory(
+        &mut self,
+        addr: MemAddr,
+        pages_delta: i64,
+        trap_on_oom: bool,
+        instance: Option<(&ModuleInstance, u32)>,
+    ) -> Result<Option<i64>, Trap> {
+        let memory = self.state.get_mem_mut(addr);
+        let old_size = memory.page_count() as u64;
+        let result = memory.grow(pages_delta, trap_on_oom);
+
+        if let Some(hook) = &mut self.hooks.memory_grow {
+            hook(&GrowEvent {
+                target: Memory(StoreItem::new(self.id, addr)),
+                instance: instance.map(|(instance, idx)| (instance.clone(), idx)),
+                old_size,
+                new_size: old_size.saturating_add_signed(pages_delta),
+                succeeded: matches!(result, Ok(Some(_))),
+            });
+        }
+        result
+    }
+
+    /// Grows the table at `addr` by `delta` elements set to `init`, reporting the growth to the hook.
+    pub(crate) fn grow_table(
+        &mut self,
+        addr: TableAddr,
+        delta: usize,
+        init: TableElement,
+        instance: Option<(&ModuleInstance, u32)>,
+    ) -> Result<(), Trap> {
+        let table = self.state.get_table_mut(addr);
+        let old_size = table.size() as u64;
+        let result = table.grow(delta, init);
+
+        if let Some(hook) = &mut self.hooks.table_grow {
+            hook(&GrowEvent {
+                target: Table(StoreItem::new(self.id, addr)),
+                instance: instance.map(|(instance, idx)| (instance.clone(), idx)),
+                old_size,
+                new_size: old_size.saturating_add(delta as u64),
+                succeeded: result.is_ok(),
+            });
+        }
+        result
+    }
+}
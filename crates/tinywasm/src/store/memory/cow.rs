@@ -0,0 +1,336 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::hint::cold_path;
+
+use tinywasm_types::{ConstInstruction, DataKind, ImportKind, Module, ModuleInner};
+
+use super::LinearMemory;
+use crate::std::sync::{Mutex, PoisonError};
+
+/// The contents of a memory right after its active data segments were applied.
+///
+/// Images are immutable and shared by all [`CowMemory`]s created from them.
+pub(crate) struct MemoryImage {
+    len: usize,
+    chunk_size: usize,
+    chunks: Box<[Option<Box<[u8]>>]>,
+}
+
+impl MemoryImage {
+    fn empty(len: usize, chunk_size: usize) -> Self {
+        Self { len, chunk_size, chunks: Box::default() }
+    }
+
+    /// Builds the image of local memory `idx` of `module`.
+    ///
+    /// Returns `None` if a segment can't be applied ahead of time, because its offset is not a
+    /// constant or it is out of bounds. Such memories are initialized by instantiation instead.
+    fn build(module: &Module, imported_memories: usize, idx: usize, chunk_size: usize) -> Option<Self> {
+        let ty = module.memory_types.get(idx)?;
+        if ty.is_shared() {
+            return None;
+        }
+
+        let len = usize::try_from(ty.initial_size()).ok()?;
+        let mut chunks: Vec<Option<Box<[u8]>>> = Vec::new();
+        chunks.resize_with(len.div_ceil(chunk_size), || None);
+
+        for data in &module.data {
+            let DataKind::Active { mem, offset } = &data.kind else { continue };
+            if *mem as usize != imported_memories + idx {
+                continue;
+            }
+
+            let offset = match &offset[..] {
+                [ConstInstruction::I32Const(offset)] => u64::from(*offset as u32),
+                [ConstInstruction::I64Const(offset)] => *offset as u64,
+                _ => return None,
+            };
+            let offset = usize::try_from(offset).ok()?;
+            let end = offset.checked_add(data.data.len())?;
+            if end > len {
+                return None;
+            }
+
+            let mut pos = offset;
+            while pos < end {
+                let chunk_offset = pos % chunk_size;
+                let copy_len = min(chunk_size - chunk_offset, end - pos);
+                let chunk =
+                    chunks[pos / chunk_size].get_or_insert_with(|| alloc::vec![0; chunk_size].into_boxed_slice());
+                chunk[chunk_offset..chunk_offset + copy_len].copy_from_slice(&data.data[pos - offset..][..copy_len]);
+                pos += copy_len;
+            }
+        }
+
+        Some(Self { len, chunk_size, chunks: chunks.into_boxed_slice() })
+    }
+
+    fn chunk(&self, chunk_idx: usize) -> Option<&[u8]> {
+        self.chunks.get(chunk_idx)?.as_deref()
+    }
+}
+
+/// The images of a module's local memories, `None` for memories that can't be initialized ahead of time.
+type ModuleImages = Arc<[Option<Arc<MemoryImage>>]>;
+
+/// The memory images of the modules instantiated with a copy-on-write backend.
+#[derive(Default)]
+pub(crate) struct ImageCache {
+    modules: Mutex<Vec<(Weak<ModuleInner>, ModuleImages)>>,
+}
+
+impl ImageCache {
+    /// Returns the image of local memory `idx` of `module`, building the images of all its memories on first use.
+    pub(crate) fn get(&self, module: &Module, idx: usize, chunk_size: usize) -> Option<Arc<MemoryImage>> {
+        let key = module.downgrade();
+        let mut modules = self.modules.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, images)) = modules.iter().find(|(module, _)| module.ptr_eq(&key)) {
+            return images.get(idx)?.clone();
+        }
+
+        // images of dropped modules are released when the next module is added
+        modules.retain(|(module, _)| module.strong_count() > 0);
+
+        let imported_memories =
+            module.imports.iter().filter(|import| matches!(import.kind, ImportKind::Memory(_))).count();
+        let images: ModuleImages = (0..module.memory_types.len())
+            .map(|idx| MemoryImage::build(module, imported_memories, idx, chunk_size).map(Arc::new))
+            .collect();
+        let image = images.get(idx)?.clone();
+        modules.push((key, images));
+        image
+    }
+}
+
+/// A chunked linear memory that reads unwritten chunks from a shared [`MemoryImage`].
+///
+/// A chunk is copied from the image, or allocated if the image has none, the first time it is written.
+pub(crate) struct CowMemory {
+    len: usize,
+    chunk_size: usize,
+    chunk_shift: u32,
+    chunk_mask: usize,
+    image: Arc<MemoryImage>,
+    chunks: Vec<Option<Box<[u8]>>>,
+}
+
+impl CowMemory {
+    /// Creates a memory with the contents of `image`.
+    pub(crate) fn new(image: Arc<MemoryImage>) -> Result<Self, crate::Trap> {
+        let chunk_size = image.chunk_size;
+        assert!(chunk_size.is_power_of_two(), "chunk_size must be a power of two");
+
+        let len = image.len;
+        let mut memory = Self {
+            len: 0,
+            chunk_size,
+            chunk_shift: chunk_size.trailing_zeros(),
+            chunk_mask: chunk_size - 1,
+            image,
+            chunks: Vec::new(),
+        };
+        memory.grow_to(len)?;
+        Ok(memory)
+    }
+
+    /// Creates a zero-initialized memory of `len` bytes.
+    pub(crate) fn empty(len: usize, chunk_size: usize) -> Result<Self, crate::Trap> {
+        Self::new(Arc::new(MemoryImage::empty(len, chunk_size)))
+    }
+
+    #[inline(always)]
+    fn chunk_slice(&self, chunk_idx: usize) -> Option<&[u8]> {
+        self.chunks[chunk_idx].as_deref().or_else(|| self.image.chunk(chunk_idx))
+    }
+
+    #[inline(always)]
+    fn chunk_mut(&mut self, chunk_idx: usize) -> Result<&mut [u8], crate::Trap> {
+        if self.chunks[chunk_idx].is_none() {
+            let mut chunk = Vec::new();
+            if chunk.try_reserve_exact(self.chunk_size).is_err() {
+                cold_path();
+                return Err(crate::Trap::OutOfMemory);
+            }
+            match self.image.chunk(chunk_idx) {
+                Some(shared) => chunk.extend_from_slice(shared),
+                None => chunk.resize(self.chunk_size, 0),
+            }
+            self.chunks[chunk_idx] = Some(chunk.into_boxed_slice());
+        }
+
+        Ok(self.chunks[chunk_idx].as_deref_mut().unwrap_or_else(|| unreachable!()))
+    }
+
+    /// Returns the chunk index and offset of `[addr, addr + len)` if it lies in a single chunk.
+    #[inline(always)]
+    fn single_chunk_range(&self, addr: usize, len: usize) -> Option<(usize, usize)> {
+        if addr.checked_add(len)? > self.len {
+            return None;
+        }
+        let chunk_offset = addr & self.chunk_mask;
+        if chunk_offset + len > self.chunk_size {
+            return None;
+        }
+        Some((addr >> self.chunk_shift, chunk_offset))
+    }
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for CowMemory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let copied_chunks = self.chunks.iter().filter(|chunk| chunk.is_some()).count();
+        f.debug_struct("CowMemory")
+            .field("len", &self.len)
+            .field("chunk_size", &self.chunk_size)
+            .field("copied_chunks", &copied_chunks)
+            .finish()
+    }
+}
+
+impl LinearMemory for CowMemory {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn grow_to(&mut self, new_len: usize) -> Result<(), crate::Trap> {
+        if new_len < self.len {
+            return Err(crate::Trap::MemoryOutOfBounds { offset: new_len, len: 0, max: self.len });
+        }
+
+        let new_chunk_count = new_len.div_ceil(self.chunk_size);
+        if new_chunk_count > self.chunks.len() {
+            if self.chunks.try_reserve_exact(new_chunk_count - self.chunks.len()).is_err() {
+                cold_path();
+                return Err(crate::Trap::OutOfMemory);
+            }
+            self.chunks.resize_with(new_chunk_count, || None);
+        }
+
+        self.len = new_len;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn read(&self, addr: usize, dst: &mut [u8]) -> usize {
+        if addr >= self.len || dst.is_empty() {
+            return 0;
+        }
+
+        let chunk_idx = addr >> self.chunk_shift;
+        let chunk_offset = addr & self.chunk_mask;
+        let read_len = min(min(self.chunk_size - chunk_offset, self.len - addr), dst.len());
+        match self.chunk_slice(chunk_idx) {
+            Some(chunk) => dst[..read_len].copy_from_slice(&chunk[chunk_offset..chunk_offset + read_len]),
+            None => dst[..read_len].fill(0),
+        }
+        read_len
+    }
+
+    #[inline(always)]
+    fn write(&mut self, addr: usize, src: &[u8]) -> usize {
+        if addr >= self.len || src.is_empty() {
+            return 0;
+        }
+
+        let chunk_idx = addr >> self.chunk_shift;
+        let chunk_offset = addr & self.chunk_mask;
+        let write_len = min(min(self.chunk_size - chunk_offset, self.len - addr), src.len());
+        let Ok(chunk) = self.chunk_mut(chunk_idx) else {
+            return 0;
+        };
+        chunk[chunk_offset..chunk_offset + write_len].copy_from_slice(&src[..write_len]);
+        write_len
+    }
+
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        let end = addr.checked_add(len)?;
+        if end > self.len {
+            return None;
+        }
+
+        let mut pos = addr;
+        while pos < end {
+            let chunk_idx = pos >> self.chunk_shift;
+            let chunk_offset = pos & self.chunk_mask;
+            let chunk_start = chunk_idx << self.chunk_shift;
+            let chunk_end = min(chunk_start + self.chunk_size, end);
+            let whole_chunk = chunk_offset == 0 && chunk_end == min(chunk_start + self.chunk_size, self.len);
+
+            // chunks covered completely are released, unless the image would show through
+            if whole_chunk && self.image.chunk(chunk_idx).is_none() {
+                self.chunks[chunk_idx] = None;
+            } else if whole_chunk || self.chunk_slice(chunk_idx).is_some() {
+                self.chunk_mut(chunk_idx).ok()?[chunk_offset..chunk_end - chunk_start].fill(0);
+            }
+            pos = chunk_end;
+        }
+        Some(())
+    }
+
+    #[inline(always)]
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        if len == 0 {
+            return (addr <= self.len).then_some(&[][..]);
+        }
+
+        let (chunk_idx, chunk_offset) = self.single_chunk_range(addr, len)?;
+        Some(&self.chunk_slice(chunk_idx)?[chunk_offset..chunk_offset + len])
+    }
+
+    #[inline(always)]
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        if len == 0 {
+            return (addr <= self.len).then_some(&mut [][..]);
+        }
+
+        let (chunk_idx, chunk_offset) = self.single_chunk_range(addr, len)?;
+        Some(&mut self.chunk_mut(chunk_idx).ok()?[chunk_offset..chunk_offset + len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CowMemory, LinearMemory, MemoryImage};
+    use alloc::sync::Arc;
+
+    fn image() -> Arc<MemoryImage> {
+        let chunks = alloc::vec![None, Some([1, 2, 3, 4].into()), None].into_boxed_slice();
+        Arc::new(MemoryImage { len: 12, chunk_size: 4, chunks })
+    }
+
+    #[test]
+    fn cow_memory_reads_from_the_image() {
+        let memory = CowMemory::new(image()).unwrap();
+        assert_eq!(memory.read_vec(2, 8).unwrap(), [0, 0, 1, 2, 3, 4, 0, 0]);
+        assert_eq!(memory.as_slice(5, 3), Some(&[2, 3, 4][..]));
+        assert!(memory.as_slice(0, 4).is_none());
+    }
+
+    #[test]
+    fn cow_memory_copies_chunks_on_write() {
+        let image = image();
+        let mut memory = CowMemory::new(image.clone()).unwrap();
+        memory.write_all(5, &[9]).unwrap();
+        memory.grow_to(16).unwrap();
+        memory.write_all(14, &[7]).unwrap();
+
+        assert_eq!(memory.read_vec(4, 4).unwrap(), [1, 9, 3, 4]);
+        assert_eq!(memory.read_vec(12, 4).unwrap(), [0, 0, 7, 0]);
+        assert_eq!(image.chunk(1), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(CowMemory::new(image).unwrap().read_vec(4, 4).unwrap(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn cow_memory_discard_hides_the_image() {
+        let mut memory = CowMemory::new(image()).unwrap();
+        memory.write_all(0, &[5; 4]).unwrap();
+        memory.discard(0, 8).unwrap();
+
+        assert!(memory.chunks[0].is_none());
+        assert_eq!(memory.read_vec(0, 8).unwrap(), [0; 8]);
+    }
+}
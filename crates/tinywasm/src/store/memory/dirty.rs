@@ -0,0 +1,266 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use super::LinearMemory;
+
+/// A linear memory wrapper that records which pages are written.
+///
+/// Written pages are kept in a bitmap until [`LinearMemory::clear_dirty`] is called. Before a page
+/// is first written, its previous contents are saved, so [`LinearMemory::reset_dirty`] can restore
+/// the memory to the state of the last clear by rewriting only the dirty pages.
+pub struct DirtyTrackingMemory {
+    inner: Box<dyn LinearMemory>,
+    page_shift: u32,
+    dirty: Vec<u64>,
+    saved: Vec<(usize, Box<[u8]>)>,
+}
+
+impl DirtyTrackingMemory {
+    /// Wraps `inner`, tracking writes in pages of `page_size` bytes.
+    ///
+    /// `page_size` has to be a power of two.
+    pub fn new(inner: impl LinearMemory + 'static, page_size: usize) -> Self {
+        Self::from_storage(Box::new(inner), page_size)
+    }
+
+    pub(crate) fn from_storage(inner: Box<dyn LinearMemory>, page_size: usize) -> Self {
+        assert!(page_size.is_power_of_two(), "page_size must be a power of two");
+        let page_shift = page_size.trailing_zeros();
+        let mut memory = Self { inner, page_shift, dirty: Vec::new(), saved: Vec::new() };
+        memory.resize_bitmap();
+        memory
+    }
+
+    fn resize_bitmap(&mut self) {
+        let pages = self.inner.len().div_ceil(1 << self.page_shift);
+        self.dirty.resize(pages.div_ceil(64), 0);
+    }
+
+    /// Marks the pages overlapping `[addr, addr + len)` as dirty, saving their contents first.
+    fn touch(&mut self, addr: usize, len: usize) {
+        let end = min(addr.saturating_add(len), self.inner.len());
+        if addr >= end {
+            return;
+        }
+
+        for page in addr >> self.page_shift..=(end - 1) >> self.page_shift {
+            let (word, bit) = (page / 64, 1 << (page % 64));
+            if self.dirty[word] & bit != 0 {
+                continue;
+            }
+
+            self.dirty[word] |= bit;
+            let start = page << self.page_shift;
+            let page_len = min(1 << self.page_shift, self.inner.len() - start);
+            let contents = self.inner.read_vec(start, page_len).unwrap_or_default();
+            self.saved.push((start, contents.into_boxed_slice()));
+        }
+    }
+
+    /// Unmarks the pages touched since `saved` pages were saved, after a write failed.
+    fn untouch(&mut self, saved: usize) {
+        for (start, _) in self.saved.drain(saved..) {
+            let page = start >> self.page_shift;
+            self.dirty[page / 64] &= !(1 << (page % 64));
+        }
+    }
+
+    /// Runs `write` on the pages overlapping `[addr, addr + len)`, marking them dirty only if it succeeds.
+    fn tracked<T>(
+        &mut self,
+        addr: usize,
+        len: usize,
+        write: impl FnOnce(&mut dyn LinearMemory) -> Option<T>,
+    ) -> Option<T> {
+        let saved = self.saved.len();
+        self.touch(addr, len);
+        let result = write(&mut *self.inner);
+        if result.is_none() {
+            self.untouch(saved);
+        }
+        result
+    }
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for DirtyTrackingMemory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DirtyTrackingMemory")
+            .field("page_size", &(1usize << self.page_shift))
+            .field("dirty_pages", &self.saved.len())
+            .finish()
+    }
+}
+
+macro_rules! tracked_fixed_width {
+    ($($read:ident, $write:ident, $size:literal),*) => {
+        $(
+            #[inline(always)]
+            fn $read(&self, addr: usize) -> core::result::Result<[u8; $size], crate::Trap> {
+                self.inner.$read(addr)
+            }
+
+            #[inline(always)]
+            fn $write(&mut self, addr: usize, bytes: &[u8]) -> core::result::Result<(), crate::Trap> {
+                let saved = self.saved.len();
+                self.touch(addr, $size);
+                let result = self.inner.$write(addr, bytes);
+                if result.is_err() {
+                    self.untouch(saved);
+                }
+                result
+            }
+        )*
+    };
+}
+
+impl LinearMemory for DirtyTrackingMemory {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn grow_to(&mut self, new_len: usize) -> Result<(), crate::Trap> {
+        self.inner.grow_to(new_len)?;
+        self.resize_bitmap();
+        Ok(())
+    }
+
+    fn read(&self, addr: usize, dst: &mut [u8]) -> usize {
+        self.inner.read(addr, dst)
+    }
+
+    fn write(&mut self, addr: usize, src: &[u8]) -> usize {
+        // stop at the end of the page, so only written pages are marked
+        let page_end = (addr | ((1 << self.page_shift) - 1)).saturating_add(1);
+        let len = min(src.len(), page_end - addr);
+        let saved = self.saved.len();
+        self.touch(addr, len);
+        let written = self.inner.write(addr, &src[..len]);
+        if written == 0 {
+            self.untouch(saved);
+        }
+        written
+    }
+
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        self.inner.as_slice(addr, len)
+    }
+
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        // the slice can't be returned from `tracked`, so check that it exists first
+        self.tracked(addr, len, |inner| inner.as_mut_slice(addr, len).map(|_| ()))?;
+        self.inner.as_mut_slice(addr, len)
+    }
+
+    fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
+        self.tracked(addr, src.len(), |inner| inner.write_all(addr, src))
+    }
+
+    fn fill(&mut self, addr: usize, len: usize, val: u8) -> Option<()> {
+        self.tracked(addr, len, |inner| inner.fill(addr, len, val))
+    }
+
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        self.tracked(addr, len, |inner| inner.discard(addr, len))
+    }
+
+    fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Option<()> {
+        self.tracked(dst, len, |inner| inner.copy_within(dst, src, len))
+    }
+
+    fn read_exact(&self, addr: usize, dst: &mut [u8]) -> Option<()> {
+        self.inner.read_exact(addr, dst)
+    }
+
+    fn dirty_pages(&self) -> Option<Vec<usize>> {
+        let mut pages = Vec::with_capacity(self.saved.len());
+        for (word_idx, &word) in self.dirty.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                pages.push(word_idx * 64 + word.trailing_zeros() as usize);
+                word &= word - 1;
+            }
+        }
+        Some(pages)
+    }
+
+    fn clear_dirty(&mut self) -> Option<()> {
+        self.dirty.fill(0);
+        self.saved.clear();
+        Some(())
+    }
+
+    fn reset_dirty(&mut self) -> Option<()> {
+        for (start, contents) in self.saved.drain(..) {
+            self.inner.write_all(start, &contents)?;
+        }
+        self.dirty.fill(0);
+        Some(())
+    }
+
+    fn flush(&mut self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    fn sync(&mut self) -> crate::Result<()> {
+        self.inner.sync()
+    }
+
+    fn discard_unflushed(&mut self) {
+        self.inner.discard_unflushed();
+    }
+
+    tracked_fixed_width!(
+        read_8, write_8, 1, read_16, write_16, 2, read_32, write_32, 4, read_64, write_64, 8, read_128, write_128, 16
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirtyTrackingMemory, LinearMemory};
+    use crate::{PagedMemory, VecMemory};
+
+    #[test]
+    fn dirty_tracking_records_written_pages() {
+        let mut memory = DirtyTrackingMemory::new(VecMemory::try_new(64).unwrap(), 16);
+        memory.write_32(14, &[1; 4]).unwrap();
+        memory.fill(40, 2, 7).unwrap();
+        assert_eq!(memory.write(47, &[1, 2, 3]), 1);
+        assert_eq!(memory.dirty_pages(), Some(alloc::vec![0, 1, 2]));
+
+        memory.clear_dirty().unwrap();
+        assert_eq!(memory.dirty_pages(), Some(alloc::vec![]));
+        memory.grow_to(128).unwrap();
+        memory.copy_within(100, 14, 4).unwrap();
+        assert_eq!(memory.dirty_pages(), Some(alloc::vec![6]));
+    }
+
+    #[test]
+    fn dirty_tracking_ignores_failed_writes() {
+        let mut memory = DirtyTrackingMemory::new(PagedMemory::try_new(64, 32).unwrap(), 16);
+        assert!(memory.write_32(62, &[1; 4]).is_err());
+        assert!(memory.fill(60, 8, 7).is_none());
+        assert!(memory.copy_within(56, 0, 16).is_none());
+        // the pages exist, but can't be borrowed as one slice across chunks
+        assert!(memory.as_mut_slice(24, 16).is_none());
+        assert_eq!(memory.dirty_pages(), Some(alloc::vec![]));
+
+        memory.write_8(20, &[1]).unwrap();
+        memory.reset_dirty().unwrap();
+        assert_eq!(memory.read_vec(0, 64).unwrap(), [0; 64]);
+    }
+
+    #[test]
+    fn dirty_tracking_resets_dirty_pages() {
+        let mut memory = DirtyTrackingMemory::new(PagedMemory::try_new(32, 8).unwrap(), 8);
+        memory.write_all(0, &[1; 32]).unwrap();
+        memory.clear_dirty().unwrap();
+
+        memory.write_all(6, &[2; 4]).unwrap();
+        memory.write_64(16, &[3; 8]).unwrap();
+        memory.reset_dirty().unwrap();
+        assert_eq!(memory.read_vec(0, 32).unwrap(), [1; 32]);
+        assert_eq!(memory.dirty_pages(), Some(alloc::vec![]));
+    }
+}
@@ -0,0 +1,239 @@
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use crate::std::fs::{File, OpenOptions, TryLockError};
+use crate::std::io::{Read, Seek, SeekFrom, Write};
+use crate::std::path::Path;
+use crate::{Error, Result};
+
+use super::{LinearMemory, PagedMemory};
+
+/// A linear memory stored in a file.
+///
+/// The file holds the raw contents of the memory and is read in chunks of `chunk_size` bytes when
+/// the memory is opened. Afterwards, the memory is kept in a [`PagedMemory`] and written chunks are
+/// only written back to the file by [`LinearMemory::flush`] and [`LinearMemory::sync`], or when the
+/// memory is dropped. Chunks of zeroes are not allocated until they are written.
+///
+/// The file is locked exclusively while the memory is open, so two memories can't overwrite each
+/// other's contents. Opening a file that is already in use fails.
+///
+/// Available with the `std` feature enabled.
+pub struct FileMemory {
+    file: File,
+    memory: PagedMemory,
+    chunk_shift: u32,
+    dirty: Vec<bool>,
+    file_len: usize,
+    /// Set by [`LinearMemory::discard_unflushed`], after which the file is never written again.
+    discarded: bool,
+}
+
+impl FileMemory {
+    /// Opens the memory stored at `path`, creating an empty file if it doesn't exist.
+    ///
+    /// Fails if the file is locked by another memory or `chunk_size` is not a power of two.
+    pub fn open(path: impl AsRef<Path>, chunk_size: usize) -> Result<Self> {
+        check_chunk_size(chunk_size)?;
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Self::from_file(file, chunk_size)
+    }
+
+    /// Reads the memory stored in `file`, which has to be opened for reading and writing.
+    ///
+    /// Fails if the file is locked by another memory or `chunk_size` is not a power of two.
+    pub fn from_file(mut file: File, chunk_size: usize) -> Result<Self> {
+        check_chunk_size(chunk_size)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(Error::Other("memory file is already in use by another memory".into()));
+            }
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        let file_len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| Error::UnsupportedFeature("memory size exceeds the host address space"))?;
+        let mut memory = PagedMemory::try_new(file_len, chunk_size)?;
+
+        file.seek(SeekFrom::Start(0))?;
+        let mut chunk = vec![0; min(chunk_size, file_len)];
+        for start in (0..file_len).step_by(chunk_size) {
+            let chunk = &mut chunk[..min(chunk_size, file_len - start)];
+            file.read_exact(chunk)?;
+            if chunk.iter().any(|&byte| byte != 0) {
+                memory.write_all(start, chunk).ok_or(crate::Trap::OutOfMemory)?;
+            }
+        }
+
+        let dirty = vec![false; file_len.div_ceil(chunk_size)];
+        Ok(Self { file, memory, chunk_shift: chunk_size.trailing_zeros(), dirty, file_len, discarded: false })
+    }
+
+    /// Returns true if the file had contents when it was last read or written.
+    pub(crate) fn is_restored(&self) -> bool {
+        self.file_len > 0
+    }
+
+    fn mark_dirty(&mut self, addr: usize, len: usize) {
+        let end = min(addr.saturating_add(len), self.memory.len());
+        if addr < end {
+            self.dirty[addr >> self.chunk_shift..=(end - 1) >> self.chunk_shift].fill(true);
+        }
+    }
+}
+
+fn check_chunk_size(chunk_size: usize) -> Result<()> {
+    if !chunk_size.is_power_of_two() {
+        return Err(Error::Other(format!("memory file chunk size {chunk_size} is not a power of two")));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for FileMemory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FileMemory")
+            .field("file", &self.file)
+            .field("len", &self.memory.len())
+            .field("dirty_chunks", &self.dirty.iter().filter(|dirty| **dirty).count())
+            .finish()
+    }
+}
+
+impl Drop for FileMemory {
+    fn drop(&mut self) {
+        // like `BufWriter`, errors on drop are ignored; call `flush` to handle them
+        let _ = self.flush();
+    }
+}
+
+macro_rules! persisted_fixed_width {
+    ($($read:ident, $write:ident, $size:literal),*) => {
+        $(
+            #[inline(always)]
+            fn $read(&self, addr: usize) -> core::result::Result<[u8; $size], crate::Trap> {
+                self.memory.$read(addr)
+            }
+
+            #[inline(always)]
+            fn $write(&mut self, addr: usize, bytes: &[u8]) -> core::result::Result<(), crate::Trap> {
+                self.mark_dirty(addr, $size);
+                self.memory.$write(addr, bytes)
+            }
+        )*
+    };
+}
+
+impl LinearMemory for FileMemory {
+    fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn grow_to(&mut self, new_len: usize) -> core::result::Result<(), crate::Trap> {
+        self.memory.grow_to(new_len)?;
+        self.dirty.resize(new_len.div_ceil(1 << self.chunk_shift), false);
+        Ok(())
+    }
+
+    fn read(&self, addr: usize, dst: &mut [u8]) -> usize {
+        self.memory.read(addr, dst)
+    }
+
+    fn write(&mut self, addr: usize, src: &[u8]) -> usize {
+        let written = self.memory.write(addr, src);
+        self.mark_dirty(addr, written);
+        written
+    }
+
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        self.memory.as_slice(addr, len)
+    }
+
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        self.mark_dirty(addr, len);
+        self.memory.as_mut_slice(addr, len)
+    }
+
+    fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
+        self.mark_dirty(addr, src.len());
+        self.memory.write_all(addr, src)
+    }
+
+    fn fill(&mut self, addr: usize, len: usize, val: u8) -> Option<()> {
+        self.mark_dirty(addr, len);
+        self.memory.fill(addr, len, val)
+    }
+
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        self.mark_dirty(addr, len);
+        self.memory.discard(addr, len)
+    }
+
+    fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Option<()> {
+        self.mark_dirty(dst, len);
+        self.memory.copy_within(dst, src, len)
+    }
+
+    fn read_exact(&self, addr: usize, dst: &mut [u8]) -> Option<()> {
+        self.memory.read_exact(addr, dst)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.discarded {
+            return Ok(());
+        }
+
+        let chunk_size = 1 << self.chunk_shift;
+        let len = self.memory.len();
+        let mut buf = Vec::new();
+        for chunk_idx in 0..self.dirty.len() {
+            if !self.dirty[chunk_idx] {
+                continue;
+            }
+
+            let start = chunk_idx << self.chunk_shift;
+            let chunk_len = min(chunk_size, len - start);
+            let chunk = match self.memory.as_slice(start, chunk_len) {
+                Some(chunk) => chunk,
+                None => {
+                    buf.resize(chunk_len, 0);
+                    self.memory.read_exact(start, &mut buf).ok_or(crate::Trap::MemoryOutOfBounds {
+                        offset: start,
+                        len: chunk_len,
+                        max: len,
+                    })?;
+                    &buf
+                }
+            };
+
+            self.file.seek(SeekFrom::Start(start as u64))?;
+            self.file.write_all(chunk)?;
+            self.dirty[chunk_idx] = false;
+        }
+
+        if self.file_len != len {
+            self.file.set_len(len as u64)?;
+            self.file_len = len;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    fn discard_unflushed(&mut self) {
+        self.dirty.fill(false);
+        self.discarded = true;
+    }
+
+    persisted_fixed_width!(
+        read_8, write_8, 1, read_16, write_16, 2, read_32, write_32, 4, read_64, write_64, 8, read_128, write_128, 16
+    );
+}
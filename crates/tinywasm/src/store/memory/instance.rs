@@ -1,9 +1,15 @@
+use alloc::boxed::Box;
 use alloc::format;
-use tinywasm_types::{MemoryArch, MemoryType};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use tinywasm_types::{MemoryArch, MemoryType, Module};
 
 use crate::{Error, MemoryBackend, Result, Trap};
 
-use super::{MemoryStorage, memory_oob};
+use super::protected::{ProtectedMemory, Protections};
+#[cfg(feature = "std")]
+use super::shared::{SharedLinearMemory, SharedMemory};
+use super::{MemoryProtection, MemoryStorage, VecMemory, grow_storage, memory_oob, rmw};
 use core::hint::cold_path;
 
 /// A WebAssembly Memory Instance
@@ -13,6 +19,10 @@ pub(crate) struct MemoryInstance {
     pub(crate) kind: MemoryType,
     pub(crate) inner: MemoryStorage,
     pub(crate) page_count: usize,
+    #[cfg(feature = "std")]
+    pub(crate) shared: Option<SharedMemory>,
+    /// The protected ranges, enforced by a [`ProtectedMemory`] wrapping the storage once a range is protected.
+    protections: Option<Rc<RefCell<Protections>>>,
 }
 
 #[cfg(feature = "debug")]
@@ -57,6 +67,10 @@ impl MemoryInstance {
     }
 
     pub(crate) fn new(kind: MemoryType, backend: &MemoryBackend) -> Result<Self> {
+        if kind.is_shared() {
+            return Self::new_shared(kind);
+        }
+
         assert!(kind.page_count_initial() <= kind.page_count_max());
 
         let initial_len = usize::try_from(kind.initial_size())
@@ -68,18 +82,27 @@ impl MemoryInstance {
             kind.page_size()
         );
 
+        // backends may restore a memory that was grown before, see `MemoryBackend::file`
         let storage = backend.create(kind, initial_len)?;
-        if storage.len() != initial_len {
+        let page_size = kind.page_size() as usize;
+        if storage.len() < initial_len
+            || storage.len() % page_size != 0
+            || (storage.len() / page_size) as u64 > kind.page_count_max()
+        {
             return Err(Error::Other(format!(
                 "memory backend returned {} bytes for a memory that requires {initial_len}",
                 storage.len()
             )));
         }
 
-        Ok(Self { kind, inner: storage, page_count: kind.page_count_initial() as usize })
+        Ok(Self::from_storage(kind, storage))
     }
 
     pub(crate) fn new_lazy(kind: MemoryType, backend: &MemoryBackend) -> Result<Self> {
+        if kind.is_shared() {
+            return Self::new_shared(kind);
+        }
+
         assert!(kind.page_count_initial() <= kind.page_count_max());
 
         let initial_len = usize::try_from(kind.initial_size())
@@ -92,7 +115,124 @@ impl MemoryInstance {
         );
 
         let storage = backend.create_lazy(kind, initial_len)?;
-        Ok(Self { kind, inner: storage, page_count: kind.page_count_initial() as usize })
+        Ok(Self::from_storage(kind, storage))
+    }
+
+    /// Creates local memory `idx` of `module` with its active data segments already applied, if the backend supports it.
+    ///
+    /// See [`MemoryBackend::create_initialized`] for `ahead_of_time`.
+    pub(crate) fn new_initialized(
+        module: &Module,
+        idx: usize,
+        backend: &MemoryBackend,
+        ahead_of_time: bool,
+    ) -> Option<Self> {
+        // shared memories always use their own storage, see `MemoryInstance::new_shared`
+        let kind = *module.memory_types.get(idx).filter(|kind| !kind.is_shared())?;
+        let storage = backend.create_initialized(module, idx, ahead_of_time)?;
+        let page_size = kind.page_size() as usize;
+        if storage.len() % page_size != 0 || (storage.len() / page_size) as u64 > kind.page_count_max() {
+            return None;
+        }
+        Some(Self::from_storage(kind, storage))
+    }
+
+    /// Creates a shared memory, which needs the `std` feature for its lock and wait queues.
+    fn new_shared(kind: MemoryType) -> Result<Self> {
+        #[cfg(feature = "std")]
+        {
+            Ok(Self::from_shared(SharedMemory::new(kind)?))
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = kind;
+            Err(Error::UnsupportedFeature("shared memories require the `std` feature"))
+        }
+    }
+
+    fn from_storage(kind: MemoryType, inner: MemoryStorage) -> Self {
+        Self {
+            kind,
+            page_count: inner.len() / kind.page_size() as usize,
+            inner,
+            #[cfg(feature = "std")]
+            shared: None,
+            protections: None,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn from_shared(shared: SharedMemory) -> Self {
+        Self {
+            kind: shared.ty(),
+            inner: alloc::boxed::Box::new(SharedLinearMemory(shared.clone())),
+            page_count: shared.page_count(),
+            shared: Some(shared),
+            protections: None,
+        }
+    }
+
+    /// The current size in pages. Shared memories may be grown by other threads at any time.
+    pub(crate) fn page_count(&self) -> usize {
+        #[cfg(feature = "std")]
+        if let Some(shared) = &self.shared {
+            return shared.page_count();
+        }
+        self.page_count
+    }
+
+    /// Protects `[offset, offset + len)` with `protection`, or removes its protections if `protection` is `None`.
+    pub(crate) fn protect(&mut self, offset: usize, len: usize, protection: Option<MemoryProtection>) -> Result<()> {
+        #[cfg(feature = "std")]
+        if self.shared.is_some() {
+            return Err(Error::UnsupportedFeature("protecting ranges of shared memories"));
+        }
+
+        let end = match offset.checked_add(len) {
+            Some(end) if end <= self.inner.len() => end,
+            _ => return Err(memory_oob(offset, len, self.inner.len()).into()),
+        };
+
+        let protections = match &self.protections {
+            Some(protections) => protections.clone(),
+            None => {
+                let protections = Rc::new(RefCell::new(Protections::default()));
+                let inner = core::mem::replace(&mut self.inner, Box::new(VecMemory::try_new(0)?));
+                self.inner = Box::new(ProtectedMemory::new(inner, protections.clone()));
+                self.protections = Some(protections.clone());
+                protections
+            }
+        };
+        protections.borrow_mut().set(offset, end, protection);
+        Ok(())
+    }
+
+    /// Whether no protected range denies writing to `[addr, addr + len)`.
+    pub(crate) fn writable(&self, addr: usize, len: usize) -> bool {
+        self.protections.as_ref().is_none_or(|protections| protections.borrow().first_denied(addr, len, true).is_none())
+    }
+
+    /// The trap for a failed access to `[addr, addr + len)`.
+    #[cold]
+    pub(crate) fn access_trap(&self, addr: usize, len: usize, write: bool) -> Trap {
+        if let Some(protections) = &self.protections
+            && let Some(denied) = protections.borrow().first_denied(addr, len, write)
+            && denied < self.inner.len()
+        {
+            return Trap::MemoryProtectionViolation { offset: addr, len };
+        }
+        memory_oob(addr, len, self.inner.len())
+    }
+
+    /// Check that `[addr, addr + len)` can be accessed without violating a protected range.
+    fn check_protection(&self, addr: usize, len: usize, write: bool) -> Result<(), Trap> {
+        if let Some(protections) = &self.protections
+            && protections.borrow().first_denied(addr, len, write).is_some()
+        {
+            cold_path();
+            return Err(Trap::MemoryProtectionViolation { offset: addr, len });
+        }
+        Ok(())
     }
 
     pub(crate) const fn is_64bit(&self) -> bool {
@@ -122,6 +262,10 @@ impl MemoryInstance {
         check_range(&src_memory.inner, src, len)?;
         check_range(&self.inner, dst, len)?;
 
+        // the copy is done in chunks, so protections are checked up front to not write a partial copy
+        src_memory.check_protection(src, len, false)?;
+        self.check_protection(dst, len, true)?;
+
         if len == 0 {
             return Ok(());
         }
@@ -132,11 +276,11 @@ impl MemoryInstance {
             let chunk_len = buf.len().min(len - copied);
             src_memory.inner.read_exact(src + copied, &mut buf[..chunk_len]).ok_or_else(|| {
                 cold_path();
-                memory_oob(src + copied, chunk_len, src_memory.inner.len())
+                src_memory.access_trap(src + copied, chunk_len, false)
             })?;
             self.inner.write_all(dst + copied, &buf[..chunk_len]).ok_or_else(|| {
                 cold_path();
-                memory_oob(dst + copied, chunk_len, self.inner.len())
+                self.access_trap(dst + copied, chunk_len, true)
             })?;
             copied += chunk_len;
         }
@@ -144,58 +288,88 @@ impl MemoryInstance {
         Ok(())
     }
 
+    pub(crate) fn discard(&mut self, addr: usize, len: usize) -> Result<(), Trap> {
+        self.inner.discard(addr, len).ok_or_else(|| {
+            cold_path();
+            self.access_trap(addr, len, true)
+        })
+    }
+
     pub(crate) fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Result<(), Trap> {
         self.inner.copy_within(dst, src, len).ok_or_else(|| {
             cold_path();
-            memory_oob(dst, len, self.inner.len())
+            match self.access_trap(src, len, false) {
+                Trap::MemoryProtectionViolation { offset, len } => Trap::MemoryProtectionViolation { offset, len },
+                _ => self.access_trap(dst, len, true),
+            }
         })
     }
 
-    pub(crate) fn grow(&mut self, pages_delta: i64, trap_on_oom: bool) -> Result<Option<i64>, Trap> {
-        if pages_delta < 0 {
+    /// Atomically replaces the `N` bytes at `addr` with `f(old)` and returns the old bytes.
+    pub(crate) fn atomic_rmw<const N: usize>(
+        &mut self,
+        addr: usize,
+        f: impl FnOnce([u8; N]) -> [u8; N],
+    ) -> Result<[u8; N], Trap> {
+        #[cfg(feature = "std")]
+        if let Some(shared) = &self.shared {
+            return shared.rmw(addr, f);
+        }
+        rmw(&mut *self.inner, addr, f).map_err(|_| self.access_trap(addr, N, true))
+    }
+
+    pub(crate) fn atomic_wait<const N: usize>(
+        &self,
+        addr: usize,
+        expected: [u8; N],
+        timeout: i64,
+    ) -> Result<i32, Trap> {
+        if !self.kind.is_shared() {
             cold_path();
-            crate::log::debug!("memory.grow failed: negative delta {}", pages_delta);
-            return Ok(None);
+            return Err(Trap::ExpectedSharedMemory);
         }
 
-        let current_pages = self.page_count;
-        let Some(pages_delta) = usize::try_from(pages_delta).ok() else {
-            return Ok(None);
-        };
-        let Some(new_pages) = current_pages.checked_add(pages_delta) else {
-            return Ok(None);
-        };
-        let max_pages = self.kind.page_count_max().try_into().unwrap_or(usize::MAX);
+        #[cfg(feature = "std")]
+        if let Some(shared) = &self.shared {
+            return shared.wait(addr, expected, timeout);
+        }
 
-        if new_pages > max_pages {
+        // Without `std` there are no other threads that could notify this one.
+        let mut current = [0; N];
+        if self.inner.read_exact(addr, &mut current).is_none() {
             cold_path();
-            crate::log::debug!("memory.grow failed: new_pages={}, max_pages={}", new_pages, max_pages);
-            return Ok(None);
+            return Err(memory_oob(addr, N, self.inner.len()));
         }
+        match (current == expected, timeout < 0) {
+            (false, _) => Ok(1),
+            (true, false) => Ok(2),
+            (true, true) => {
+                cold_path();
+                Err(Trap::Other("memory.atomic.wait would block forever"))
+            }
+        }
+    }
 
-        let Some(new_size) = (new_pages as u64).checked_mul(self.kind.page_size()) else {
-            return Ok(None);
-        };
-        if new_size > self.kind.max_size() {
+    pub(crate) fn atomic_notify(&self, addr: usize, count: u32) -> Result<u32, Trap> {
+        if addr.checked_add(4).is_none_or(|end| end > self.inner.len()) {
             cold_path();
-            crate::log::debug!("memory.grow failed: new_size={}, max_size={}", new_size, self.kind.max_size());
-            return Ok(None);
+            return Err(memory_oob(addr, 4, self.inner.len()));
         }
 
-        let Some(new_size) = usize::try_from(new_size).ok() else {
-            return Ok(None);
-        };
-        if new_size == self.inner.len() {
-            return Ok(i64::try_from(current_pages).ok());
+        #[cfg(feature = "std")]
+        if let Some(shared) = &self.shared {
+            return Ok(shared.notify(addr, count));
         }
 
-        if let Err(err) = self.inner.grow_to(new_size) {
-            if trap_on_oom {
-                return Err(err);
-            }
-            return Ok(None);
+        let _ = count;
+        Ok(0)
+    }
+
+    pub(crate) fn grow(&mut self, pages_delta: i64, trap_on_oom: bool) -> Result<Option<i64>, Trap> {
+        #[cfg(feature = "std")]
+        if let Some(shared) = &self.shared {
+            return shared.grow_inner(pages_delta, trap_on_oom);
         }
-        self.page_count = new_pages;
-        Ok(i64::try_from(current_pages).ok())
+        grow_storage(self.kind, &mut *self.inner, &mut self.page_count, pages_delta, trap_on_oom)
     }
 }
@@ -82,6 +82,31 @@ impl LinearMemory for LazyLinearMemory {
         self.materialize().write(addr, src)
     }
 
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        self.inner.as_deref()?.as_slice(addr, len)
+    }
+
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        if addr.checked_add(len)? > self.len() {
+            return None;
+        }
+        self.try_materialize().ok()?.as_mut_slice(addr, len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.as_deref_mut().map_or(Ok(()), LinearMemory::flush)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.as_deref_mut().map_or(Ok(()), LinearMemory::sync)
+    }
+
+    fn discard_unflushed(&mut self) {
+        if let Some(inner) = &mut self.inner {
+            inner.discard_unflushed();
+        }
+    }
+
     fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
         let end = addr.checked_add(src.len())?;
         if end > self.len() {
@@ -104,6 +129,13 @@ impl LinearMemory for LazyLinearMemory {
         self.materialize().fill(addr, len, val)
     }
 
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        match self.inner.as_deref_mut() {
+            Some(inner) => inner.discard(addr, len),
+            None => (addr.checked_add(len)? <= self.initial_len).then_some(()),
+        }
+    }
+
     fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Option<()> {
         let src_end = src.checked_add(len)?;
         let dst_end = dst.checked_add(len)?;
@@ -3,20 +3,31 @@ use alloc::{vec, vec::Vec};
 use core::cmp::min;
 use core::hint::cold_path;
 
-use tinywasm_types::MemoryType;
+use tinywasm_types::{MemoryType, Module};
 
 use crate::interpreter::Value128;
 use crate::{Error, Result};
 
+#[cfg(feature = "std")]
+mod cow;
+mod dirty;
+#[cfg(feature = "std")]
+mod file;
 mod instance;
 mod lazy;
 
 mod paged;
+mod protected;
+#[cfg(feature = "std")]
+mod shared;
 #[path = "vec.rs"]
 mod vec_memory;
 
 pub(crate) use instance::MemoryInstance;
-pub use {lazy::LazyLinearMemory, paged::PagedMemory, vec_memory::VecMemory};
+pub use protected::MemoryProtection;
+pub use {dirty::DirtyTrackingMemory, lazy::LazyLinearMemory, paged::PagedMemory, vec_memory::VecMemory};
+#[cfg(feature = "std")]
+pub use {file::FileMemory, shared::SharedMemory};
 
 /// Backend storage for a linear memory
 ///
@@ -50,6 +61,62 @@ pub trait LinearMemory {
     /// non-contiguous backends stop at a natural boundary such as the end of a chunk.
     fn write(&mut self, addr: usize, src: &[u8]) -> usize;
 
+    /// Returns the range `[addr, addr + len)` as one contiguous slice, if the backend stores it that way.
+    ///
+    /// This is an optional capability used for zero-copy host access. Backends return `None` when the
+    /// range is out of bounds or not contiguous in their storage, and callers fall back to [`Self::read`].
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        let _ = (addr, len);
+        None
+    }
+
+    /// Returns the range `[addr, addr + len)` as one contiguous mutable slice, if the backend stores it that way.
+    ///
+    /// See [`Self::as_slice`]. Callers fall back to [`Self::write`] when this returns `None`.
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        let _ = (addr, len);
+        None
+    }
+
+    /// Returns the indices of the pages written since the last [`Self::clear_dirty`] in ascending order.
+    ///
+    /// This is an optional capability of backends that track writes, like [`DirtyTrackingMemory`].
+    /// Other backends return `None`.
+    fn dirty_pages(&self) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// Marks all pages as clean, making the current contents the ones restored by [`Self::reset_dirty`].
+    ///
+    /// Returns `None` if the backend doesn't track writes.
+    fn clear_dirty(&mut self) -> Option<()> {
+        None
+    }
+
+    /// Restores the pages written since the last [`Self::clear_dirty`] and marks them as clean.
+    ///
+    /// Returns `None` if the backend doesn't track writes.
+    fn reset_dirty(&mut self) -> Option<()> {
+        None
+    }
+
+    /// Writes changes that are only held in memory to the backend's persistent storage, like [`FileMemory`].
+    ///
+    /// Backends without persistent storage have nothing to write and return `Ok(())`.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes the memory like [`Self::flush`] and waits until the storage device has written all changes.
+    fn sync(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    /// Drops changes that were not flushed yet, so they are never written to persistent storage.
+    ///
+    /// Called before a failed instantiation removes the memory. Backends without persistent storage ignore it.
+    fn discard_unflushed(&mut self) {}
+
     /// Writes all bytes in `src` starting at `addr`, or returns `None` if any byte could not be written.
     fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
         let Some(end) = addr.checked_add(src.len()) else {
@@ -93,6 +160,14 @@ pub trait LinearMemory {
         Some(())
     }
 
+    /// Zeroes the range `[addr, addr + len)` and releases the storage backing it where possible.
+    ///
+    /// This implements `memory.discard`. [`PagedMemory`] frees the chunks the range covers completely,
+    /// while contiguous backends like [`VecMemory`] only zero the range.
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        self.fill(addr, len, 0)
+    }
+
     /// Copies `len` bytes from `src` to `dst` within the same memory.
     fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Option<()> {
         let src_end = src.checked_add(len)?;
@@ -272,6 +347,17 @@ enum MemoryBackendInner {
         chunk_size: usize,
     },
     Custom(Arc<MemoryFactory>),
+    #[cfg(feature = "std")]
+    CopyOnWrite {
+        chunk_size: usize,
+        images: Arc<cow::ImageCache>,
+    },
+    DirtyTracking(Arc<MemoryBackend>),
+    #[cfg(feature = "std")]
+    File {
+        path: crate::std::path::PathBuf,
+        chunk_size: usize,
+    },
 }
 
 impl MemoryBackend {
@@ -294,6 +380,55 @@ impl MemoryBackend {
         Self(MemoryBackendInner::Paged { chunk_size })
     }
 
+    /// Shares the initial contents of module memories between instances and copies chunks on their first write.
+    ///
+    /// The first instantiation of a module builds an immutable image of each of its memories with the
+    /// active data segments applied, which is kept for as long as the module is alive. Instances of the
+    /// module read from that image until they write to a chunk, so many instances of one module share
+    /// their read-only data. `chunk_size` is the size of the copied chunks in bytes and has to be a
+    /// power of two.
+    ///
+    /// Segments are only applied ahead of time if their offsets are constants. Without
+    /// [`Config::atomic_instantiation`](crate::engine::Config::atomic_instantiation), a failed instantiation
+    /// leaves the segments written before the failure in place, so images are also only used if none of the
+    /// module's active segments can be out of bounds. Other memories, including memories created by the
+    /// host, behave like [`Self::paged`] ones.
+    ///
+    /// Available with the `std` feature enabled.
+    #[cfg(feature = "std")]
+    pub fn copy_on_write(chunk_size: usize) -> Self {
+        assert!(chunk_size.is_power_of_two(), "chunk_size must be a power of two");
+        Self(MemoryBackendInner::CopyOnWrite { chunk_size, images: Arc::default() })
+    }
+
+    /// Stores memories in the file at `path`, see [`FileMemory`].
+    ///
+    /// The file is read when a memory is created, so a memory written by an earlier process is
+    /// restored with its contents and size. Active data segments are not applied again to a restored
+    /// memory of a module instance. Changes are written to the file by [`Memory::flush`](crate::Memory::flush)
+    /// and [`Memory::sync`](crate::Memory::sync), or when the store is dropped.
+    ///
+    /// Every memory created with this backend uses the same file, which is locked while the memory is
+    /// alive, so creating a second memory fails until the first one is dropped. Use
+    /// [`Config::with_memory_backend_selector`](crate::engine::Config::with_memory_backend_selector) to
+    /// give each memory of a module its own file. `chunk_size` has to be a power of two.
+    /// Available with the `std` feature enabled.
+    #[cfg(feature = "std")]
+    pub fn file(path: impl Into<crate::std::path::PathBuf>, chunk_size: usize) -> Self {
+        assert!(chunk_size.is_power_of_two(), "chunk_size must be a power of two");
+        Self(MemoryBackendInner::File { path: path.into(), chunk_size })
+    }
+
+    /// Wraps the memories created by this backend in a [`DirtyTrackingMemory`] with the page size of the memory.
+    ///
+    /// This enables [`Memory::dirty_pages`](crate::Memory::dirty_pages),
+    /// [`Memory::clear_dirty`](crate::Memory::clear_dirty) and [`Memory::reset_dirty`](crate::Memory::reset_dirty).
+    /// Memories of a module instance start out clean once its data segments are applied, so resetting
+    /// them restores their initial contents. Shared memories are not tracked.
+    pub fn track_dirty_pages(self) -> Self {
+        Self(MemoryBackendInner::DirtyTracking(Arc::new(self)))
+    }
+
     /// Uses a custom factory to create memory instances.
     pub fn custom<F, M>(factory: F) -> Self
     where
@@ -315,6 +450,21 @@ impl MemoryBackend {
                 Box::new(PagedMemory::try_new(initial_len, *chunk_size).map_err(Error::Trap)?) as Box<dyn LinearMemory>
             }
             MemoryBackendInner::Custom(factory) => factory(ty)?,
+            #[cfg(feature = "std")]
+            MemoryBackendInner::CopyOnWrite { chunk_size, .. } => {
+                Box::new(cow::CowMemory::empty(initial_len, *chunk_size).map_err(Error::Trap)?) as Box<dyn LinearMemory>
+            }
+            MemoryBackendInner::DirtyTracking(inner) => {
+                Box::new(DirtyTrackingMemory::from_storage(inner.create(ty, initial_len)?, ty.page_size() as usize))
+            }
+            #[cfg(feature = "std")]
+            MemoryBackendInner::File { path, chunk_size } => {
+                let mut memory = FileMemory::open(path, *chunk_size)?;
+                if memory.len() < initial_len {
+                    memory.grow_to(initial_len)?;
+                }
+                Box::new(memory)
+            }
         };
 
         if storage.len() < initial_len {
@@ -327,7 +477,50 @@ impl MemoryBackend {
         Ok(storage)
     }
 
+    /// Creates local memory `idx` of `module` with its active data segments already applied.
+    ///
+    /// Restored file memories are returned regardless of `ahead_of_time`, as their segments are never applied
+    /// again. Returns `None` if this backend doesn't share memory images, the segments can't be applied
+    /// ahead of time, or there is nothing to restore.
+    pub(crate) fn create_initialized(&self, module: &Module, idx: usize, ahead_of_time: bool) -> Option<MemoryStorage> {
+        match &self.0 {
+            #[cfg(feature = "std")]
+            MemoryBackendInner::CopyOnWrite { chunk_size, images } if ahead_of_time => {
+                let image = images.get(module, idx, *chunk_size)?;
+                Some(Box::new(cow::CowMemory::new(image).ok()?))
+            }
+            MemoryBackendInner::DirtyTracking(inner) => {
+                let storage = inner.create_initialized(module, idx, ahead_of_time)?;
+                let page_size = module.memory_types.get(idx)?.page_size() as usize;
+                Some(Box::new(DirtyTrackingMemory::from_storage(storage, page_size)))
+            }
+            #[cfg(feature = "std")]
+            MemoryBackendInner::File { path, chunk_size } => {
+                let mut memory = FileMemory::open(path, *chunk_size).ok().filter(FileMemory::is_restored)?;
+                let initial_len = usize::try_from(module.memory_types.get(idx)?.initial_size()).ok()?;
+                if memory.len() < initial_len {
+                    memory.grow_to(initial_len).ok()?;
+                }
+                Some(Box::new(memory))
+            }
+            _ => {
+                let _ = (module, idx, ahead_of_time);
+                None
+            }
+        }
+    }
+
     pub(crate) fn create_lazy(&self, ty: MemoryType, initial_len: usize) -> Result<MemoryStorage> {
+        match &self.0 {
+            MemoryBackendInner::DirtyTracking(inner) => {
+                let storage = inner.create_lazy(ty, initial_len)?;
+                return Ok(Box::new(DirtyTrackingMemory::from_storage(storage, ty.page_size() as usize)));
+            }
+            // reads have to see the contents of the file
+            #[cfg(feature = "std")]
+            MemoryBackendInner::File { .. } => return self.create(ty, initial_len),
+            _ => {}
+        }
         Ok(Box::new(LazyLinearMemory::new_with_initial_len(ty, initial_len, self.clone())))
     }
 }
@@ -341,6 +534,17 @@ impl core::fmt::Debug for MemoryBackend {
                 f.debug_struct("MemoryBackend::Paged").field("chunk_size", chunk_size).finish()
             }
             MemoryBackendInner::Custom(_) => f.debug_tuple("MemoryBackend::Custom").finish(),
+            #[cfg(feature = "std")]
+            MemoryBackendInner::CopyOnWrite { chunk_size, .. } => {
+                f.debug_struct("MemoryBackend::CopyOnWrite").field("chunk_size", chunk_size).finish()
+            }
+            MemoryBackendInner::DirtyTracking(inner) => {
+                f.debug_tuple("MemoryBackend::DirtyTracking").field(inner).finish()
+            }
+            #[cfg(feature = "std")]
+            MemoryBackendInner::File { path, chunk_size } => {
+                f.debug_struct("MemoryBackend::File").field("path", path).field("chunk_size", chunk_size).finish()
+            }
         }
     }
 }
@@ -407,6 +611,75 @@ fn memory_oob(offset: usize, len: usize, max: usize) -> crate::Trap {
     crate::Trap::MemoryOutOfBounds { offset, len, max }
 }
 
+/// Grows `memory` by `pages_delta` pages, returning the previous page count on success.
+fn grow_storage(
+    kind: MemoryType,
+    memory: &mut dyn LinearMemory,
+    page_count: &mut usize,
+    pages_delta: i64,
+    trap_on_oom: bool,
+) -> core::result::Result<Option<i64>, crate::Trap> {
+    if pages_delta < 0 {
+        cold_path();
+        crate::log::debug!("memory.grow failed: negative delta {}", pages_delta);
+        return Ok(None);
+    }
+
+    let current_pages = *page_count;
+    let Some(pages_delta) = usize::try_from(pages_delta).ok() else {
+        return Ok(None);
+    };
+    let Some(new_pages) = current_pages.checked_add(pages_delta) else {
+        return Ok(None);
+    };
+    let max_pages = kind.page_count_max().try_into().unwrap_or(usize::MAX);
+
+    if new_pages > max_pages {
+        cold_path();
+        crate::log::debug!("memory.grow failed: new_pages={}, max_pages={}", new_pages, max_pages);
+        return Ok(None);
+    }
+
+    let Some(new_size) = (new_pages as u64).checked_mul(kind.page_size()) else {
+        return Ok(None);
+    };
+    if new_size > kind.max_size() {
+        cold_path();
+        crate::log::debug!("memory.grow failed: new_size={}, max_size={}", new_size, kind.max_size());
+        return Ok(None);
+    }
+
+    let Some(new_size) = usize::try_from(new_size).ok() else {
+        return Ok(None);
+    };
+    if new_size == memory.len() {
+        return Ok(i64::try_from(current_pages).ok());
+    }
+
+    if let Err(err) = memory.grow_to(new_size) {
+        if trap_on_oom {
+            return Err(err);
+        }
+        return Ok(None);
+    }
+    *page_count = new_pages;
+    Ok(i64::try_from(current_pages).ok())
+}
+
+/// Replaces the `N` bytes at `addr` with `f(old)` and returns the old bytes.
+fn rmw<const N: usize>(
+    memory: &mut dyn LinearMemory,
+    addr: usize,
+    f: impl FnOnce([u8; N]) -> [u8; N],
+) -> core::result::Result<[u8; N], crate::Trap> {
+    let mut old = [0; N];
+    if memory.read_exact(addr, &mut old).is_none() || memory.write_all(addr, &f(old)).is_none() {
+        cold_path();
+        return Err(memory_oob(addr, N, memory.len()));
+    }
+    Ok(old)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,6 +79,17 @@ impl PagedMemory {
         Some(end)
     }
 
+    /// Returns the chunk index and offset of `[addr, addr + len)` if it lies in a single chunk.
+    #[inline(always)]
+    fn single_chunk_range(&self, addr: usize, len: usize) -> Option<(usize, usize)> {
+        self.checked_end(addr, len)?;
+        let chunk_offset = addr & self.chunk_mask;
+        if chunk_offset + len > self.chunk_size {
+            return None;
+        }
+        Some((addr >> self.chunk_shift, chunk_offset))
+    }
+
     #[inline(always)]
     fn copy_within_single_chunk(&mut self, dst: usize, src: usize, len: usize) -> bool {
         if len == 0 {
@@ -187,6 +198,27 @@ impl LinearMemory for PagedMemory {
         write_len
     }
 
+    #[inline(always)]
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        if len == 0 {
+            return self.checked_end(addr, 0).map(|_| &[][..]);
+        }
+
+        // unallocated chunks have no storage to borrow, so their zeroes are read by copying instead
+        let (chunk_idx, chunk_offset) = self.single_chunk_range(addr, len)?;
+        Some(&self.chunk_slice(chunk_idx)?[chunk_offset..chunk_offset + len])
+    }
+
+    #[inline(always)]
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        if len == 0 {
+            return self.checked_end(addr, 0).map(|_| &mut [][..]);
+        }
+
+        let (chunk_idx, chunk_offset) = self.single_chunk_range(addr, len)?;
+        Some(&mut self.chunk_mut(chunk_idx).ok()?[chunk_offset..chunk_offset + len])
+    }
+
     #[inline(always)]
     fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
         let end = self.checked_end(addr, src.len())?;
@@ -332,6 +364,33 @@ mod tests {
         assert_eq!(dst, [1, 2, 1, 2, 3, 4, 5, 6]);
     }
 
+    #[test]
+    fn paged_memory_slices_are_limited_to_one_chunk() {
+        let mut memory = PagedMemory::try_new(16, 4).expect("test memory should be constructible");
+        assert!(memory.as_slice(4, 4).is_none());
+
+        memory.as_mut_slice(4, 4).unwrap().copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(memory.as_slice(5, 3), Some(&[2, 3, 4][..]));
+        assert!(memory.as_slice(3, 2).is_none());
+        assert!(memory.as_mut_slice(7, 2).is_none());
+        assert!(memory.as_slice(14, 4).is_none());
+        assert_eq!(memory.as_slice(16, 0), Some(&[][..]));
+    }
+
+    #[test]
+    fn paged_memory_discard_frees_covered_chunks() {
+        let mut memory = PagedMemory::try_new(16, 4).expect("test memory should be constructible");
+        memory.write_all(0, &[1; 16]).unwrap();
+        memory.discard(2, 12).unwrap();
+
+        assert_eq!(
+            memory.chunks.iter().map(Option::is_some).collect::<alloc::vec::Vec<_>>(),
+            [true, false, false, true]
+        );
+        assert_eq!(memory.read_vec(0, 16).unwrap(), [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1]);
+        assert!(memory.discard(12, 8).is_none());
+    }
+
     #[test]
     fn paged_memory_write_stops_at_chunk_boundary() {
         let mut memory = PagedMemory::try_new(16, 4).expect("test memory should be constructible");
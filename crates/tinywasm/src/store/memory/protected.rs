@@ -0,0 +1,221 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::cmp::max;
+
+use super::{LinearMemory, MemoryStorage};
+use crate::Trap;
+
+/// The access allowed to a protected range of a [`crate::Memory`], see [`crate::Memory::protect`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub enum MemoryProtection {
+    /// The range can be read, but writes trap.
+    ReadOnly,
+    /// Reads and writes of the range trap.
+    NoAccess,
+}
+
+/// The protected ranges of a memory, sorted by their start and not overlapping.
+#[derive(Default)]
+pub(crate) struct Protections {
+    ranges: Vec<(usize, usize, MemoryProtection)>,
+}
+
+impl Protections {
+    /// Protects `[start, end)` with `protection`, or removes its protections if `protection` is `None`.
+    pub(crate) fn set(&mut self, start: usize, end: usize, protection: Option<MemoryProtection>) {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 2);
+        for &(range_start, range_end, range_protection) in &self.ranges {
+            if range_end <= start || range_start >= end {
+                ranges.push((range_start, range_end, range_protection));
+                continue;
+            }
+            if range_start < start {
+                ranges.push((range_start, start, range_protection));
+            }
+            if range_end > end {
+                ranges.push((end, range_end, range_protection));
+            }
+        }
+
+        if let Some(protection) = protection
+            && start < end
+        {
+            ranges.push((start, end, protection));
+        }
+        ranges.sort_unstable_by_key(|&(range_start, ..)| range_start);
+        self.ranges = ranges;
+    }
+
+    /// Returns the first address in `[addr, addr + len)` that can't be accessed.
+    pub(crate) fn first_denied(&self, addr: usize, len: usize, write: bool) -> Option<usize> {
+        let end = addr.saturating_add(len);
+        let first = self.ranges.partition_point(|&(_, range_end, _)| range_end <= addr);
+        self.ranges[first..]
+            .iter()
+            .take_while(|&&(range_start, ..)| range_start < end)
+            .find(|&&(_, _, protection)| write || protection == MemoryProtection::NoAccess)
+            .map(|&(range_start, ..)| max(range_start, addr))
+    }
+}
+
+/// A linear memory wrapper that enforces [`Protections`].
+///
+/// Memories are only wrapped once a range is protected, so unprotected memories don't pay for the checks.
+pub(crate) struct ProtectedMemory {
+    inner: MemoryStorage,
+    protections: Rc<RefCell<Protections>>,
+}
+
+impl ProtectedMemory {
+    pub(crate) fn new(inner: MemoryStorage, protections: Rc<RefCell<Protections>>) -> Self {
+        Self { inner, protections }
+    }
+
+    /// Returns how many bytes starting at `addr` can be accessed, up to `len`.
+    #[inline]
+    fn accessible(&self, addr: usize, len: usize, write: bool) -> usize {
+        match self.protections.borrow().first_denied(addr, len, write) {
+            Some(denied) => denied - addr,
+            None => len,
+        }
+    }
+
+    #[inline]
+    fn check(&self, addr: usize, len: usize, write: bool) -> Result<(), Trap> {
+        match self.protections.borrow().first_denied(addr, len, write) {
+            Some(_) => Err(Trap::MemoryProtectionViolation { offset: addr, len }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for ProtectedMemory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ProtectedMemory").field("ranges", &self.protections.borrow().ranges).finish()
+    }
+}
+
+macro_rules! protected_fixed_width {
+    ($($read:ident, $write:ident, $size:literal),*) => {
+        $(
+            #[inline(always)]
+            fn $read(&self, addr: usize) -> Result<[u8; $size], Trap> {
+                self.check(addr, $size, false)?;
+                self.inner.$read(addr)
+            }
+
+            #[inline(always)]
+            fn $write(&mut self, addr: usize, bytes: &[u8]) -> Result<(), Trap> {
+                self.check(addr, $size, true)?;
+                self.inner.$write(addr, bytes)
+            }
+        )*
+    };
+}
+
+impl LinearMemory for ProtectedMemory {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn grow_to(&mut self, new_len: usize) -> Result<(), Trap> {
+        self.inner.grow_to(new_len)
+    }
+
+    fn read(&self, addr: usize, dst: &mut [u8]) -> usize {
+        let len = self.accessible(addr, dst.len(), false);
+        self.inner.read(addr, &mut dst[..len])
+    }
+
+    fn write(&mut self, addr: usize, src: &[u8]) -> usize {
+        let len = self.accessible(addr, src.len(), true);
+        self.inner.write(addr, &src[..len])
+    }
+
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        self.check(addr, len, false).ok()?;
+        self.inner.as_slice(addr, len)
+    }
+
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        self.check(addr, len, true).ok()?;
+        self.inner.as_mut_slice(addr, len)
+    }
+
+    fn dirty_pages(&self) -> Option<Vec<usize>> {
+        self.inner.dirty_pages()
+    }
+
+    fn clear_dirty(&mut self) -> Option<()> {
+        self.inner.clear_dirty()
+    }
+
+    fn reset_dirty(&mut self) -> Option<()> {
+        self.inner.reset_dirty()
+    }
+
+    fn flush(&mut self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    fn sync(&mut self) -> crate::Result<()> {
+        self.inner.sync()
+    }
+
+    fn discard_unflushed(&mut self) {
+        self.inner.discard_unflushed();
+    }
+
+    fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
+        self.check(addr, src.len(), true).ok()?;
+        self.inner.write_all(addr, src)
+    }
+
+    fn fill(&mut self, addr: usize, len: usize, val: u8) -> Option<()> {
+        self.check(addr, len, true).ok()?;
+        self.inner.fill(addr, len, val)
+    }
+
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        self.check(addr, len, true).ok()?;
+        self.inner.discard(addr, len)
+    }
+
+    fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Option<()> {
+        self.check(src, len, false).ok()?;
+        self.check(dst, len, true).ok()?;
+        self.inner.copy_within(dst, src, len)
+    }
+
+    fn read_exact(&self, addr: usize, dst: &mut [u8]) -> Option<()> {
+        self.check(addr, dst.len(), false).ok()?;
+        self.inner.read_exact(addr, dst)
+    }
+
+    protected_fixed_width!(
+        read_8, write_8, 1, read_16, write_16, 2, read_32, write_32, 4, read_64, write_64, 8, read_128, write_128, 16
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryProtection, Protections};
+
+    #[test]
+    fn protections_split_and_replace_ranges() {
+        let mut protections = Protections::default();
+        protections.set(10, 30, Some(MemoryProtection::ReadOnly));
+        protections.set(15, 20, Some(MemoryProtection::NoAccess));
+        protections.set(25, 40, None);
+
+        assert_eq!(protections.first_denied(0, 10, true), None);
+        assert_eq!(protections.first_denied(0, 12, true), Some(10));
+        assert_eq!(protections.first_denied(0, 40, false), Some(15));
+        assert_eq!(protections.first_denied(20, 5, false), None);
+        assert_eq!(protections.first_denied(22, 10, true), Some(22));
+        assert_eq!(protections.first_denied(25, 100, true), None);
+    }
+}
@@ -0,0 +1,299 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hint::cold_path;
+use core::time::Duration;
+
+use tinywasm_types::MemoryType;
+
+use super::{LinearMemory, VecMemory, grow_storage, memory_oob, rmw};
+use crate::std::sync::{Condvar, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::std::time::Instant;
+use crate::{Error, Result, Trap};
+
+/// A linear memory that can be shared between stores on different threads.
+///
+/// Every store that imports the same `SharedMemory` observes the same bytes, so several
+/// [`crate::Store`]s running on different OS threads can execute multi-threaded guests such as
+/// `wasi-threads` programs. Accesses are serialized through a lock, which makes the
+/// `memory.atomic.*` instructions atomic across threads and lets `memory.atomic.wait` block the
+/// calling thread until another thread calls `memory.atomic.notify`.
+///
+/// Shared memories always use contiguous storage and ignore the configured
+/// [`crate::MemoryBackend`]. Available with the `std` feature enabled.
+///
+/// ## Example
+/// ```rust
+/// # fn main() -> tinywasm::Result<()> {
+/// use tinywasm::types::MemoryType;
+/// use tinywasm::{Imports, SharedMemory};
+///
+/// let ty = MemoryType::default()
+///     .with_page_count_initial(1)
+///     .with_page_count_max(Some(4))
+///     .with_shared(true);
+/// let memory = SharedMemory::new(ty)?;
+///
+/// let handle = std::thread::spawn({
+///     let memory = memory.clone();
+///     move || memory.copy_from_slice(0, b"hi")
+/// });
+/// handle.join().unwrap()?;
+///
+/// assert_eq!(memory.read_vec(0, 2)?, b"hi");
+///
+/// let mut imports = Imports::new();
+/// imports.define("env", "memory", memory);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SharedMemory(Arc<SharedMemoryInner>);
+
+struct SharedMemoryInner {
+    ty: MemoryType,
+    data: RwLock<SharedData>,
+    waiters: Mutex<BTreeMap<usize, Vec<Arc<Waiter>>>>,
+}
+
+struct SharedData {
+    memory: VecMemory,
+    page_count: usize,
+}
+
+#[derive(Default)]
+struct Waiter {
+    notified: Mutex<bool>,
+    cond: Condvar,
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for SharedMemory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SharedMemory").field("ty", &self.0.ty).field("page_count", &self.page_count()).finish()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl SharedMemory {
+    /// Create a new shared memory.
+    ///
+    /// The memory type must be marked as shared with [`MemoryType::with_shared`], and its initial
+    /// page count must not exceed its maximum.
+    pub fn new(ty: MemoryType) -> Result<Self> {
+        if !ty.is_shared() {
+            return Err(Error::Other("shared memories require a shared memory type".into()));
+        }
+        if ty.page_count_initial() > ty.page_count_max() {
+            return Err(Error::Other(format!(
+                "shared memory has {} initial pages, more than its maximum of {}",
+                ty.page_count_initial(),
+                ty.page_count_max()
+            )));
+        }
+
+        let initial_len = usize::try_from(ty.initial_size())
+            .map_err(|_| Error::UnsupportedFeature("memory size exceeds the host address space"))?;
+
+        let memory = VecMemory::try_new(initial_len).map_err(Error::Trap)?;
+        let data = SharedData { memory, page_count: ty.page_count_initial() as usize };
+        Ok(Self(Arc::new(SharedMemoryInner { ty, data: RwLock::new(data), waiters: Mutex::new(BTreeMap::new()) })))
+    }
+
+    fn read_data(&self) -> RwLockReadGuard<'_, SharedData> {
+        self.0.data.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_data(&self) -> RwLockWriteGuard<'_, SharedData> {
+        self.0.data.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Returns the memory type, including page size and limits.
+    pub fn ty(&self) -> MemoryType {
+        self.0.ty
+    }
+
+    /// Returns the raw memory byte length.
+    pub fn len(&self) -> usize {
+        self.read_data().memory.len()
+    }
+
+    /// Returns true if the memory is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the current size of the memory in pages.
+    pub fn page_count(&self) -> usize {
+        self.read_data().page_count
+    }
+
+    /// Grow the memory by the given number of pages.
+    ///
+    /// Returns the previous page count, or `None` if the memory could not be grown.
+    ///
+    /// The handle is not tied to a store, so this growth is not reported to the hooks set with
+    /// [`Store::on_memory_grow`](crate::Store::on_memory_grow). Use [`Memory::grow`](crate::Memory::grow)
+    /// to grow the memory through a store instead.
+    pub fn grow(&self, delta_pages: i64) -> Result<Option<i64>> {
+        self.grow_inner(delta_pages, true).map_err(Into::into)
+    }
+
+    pub(crate) fn grow_inner(&self, delta_pages: i64, trap_on_oom: bool) -> Result<Option<i64>, Trap> {
+        let mut data = self.write_data();
+        let SharedData { memory, page_count } = &mut *data;
+        grow_storage(self.0.ty, memory, page_count, delta_pages, trap_on_oom)
+    }
+
+    /// Reads exactly `dst.len()` bytes from memory.
+    pub fn read_exact(&self, offset: usize, dst: &mut [u8]) -> Result<()> {
+        let data = self.read_data();
+        data.memory.read_exact(offset, dst).ok_or_else(|| memory_oob(offset, dst.len(), data.memory.len()).into())
+    }
+
+    /// Reads `len` bytes from memory into a newly allocated buffer.
+    pub fn read_vec(&self, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let data = self.read_data();
+        data.memory.read_vec(offset, len).ok_or_else(|| memory_oob(offset, len, data.memory.len()).into())
+    }
+
+    /// Copies a full slice into memory.
+    pub fn copy_from_slice(&self, offset: usize, src: &[u8]) -> Result<()> {
+        let mut data = self.write_data();
+        data.memory.write_all(offset, src).ok_or_else(|| memory_oob(offset, src.len(), data.memory.len()).into())
+    }
+
+    /// Wakes up to `count` threads waiting on `addr` with `memory.atomic.wait`.
+    ///
+    /// Returns the number of woken threads.
+    pub fn notify(&self, addr: usize, count: u32) -> u32 {
+        let mut waiters = lock(&self.0.waiters);
+        let Some(queue) = waiters.get_mut(&addr) else {
+            return 0;
+        };
+
+        let woken = queue.len().min(count as usize);
+        for waiter in queue.drain(..woken) {
+            *lock(&waiter.notified) = true;
+            waiter.cond.notify_one();
+        }
+
+        if queue.is_empty() {
+            waiters.remove(&addr);
+        }
+        woken as u32
+    }
+
+    pub(crate) fn rmw<const N: usize>(&self, addr: usize, f: impl FnOnce([u8; N]) -> [u8; N]) -> Result<[u8; N], Trap> {
+        rmw(&mut self.write_data().memory, addr, f)
+    }
+
+    /// Blocks until notified, returning `0` (ok), `1` (not-equal) or `2` (timed-out).
+    ///
+    /// A negative `timeout` (in nanoseconds) waits forever.
+    pub(crate) fn wait<const N: usize>(&self, addr: usize, expected: [u8; N], timeout: i64) -> Result<i32, Trap> {
+        let waiter = {
+            // Hold the waiter queue while comparing so a notify that follows a write can't be missed.
+            let mut waiters = lock(&self.0.waiters);
+            let data = self.read_data();
+            let mut current = [0; N];
+            if data.memory.read_exact(addr, &mut current).is_none() {
+                cold_path();
+                return Err(memory_oob(addr, N, data.memory.len()));
+            }
+            if current != expected {
+                return Ok(1);
+            }
+
+            let waiter = Arc::new(Waiter::default());
+            waiters.entry(addr).or_default().push(waiter.clone());
+            waiter
+        };
+
+        let deadline = u64::try_from(timeout).ok().map(|nanos| Instant::now() + Duration::from_nanos(nanos));
+        let mut notified = lock(&waiter.notified);
+        while !*notified {
+            match deadline {
+                None => notified = waiter.cond.wait(notified).unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    notified =
+                        waiter.cond.wait_timeout(notified, deadline - now).unwrap_or_else(PoisonError::into_inner).0;
+                }
+            }
+        }
+
+        if *notified {
+            return Ok(0);
+        }
+        drop(notified);
+
+        // Timed out, but a notify may have raced us between the last check and taking the queue lock.
+        let mut waiters = lock(&self.0.waiters);
+        if *lock(&waiter.notified) {
+            return Ok(0);
+        }
+        if let Some(queue) = waiters.get_mut(&addr) {
+            queue.retain(|w| !Arc::ptr_eq(w, &waiter));
+            if queue.is_empty() {
+                waiters.remove(&addr);
+            }
+        }
+        Ok(2)
+    }
+}
+
+/// [`LinearMemory`] view of a [`SharedMemory`] used by the store's memory instances.
+pub(crate) struct SharedLinearMemory(pub(crate) SharedMemory);
+
+impl LinearMemory for SharedLinearMemory {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn grow_to(&mut self, new_len: usize) -> core::result::Result<(), Trap> {
+        let mut data = self.0.write_data();
+        data.memory.grow_to(new_len)?;
+        data.page_count = (new_len as u64 / self.0.ty().page_size()) as usize;
+        Ok(())
+    }
+
+    fn read(&self, addr: usize, dst: &mut [u8]) -> usize {
+        self.0.read_data().memory.read(addr, dst)
+    }
+
+    fn write(&mut self, addr: usize, src: &[u8]) -> usize {
+        self.0.write_data().memory.write(addr, src)
+    }
+
+    fn read_exact(&self, addr: usize, dst: &mut [u8]) -> Option<()> {
+        self.0.read_data().memory.read_exact(addr, dst)
+    }
+
+    fn write_all(&mut self, addr: usize, src: &[u8]) -> Option<()> {
+        self.0.write_data().memory.write_all(addr, src)
+    }
+
+    fn fill(&mut self, addr: usize, len: usize, val: u8) -> Option<()> {
+        self.0.write_data().memory.fill(addr, len, val)
+    }
+
+    fn discard(&mut self, addr: usize, len: usize) -> Option<()> {
+        self.0.write_data().memory.discard(addr, len)
+    }
+
+    fn copy_within(&mut self, dst: usize, src: usize, len: usize) -> Option<()> {
+        self.0.write_data().memory.copy_within(dst, src, len)
+    }
+}
@@ -80,6 +80,16 @@ impl LinearMemory for VecMemory {
         read_len
     }
 
+    #[inline(always)]
+    fn as_slice(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        self.data.get(addr..addr.checked_add(len)?)
+    }
+
+    #[inline(always)]
+    fn as_mut_slice(&mut self, addr: usize, len: usize) -> Option<&mut [u8]> {
+        self.data.get_mut(addr..addr.checked_add(len)?)
+    }
+
     #[inline(always)]
     fn read_exact(&self, addr: usize, dst: &mut [u8]) -> Option<()> {
         dst.copy_from_slice(self.data.get(addr..addr.checked_add(dst.len())?)?);
@@ -1,32 +1,51 @@
 use alloc::sync::Arc;
-use alloc::{boxed::Box, format, vec::Vec};
+use alloc::{format, vec::Vec};
 use core::hint::cold_path;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use tinywasm_types::*;
 
-use crate::interpreter::stack::{CallStack, ValueStack};
+use crate::imports::ResolvedImports;
+use crate::interpreter::stack::{CallStack, HandlerFrame, Stacks, ValueStack};
 use crate::interpreter::{TinyWasmValue, ValueRef};
 use crate::{Engine, Error, ModuleInstance, Result, Trap};
 
+mod continuation;
 mod data;
 mod element;
+mod exception;
 mod function;
+mod gc;
 mod global;
+mod hooks;
 mod memory;
 mod table;
-
-pub use memory::{LazyLinearMemory, LinearMemory, MemoryBackend, PagedMemory, VecMemory};
+mod tag;
+
+pub(crate) use gc::*;
+pub use gc::{GcType, GcValue};
+pub use hooks::GrowEvent;
+pub(crate) use hooks::StoreHooks;
+pub use memory::{
+    DirtyTrackingMemory, LazyLinearMemory, LinearMemory, MemoryBackend, MemoryProtection, PagedMemory, VecMemory,
+};
+#[cfg(feature = "std")]
+pub use memory::{FileMemory, SharedMemory};
 pub(crate) use memory::{MemValue, MemoryInstance};
-pub(crate) use {data::*, element::*, function::*, global::*, table::*};
+pub(crate) use {continuation::*, data::*, element::*, exception::*, function::*, global::*, table::*, tag::*};
 
 // global store id counter
 static STORE_ID: AtomicUsize = AtomicUsize::new(0);
 
+/// The number of stacks kept for reuse by `cont.new`.
+const MAX_SPARE_STACKS: usize = 8;
+
 /// Global state that can be manipulated by WebAssembly programs
 ///
-/// Note that the state doesn't do any garbage collection - so it will grow
-/// indefinitely if you keep adding modules to it. When calling temporary
-/// functions, you should create a new store and then drop it when you're done (e.g. in a request handler).
+/// Only GC objects (structs, arrays and converted references), continuations and exceptions are garbage collected, see
+/// [`Store::collect_garbage`]. Everything else, like module instances, functions and memories,
+/// stays alive as long as the store - so it will grow indefinitely if you keep adding modules to it.
+/// When calling temporary functions, you should create a new store and then drop it when you're done
+/// (e.g. in a request handler).
 ///
 /// ## Example
 /// ```rust
@@ -49,6 +68,13 @@ pub struct Store {
     pub(crate) state: State,
     pub(crate) call_stack: CallStack,
     pub(crate) value_stack: ValueStack,
+    pub(crate) handler_stack: Vec<HandlerFrame>,
+    /// Stacks of finished continuations, reused by `cont.new`.
+    spare_stacks: Vec<Stacks>,
+    /// Canonical types of all module instances.
+    pub(crate) types: TypeRegistry,
+    pub(crate) gc: GcHeap,
+    pub(crate) hooks: StoreHooks,
 }
 
 #[cfg(feature = "debug")]
@@ -72,6 +98,11 @@ impl Store {
             state: State::default(),
             call_stack: CallStack::new(engine.config()),
             value_stack: ValueStack::new(engine.config()),
+            handler_stack: Vec::new(),
+            spare_stacks: Vec::new(),
+            types: TypeRegistry::default(),
+            gc: GcHeap::default(),
+            hooks: StoreHooks::default(),
             engine,
             execution_fuel: 0,
             execution_active: false,
@@ -105,6 +136,57 @@ impl Store {
     pub(crate) fn exit_execution(&mut self) {
         self.execution_active = false;
     }
+
+    /// Exchange the store's current stacks with the given ones.
+    pub(crate) fn swap_stacks(&mut self, stacks: &mut Stacks) {
+        self.swap_continuation_stacks(stacks);
+        core::mem::swap(&mut self.handler_stack, &mut stacks.handler_stack);
+    }
+
+    /// Exchange the store's call and value stacks with the given ones, keeping the handler stack.
+    /// Used to switch between continuations of the same execution.
+    pub(crate) fn swap_continuation_stacks(&mut self, stacks: &mut Stacks) {
+        core::mem::swap(&mut self.call_stack, &mut stacks.call_stack);
+        core::mem::swap(&mut self.value_stack, &mut stacks.value_stack);
+    }
+
+    /// Clear the store's stacks before starting a new root invocation.
+    pub(crate) fn clear_stacks(&mut self) {
+        self.call_stack.clear();
+        self.value_stack.clear();
+        self.handler_stack.clear();
+    }
+
+    /// Get empty stacks for a new continuation.
+    pub(crate) fn take_spare_stacks(&mut self) -> Stacks {
+        self.spare_stacks.pop().unwrap_or_else(|| Stacks::new(self.engine.config()))
+    }
+
+    /// Return the stacks of a finished continuation so they can be reused.
+    ///
+    /// Only a few stacks are kept, the rest is dropped.
+    pub(crate) fn recycle_stacks(&mut self, mut stacks: Stacks) {
+        if self.spare_stacks.len() < MAX_SPARE_STACKS {
+            stacks.clear();
+            self.spare_stacks.push(stacks);
+        }
+    }
+
+    /// Leave all continuations entered after `handler_base`, restoring the stacks they were resumed from.
+    /// Used when a trap unwinds through running continuations.
+    pub(crate) fn unwind_handlers(&mut self, handler_base: usize) {
+        if self.handler_stack.len() <= handler_base {
+            return;
+        }
+
+        let mut frames = self.handler_stack.split_off(handler_base);
+        let mut outermost = frames.swap_remove(0);
+        self.swap_continuation_stacks(&mut outermost.parent);
+        self.recycle_stacks(outermost.parent);
+        for frame in frames {
+            self.recycle_stacks(frame.parent);
+        }
+    }
 }
 
 impl PartialEq for Store {
@@ -131,6 +213,13 @@ pub(crate) struct State {
     pub(crate) globals: Vec<GlobalInstance>,
     pub(crate) elements: Vec<ElementInstance>,
     pub(crate) data: Vec<DataInstance>,
+    pub(crate) tags: Vec<TagInstance>,
+    pub(crate) continuations: Vec<ContinuationInstance>,
+    /// Addresses of continuations freed by the garbage collector.
+    pub(crate) free_continuations: Vec<ContAddr>,
+    /// Exceptions, or `None` for slots freed by the garbage collector.
+    pub(crate) exceptions: Vec<Option<ExceptionInstance>>,
+    pub(crate) free_exceptions: Vec<ExnAddr>,
 }
 
 impl State {
@@ -216,6 +305,53 @@ impl State {
         Self::get_mut(&mut self.globals, addr, "global")
     }
 
+    /// Get the tag at the actual index in the store
+    pub(crate) fn get_tag(&self, addr: TagAddr) -> &TagInstance {
+        Self::get(&self.tags, addr, "tag")
+    }
+
+    /// Add a tag to the store, returning its address
+    pub(crate) fn add_tag(&mut self, tag: TagInstance) -> TagAddr {
+        self.tags.push(tag);
+        (self.tags.len() - 1) as TagAddr
+    }
+
+    /// Get the exception at the actual index in the store
+    pub(crate) fn get_exception(&self, addr: ExnAddr) -> &ExceptionInstance {
+        Self::get(&self.exceptions, addr, "exception")
+            .as_ref()
+            .unwrap_or_else(|| unreachable!("exception {addr} was freed while still referenced"))
+    }
+
+    /// Add an exception to the store, returning its address
+    ///
+    /// Use [`Store::add_exception`] instead, which counts it towards the next garbage collection.
+    fn add_exception(&mut self, exn: ExceptionInstance) -> ExnAddr {
+        if let Some(addr) = self.free_exceptions.pop() {
+            self.exceptions[addr as usize] = Some(exn);
+            return addr;
+        }
+        self.exceptions.push(Some(exn));
+        (self.exceptions.len() - 1) as ExnAddr
+    }
+
+    /// Get the continuation at the actual index in the store
+    pub(crate) fn get_cont_mut(&mut self, addr: ContAddr) -> &mut ContinuationInstance {
+        Self::get_mut(&mut self.continuations, addr, "continuation")
+    }
+
+    /// Add a continuation to the store, returning its address
+    ///
+    /// Use [`Store::add_cont`] instead, which counts it towards the next garbage collection.
+    fn add_cont(&mut self, cont: ContinuationInstance) -> ContAddr {
+        if let Some(addr) = self.free_continuations.pop() {
+            self.continuations[addr as usize] = cont;
+            return addr;
+        }
+        self.continuations.push(cont);
+        (self.continuations.len() - 1) as ContAddr
+    }
+
     /// Get the global at the actual index in the store
     pub(crate) fn get_global_val(&self, addr: GlobalAddr) -> TinyWasmValue {
         self.get_global(addr).value
@@ -255,40 +391,182 @@ impl Store {
     }
 }
 
+/// The store's state before an instantiation, used to roll back a failed one.
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct InstantiationLog {
+    instances: usize,
+    funcs: usize,
+    tables: usize,
+    memories: usize,
+    globals: usize,
+    elements: usize,
+    data: usize,
+    tags: usize,
+    /// Previous contents of imported tables and memories overwritten by active segments,
+    /// only recorded with [`Config::atomic_instantiation`](crate::engine::Config::atomic_instantiation).
+    overwritten: Option<Vec<Overwritten>>,
+    /// Whether an active element segment was written to an imported table.
+    imported_table_written: bool,
+}
+
+#[cfg_attr(feature = "debug", derive(Debug))]
+enum Overwritten {
+    Table { addr: TableAddr, offset: usize, elements: Vec<TableElement> },
+    Memory { addr: MemAddr, offset: usize, bytes: Vec<u8> },
+}
+
+impl InstantiationLog {
+    fn record_table(&mut self, table: &TableInstance, addr: TableAddr, offset: usize, len: usize) {
+        if addr as usize >= self.tables {
+            return;
+        }
+
+        // out of bounds segments trap without writing anything
+        let Ok(elements) = table.load(offset, len) else { return };
+        self.imported_table_written = true;
+        if let Some(overwritten) = &mut self.overwritten {
+            overwritten.push(Overwritten::Table { addr, offset, elements: elements.to_vec() });
+        }
+    }
+
+    fn record_memory(&mut self, mem: &MemoryInstance, addr: MemAddr, offset: usize, len: usize) {
+        if addr as usize >= self.memories {
+            return;
+        }
+
+        if let (Some(overwritten), Some(bytes)) = (&mut self.overwritten, mem.inner.read_vec(offset, len)) {
+            overwritten.push(Overwritten::Memory { addr, offset, bytes });
+        }
+    }
+}
+
 // Linking related functions
 impl Store {
+    /// Remember the store's state before instantiating a module.
+    pub(crate) fn begin_instantiation(&self) -> InstantiationLog {
+        InstantiationLog {
+            instances: self.module_instances.len(),
+            funcs: self.state.funcs.len(),
+            tables: self.state.tables.len(),
+            memories: self.state.memories.len(),
+            globals: self.state.globals.len(),
+            elements: self.state.elements.len(),
+            data: self.state.data.len(),
+            tags: self.state.tags.len(),
+            overwritten: self.engine.config().atomic_instantiation.then(Vec::new),
+            imported_table_written: false,
+        }
+    }
+
+    /// Remove everything a failed instantiation added to the store.
+    ///
+    /// Without atomic instantiation, an imported table may already refer to the new instance's functions,
+    /// so the instance is kept in that case. Canonical types stay registered, as they are shared between modules.
+    pub(crate) fn rollback_instantiation(&mut self, log: &mut InstantiationLog) {
+        let Some(overwritten) = log.overwritten.take() else {
+            if !log.imported_table_written {
+                self.truncate(log);
+            }
+            return;
+        };
+
+        for write in overwritten.into_iter().rev() {
+            match write {
+                Overwritten::Table { addr, offset, elements } => {
+                    let _ = self.state.get_table_mut(addr).init(offset, &elements);
+                }
+                Overwritten::Memory { addr, offset, bytes } => {
+                    let _ = self.state.get_mem_mut(addr).inner.write_all(offset, &bytes);
+                }
+            }
+        }
+        self.truncate(log);
+    }
+
+    fn truncate(&mut self, log: &InstantiationLog) {
+        // half-initialized memories must not be persisted, or they would be restored later
+        for mem in &mut self.state.memories[log.memories..] {
+            mem.inner.discard_unflushed();
+        }
+        self.module_instances.truncate(log.instances);
+        self.state.funcs.truncate(log.funcs);
+        self.state.tables.truncate(log.tables);
+        self.state.memories.truncate(log.memories);
+        self.state.globals.truncate(log.globals);
+        self.state.elements.truncate(log.elements);
+        self.state.data.truncate(log.data);
+        self.state.tags.truncate(log.tags);
+    }
+
     /// Add functions to the store, returning their addresses in the store
     pub(crate) fn init_funcs(
         &mut self,
         funcs: &[Arc<WasmFunction>],
+        type_ids: impl Iterator<Item = u32>,
         idx: ModuleInstanceAddr,
     ) -> impl ExactSizeIterator<Item = FuncAddr> {
         let start = self.state.funcs.len() as FuncAddr;
-        self.state.funcs.extend(
-            funcs.iter().map(|func| FunctionInstance::Wasm(WasmFunctionInstance { func: func.clone(), owner: idx })),
-        );
+        self.state.funcs.extend(funcs.iter().zip(type_ids).map(|(func, type_id)| {
+            FunctionInstance::Wasm(WasmFunctionInstance { func: func.clone(), owner: idx, type_id })
+        }));
         start..start + funcs.len() as FuncAddr
     }
 
-    /// Add tables to the store, returning their addresses in the store
-    pub(crate) fn init_tables(&mut self, tables: &[TableType]) -> Result<impl ExactSizeIterator<Item = TableAddr>> {
-        let start = self.state.tables.len() as TableAddr;
-        self.state.tables.reserve_exact(tables.len());
-        for &table in tables {
-            self.state.tables.push(TableInstance::new(table)?);
+    /// Add a table to the store, returning its address in the store
+    ///
+    /// Tables with an initializer expression are filled with its value, which can only refer to imported globals.
+    pub(crate) fn init_table(
+        &mut self,
+        table: TableType,
+        init: Option<&[ConstInstruction]>,
+        globals: &[Addr],
+        funcs: &[FuncAddr],
+        type_ids: &[u32],
+    ) -> Result<TableAddr> {
+        let mut kind = table;
+        kind.element_ref_type = table.element_ref_type.map(|ty| map_ref_type(ty, |idx| type_ids[idx as usize]));
+
+        let init = match init {
+            Some(expr) => match self.eval_const(expr, globals, funcs, type_ids)? {
+                TinyWasmValue::ValueRef(v) => TableElement::from(v.addr()),
+                other => {
+                    cold_path();
+                    return Err(Error::Other(format!("expected ref type, got {other:?}")));
+                }
+            },
+            None => TableElement::Uninitialized,
+        };
+        self.state.tables.push(TableInstance::new_with_init(kind, init)?);
+        Ok(self.state.tables.len() as TableAddr - 1)
+    }
+
+    /// Add tags to the store, returning their addresses in the store
+    pub(crate) fn init_tags(
+        &mut self,
+        tags: &[TypeAddr],
+        func_types: &[Arc<FuncType>],
+        type_ids: &[u32],
+    ) -> Result<impl ExactSizeIterator<Item = TagAddr>> {
+        let start = self.state.tags.len() as TagAddr;
+        for &idx in tags {
+            let (Some(ty), Some(type_id)) = (func_types.get(idx as usize), type_ids.get(idx as usize)) else {
+                cold_path();
+                return Err(Error::Other(format!("tag type {idx} not found")));
+            };
+            self.state.tags.push(TagInstance { ty: ty.clone(), type_id: Some(*type_id) });
         }
-        Ok(start..start + tables.len() as TableAddr)
+        Ok(start..start + tags.len() as TagAddr)
     }
 
     /// Add memories to the store, returning their addresses in the store
     pub(crate) fn init_memories(
         &mut self,
         memories: &[MemoryType],
-        init: impl Fn(MemoryType, &MemoryBackend) -> Result<MemoryInstance>,
+        mut init: impl FnMut(usize, MemoryType, &crate::engine::Config) -> Result<MemoryInstance>,
     ) -> Result<impl ExactSizeIterator<Item = MemAddr>> {
         let start = self.state.memories.len() as MemAddr;
-        for mem in memories {
-            self.state.memories.push(match init(*mem, &self.engine.config().memory_backend) {
+        for (idx, mem) in memories.iter().enumerate() {
+            self.state.memories.push(match init(idx, *mem, self.engine.config()) {
                 Ok(mem) => mem,
                 Err(e) => {
                     cold_path();
@@ -299,33 +577,49 @@ impl Store {
         Ok(start..start + memories.len() as MemAddr)
     }
 
-    /// Add globals to the store, returning their addresses in the store
-    pub(crate) fn init_globals(
-        &mut self,
-        out: &mut Vec<Addr>,
-        globals: &[Global],
-        func_addrs: &[FuncAddr],
-    ) -> Result<()> {
-        let start = self.state.globals.len() as Addr;
-        out.extend(start..start + globals.len() as Addr);
-
-        for global in globals {
-            let value = match self.eval_const(&global.init, out, func_addrs) {
-                Ok(val) => val,
-                Err(e) => {
-                    cold_path();
-                    return Err(e);
-                }
-            };
-            self.state.globals.push(GlobalInstance::new(global.ty, value));
+    /// Get the address of the memory instance backed by `memory`, adding one if this store has none yet.
+    #[cfg(feature = "std")]
+    pub(crate) fn shared_memory_addr(&mut self, memory: SharedMemory) -> MemAddr {
+        let existing =
+            self.state.memories.iter().position(|mem| mem.shared.as_ref().is_some_and(|s| s.ptr_eq(&memory)));
+        if let Some(addr) = existing {
+            return addr as MemAddr;
         }
 
-        Ok(())
+        self.state.memories.push(MemoryInstance::from_shared(memory));
+        (self.state.memories.len() - 1) as MemAddr
     }
 
-    fn elem_addr(&self, item: &ElementItem, globals: &[Addr], funcs: &[FuncAddr]) -> Result<Option<u32>> {
+    /// Add a global to the store, returning its address in the store
+    pub(crate) fn init_global(
+        &mut self,
+        global: &Global,
+        globals: &[Addr],
+        func_addrs: &[FuncAddr],
+        type_ids: &[u32],
+    ) -> Result<Addr> {
+        let value = match self.eval_const(&global.init, globals, func_addrs, type_ids) {
+            Ok(val) => val,
+            Err(e) => {
+                cold_path();
+                return Err(e);
+            }
+        };
+        let mut ty = global.ty;
+        ty.ref_type = global.ty.ref_type.map(|ty| map_ref_type(ty, |idx| type_ids[idx as usize]));
+        self.state.globals.push(GlobalInstance::new(ty, value));
+        Ok(self.state.globals.len() as Addr - 1)
+    }
+
+    fn elem_addr(
+        &mut self,
+        item: &ElementItem,
+        globals: &[Addr],
+        funcs: &[FuncAddr],
+        type_ids: &[u32],
+    ) -> Result<Option<u32>> {
         match item {
-            ElementItem::Expr(expr) => match self.eval_const(expr, globals, funcs)? {
+            ElementItem::Expr(expr) => match self.eval_const(expr, globals, funcs, type_ids)? {
                 TinyWasmValue::ValueRef(v) => Ok(v.addr()),
                 other => {
                     cold_path();
@@ -344,118 +638,111 @@ impl Store {
         }
     }
 
-    /// Add elements to the store, returning their addresses in the store
+    /// Add an element segment to the store, pushing its address to `out`
     /// Should be called after the tables have been added
-    pub(crate) fn init_elements(
+    ///
+    /// Returns the trap if an active segment is out of bounds, in which case nothing is added.
+    pub(crate) fn init_element(
         &mut self,
-        table_addrs: &[TableAddr],
-        func_addrs: &[FuncAddr],
-        global_addrs: &[Addr],
-        elements: &[Element],
-    ) -> Result<(Box<[Addr]>, Option<Trap>)> {
-        let elem_count = self.state.elements.len();
-        let mut elem_addrs = Vec::with_capacity(elem_count);
-        for (i, element) in elements.iter().enumerate() {
-            let init = element
-                .items
-                .iter()
-                .map(|item| Ok(TableElement::from(self.elem_addr(item, global_addrs, func_addrs)?)))
-                .collect::<Result<Vec<_>>>()?;
-
-            let items = match &element.kind {
-                // doesn't need to be initialized, can be initialized lazily using the `table.init` instruction
-                ElementKind::Passive => Some(init),
-
-                // this one is not available to the runtime but needs to be initialized to declare references
-                ElementKind::Declared => None, // a. Execute the instruction elm.drop i
-
-                // this one is active, so we need to initialize it (essentially a `table.init` instruction)
-                ElementKind::Active { offset, table } => {
-                    let offset = self.eval_size_const(offset, global_addrs, func_addrs)?;
-                    let table_addr = table_addrs
-                        .get(*table as usize)
-                        .copied()
-                        .ok_or_else(|| Error::Other(format!("table {table} not found for element {i}")))?;
-
-                    let Some(table) = self.state.tables.get_mut(table_addr as usize) else {
-                        return Err(Error::Other(format!("table {table} not found for element {i}")));
-                    };
-
-                    // In wasm 2.0, it's possible to call a function that hasn't been instantiated yet,
-                    // when using a partially initialized active element segments.
-                    // This isn't mentioned in the spec, but the "unofficial" testsuite has a test for it:
-                    // https://github.com/WebAssembly/testsuite/blob/5a1a590603d81f40ef471abba70a90a9ae5f4627/linking.wast#L264-L276
-                    // I have NO IDEA why this is allowed, but it is.
-                    let Ok(offset) = usize::try_from(offset) else {
-                        return Ok((
-                            elem_addrs.into_boxed_slice(),
-                            Some(Trap::TableOutOfBounds { offset: usize::MAX, len: init.len(), max: table.size() }),
-                        ));
-                    };
-
-                    if let Err(trap) = table.init(offset, &init) {
-                        return Ok((elem_addrs.into_boxed_slice(), Some(trap)));
-                    }
-
-                    // f. Execute the instruction elm.drop i
-                    None
+        out: &mut Vec<Addr>,
+        log: &mut InstantiationLog,
+        element: &Element,
+        addrs: &ResolvedImports,
+        type_ids: &[u32],
+    ) -> Result<Option<Trap>> {
+        let (i, global_addrs, func_addrs) = (out.len(), &addrs.globals[..], &addrs.funcs[..]);
+        let init = element
+            .items
+            .iter()
+            .map(|item| Ok(TableElement::from(self.elem_addr(item, global_addrs, func_addrs, type_ids)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let items = match &element.kind {
+            // doesn't need to be initialized, can be initialized lazily using the `table.init` instruction
+            ElementKind::Passive => Some(init),
+
+            // this one is not available to the runtime but needs to be initialized to declare references
+            ElementKind::Declared => None, // a. Execute the instruction elm.drop i
+
+            // this one is active, so we need to initialize it (essentially a `table.init` instruction)
+            ElementKind::Active { offset, table } => {
+                let offset = self.eval_size_const(offset, global_addrs, func_addrs, type_ids)?;
+                let table_addr = addrs
+                    .tables
+                    .get(*table as usize)
+                    .copied()
+                    .ok_or_else(|| Error::Other(format!("table {table} not found for element {i}")))?;
+
+                let Some(table) = self.state.tables.get_mut(table_addr as usize) else {
+                    return Err(Error::Other(format!("table {table} not found for element {i}")));
+                };
+
+                // In wasm 2.0, it's possible to call a function that hasn't been instantiated yet,
+                // when using a partially initialized active element segments.
+                // This isn't mentioned in the spec, but the "unofficial" testsuite has a test for it:
+                // https://github.com/WebAssembly/testsuite/blob/5a1a590603d81f40ef471abba70a90a9ae5f4627/linking.wast#L264-L276
+                // I have NO IDEA why this is allowed, but it is.
+                let Ok(offset) = usize::try_from(offset) else {
+                    return Ok(Some(Trap::TableOutOfBounds { offset: usize::MAX, len: init.len(), max: table.size() }));
+                };
+
+                log.record_table(table, table_addr, offset, init.len());
+                if let Err(trap) = table.init(offset, &init) {
+                    return Ok(Some(trap));
                 }
-            };
 
-            self.state.elements.push(ElementInstance { items });
-            elem_addrs.push((i + elem_count) as Addr);
-        }
+                // f. Execute the instruction elm.drop i
+                None
+            }
+        };
 
-        // this should be optimized out by the compiler
-        Ok((elem_addrs.into_boxed_slice(), None))
+        self.state.elements.push(ElementInstance { items });
+        out.push(self.state.elements.len() as Addr - 1);
+        Ok(None)
     }
 
-    /// Add data to the store, returning their addresses in the store
+    /// Add a data segment to the store, pushing its address to `out`
+    ///
+    /// Returns the trap if an active segment is out of bounds, in which case nothing is added.
     pub(crate) fn init_data(
         &mut self,
-        mem_addrs: &[MemAddr],
-        global_addrs: &[Addr],
-        func_addrs: &[FuncAddr],
-        data: &[Data],
-    ) -> Result<(Box<[Addr]>, Option<Trap>)> {
-        let data_count = self.state.data.len();
-        let mut data_addrs = Vec::with_capacity(data_count);
-        for (i, data) in data.iter().enumerate() {
-            let data_val = match &data.kind {
-                tinywasm_types::DataKind::Active { mem: mem_addr, offset } => {
-                    let Some(mem_addr) = mem_addrs.get(*mem_addr as usize) else {
-                        return Err(Error::Other(format!("memory {mem_addr} not found for data segment {i}")));
-                    };
-
-                    let offset = self.eval_size_const(offset, global_addrs, func_addrs)?;
-                    let Some(mem) = self.state.memories.get_mut(*mem_addr as usize) else {
-                        return Err(Error::Other(format!("memory {mem_addr} not found for data segment {i}")));
-                    };
-
-                    let offset = usize::try_from(offset).unwrap_or(usize::MAX);
-                    match mem.inner.write_all(offset, &data.data) {
-                        Some(()) => None,
-                        None => {
-                            return Ok((
-                                data_addrs.into_boxed_slice(),
-                                Some(crate::Trap::MemoryOutOfBounds {
-                                    offset,
-                                    len: data.data.len(),
-                                    max: mem.inner.len(),
-                                }),
-                            ));
-                        }
-                    }
+        out: &mut Vec<Addr>,
+        log: &mut InstantiationLog,
+        data: &Data,
+        addrs: &ResolvedImports,
+        type_ids: &[u32],
+    ) -> Result<Option<Trap>> {
+        let (i, global_addrs, func_addrs) = (out.len(), &addrs.globals[..], &addrs.funcs[..]);
+        let data_val = match &data.kind {
+            tinywasm_types::DataKind::Active { mem: mem_addr, offset } => {
+                let Some(mem_addr) = addrs.memories.get(*mem_addr as usize) else {
+                    return Err(Error::Other(format!("memory {mem_addr} not found for data segment {i}")));
+                };
+
+                let offset = self.eval_size_const(offset, global_addrs, func_addrs, type_ids)?;
+                let Some(mem) = self.state.memories.get_mut(*mem_addr as usize) else {
+                    return Err(Error::Other(format!("memory {mem_addr} not found for data segment {i}")));
+                };
+
+                let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+                log.record_memory(mem, *mem_addr, offset, data.data.len());
+                if mem.inner.write_all(offset, &data.data).is_none() {
+                    return Ok(Some(Trap::MemoryOutOfBounds { offset, len: data.data.len(), max: mem.inner.len() }));
                 }
-                tinywasm_types::DataKind::Passive => Some(data.data.to_vec()),
-            };
+                None
+            }
+            tinywasm_types::DataKind::Passive => Some(data.data.to_vec()),
+        };
 
-            self.state.data.push(DataInstance { data: data_val });
-            data_addrs.push((i + data_count) as Addr);
-        }
+        self.state.data.push(DataInstance { data: data_val });
+        out.push(self.state.data.len() as Addr - 1);
+        Ok(None)
+    }
 
-        // this should be optimized out by the compiler
-        Ok((data_addrs.into_boxed_slice(), None))
+    /// Add an active data segment that was already applied to its memory, pushing its address to `out`
+    pub(crate) fn init_applied_data(&mut self, out: &mut Vec<Addr>) {
+        self.state.data.push(DataInstance { data: None });
+        out.push(self.state.data.len() as Addr - 1);
     }
 
     pub(crate) fn add_func(&mut self, func: FunctionInstance) -> FuncAddr {
@@ -465,12 +752,13 @@ impl Store {
 
     /// Evaluate a constant expression that's either a i32 or a i64 as a global or a const instruction
     fn eval_size_const(
-        &self,
+        &mut self,
         const_instrs: &[tinywasm_types::ConstInstruction],
         module_global_addrs: &[Addr],
         module_func_addrs: &[FuncAddr],
+        module_type_ids: &[u32],
     ) -> Result<u64> {
-        let value = self.eval_const(const_instrs, module_global_addrs, module_func_addrs)?;
+        let value = self.eval_const(const_instrs, module_global_addrs, module_func_addrs, module_type_ids)?;
         match value {
             TinyWasmValue::Value32(i) => Ok(u64::from(i)),
             TinyWasmValue::Value64(i) => Ok(i),
@@ -481,10 +769,11 @@ impl Store {
     /// Evaluate a constant expression
     #[inline]
     fn eval_const(
-        &self,
+        &mut self,
         const_instrs: &[tinywasm_types::ConstInstruction],
         module_global_addrs: &[Addr],
         module_func_addrs: &[FuncAddr],
+        module_type_ids: &[u32],
     ) -> Result<TinyWasmValue> {
         use tinywasm_types::ConstInstruction::*;
 
@@ -516,26 +805,58 @@ impl Store {
             }
         };
 
-        if const_instrs.len() == 1 {
-            let val = match &const_instrs[0] {
-                F32Const(f) => (*f).into(),
-                F64Const(f) => (*f).into(),
-                I32Const(i) => (*i).into(),
-                I64Const(i) => (*i).into(),
-                V128Const(i) => (*i).into(),
-                GlobalGet(addr) => resolve_global(*addr)?,
-                RefFunc(None) => TinyWasmValue::ValueRef(ValueRef::NULL),
-                RefExtern(None) => TinyWasmValue::ValueRef(ValueRef::NULL),
-                RefFunc(Some(idx)) => TinyWasmValue::ValueRef(ValueRef::from_addr(Some(resolve_func(*idx)?))),
-                _ => {
+        let resolve_type = |idx: u32| -> Result<u32> {
+            match module_type_ids.get(idx as usize) {
+                Some(id) => Ok(*id),
+                None => {
                     cold_path();
-                    return Err(Error::other("unsupported const instruction"));
+                    Err(Error::Other(format!("type {idx} not found. This should have been caught by the validator")))
+                }
+            }
+        };
+
+        if let [instr] = const_instrs {
+            let val = match instr {
+                F32Const(f) => Some((*f).into()),
+                F64Const(f) => Some((*f).into()),
+                I32Const(i) => Some((*i).into()),
+                I64Const(i) => Some((*i).into()),
+                V128Const(i) => Some((*i).into()),
+                GlobalGet(addr) => Some(resolve_global(*addr)?),
+                RefFunc(None) | RefExtern(None) | RefNullCont | RefNullExn | RefNullAny => {
+                    Some(TinyWasmValue::ValueRef(ValueRef::NULL))
                 }
+                RefFunc(Some(idx)) => Some(TinyWasmValue::ValueRef(ValueRef::from_addr(Some(resolve_func(*idx)?)))),
+                _ => None,
             };
 
-            return Ok(val);
+            if let Some(val) = val {
+                return Ok(val);
+            }
         }
 
+        let pop = |stack: &mut Vec<TinyWasmValue>| -> Result<TinyWasmValue> {
+            stack.pop().ok_or_else(|| Error::other("const stack underflow"))
+        };
+        let pop_ref = |stack: &mut Vec<TinyWasmValue>| -> Result<u32> {
+            match pop(stack)? {
+                TinyWasmValue::ValueRef(v) => Ok(v.raw()),
+                _ => {
+                    cold_path();
+                    Err(Error::other("type mismatch in const ref op"))
+                }
+            }
+        };
+        let pop_u32 = |stack: &mut Vec<TinyWasmValue>| -> Result<u32> {
+            match pop(stack)? {
+                TinyWasmValue::Value32(v) => Ok(v),
+                _ => {
+                    cold_path();
+                    Err(Error::other("type mismatch in const i32 op"))
+                }
+            }
+        };
+
         let mut stack = Vec::new();
         for instr in const_instrs {
             match instr {
@@ -545,7 +866,9 @@ impl Store {
                 F64Const(f) => stack.push(TinyWasmValue::Value64(f.to_bits())),
                 V128Const(i) => stack.push(TinyWasmValue::Value128((*i).into())),
                 GlobalGet(addr) => stack.push(resolve_global(*addr)?),
-                RefFunc(None) | RefExtern(None) => stack.push(TinyWasmValue::ValueRef(ValueRef::NULL)),
+                RefFunc(None) | RefExtern(None) | RefNullCont | RefNullExn | RefNullAny => {
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::NULL))
+                }
                 RefFunc(Some(idx)) => {
                     stack.push(TinyWasmValue::ValueRef(ValueRef::from_addr(Some(resolve_func(*idx)?))))
                 }
@@ -553,6 +876,58 @@ impl Store {
                     cold_path();
                     return Err(Error::other("ref.extern constants are not supported in init expressions"));
                 }
+                RefI31 => {
+                    let value = pop_u32(&mut stack)?;
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::from_raw(AnyRef::i31(value as i32).raw())));
+                }
+                StructNew(ty) | StructNewDefault(ty) => {
+                    let ty = resolve_type(*ty)?;
+                    let idx = self.gc.alloc_struct(&self.types, ty)?;
+                    if let StructNew(_) = instr {
+                        let fields = self.types.struct_fields(ty);
+                        let Some(start) = stack.len().checked_sub(fields.len()) else {
+                            cold_path();
+                            return Err(Error::other("const stack underflow"));
+                        };
+                        let (_, data) = self.gc.struct_data_mut(idx);
+                        for (field, value) in fields.iter().zip(stack.drain(start..)) {
+                            field.kind.write(&mut data[field.range()], value);
+                        }
+                    }
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::from_raw(idx)));
+                }
+                ArrayNew(ty) | ArrayNewDefault(ty) => {
+                    let ty = resolve_type(*ty)?;
+                    let len = pop_u32(&mut stack)?;
+                    let init = match instr {
+                        ArrayNew(_) => Some(pop(&mut stack)?),
+                        _ => None,
+                    };
+                    let idx = self.gc.alloc_array(&self.types, ty, len, init)?;
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::from_raw(idx)));
+                }
+                ArrayNewFixed(ty, len) => {
+                    let ty = resolve_type(*ty)?;
+                    let Some(start) = stack.len().checked_sub(*len as usize) else {
+                        cold_path();
+                        return Err(Error::other("const stack underflow"));
+                    };
+                    let idx = self.gc.alloc_array(&self.types, ty, *len, None)?;
+                    let kind = self.types.array_element(ty).kind;
+                    let (_, _, data) = self.gc.array_data_mut(idx);
+                    for (element, value) in data.chunks_exact_mut(kind.size()).zip(stack.drain(start..)) {
+                        kind.write(element, value);
+                    }
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::from_raw(idx)));
+                }
+                AnyConvertExtern => {
+                    let raw = pop_ref(&mut stack)?;
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::from_raw(self.gc.internalize(raw)?)));
+                }
+                ExternConvertAny => {
+                    let raw = pop_ref(&mut stack)?;
+                    stack.push(TinyWasmValue::ValueRef(ValueRef::from_raw(self.gc.externalize(raw)?)));
+                }
                 I32Add | I32Sub | I32Mul => {
                     let rhs = stack.pop().ok_or_else(|| Error::other("const stack underflow"))?;
                     let lhs = stack.pop().ok_or_else(|| Error::other("const stack underflow"))?;
@@ -1,3 +1,4 @@
+use crate::interpreter::ValueRef;
 use crate::{Result, Trap};
 use alloc::vec::Vec;
 use core::ops::Range;
@@ -15,6 +16,7 @@ pub(crate) struct TableInstance {
 }
 
 impl TableInstance {
+    #[cfg(test)]
     pub(crate) fn new(kind: TableType) -> Result<Self> {
         Self::new_with_init(kind, TableElement::Uninitialized)
     }
@@ -49,7 +51,8 @@ impl TableInstance {
 
         Ok(match self.kind.element_type {
             WasmType::RefFunc => WasmValue::RefFunc(FuncRef::new(val)),
-            WasmType::RefExtern => WasmValue::RefExtern(ExternRef::new(val)),
+            WasmType::RefExtern => WasmValue::RefExtern(ExternRef::from_raw(ValueRef::from_addr(val).raw())),
+            WasmType::RefAny => WasmValue::RefAny(AnyRef::from_raw(ValueRef::from_addr(val).raw())),
             _ => Err(Trap::Other("non-ref table"))?,
         })
     }
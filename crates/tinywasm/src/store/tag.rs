@@ -0,0 +1,12 @@
+use alloc::sync::Arc;
+use tinywasm_types::FuncType;
+
+/// A WebAssembly Tag Instance
+///
+/// See <https://webassembly.github.io/spec/core/exec/runtime.html#tag-instances>
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub(crate) struct TagInstance {
+    pub(crate) ty: Arc<FuncType>,
+    /// The canonical id of the tag's type, or `None` for tags created by the host.
+    pub(crate) type_id: Option<u32>,
+}
@@ -0,0 +1,285 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+
+use eyre::Result;
+use tinywasm::types::{AnyRef, FuncType, WasmType, WasmValue};
+use tinywasm::{Error, Exception, ExecProgress, HostFunction, Imports, ModuleInstance, Store, Tag, Trap};
+
+const FIBONACCI_WASM: &[u8] = include_bytes!("../../../examples/rust/out/fibonacci.wasm");
+
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Polls `future` until it is ready, returning the output and the number of `Poll::Pending` results.
+fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(CountingWaker::default()));
+    let mut cx = Context::from_waker(&waker);
+    let mut pending = 0;
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return (output, pending),
+            Poll::Pending => pending += 1,
+        }
+    }
+}
+
+/// A future that stays pending until `ready` is set, remembering the last waker.
+struct Gate {
+    ready: Rc<Cell<bool>>,
+    waker: Rc<RefCell<Option<Waker>>>,
+    value: i32,
+}
+
+impl Future for Gate {
+    type Output = tinywasm::Result<i32>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.ready.get() {
+            return Poll::Ready(Ok(self.value));
+        }
+        *self.waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+fn host_module() -> Result<tinywasm::Module> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "host" "delay" (func $delay (param i32) (result i32)))
+          (func (export "run") (param i32) (result i32)
+            (i32.add (call $delay (local.get 0)) (i32.const 1))))
+        "#,
+    )?;
+    Ok(tinywasm::parse_bytes(&wasm)?)
+}
+
+#[test]
+fn call_async_yields_between_fuel_slices() -> Result<()> {
+    let module = tinywasm::parse_bytes(FIBONACCI_WASM)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let func = instance.func::<i32, i32>(&store, "fibonacci_recursive")?;
+    let expected = func.call(&mut store, 20)?;
+
+    let (result, pending) = block_on(func.call_async(&mut store, 20).with_fuel_slice(256));
+    assert_eq!(result?, expected);
+    assert!(pending > 0, "expected the call to yield at least once");
+
+    let untyped = instance.func_untyped(&store, "fibonacci_recursive")?;
+    let (result, _) = block_on(untyped.call_async(&mut store, &[WasmValue::I32(20)]));
+    assert_eq!(result?, vec![WasmValue::I32(expected)]);
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn call_async_supports_time_slices() -> Result<()> {
+    let module = tinywasm::parse_bytes(FIBONACCI_WASM)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let func = instance.func::<i32, i32>(&store, "fibonacci_recursive")?;
+    let expected = func.call(&mut store, 15)?;
+
+    let future = func.call_async(&mut store, 15).with_time_slice(std::time::Duration::from_micros(50));
+    assert_eq!(block_on(future).0?, expected);
+    Ok(())
+}
+
+#[test]
+fn call_async_awaits_async_host_functions() -> Result<()> {
+    let module = host_module()?;
+    let mut store = Store::default();
+
+    let ready = Rc::new(Cell::new(false));
+    let stored_waker = Rc::new(RefCell::new(None));
+    let (host_ready, host_waker) = (ready.clone(), stored_waker.clone());
+    let delay = HostFunction::from_async(&mut store, move |_ctx, value: i32| Gate {
+        ready: host_ready.clone(),
+        waker: host_waker.clone(),
+        value: value * 2,
+    });
+
+    let mut imports = Imports::new();
+    imports.define("host", "delay", delay);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let run = instance.func::<i32, i32>(&store, "run")?;
+
+    let counter = Arc::new(CountingWaker::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(run.call_async(&mut store, 20));
+
+    // The first poll reaches the host call and yields, the second one waits on the host future.
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert!(stored_waker.borrow().is_some(), "host future should have registered the waker");
+    let wakes = counter.0.load(Ordering::SeqCst);
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(counter.0.load(Ordering::SeqCst), wakes, "waiting on a host future must not self-wake");
+
+    ready.set(true);
+    stored_waker.borrow_mut().take().expect("waker").wake();
+    let result = loop {
+        if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+            break result?;
+        }
+    };
+    assert_eq!(result, 41);
+
+    Ok(())
+}
+
+#[test]
+fn async_host_functions_work_with_resumable_calls() -> Result<()> {
+    let module = host_module()?;
+    let mut store = Store::default();
+    let ready = Rc::new(Cell::new(false));
+    let host_ready = ready.clone();
+    let delay = HostFunction::from_async(&mut store, move |_ctx, value: i32| Gate {
+        ready: host_ready.clone(),
+        waker: Rc::default(),
+        value,
+    });
+
+    let mut imports = Imports::new();
+    imports.define("host", "delay", delay.clone());
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let run = instance.func::<i32, i32>(&store, "run")?;
+
+    let mut exec = run.call_resumable(&mut store, 1)?;
+    for _ in 0..3 {
+        assert!(matches!(exec.resume_with_fuel(1024)?, ExecProgress::Suspended));
+    }
+    ready.set(true);
+    assert!(matches!(exec.resume_with_fuel(1024)?, ExecProgress::Completed(2)));
+
+    // Async host functions can also be the root of an invocation.
+    let (result, _) = block_on(delay.call_async(&mut store, &[WasmValue::I32(7)]));
+    assert_eq!(result?, vec![WasmValue::I32(7)]);
+
+    Ok(())
+}
+
+#[test]
+fn async_host_functions_trap_in_blocking_calls() -> Result<()> {
+    let module = host_module()?;
+    let mut store = Store::default();
+    let delay = HostFunction::from_async(&mut store, |_ctx, value: i32| async move { Ok(value) });
+
+    let mut imports = Imports::new();
+    imports.define("host", "delay", delay.clone());
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let run = instance.func::<i32, i32>(&store, "run")?;
+
+    assert!(matches!(run.call(&mut store, 1), Err(Error::Trap(Trap::Other(_)))));
+    assert!(matches!(delay.call(&mut store, &[WasmValue::I32(1)]), Err(Error::Trap(Trap::Other(_)))));
+
+    // The store is still usable afterwards.
+    assert_eq!(block_on(run.call_async(&mut store, 1)).0?, 2);
+    Ok(())
+}
+
+#[test]
+fn async_host_errors_become_traps() -> Result<()> {
+    let module = host_module()?;
+    let mut store = Store::default();
+    let delay =
+        HostFunction::from_async(
+            &mut store,
+            |_ctx, _value: i32| async move { Err::<i32, _>(Error::Other("boom".into())) },
+        );
+
+    let mut imports = Imports::new();
+    imports.define("host", "delay", delay);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let run = instance.func::<i32, i32>(&store, "run")?;
+
+    let (result, _) = block_on(run.call_async(&mut store, 1));
+    assert!(matches!(result, Err(Error::Trap(Trap::HostFunction(_)))));
+    Ok(())
+}
+
+#[test]
+fn async_host_exceptions_can_be_caught() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "host" "tag" (tag $e (param i32)))
+          (import "host" "delay" (func $delay (param i32) (result i32)))
+          (func (export "run") (param i32) (result i32)
+            (block $h (result i32)
+              (try_table (result i32) (catch $e $h) (call $delay (local.get 0))))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let tag = Tag::new(&mut store, FuncType::new(&[WasmType::I32], &[]));
+    let delay = HostFunction::from_async(&mut store, move |ctx, value: i32| {
+        let exception = Exception::new(ctx.store(), tag, &[WasmValue::I32(value + 1)]);
+        async move { Err::<i32, Error>(exception?.into()) }
+    });
+
+    let mut imports = Imports::new();
+    imports.define("host", "tag", tag).define("host", "delay", delay);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let run = instance.func::<i32, i32>(&store, "run")?;
+
+    let (result, _) = block_on(run.call_async(&mut store, 1));
+    assert_eq!(result?, 2);
+    Ok(())
+}
+
+#[test]
+fn pending_async_host_calls_keep_their_arguments_alive() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $box (struct (field i32)))
+          (import "host" "hold" (func $hold (param anyref) (result anyref)))
+          (global $other (mut (ref null $box)) (ref.null $box))
+          (func (export "run") (result i32)
+            (struct.get $box 0 (ref.cast (ref $box) (call $hold (struct.new $box (i32.const 42))))))
+          (func (export "alloc") (global.set $other (struct.new $box (i32.const 7)))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let ready = Rc::new(Cell::new(false));
+    let host_ready = ready.clone();
+    let hold = HostFunction::from_async(&mut store, move |_ctx, value: AnyRef| {
+        let ready = host_ready.clone();
+        std::future::poll_fn(move |_| match ready.get() {
+            true => Poll::Ready(Ok(value)),
+            false => Poll::Pending,
+        })
+    });
+
+    let mut imports = Imports::new();
+    imports.define("host", "hold", hold);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let run = instance.func::<(), i32>(&store, "run")?;
+
+    let mut exec = run.call_detached(&mut store, ())?;
+    assert!(matches!(exec.resume_with_fuel(&mut store, 1024)?, ExecProgress::Suspended));
+
+    // the struct is only referenced by the pending call, so its slot must not be reused
+    store.collect_garbage();
+    instance.func::<(), ()>(&store, "alloc")?.call(&mut store, ())?;
+    ready.set(true);
+    assert!(matches!(exec.resume_with_fuel(&mut store, 1024)?, ExecProgress::Completed(42)));
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use eyre::Result;
+use tinywasm::types::WasmValue;
+use tinywasm::{Error, ExecProgress, ModuleInstance, Store, Trap};
+
+const FIBONACCI_WASM: &[u8] = include_bytes!("../../../examples/rust/out/fibonacci.wasm");
+
+#[test]
+fn interleaved_detached_executions_complete_independently() -> Result<()> {
+    let module = tinywasm::parse_bytes(FIBONACCI_WASM)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let fib = instance.func::<i32, i32>(&store, "fibonacci_recursive")?;
+
+    let inputs = [18, 12, 20, 5, 15];
+    let expected = inputs.iter().map(|&n| fib.call(&mut store, n)).collect::<tinywasm::Result<Vec<_>>>()?;
+
+    let mut tasks = inputs.iter().map(|&n| fib.call_detached(&mut store, n)).collect::<tinywasm::Result<Vec<_>>>()?;
+    let mut results = vec![None; tasks.len()];
+    let mut rounds = 0;
+
+    // Round-robin scheduling, resuming the tasks in reverse order to interleave them.
+    while results.iter().any(Option::is_none) {
+        rounds += 1;
+        for (i, task) in tasks.iter_mut().enumerate().rev() {
+            if results[i].is_some() {
+                continue;
+            }
+            if let ExecProgress::Completed(value) = task.resume_with_fuel(&mut store, 256)? {
+                assert!(task.is_completed());
+                results[i] = Some(value);
+            }
+        }
+    }
+
+    assert!(rounds > 1, "expected executions to be suspended at least once");
+    assert_eq!(results.into_iter().map(Option::unwrap).collect::<Vec<_>>(), expected);
+    Ok(())
+}
+
+#[test]
+fn blocking_calls_do_not_disturb_suspended_executions() -> Result<()> {
+    let module = tinywasm::parse_bytes(FIBONACCI_WASM)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let fib = instance.func_untyped(&store, "fibonacci_recursive")?;
+
+    let mut task = fib.call_detached(&mut store, &[WasmValue::I32(16)])?;
+    assert!(matches!(task.resume_with_fuel(&mut store, 128)?, ExecProgress::Suspended));
+
+    // A blocking call and a borrowed resumable call both use the store's own stacks.
+    let direct = fib.call(&mut store, &[WasmValue::I32(16)])?;
+    let mut borrowed = fib.call_resumable(&mut store, &[WasmValue::I32(10)])?;
+    assert!(matches!(borrowed.resume_with_fuel(64)?, ExecProgress::Suspended));
+
+    let result = loop {
+        if let ExecProgress::Completed(values) = task.resume_with_fuel(&mut store, 1024)? {
+            break values;
+        }
+    };
+    assert_eq!(result, direct);
+    Ok(())
+}
+
+#[test]
+fn detached_execution_rejects_other_stores() -> Result<()> {
+    let module = tinywasm::parse_bytes(FIBONACCI_WASM)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let fib = instance.func::<i32, i32>(&store, "fibonacci_recursive")?;
+
+    let mut task = fib.call_detached(&mut store, 10)?;
+    let mut other = Store::default();
+    assert!(matches!(task.resume_with_fuel(&mut other, 1024), Err(Error::Trap(Trap::InvalidStore))));
+    assert!(!task.is_completed());
+
+    let result = loop {
+        if let ExecProgress::Completed(value) = task.resume_with_fuel(&mut store, 1024)? {
+            break value;
+        }
+    };
+    assert_eq!(result, 55);
+    Ok(())
+}
+
+#[test]
+fn trapped_detached_execution_is_completed() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (func (export "trap") (param i32) (result i32)
+            (if (local.get 0) (then unreachable))
+            (local.get 0)))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let func = instance.func::<i32, i32>(&store, "trap")?;
+
+    let mut trapping = func.call_detached(&mut store, 1)?;
+    let mut ok = func.call_detached(&mut store, 0)?;
+
+    assert!(matches!(trapping.resume_with_fuel(&mut store, 1024), Err(Error::Trap(Trap::Unreachable))));
+    assert!(trapping.is_completed());
+    assert!(trapping.resume_with_fuel(&mut store, 1024).is_err());
+
+    assert!(matches!(ok.resume_with_fuel(&mut store, 1024)?, ExecProgress::Completed(0)));
+    Ok(())
+}
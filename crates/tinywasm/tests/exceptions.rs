@@ -0,0 +1,134 @@
+use eyre::Result;
+use tinywasm::types::{FuncType, WasmType, WasmValue};
+use tinywasm::{Error, Exception, FuncContext, HostFunction, Imports, ModuleInstance, Store, Tag, Trap};
+
+#[test]
+fn wasm_catches_host_exception() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "host" "tag" (tag $host (param i32)))
+          (import "host" "throw" (func $throw (param i32)))
+          (func (export "catch-host") (param i32) (result i32)
+            (block $h (result i32)
+              (try_table (catch $host $h) (call $throw (local.get 0)))
+              (i32.const -1))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let tag = Tag::new(&mut store, FuncType::new(&[WasmType::I32], &[]));
+    let throw = HostFunction::from(&mut store, move |ctx: FuncContext<'_>, value: i32| -> tinywasm::Result<()> {
+        Err(Exception::new(ctx.store(), tag, &[WasmValue::I32(value * 2)])?.into())
+    });
+    let mut imports = Imports::new();
+    imports.define("host", "tag", tag).define("host", "throw", throw);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+
+    let catch = instance.func::<i32, i32>(&store, "catch-host")?;
+    assert_eq!(catch.call(&mut store, 21)?, 42);
+    Ok(())
+}
+
+#[test]
+fn host_receives_uncaught_exception() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "host" "tag" (tag $host (param i32)))
+          (tag $local (export "local") (param i64 i32))
+          (func (export "throw-local") (param i64 i32)
+            (throw $local (local.get 0) (local.get 1)))
+          (func (export "throw-host") (param i32)
+            (throw $host (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let host_tag = Tag::new(&mut store, FuncType::new(&[WasmType::I32], &[]));
+    let mut imports = Imports::new();
+    imports.define("host", "tag", host_tag);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+
+    let throw = instance.func::<(i64, i32), ()>(&store, "throw-local")?;
+    let Err(Error::Trap(Trap::Exception(exception))) = throw.call(&mut store, (7, 8)) else {
+        panic!("expected an uncaught exception");
+    };
+    assert_eq!(exception.tag(), instance.tag("local")?);
+    assert_ne!(exception.tag(), host_tag);
+    assert_eq!(exception.payload(), &[WasmValue::I64(7), WasmValue::I32(8)]);
+
+    let throw = instance.func::<i32, ()>(&store, "throw-host")?;
+    let Err(Error::Trap(Trap::Exception(exception))) = throw.call(&mut store, 3) else {
+        panic!("expected an uncaught exception");
+    };
+    assert_eq!(exception.tag(), host_tag);
+    assert_eq!(exception.payload(), &[WasmValue::I32(3)]);
+    Ok(())
+}
+
+#[test]
+fn exception_propagates_through_reentrant_host_call() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "host" "tag" (tag $host (param i32)))
+          (import "host" "call" (func $call (param i32)))
+          (func (export "throw-host") (param i32)
+            (throw $host (local.get 0)))
+          (func (export "catch-reentrant") (param i32) (result i32)
+            (block $h (result i32)
+              (try_table (catch $host $h) (call $call (local.get 0)))
+              (i32.const -1))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let tag = Tag::new(&mut store, FuncType::new(&[WasmType::I32], &[]));
+    // calls back into the module and lets its exception propagate through the host function
+    let call = HostFunction::from(&mut store, |mut ctx: FuncContext<'_>, value: i32| -> tinywasm::Result<()> {
+        let throw = ctx.module().func::<i32, ()>(ctx.store(), "throw-host")?;
+        ctx.call(&throw, value)
+    });
+    let mut imports = Imports::new();
+    imports.define("host", "tag", tag).define("host", "call", call);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+
+    let catch = instance.func::<i32, i32>(&store, "catch-reentrant")?;
+    assert_eq!(catch.call(&mut store, 5)?, 5);
+    Ok(())
+}
+
+#[test]
+fn exception_payload_must_match_tag() -> Result<()> {
+    let mut store = Store::default();
+    let tag = Tag::new(&mut store, FuncType::new(&[WasmType::I32], &[]));
+
+    assert!(Exception::new(&store, tag, &[]).is_err());
+    assert!(Exception::new(&store, tag, &[WasmValue::I64(1)]).is_err());
+    assert_eq!(tag.ty(&store)?.params(), &[WasmType::I32]);
+
+    let other_store = Store::default();
+    assert!(Exception::new(&other_store, tag, &[WasmValue::I32(1)]).is_err());
+    Ok(())
+}
+
+#[test]
+fn tag_imports_are_type_checked() -> Result<()> {
+    let wasm = wat::parse_str(r#"(module (import "host" "tag" (tag (param i32))))"#)?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+
+    let tag = Tag::new(&mut store, FuncType::new(&[WasmType::I64], &[]));
+    let mut imports = Imports::new();
+    imports.define("host", "tag", tag);
+
+    let Err(Error::Linker(err)) = ModuleInstance::instantiate(&mut store, &module, Some(imports)) else {
+        panic!("expected a linking error");
+    };
+    assert_eq!(err.message(), "incompatible import type");
+    Ok(())
+}
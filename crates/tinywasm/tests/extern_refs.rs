@@ -0,0 +1,212 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use eyre::Result;
+use tinywasm::engine::Config;
+use tinywasm::types::{ExternRef, FuncType, WasmType, WasmValue};
+use tinywasm::{Engine, FuncContext, HostFunction, Imports, ModuleInstance, Store};
+
+struct Counter {
+    value: i32,
+    dropped: Rc<Cell<bool>>,
+}
+
+impl Drop for Counter {
+    fn drop(&mut self) {
+        self.dropped.set(true);
+    }
+}
+
+fn counter(store: &mut Store, value: i32) -> Result<(ExternRef, Rc<Cell<bool>>)> {
+    let dropped = Rc::new(Cell::new(false));
+    let handle = store.new_extern_ref(Counter { value, dropped: dropped.clone() })?;
+    Ok((handle, dropped))
+}
+
+#[test]
+fn host_values_round_trip_through_tables() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "host" "read" (func $read (param externref) (result i32)))
+          (table $handles 4 externref)
+          (func (export "store") (param i32 externref)
+            (table.set $handles (local.get 0) (local.get 1)))
+          (func (export "read") (param i32) (result i32)
+            (call $read (table.get $handles (local.get 0)))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let ty = FuncType::new(&[WasmType::RefExtern], &[WasmType::I32]);
+    let read = HostFunction::from_untyped(&mut store, &ty, |mut ctx: FuncContext<'_>, params| {
+        let WasmValue::RefExtern(handle) = params[0] else { unreachable!() };
+        let counter = ctx.store_mut().downcast_mut::<Counter>(handle).expect("a counter");
+        counter.value += 1;
+        Ok(vec![WasmValue::I32(counter.value)])
+    });
+    let mut imports = Imports::new();
+    imports.define("host", "read", read);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    let (handle, _) = counter(&mut store, 10)?;
+
+    instance.func::<(i32, ExternRef), ()>(&store, "store")?.call(&mut store, (2, handle))?;
+    let read = instance.func::<i32, i32>(&store, "read")?;
+    assert_eq!(read.call(&mut store, 2)?, 11);
+    assert_eq!(read.call(&mut store, 2)?, 12);
+    assert_eq!(store.downcast_ref::<Counter>(handle).map(|counter| counter.value), Some(12));
+    assert!(store.downcast_ref::<String>(handle).is_none());
+    assert!(store.downcast_ref::<Counter>(ExternRef::new(Some(3)).expect("a plain address")).is_none());
+    assert!(ExternRef::new(Some(ExternRef::MAX_ADDR + 1)).is_none());
+    Ok(())
+}
+
+#[test]
+fn unreachable_host_values_are_dropped() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (table $handles 4 externref)
+          (global $current (mut externref) (ref.null extern))
+          (func (export "store") (param i32 externref)
+            (table.set $handles (local.get 0) (local.get 1)))
+          (func (export "clear") (param i32)
+            (table.set $handles (local.get 0) (ref.null extern)))
+          (func (export "set-current") (param externref)
+            (global.set $current (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let (in_table, table_dropped) = counter(&mut store, 0)?;
+    let (in_global, global_dropped) = counter(&mut store, 0)?;
+    let (pinned, pinned_dropped) = counter(&mut store, 0)?;
+
+    instance.func::<(i32, ExternRef), ()>(&store, "store")?.call(&mut store, (0, in_table))?;
+    instance.func::<ExternRef, ()>(&store, "set-current")?.call(&mut store, in_global)?;
+    instance.func::<(i32, ExternRef), ()>(&store, "store")?.call(&mut store, (1, pinned))?;
+    instance.func::<i32, ()>(&store, "clear")?.call(&mut store, 1)?;
+    store.pin(pinned);
+    store.collect_garbage();
+    assert!(!table_dropped.get() && !global_dropped.get() && !pinned_dropped.get());
+
+    instance.func::<i32, ()>(&store, "clear")?.call(&mut store, 0)?;
+    store.unpin(pinned);
+    store.collect_garbage();
+    assert!(table_dropped.get() && pinned_dropped.get());
+    assert!(!global_dropped.get());
+    Ok(())
+}
+
+#[test]
+fn new_host_values_are_kept_until_handed_over() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (global $current (mut externref) (ref.null extern))
+          (func (export "set-current") (param externref)
+            (global.set $current (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let (handle, dropped) = counter(&mut store, 0)?;
+    store.collect_garbage();
+    assert!(!dropped.get());
+    assert_eq!(store.downcast_ref::<Counter>(handle).map(|counter| counter.value), Some(0));
+
+    let set_current = instance.func::<ExternRef, ()>(&store, "set-current")?;
+    set_current.call(&mut store, handle)?;
+    set_current.call(&mut store, ExternRef::null())?;
+    store.collect_garbage();
+    assert!(dropped.get());
+    Ok(())
+}
+
+#[test]
+fn host_values_can_be_released_explicitly() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (table $handles 4 externref)
+          (func (export "store") (param i32 externref)
+            (table.set $handles (local.get 0) (local.get 1))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let (handle, dropped) = counter(&mut store, 5)?;
+    instance.func::<(i32, ExternRef), ()>(&store, "store")?.call(&mut store, (1, handle))?;
+
+    let released = store.release_extern_ref(handle).expect("a host value");
+    assert_eq!(released.downcast_ref::<Counter>().map(|counter| counter.value), Some(5));
+    drop(released);
+    assert!(dropped.get());
+
+    assert!(store.downcast_ref::<Counter>(handle).is_none());
+    assert!(store.release_extern_ref(handle).is_none());
+    Ok(())
+}
+
+#[test]
+fn creating_host_values_collects_garbage() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (table $handles 4 externref)
+          (func (export "store") (param i32 externref)
+            (table.set $handles (local.get 0) (local.get 1))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::new(Engine::new(Config::new().with_gc_threshold(1024)));
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let store_handle = instance.func::<(i32, ExternRef), ()>(&store, "store")?;
+    let (first, first_dropped) = counter(&mut store, 0)?;
+    store_handle.call(&mut store, (0, first))?;
+    for value in 0..100 {
+        let (handle, _) = counter(&mut store, value)?;
+        store_handle.call(&mut store, (0, handle))?;
+    }
+    assert!(first_dropped.get());
+    assert!(store.gc_object_count() < 100);
+    Ok(())
+}
+
+#[test]
+fn stale_references_do_not_alias_new_host_values() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (global $current (mut externref) (ref.null extern))
+          (func (export "set-current") (param externref)
+            (global.set $current (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let (stale, dropped) = counter(&mut store, 1)?;
+    let set_current = instance.func::<ExternRef, ()>(&store, "set-current")?;
+    set_current.call(&mut store, stale)?;
+    set_current.call(&mut store, ExternRef::null())?;
+    store.collect_garbage();
+    assert!(dropped.get());
+
+    // the new value reuses the slot of the collected one
+    let (handle, _) = counter(&mut store, 2)?;
+    assert_ne!(stale, handle);
+    assert!(store.downcast_ref::<Counter>(stale).is_none());
+    assert!(store.release_extern_ref(stale).is_none());
+    assert_eq!(store.downcast_ref::<Counter>(handle).map(|counter| counter.value), Some(2));
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use eyre::Result;
+use tinywasm::types::{FuncRef, WasmValue};
+use tinywasm::{Error, Function, HostFunction, ModuleInstance, Store};
+
+#[test]
+fn guest_funcrefs_are_callable() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func $add (type $binop) (i32.add (local.get 0) (local.get 1)))
+          (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+          (table $ops (export "ops") 4 funcref)
+          (elem (table $ops) (i32.const 0) func $add $sub)
+          (func (export "get") (param i32) (result funcref)
+            (table.get $ops (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    let get = instance.func::<i32, FuncRef>(&store, "get")?;
+    let sub_ref = get.call(&mut store, 1)?;
+    let sub = Function::from_funcref(&store, sub_ref)?.expect("a function");
+    assert_eq!(sub.call(&mut store, &[WasmValue::I32(7), WasmValue::I32(2)])?, vec![WasmValue::I32(5)]);
+    assert_eq!(sub.to_funcref(), sub_ref);
+
+    let null = get.call(&mut store, 2)?;
+    assert!(Function::from_funcref(&store, null)?.is_none());
+    assert!(Function::from_funcref(&store, FuncRef::new(Some(1000))).is_err());
+    assert!(matches!(sub.typed::<i32, i32>(), Err(Error::Other(_))));
+    Ok(())
+}
+
+#[test]
+fn tables_return_typed_functions() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func $add (type $binop) (i32.add (local.get 0) (local.get 1)))
+          (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+          (table $ops (export "ops") 4 funcref)
+          (elem (table $ops) (i32.const 0) func $add $sub))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let ops = instance.table("ops")?;
+
+    let add = ops.get_typed_func::<(i32, i32), i32>(&store, 0)?.expect("a function");
+    assert_eq!(add.call(&mut store, (3, 4))?, 7);
+    assert!(ops.get_func(&store, 3)?.is_none());
+    assert!(ops.get_typed_func::<i32, i32>(&store, 1).is_err());
+    assert!(ops.get_func(&store, 4).is_err());
+    Ok(())
+}
+
+#[test]
+fn host_functions_are_installed_into_tables() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func $add (type $binop) (i32.add (local.get 0) (local.get 1)))
+          (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+          (table $ops (export "ops") 4 funcref)
+          (elem (table $ops) (i32.const 0) func $add $sub)
+          (table $typed (export "typed") 2 (ref null $binop))
+          (func (export "apply") (param i32 i32 i32) (result i32)
+            (call_indirect $ops (type $binop) (local.get 1) (local.get 2) (local.get 0)))
+          (func (export "apply-typed") (param i32 i32 i32) (result i32)
+            (call_ref $binop (local.get 1) (local.get 2) (ref.as_non_null (table.get $typed (local.get 0))))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let mul = HostFunction::from(&mut store, |_, (lhs, rhs): (i32, i32)| Ok(lhs * rhs));
+    let neg = HostFunction::from(&mut store, |_, value: i32| Ok(-value));
+
+    let ops = instance.table("ops")?;
+    ops.set_func(&mut store, 2, &mul)?;
+    assert_eq!(instance.func::<(i32, i32, i32), i32>(&store, "apply")?.call(&mut store, (2, 6, 7))?, 42);
+    assert!(ops.get_func(&store, 2)?.is_some_and(|func| func.to_funcref() == mul.to_funcref()));
+
+    let typed = instance.table("typed")?;
+    typed.set_func(&mut store, 0, &mul)?;
+    let sub = ops.get_func(&store, 1)?.expect("a function");
+    typed.set_func(&mut store, 1, &sub)?;
+    let apply_typed = instance.func::<(i32, i32, i32), i32>(&store, "apply-typed")?;
+    assert_eq!(apply_typed.call(&mut store, (0, 6, 7))?, 42);
+    assert_eq!(apply_typed.call(&mut store, (1, 6, 7))?, -1);
+    assert!(typed.set_func(&mut store, 0, &neg).is_err());
+
+    let mut other = Store::default();
+    let foreign = HostFunction::from(&mut other, |_, value: i32| Ok(value));
+    assert!(ops.set_func(&mut store, 0, &foreign).is_err());
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use eyre::Result;
+use tinywasm::types::{FuncRef, GlobalType, HeapType, RefType, WasmType};
+use tinywasm::{Error, Imports, LinkingError, ModuleInstance, Store, Trap};
+
+#[test]
+fn typed_references_are_called() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func $add (type $binop) (i32.add (local.get 0) (local.get 1)))
+          (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+          (elem declare func $add $sub)
+
+          (table $ops 2 (ref $binop) (ref.func $add))
+          (global $op (ref $binop) (ref.func $sub))
+
+          (func (export "apply") (param i32 i32 i32) (result i32)
+            (call_ref $binop (local.get 1) (local.get 2) (table.get $ops (local.get 0))))
+
+          (func (export "apply-global") (param i32 i32) (result i32)
+            (return_call_ref $binop (local.get 0) (local.get 1) (global.get $op))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    let apply = instance.func::<(i32, i32, i32), i32>(&store, "apply")?;
+    assert_eq!(apply.call(&mut store, (0, 3, 4))?, 7);
+    assert_eq!(apply.call(&mut store, (1, 3, 4))?, 7);
+    assert_eq!(instance.func::<(i32, i32), i32>(&store, "apply-global")?.call(&mut store, (3, 4))?, -1);
+    Ok(())
+}
+
+#[test]
+fn null_references_branch_or_trap() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func $add (type $binop) (i32.add (local.get 0) (local.get 1)))
+          (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+          (elem declare func $add $sub)
+
+          (func (export "apply-or") (param i32) (result i32)
+            (block $null (result)
+              (return (call_ref $binop (i32.const 10) (local.get 0)
+                (br_on_null $null (select (result (ref null $binop))
+                  (ref.func $sub) (ref.null $binop) (local.get 0))))))
+            (i32.const -1))
+
+          (func (export "non-null") (param i32)
+            (drop (ref.as_non_null (select (result (ref null $binop))
+              (ref.func $add) (ref.null $binop) (local.get 0))))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    let apply_or = instance.func::<i32, i32>(&store, "apply-or")?;
+    assert_eq!(apply_or.call(&mut store, 4)?, 6);
+    assert_eq!(apply_or.call(&mut store, 0)?, -1);
+
+    let non_null = instance.func::<i32, ()>(&store, "non-null")?;
+    non_null.call(&mut store, 1)?;
+    assert_eq!(non_null.call(&mut store, 0).unwrap_err(), Error::Trap(Trap::NullReference));
+    Ok(())
+}
+
+#[test]
+fn typed_globals_are_checked_on_import() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+          (elem declare func $sub)
+          (global (export "op") (ref $binop) (ref.func $sub)))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    let export = instance.global("op")?;
+    let ty = export.ty(&store)?;
+    assert_eq!(ty.ty, WasmType::RefFunc);
+    assert_eq!(ty.precise_ref_type().map(|ty| ty.nullable), Some(false));
+
+    let mut import = |ty: &str| -> Result<_> {
+        let wat = format!(r#"(module (type $t (func (param i32) (result i32))) (import "m" "op" (global {ty})))"#);
+        let module = tinywasm::parse_bytes(&wat::parse_str(wat)?)?;
+        let mut imports = Imports::new();
+        imports.define("m", "op", export);
+        Ok(ModuleInstance::instantiate(&mut store, &module, Some(imports)).map(|_| ()))
+    };
+
+    assert!(import("funcref")?.is_ok());
+    assert!(import("(ref func)")?.is_ok());
+    assert!(matches!(import("(ref $t)")?, Err(Error::Linker(LinkingError::IncompatibleImportType { .. }))));
+    assert!(matches!(import("(mut (ref func))")?, Err(Error::Linker(LinkingError::IncompatibleImportType { .. }))));
+    Ok(())
+}
+
+#[test]
+fn precise_types_default_to_nullable_top_types() {
+    let funcref = GlobalType::new(WasmType::RefFunc, false);
+    assert_eq!(funcref.precise_ref_type(), Some(RefType::new(true, HeapType::Func)));
+    assert_eq!(GlobalType::new(WasmType::I32, false).precise_ref_type(), None);
+
+    let non_null = funcref.with_ref_type(RefType::new(false, HeapType::Func));
+    assert_ne!(non_null, funcref);
+    assert_eq!(non_null.with_ty(WasmType::RefFunc), funcref);
+}
+
+#[test]
+fn host_function_references_are_checked_against_precise_types() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (func (export "neg") (param i32) (result i32) (i32.sub (i32.const 0) (local.get 0)))
+          (func (export "add") (type $binop) (i32.add (local.get 0) (local.get 1)))
+          (func (export "call") (param (ref $binop)) (result i32)
+            (call_ref $binop (i32.const 1) (i32.const 2) (local.get 0))))
+        "#,
+    )?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &tinywasm::parse_bytes(&wasm)?, None)?;
+    let call = instance.func::<FuncRef, i32>(&store, "call")?;
+    let neg = instance.func_untyped(&store, "neg")?.to_funcref();
+    let add = instance.func_untyped(&store, "add")?.to_funcref();
+
+    // a function with a different signature, a null reference and an address outside the store
+    assert!(call.call(&mut store, neg).is_err());
+    assert!(call.call(&mut store, FuncRef::null()).is_err());
+    assert!(call.call(&mut store, FuncRef::new(Some(u32::MAX - 1))).is_err());
+    assert_eq!(call.call(&mut store, add)?, 3);
+    Ok(())
+}
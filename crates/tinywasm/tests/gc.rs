@@ -0,0 +1,260 @@
+use eyre::Result;
+use tinywasm::engine::Config;
+use tinywasm::types::{AnyRef, ExternRef, FuncType, WasmType, WasmValue};
+use tinywasm::{Engine, FuncContext, GcValue, HostFunction, Imports, ModuleInstance, Store};
+
+#[test]
+fn unreachable_objects_are_collected() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field i32) (field i64)))
+          (global $kept (export "kept") (mut anyref) (ref.null any))
+          (func (export "alloc") (param i32)
+            (loop $l
+              (drop (struct.new $point (local.get 0) (i64.const 0)))
+              (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1))))))
+          (func (export "keep") (param i32)
+            (global.set $kept (struct.new $point (local.get 0) (i64.const 7)))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    instance.func::<i32, ()>(&store, "alloc")?.call(&mut store, 100)?;
+    instance.func::<i32, ()>(&store, "keep")?.call(&mut store, 5)?;
+    assert_eq!(store.gc_object_count(), 101);
+
+    store.collect_garbage();
+    assert_eq!(store.gc_object_count(), 1);
+
+    let WasmValue::RefAny(kept) = instance.global_get(&store, "kept")? else { panic!("expected an anyref") };
+    let ty = instance.gc_type(&store, 0)?;
+    assert_eq!(store.inspect(kept)?, Some(GcValue::Struct(ty)));
+    assert_eq!(store.struct_get(kept, 0)?, WasmValue::I32(5));
+    assert_eq!(store.struct_get(kept, 1)?, WasmValue::I64(7));
+    Ok(())
+}
+
+#[test]
+fn allocation_triggers_collection() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field i32)))
+          (func (export "alloc") (param i32)
+            (loop $l
+              (drop (struct.new $point (local.get 0)))
+              (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::new(Engine::new(Config::new().with_gc_threshold(1024)));
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    instance.func::<i32, ()>(&store, "alloc")?.call(&mut store, 10_000)?;
+    assert!(store.gc_object_count() < 100);
+    Ok(())
+}
+
+#[test]
+fn pinned_objects_survive_collection() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field $x i32) (field $y i64)))
+          (func (export "point-x") (param anyref) (result i32)
+            (struct.get $point $x (ref.cast (ref $point) (local.get 0)))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let point = instance.gc_type(&store, 0)?;
+
+    let value = store.new_struct(point, &[WasmValue::I32(3), WasmValue::I64(4)])?;
+    store.pin(value);
+    store.collect_garbage();
+    assert_eq!(instance.func::<AnyRef, i32>(&store, "point-x")?.call(&mut store, value)?, 3);
+
+    store.unpin(value);
+    store.collect_garbage();
+    assert_eq!(store.gc_object_count(), 0);
+    assert!(store.inspect(value).is_err());
+    Ok(())
+}
+
+#[test]
+fn host_creates_and_updates_arrays() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $bytes (array (mut i8)))
+          (func (export "bytes-sum") (param anyref) (result i32)
+            (local $arr (ref $bytes)) (local $i i32) (local $sum i32)
+            (local.set $arr (ref.cast (ref $bytes) (local.get 0)))
+            (block $done
+              (loop $l
+                (br_if $done (i32.ge_u (local.get $i) (array.len (local.get $arr))))
+                (local.set $sum (i32.add (local.get $sum) (array.get_u $bytes (local.get $arr) (local.get $i))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $l)))
+            (local.get $sum)))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let bytes = instance.gc_type(&store, 0)?;
+
+    let array = store.new_array(bytes, 4, WasmValue::I32(10))?;
+    store.array_set(array, 3, WasmValue::I32(250))?;
+    assert_eq!(store.inspect(array)?, Some(GcValue::Array(bytes)));
+    assert_eq!(store.array_len(array)?, 4);
+    assert_eq!(store.array_get(array, 3)?, WasmValue::I32(250));
+    assert!(store.array_get(array, 4).is_err());
+
+    assert_eq!(instance.func::<AnyRef, i32>(&store, "bytes-sum")?.call(&mut store, array)?, 280);
+    Ok(())
+}
+
+#[test]
+fn immutable_fields_and_mismatched_values_are_rejected() -> Result<()> {
+    let wasm = wat::parse_str(r#"(module (type $point (struct (field (mut i32)) (field i64))))"#)?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let point = instance.gc_type(&store, 0)?;
+
+    assert!(store.new_struct(point, &[WasmValue::I32(1)]).is_err());
+    assert!(store.new_struct(point, &[WasmValue::I64(1), WasmValue::I64(2)]).is_err());
+
+    let value = store.new_struct(point, &[WasmValue::I32(1), WasmValue::I64(2)])?;
+    assert!(store.struct_set(value, 1, WasmValue::I64(3)).is_err());
+    assert!(instance.gc_type(&store, 1).is_err());
+    Ok(())
+}
+
+#[test]
+fn host_call_arguments_are_rooted() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field $x i32)))
+          (import "host" "keep" (func $keep (param anyref) (result anyref)))
+          (func (export "through-host") (param i32) (result i32)
+            (struct.get $point $x
+              (ref.cast (ref $point)
+                (call $keep (struct.new $point (local.get 0)))))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let ty = FuncType::new(&[WasmType::RefAny], &[WasmType::RefAny]);
+    // collects garbage while the argument is only referenced by the host call
+    let keep = HostFunction::from_untyped(&mut store, &ty, |mut ctx: FuncContext<'_>, params| {
+        ctx.store_mut().collect_garbage();
+        Ok(params.to_vec())
+    });
+    let mut imports = Imports::new();
+    imports.define("host", "keep", keep);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+
+    assert_eq!(instance.func::<i32, i32>(&store, "through-host")?.call(&mut store, 9)?, 9);
+    Ok(())
+}
+
+#[test]
+fn externrefs_round_trip_through_anyref() -> Result<()> {
+    let mut store = Store::default();
+    let host = ExternRef::new(Some(17)).expect("a plain address");
+
+    let any = store.extern_to_any(host)?;
+    assert_eq!(store.inspect(any)?, Some(GcValue::Extern(host)));
+    assert_eq!(store.any_to_extern(any)?, host);
+
+    let i31 = AnyRef::i31(-5);
+    assert_eq!(store.inspect(i31)?, Some(GcValue::I31(-5)));
+    Ok(())
+}
+
+#[test]
+fn host_arguments_are_checked_against_precise_types() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field $x i32)))
+          (type $bytes (array (mut i8)))
+          (func (export "x") (param (ref $point)) (result i32)
+            (struct.get $point $x (local.get 0))))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let point = store.new_struct(instance.gc_type(&store, 0)?, &[WasmValue::I32(3)])?;
+    let bytes = store.new_array(instance.gc_type(&store, 1)?, 1, WasmValue::I32(0))?;
+
+    let x = instance.func::<AnyRef, i32>(&store, "x")?;
+    assert!(x.call(&mut store, bytes).is_err());
+    assert!(x.call(&mut store, AnyRef::i31(1)).is_err());
+    assert!(x.call(&mut store, AnyRef::null()).is_err());
+    // the failed calls leave the store usable
+    assert_eq!(x.call(&mut store, point)?, 3);
+    Ok(())
+}
+
+#[test]
+fn host_table_values_are_checked_against_precise_types() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field i32)))
+          (type $bytes (array (mut i8)))
+          (table (export "points") 1 (ref null $point)))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let point = WasmValue::RefAny(store.new_struct(instance.gc_type(&store, 0)?, &[WasmValue::I32(3)])?);
+    let bytes = WasmValue::RefAny(store.new_array(instance.gc_type(&store, 1)?, 1, WasmValue::I32(0))?);
+
+    let table = instance.table("points")?;
+    assert!(table.set(&mut store, 0, bytes).is_err());
+    assert!(table.grow(&mut store, 1, bytes).is_err());
+    assert_eq!(table.size(&store)?, 1);
+    table.set(&mut store, 0, point).map_err(tinywasm::Error::from)?;
+    Ok(())
+}
+
+#[test]
+fn host_global_values_are_checked_against_precise_types() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (type $point (struct (field i32)))
+          (type $bytes (array (mut i8)))
+          (global (export "point") (mut (ref null $point)) (ref.null $point)))
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let point = WasmValue::RefAny(store.new_struct(instance.gc_type(&store, 0)?, &[WasmValue::I32(3)])?);
+    let bytes = WasmValue::RefAny(store.new_array(instance.gc_type(&store, 1)?, 1, WasmValue::I32(0))?);
+
+    let global = instance.global("point")?;
+    assert!(global.set(&mut store, bytes).is_err());
+    global.set(&mut store, point)?;
+    Ok(())
+}
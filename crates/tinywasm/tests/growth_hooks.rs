@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use eyre::Result;
+use tinywasm::types::{FuncRef, WasmValue};
+use tinywasm::{ModuleInstance, Store};
+
+const MODULE: &str = r#"
+    (module
+      (memory (export "memory") 1 2)
+      (table (export "table") 1 2 funcref)
+      (func (export "grow_memory") (param i32) (result i32) (memory.grow (local.get 0)))
+      (func (export "grow_table") (param i32) (result i32) (table.grow (ref.null func) (local.get 0))))
+"#;
+
+#[test]
+fn memory_growth_is_reported_to_the_hook() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MODULE)?)?;
+    let mut store = Store::default();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    store.on_memory_grow(move |event| {
+        let instance = event.instance.as_ref().map(|(instance, idx)| (instance.id(), *idx));
+        seen.borrow_mut().push((event.target, instance, event.old_size, event.new_size, event.succeeded));
+    });
+
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let memory = instance.memory("memory")?;
+    let grow = instance.func::<i32, i32>(&store, "grow_memory")?;
+    assert_eq!(grow.call(&mut store, 1)?, 1);
+    assert_eq!(grow.call(&mut store, 1)?, -1);
+    assert_eq!(memory.grow(&mut store, 0)?, Some(2));
+
+    let id = instance.id();
+    assert_eq!(
+        *events.borrow(),
+        [(memory, Some((id, 0)), 1, 2, true), (memory, Some((id, 0)), 2, 3, false), (memory, None, 2, 2, true)]
+    );
+    Ok(())
+}
+
+#[test]
+fn table_growth_is_reported_to_the_hook() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MODULE)?)?;
+    let mut store = Store::default();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    store.on_table_grow(move |event| {
+        seen.borrow_mut().push((
+            event.target,
+            event.instance.is_some(),
+            event.old_size,
+            event.new_size,
+            event.succeeded,
+        ))
+    });
+
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let table = instance.table("table")?;
+    let grow = instance.func::<i32, i32>(&store, "grow_table")?;
+    assert_eq!(table.grow(&mut store, 1, WasmValue::RefFunc(FuncRef::null()))?, 1);
+    assert_eq!(grow.call(&mut store, 1)?, -1);
+
+    assert_eq!(*events.borrow(), [(table, false, 1, 2, true), (table, true, 2, 3, false)]);
+    Ok(())
+}
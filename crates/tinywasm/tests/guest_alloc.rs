@@ -0,0 +1,156 @@
+use eyre::Result;
+use tinywasm::types::WasmValue;
+use tinywasm::{ModuleInstance, Store};
+
+const MALLOC_GUEST: &str = r#"
+    (module
+      (memory (export "memory") 1)
+      (global $next (mut i32) (i32.const 16))
+      (global $frees (export "frees") (mut i32) (i32.const 0))
+      (func $malloc (export "malloc") (param i32) (result i32)
+        (global.get $next)
+        (global.set $next (i32.add (global.get $next) (local.get 0))))
+      (func (export "free") (param i32)
+        (global.set $frees (i32.add (global.get $frees) (i32.const 1))))
+      (func $sum (export "sum") (param $ptr i32) (param $len i32) (result i32)
+        (local $sum i32)
+        (block $done
+          (loop $next
+            (br_if $done (i32.eqz (local.get $len)))
+            (local.set $sum (i32.add (local.get $sum) (i32.load8_u (local.get $ptr))))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+            (local.set $len (i32.sub (local.get $len) (i32.const 1)))
+            (br $next)))
+        (local.get $sum))
+      (func (export "sum_both") (param i32 i32 i32 i32) (result i32)
+        (i32.add (call $sum (local.get 0) (local.get 1)) (call $sum (local.get 2) (local.get 3))))
+      (func (export "greeting") (result i32 i32)
+        (local $ptr i32)
+        (local.set $ptr (call $malloc (i32.const 2)))
+        (i32.store16 (local.get $ptr) (i32.const 0x6968))
+        (local.get $ptr)
+        (i32.const 2)))
+"#;
+
+#[test]
+fn guest_buffers_are_freed_after_use() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MALLOC_GUEST)?)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let sum = instance.func::<(i32, i32), i32>(&store, "sum")?;
+    let allocator = instance.guest_allocator(&store)?;
+
+    let total = allocator.with_bytes(&mut store, &[1, 2, 3], |store, bytes| {
+        assert_eq!((bytes.ptr(), bytes.len()), (16, 3));
+        sum.call(store, bytes.args()?)
+    })?;
+    assert_eq!(total, 6);
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(1));
+
+    let failed = allocator
+        .with_str(&mut store, "abc", |_, _| -> tinywasm::Result<()> { Err(tinywasm::Error::Other("failed".into())) });
+    assert!(failed.is_err());
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(2));
+    Ok(())
+}
+
+#[test]
+fn guest_buffers_are_freed_explicitly() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MALLOC_GUEST)?)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let sum = instance.func::<(i32, i32), i32>(&store, "sum")?;
+    let allocator = instance.guest_allocator(&store)?;
+    assert!(allocator.can_free());
+
+    let bytes = allocator.copy_bytes(&mut store, &[1, 2, 3])?;
+    assert_eq!((bytes.ptr(), bytes.len()), (16, 3));
+    assert_eq!(sum.call(&mut store, bytes.args()?)?, 6);
+    bytes.free(&mut store)?;
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(1));
+
+    let name = allocator.copy_str(&mut store, "abc")?;
+    assert_eq!(allocator.read_string(&store, 19, 3)?, "abc");
+    assert_eq!(name.leak(), (19, 3));
+    allocator.copy_str(&mut store, "")?.free(&mut store)?;
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(2));
+    Ok(())
+}
+
+#[test]
+fn several_guest_buffers_can_be_passed_to_one_call() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MALLOC_GUEST)?)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let sum_both = instance.func::<(i32, i32, i32, i32), i32>(&store, "sum_both")?;
+    let allocator = instance.guest_allocator(&store)?;
+
+    let total = allocator.with_bytes(&mut store, &[1, 2], |store, first| {
+        allocator.with_bytes(store, &[3, 4, 5], |store, second| {
+            let ((ptr1, len1), (ptr2, len2)) = (first.args()?, second.args()?);
+            sum_both.call(store, (ptr1, len1, ptr2, len2))
+        })
+    })?;
+    assert_eq!(total, 15);
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(2));
+    Ok(())
+}
+
+#[test]
+fn out_of_range_lengths_and_pointers_are_rejected() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MALLOC_GUEST)?)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let allocator = instance.guest_allocator(&store)?;
+
+    assert!(allocator.alloc(&mut store, u32::MAX).is_err());
+    assert!(allocator.free(&mut store, 1 << 31).is_err());
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(0));
+    Ok(())
+}
+
+#[test]
+fn guest_returned_buffers_are_taken() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(MALLOC_GUEST)?)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let greeting = instance.func::<(), (i32, i32)>(&store, "greeting")?;
+    let allocator = instance.guest_allocator(&store)?;
+
+    let (ptr, len) = greeting.call(&mut store, ())?;
+    assert_eq!(allocator.read_bytes(&store, ptr as u32, len as u32)?, b"hi");
+    assert_eq!(allocator.take_string(&mut store, ptr as u32, len as u32)?, "hi");
+    assert_eq!(instance.global_get(&store, "frees")?, WasmValue::I32(1));
+    Ok(())
+}
+
+#[test]
+fn guest_allocators_use_realloc_and_custom_exports() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory (export "heap") 1)
+          (global $next (mut i32) (i32.const 8))
+          (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+            (global.get $next)
+            (global.set $next (i32.add (global.get $next) (local.get 3))))
+          (func (export "alloc_none") (param i32) (result i32) (i32.const 0)))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+
+    assert!(instance.guest_allocator(&store).is_err());
+    let allocator = instance.guest_allocator_with(&store, "heap", "cabi_realloc", None)?;
+    assert!(!allocator.can_free());
+    let buffer = allocator.copy_str(&mut store, "data")?;
+    assert_eq!(buffer.ptr(), 8);
+    assert!(buffer.free(&mut store).is_err());
+    assert!(allocator.with_str(&mut store, "data", |_, _| Ok(())).is_err());
+    assert_eq!(allocator.alloc(&mut store, 4)?, 12);
+
+    let failing = instance.guest_allocator_with(&store, "heap", "alloc_none", None)?;
+    assert!(failing.copy_bytes(&mut store, &[1]).is_err());
+    Ok(())
+}
@@ -122,7 +122,10 @@ fn to_name(ty: &WasmType) -> &str {
         WasmType::F64 => "f64",
         WasmType::V128 => "v128",
         WasmType::RefFunc => "funcref",
+        WasmType::RefCont => "contref",
+        WasmType::RefExn => "exnref",
         WasmType::RefExtern => "externref",
+        WasmType::RefAny => "anyref",
     }
 }
 
@@ -0,0 +1,79 @@
+use eyre::Result;
+use tinywasm::types::{ExternType, FuncType, GlobalType, MemoryType, WasmType, WasmValue};
+use tinywasm::{Error, Extern, Global, HostFunction, HostModule, Imports, LinkingError, Memory, ModuleInstance, Store};
+
+/// A small host API with a function, a global and a memory.
+struct Env {
+    base: i32,
+}
+
+impl HostModule for Env {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn externs(&self, store: &mut Store) -> tinywasm::Result<Vec<(String, Extern)>> {
+        let base = self.base;
+        Ok(vec![
+            ("offset".into(), HostFunction::from(store, move |_, value: i32| Ok(base + value)).into()),
+            ("base".into(), Global::new(store, GlobalType::new(WasmType::I32, false), WasmValue::I32(base))?.into()),
+            ("memory".into(), Memory::new(store, MemoryType::default().with_page_count_initial(1))?.into()),
+        ])
+    }
+}
+
+const WASM: &str = r#"
+    (module
+      (import "env" "offset" (func $offset (param i32) (result i32)))
+      (import "env" "base" (global $base i32))
+      (import "env" "memory" (memory 1))
+      (func (export "run") (result i32)
+        (i32.store (i32.const 0) (call $offset (global.get $base)))
+        (i32.load (i32.const 0))))
+"#;
+
+#[test]
+fn host_modules_are_defined_at_once() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+    let mut store = Store::default();
+    let mut imports = Imports::new();
+    imports.define_module(&mut store, &Env { base: 20 })?;
+
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    assert_eq!(instance.func::<(), i32>(&store, "run")?.call(&mut store, ())?, 40);
+    Ok(())
+}
+
+#[test]
+fn host_modules_are_enumerated() -> Result<()> {
+    let types = Env { base: 0 }.extern_types()?;
+    let described: Vec<_> = types.iter().map(|(name, ty)| format!("{name}: {ty}")).collect();
+    assert_eq!(described, ["offset: (func (param i32) (result i32))", "base: (global i32)", "memory: (memory 1)"]);
+    assert_eq!(types[0].1, ExternType::Func(FuncType::new(&[WasmType::I32], &[WasmType::I32])));
+    Ok(())
+}
+
+#[test]
+fn host_modules_check_module_imports() -> Result<()> {
+    let env = Env { base: 0 };
+    env.check_imports(&tinywasm::parse_bytes(&wat::parse_str(WASM)?)?)?;
+
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "env" "offset" (func (param i64) (result i32)))
+          (import "env" "missing" (func))
+          (import "env" "memory" (memory 2))
+          (import "other" "ignored" (func)))
+        "#,
+    )?;
+    let Err(Error::Linker(LinkingError::Multiple(errors))) = env.check_imports(&tinywasm::parse_bytes(&wasm)?) else {
+        panic!("expected multiple linking errors");
+    };
+
+    assert_eq!(errors.len(), 3);
+    assert!(matches!(&errors[0], LinkingError::IncompatibleImportType { name, .. } if name == "offset"));
+    assert_eq!(errors[1], LinkingError::UnknownImport { module: "env".into(), name: "missing".into() });
+    assert!(matches!(&errors[2], LinkingError::IncompatibleImportType { name, .. } if name == "memory"));
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use eyre::Result;
-use tinywasm::{Error, Imports, Module, ModuleInstance, Store, Trap};
+use tinywasm::types::{ExternType, FuncType, GlobalType, ImportType, MemoryType, WasmType, WasmValue};
+use tinywasm::{
+    Error, Global, HostFunction, Imports, LinkingError, Memory, Module, ModuleInstance, Store, Trap, UnresolvedImports,
+};
 
 const WASM_ADD: &str = r#"
     (module
@@ -55,3 +58,153 @@ fn link_module_rejects_cross_store_instance() -> Result<()> {
     assert_eq!(err, Error::from(Trap::InvalidStore));
     Ok(())
 }
+
+const WASM_PLUGIN: &str = r#"
+    (module
+      (import "sdk" "version" (func $version (result i32)))
+      (import "sdk" "log" (func $log (param i32)))
+      (import "sdk" "limit" (global i32))
+      (func (export "version") (result i32) (call $version))
+      (func (export "log") (call $log (i32.const 1))))
+"#;
+
+fn unknown(module: &str, name: &str) -> LinkingError {
+    LinkingError::UnknownImport { module: module.into(), name: name.into() }
+}
+
+#[test]
+fn resolver_provides_missing_imports() -> Result<()> {
+    let plugin = tinywasm::parse_bytes(&wat::parse_str(WASM_PLUGIN)?)?;
+    let mut store = Store::default();
+
+    let version = HostFunction::from(&mut store, |_, ()| Ok(2));
+    let mut imports = Imports::new();
+    imports.define("sdk", "version", version).set_resolver(|store, name, ty| match (name.name(), ty) {
+        ("log", ImportType::Func(ty)) => Some(HostFunction::from_untyped(store, ty, |_, _| Ok(vec![])).into()),
+        ("limit", ImportType::Global(ty)) => tinywasm::Global::new(store, **ty, WasmValue::I32(8)).ok().map(Into::into),
+        ("version", _) => panic!("defined imports are not resolved"),
+        _ => None,
+    });
+
+    let instance = ModuleInstance::instantiate(&mut store, &plugin, Some(imports))?;
+    assert_eq!(instance.func::<(), i32>(&store, "version")?.call(&mut store, ())?, 2);
+    instance.func::<(), ()>(&store, "log")?.call(&mut store, ())?;
+    Ok(())
+}
+
+#[test]
+fn unresolved_imports_are_reported_at_once() -> Result<()> {
+    let plugin = tinywasm::parse_bytes(&wat::parse_str(WASM_PLUGIN)?)?;
+    let mut store = Store::default();
+
+    let err = ModuleInstance::instantiate(&mut store, &plugin, None).unwrap_err();
+    assert_eq!(err, Error::Linker(unknown("sdk", "version")));
+
+    let mut imports = Imports::new();
+    imports.on_unresolved(UnresolvedImports::Report);
+    let err = ModuleInstance::instantiate(&mut store, &plugin, Some(imports)).unwrap_err();
+    let expected = vec![unknown("sdk", "version"), unknown("sdk", "log"), unknown("sdk", "limit")];
+    assert_eq!(err, Error::Linker(LinkingError::Multiple(expected)));
+    assert!(err.to_string().contains("unknown import: sdk.limit"));
+    Ok(())
+}
+
+#[test]
+fn unresolved_functions_can_be_stubbed() -> Result<()> {
+    let plugin = tinywasm::parse_bytes(&wat::parse_str(WASM_PLUGIN)?)?;
+    let mut store = Store::default();
+
+    let mut imports = Imports::new();
+    imports.on_unresolved(UnresolvedImports::TrapFunctions);
+    let err = ModuleInstance::instantiate(&mut store, &plugin, Some(imports.clone())).unwrap_err();
+    assert_eq!(err, Error::Linker(unknown("sdk", "limit")));
+
+    let limit = tinywasm::Global::new(&mut store, Default::default(), WasmValue::I32(0))?;
+    imports.define("sdk", "limit", limit);
+    let instance = ModuleInstance::instantiate(&mut store, &plugin, Some(imports))?;
+    let err = instance.func::<(), ()>(&store, "log")?.call(&mut store, ()).unwrap_err();
+    let Error::Trap(Trap::HostFunction(err)) = err else { panic!("expected a host function trap, got {err}") };
+    assert_eq!(err.downcast_ref::<Error>(), Some(&Error::Linker(unknown("sdk", "log"))));
+    Ok(())
+}
+
+#[test]
+fn incompatible_imports_report_both_types() -> Result<()> {
+    const WASM: &str = r#"
+        (module
+          (import "sdk" "version" (func (param i32) (result i32)))
+          (import "sdk" "memory" (memory i64 1 4))
+          (import "sdk" "counter" (global (mut i64))))
+    "#;
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+    let mut store = Store::default();
+
+    let version = HostFunction::from(&mut store, |_, ()| Ok(1));
+    let memory = Memory::new(&mut store, MemoryType::default().with_page_count_initial(2))?;
+    let counter = Global::new(&mut store, GlobalType::new(WasmType::I64, false), WasmValue::I64(0))?;
+    let mut imports = Imports::new();
+    imports.define("sdk", "version", version).define("sdk", "memory", memory).define("sdk", "counter", counter);
+
+    let Error::Linker(LinkingError::Multiple(errors)) =
+        ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err()
+    else {
+        panic!("expected multiple linking errors");
+    };
+    assert_eq!(errors.len(), 3);
+
+    let LinkingError::IncompatibleImportType { name, expected, actual, .. } = &errors[0] else { panic!() };
+    assert_eq!(name, "version");
+    assert_eq!(**expected, ExternType::Func(FuncType::new(&[WasmType::I32], &[WasmType::I32])));
+    assert_eq!(**actual, ExternType::Func(FuncType::new(&[], &[WasmType::I32])));
+
+    let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        [
+            "incompatible import type: sdk.version: expected (func (param i32) (result i32)), found (func (result i32))",
+            "incompatible import type: sdk.memory: expected (memory i64 1 4), found (memory 2)",
+            "incompatible import type: sdk.counter: expected (global (mut i64)), found (global i64)",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn incompatible_imports_report_types_relative_to_the_importing_module() -> Result<()> {
+    const EXPORTS: &str = r#"
+        (module
+          (type $pad (func))
+          (type $s (struct (field i32)))
+          (type $a (array i32))
+          (global (export "g") (ref null $s) (ref.null $s))
+          (table (export "t") 1 (ref null $a)))
+    "#;
+    const WASM: &str = r#"
+        (module
+          (type $s (struct (field i32)))
+          (type $t (struct (field i64)))
+          (import "m" "g" (global (ref null $t)))
+          (import "m" "t" (table 1 (ref null $t))))
+    "#;
+    let mut store = Store::default();
+    let exports = ModuleInstance::instantiate(&mut store, &tinywasm::parse_bytes(&wat::parse_str(EXPORTS)?)?, None)?;
+    let mut imports = Imports::new();
+    imports.define("m", "g", exports.global("g")?).define("m", "t", exports.table("t")?);
+
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+    let Error::Linker(LinkingError::Multiple(errors)) =
+        ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err()
+    else {
+        panic!("expected multiple linking errors");
+    };
+
+    let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        [
+            "incompatible import type: m.g: expected (global (ref null 1)), found (global (ref null 0))",
+            "incompatible import type: m.t: expected (table 1 (ref null 1)), found (table 1 (ref null array))",
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use eyre::Result;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tinywasm::engine::Config;
+use tinywasm::types::{FuncRef, MemoryType, TableType, WasmType, WasmValue};
+use tinywasm::{Engine, Error, Extern, Function, HostFunction, Imports, Memory, ModuleInstance, Store, Table, Trap};
+
+const WASM: &str = r#"
+    (module
+      (import "env" "table" (table 4 funcref))
+      (import "env" "memory" (memory 1))
+      (memory $own 1)
+      (global $g (mut i32) (i32.const 1))
+      (func $f (result i32) (i32.const 7))
+      (elem (i32.const 0) $f)
+      (data (memory 0) (i32.const 0) "abc")
+      (data (memory $own) (i32.const 0) "own")
+      (elem (i32.const 3) $f $f))
+"#;
+
+const VALID: &str = r#"(module (func (export "f") (result i32) (i32.const 1)))"#;
+
+struct Env {
+    table: Table,
+    memory: Memory,
+    imports: Imports,
+}
+
+fn env(store: &mut Store) -> Result<Env> {
+    let table =
+        Table::new(store, TableType::new(WasmType::RefFunc, 4, None), WasmValue::default_for(WasmType::RefFunc))?;
+    let memory = Memory::new(store, MemoryType::default().with_page_count_initial(1))?;
+    memory.copy_from_slice(store, 0, b"xyz")?;
+
+    let mut imports = Imports::new();
+    imports.define("env", "table", table).define("env", "memory", memory);
+    Ok(Env { table, memory, imports })
+}
+
+fn atomic_store() -> Store {
+    Store::new(Engine::new(Config::new().with_atomic_instantiation(true)))
+}
+
+/// The address the next function added to the store gets.
+fn next_func_addr(store: &mut Store) -> FuncRef {
+    HostFunction::from(store, |_, ()| Ok(())).to_funcref()
+}
+
+#[test]
+fn out_of_bounds_segments_leave_the_store_unchanged() -> Result<()> {
+    let mut store = atomic_store();
+    let Env { table, memory, imports } = env(&mut store)?;
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+
+    let before = next_func_addr(&mut store);
+    let err = ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::TableOutOfBounds { .. })));
+
+    // the imported table and memory are restored and no functions were left behind
+    assert!(table.get_func(&store, 0)?.is_none());
+    assert_eq!(memory.read_vec(&store, 0, 3)?, b"xyz");
+    assert_eq!(next_func_addr(&mut store).addr(), before.addr().map(|addr| addr + 1));
+
+    // the next instance takes the place of the failed one
+    let valid = tinywasm::parse_bytes(&wat::parse_str(VALID)?)?;
+    let instance = ModuleInstance::instantiate(&mut store, &valid, None)?;
+    assert_eq!(instance.id(), 0);
+    assert_eq!(instance.func::<(), i32>(&store, "f")?.call(&mut store, ())?, 1);
+    Ok(())
+}
+
+#[test]
+fn out_of_bounds_data_restores_imported_memory() -> Result<()> {
+    let mut store = atomic_store();
+    let Env { memory, imports, .. } = env(&mut store)?;
+    let wat = r#"
+        (module
+          (import "env" "memory" (memory 1))
+          (data (i32.const 1) "bc")
+          (data (i32.const 65535) "de"))
+    "#;
+    let module = tinywasm::parse_bytes(&wat::parse_str(wat)?)?;
+
+    let err = ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::MemoryOutOfBounds { .. })));
+    assert_eq!(memory.read_vec(&store, 0, 3)?, b"xyz");
+    Ok(())
+}
+
+#[test]
+fn items_created_by_the_resolver_survive_a_failed_instantiation() -> Result<()> {
+    let mut store = atomic_store();
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+
+    // the resolver caches what it creates, so a failed instantiation must not free it
+    let cache = Rc::new(RefCell::new(Vec::new()));
+    let mut imports = Imports::new();
+    imports.set_resolver({
+        let cache = cache.clone();
+        move |store, name, _| {
+            let item: Extern = match name.name() {
+                "table" => {
+                    let ty = TableType::new(WasmType::RefFunc, 4, None);
+                    Table::new(store, ty, WasmValue::default_for(WasmType::RefFunc)).ok()?.into()
+                }
+                "memory" => Memory::new(store, MemoryType::default().with_page_count_initial(1)).ok()?.into(),
+                _ => return None,
+            };
+            cache.borrow_mut().push(item.clone());
+            Some(item)
+        }
+    });
+
+    let err = ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::TableOutOfBounds { .. })));
+
+    let cached = cache.borrow().clone();
+    let [Extern::Table(table), Extern::Memory(memory)] = cached[..] else {
+        panic!("expected a table and a memory");
+    };
+    assert_eq!(table.size(&store)?, 4);
+    assert!(table.get_func(&store, 0)?.is_none());
+    assert_eq!(memory.read_vec(&store, 0, 3)?, [0; 3]);
+
+    // new items get their own addresses
+    let other = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+    assert_ne!(other, memory);
+    Ok(())
+}
+
+#[test]
+fn spec_instantiation_keeps_written_segments() -> Result<()> {
+    let mut store = Store::default();
+    let Env { table, memory, imports } = env(&mut store)?;
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+
+    let err = ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::TableOutOfBounds { .. })));
+
+    // the first segment stays written, and the function it refers to stays callable
+    let f = table.get_func(&store, 0)?.expect("a function");
+    assert!(Function::from_funcref(&store, f.to_funcref())?.is_some());
+    assert_eq!(f.typed::<(), i32>()?.call(&mut store, ())?, 7);
+    // data segments are only initialized after all element segments
+    assert_eq!(memory.read_vec(&store, 0, 3)?, b"xyz");
+    Ok(())
+}
+
+#[test]
+fn start_function_traps_are_not_rolled_back() -> Result<()> {
+    let mut store = Store::default();
+    let Env { table, memory, imports } = env(&mut store)?;
+    let wat = r#"
+        (module
+          (import "env" "table" (table 4 funcref))
+          (import "env" "memory" (memory 1))
+          (func $f (result i32) (i32.const 7))
+          (func $start (i32.store8 (i32.const 0) (i32.const 0x61)) (unreachable))
+          (elem (i32.const 0) $f)
+          (data (i32.const 1) "bc")
+          (start $start))
+    "#;
+    let module = tinywasm::parse_bytes(&wat::parse_str(wat)?)?;
+
+    let err = ModuleInstance::instantiate(&mut store, &module, Some(imports)).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::Unreachable)));
+
+    // both the segments and the writes of the start function persist
+    let f = table.get_func(&store, 0)?.expect("a function");
+    assert_eq!(f.typed::<(), i32>()?.call(&mut store, ())?, 7);
+    assert_eq!(memory.read_vec(&store, 0, 3)?, b"abc");
+    Ok(())
+}
@@ -8,8 +8,8 @@ use std::io::{Read, Seek, SeekFrom, Write};
 
 use eyre::Result;
 use tinywasm::engine::Config;
-use tinywasm::types::{MemoryArch, MemoryType};
-use tinywasm::{Engine, Memory, MemoryBackend, Module, ModuleInstance, PagedMemory, Store};
+use tinywasm::types::{GlobalType, MemoryArch, MemoryType, WasmType, WasmValue};
+use tinywasm::{Engine, Global, Imports, Memory, MemoryBackend, Module, ModuleInstance, PagedMemory, Store, Trap};
 use tinywasm_parser::{Parser, ParserOptions};
 
 fn instantiate_module_with_counting_backend(module: Module) -> Result<usize> {
@@ -239,6 +239,39 @@ fn paged_read_and_write_stop_at_chunk_boundaries() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn slices_borrow_vec_memory() -> Result<()> {
+    let mut store = Store::default();
+    let memory = Memory::new(&mut store, MemoryType::new(MemoryArch::I32, 1, Some(1), Some(16)))?;
+
+    let ptr = memory.with_slice_mut(&mut store, 4, 8, |bytes| {
+        bytes.copy_from_slice(b"abcdefgh");
+        bytes.as_ptr()
+    })?;
+    assert_eq!(memory.with_slice(&store, 4, 8, |bytes| bytes.as_ptr())?, ptr);
+    assert_eq!(memory.with_slice(&store, 6, 4, <[u8]>::to_vec)?, b"cdef");
+    assert_eq!(memory.with_slice(&store, 16, 0, <[u8]>::len)?, 0);
+
+    assert!(memory.with_slice(&store, 12, 8, |_| ()).is_err());
+    assert!(memory.with_slice_mut(&mut store, usize::MAX, 2, |_| ()).is_err());
+    Ok(())
+}
+
+#[test]
+fn slices_spanning_paged_chunks_are_copied() -> Result<()> {
+    let engine = Engine::new(Config::new().with_memory_backend(MemoryBackend::paged(4)));
+    let mut store = Store::new(engine);
+    let memory = Memory::new(&mut store, MemoryType::new(MemoryArch::I32, 1, Some(1), Some(16)))?;
+
+    memory.with_slice_mut(&mut store, 2, 8, |bytes| bytes.copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]))?;
+    assert_eq!(memory.read_vec(&store, 0, 12)?, &[0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 0, 0]);
+    assert_eq!(memory.with_slice(&store, 4, 4, <[u8]>::to_vec)?, &[3, 4, 5, 6]);
+    assert_eq!(memory.with_slice(&store, 1, 6, <[u8]>::to_vec)?, &[0, 1, 2, 3, 4, 5]);
+    assert_eq!(memory.with_slice(&store, 12, 4, <[u8]>::to_vec)?, &[0; 4]);
+    assert!(memory.with_slice_mut(&mut store, 14, 4, |_| ()).is_err());
+    Ok(())
+}
+
 #[cfg(feature = "std")]
 #[test]
 fn memory_cursor_supports_read_write_and_seek() -> Result<()> {
@@ -260,3 +293,347 @@ fn memory_cursor_supports_read_write_and_seek() -> Result<()> {
     assert_eq!(memory.read_vec(&store, 0, 8)?, &[0, 0, b'a', b'b', b'c', 0, 0, b'z']);
     Ok(())
 }
+
+#[cfg(feature = "std")]
+#[test]
+fn copy_on_write_instances_share_initial_data() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (global $offset (import "env" "offset") i32)
+          (memory (export "memory") 1)
+          (memory $dynamic (export "dynamic") 1)
+          (data (i32.const 16) "hello")
+          (data (memory $dynamic) (global.get $offset) "world")
+          (func (export "store") (param i32 i32) (i32.store8 (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let config = Config::new().with_memory_backend(MemoryBackend::copy_on_write(16)).with_atomic_instantiation(true);
+    let engine = Engine::new(config);
+    let mut store = Store::new(engine);
+
+    let instantiate = |store: &mut Store, offset: i32| -> Result<ModuleInstance> {
+        let offset = Global::new(store, GlobalType::new(WasmType::I32, false), WasmValue::I32(offset))?;
+        let mut imports = Imports::new();
+        imports.define("env", "offset", offset);
+        Ok(ModuleInstance::instantiate(store, &module, Some(imports))?)
+    };
+    let first = instantiate(&mut store, 32)?;
+    let second = instantiate(&mut store, 40)?;
+    let (first_memory, second_memory) = (first.memory("memory")?, second.memory("memory")?);
+    let (first_dynamic, second_dynamic) = (first.memory("dynamic")?, second.memory("dynamic")?);
+
+    // the constant segment is read from the shared image until it is written
+    let first_ptr = first_memory.with_slice(&store, 16, 5, |bytes| bytes.as_ptr())?;
+    assert_eq!(second_memory.with_slice(&store, 16, 5, |bytes| bytes.as_ptr())?, first_ptr);
+
+    first.func::<(i32, i32), ()>(&store, "store")?.call(&mut store, (17, b'a' as i32))?;
+    assert_eq!(first_memory.read_vec(&store, 16, 5)?, b"hallo");
+    assert_eq!(second_memory.read_vec(&store, 16, 5)?, b"hello");
+    // memories with segments at non-constant offsets are initialized by each instance
+    assert_eq!(first_dynamic.read_vec(&store, 32, 5)?, b"world");
+    assert_eq!(second_dynamic.read_vec(&store, 40, 5)?, b"world");
+    assert_eq!(second_dynamic.read_vec(&store, 32, 5)?, [0; 5]);
+
+    // segments that don't fit still fail instantiation
+    assert!(instantiate(&mut store, 65534).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn copy_on_write_images_are_shared_in_every_instantiation_mode() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (table 2 funcref)
+          (elem (i32.const 0) $f)
+          (func $f)
+          (data (i32.const 16) "hello"))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let shared_ptr = |config: Config| -> Result<bool> {
+        let mut store = Store::new(Engine::new(config.with_memory_backend(MemoryBackend::copy_on_write(16))));
+        let first = ModuleInstance::instantiate(&mut store, &module, None)?.memory("memory")?;
+        let second = ModuleInstance::instantiate(&mut store, &module, None)?.memory("memory")?;
+        assert_eq!(second.read_vec(&store, 16, 5)?, b"hello");
+        let first_ptr = first.with_slice(&store, 16, 5, |bytes| bytes.as_ptr())?;
+        Ok(second.with_slice(&store, 16, 5, |bytes| bytes.as_ptr())? == first_ptr)
+    };
+
+    assert!(shared_ptr(Config::new())?);
+    assert!(shared_ptr(Config::new().with_atomic_instantiation(false))?);
+    assert!(shared_ptr(Config::new().with_atomic_instantiation(true))?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn copy_on_write_images_are_not_used_if_a_failed_segment_could_be_observed() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (global $offset (import "env" "offset") i32)
+          (memory (export "memory") 1)
+          (table 2 funcref)
+          (elem (global.get $offset) $f)
+          (func $f)
+          (data (i32.const 16) "hello"))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let config = Config::new().with_memory_backend(MemoryBackend::copy_on_write(16)).with_atomic_instantiation(false);
+    let mut store = Store::new(Engine::new(config));
+    let instantiate = |store: &mut Store| -> Result<Memory> {
+        let offset = Global::new(store, GlobalType::new(WasmType::I32, false), WasmValue::I32(0))?;
+        let mut imports = Imports::new();
+        imports.define("env", "offset", offset);
+        Ok(ModuleInstance::instantiate(store, &module, Some(imports))?.memory("memory")?)
+    };
+
+    // the element segment could be out of bounds, so each instance applies its data segment itself
+    let (first, second) = (instantiate(&mut store)?, instantiate(&mut store)?);
+    assert_eq!(second.read_vec(&store, 16, 5)?, b"hello");
+    let first_ptr = first.with_slice(&store, 16, 5, |bytes| bytes.as_ptr())?;
+    assert_ne!(second.with_slice(&store, 16, 5, |bytes| bytes.as_ptr())?, first_ptr);
+    Ok(())
+}
+
+#[test]
+fn dirty_pages_reset_to_the_instantiated_image() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 4)
+          (data (i32.const 65536) "initial")
+          (func (export "store") (param i32 i32) (i32.store8 (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let engine = Engine::new(Config::new().with_memory_backend(MemoryBackend::paged(4096).track_dirty_pages()));
+    let mut store = Store::new(engine);
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let memory = instance.memory("memory")?;
+    let store_byte = instance.func::<(i32, i32), ()>(&store, "store")?;
+    assert!(memory.dirty_pages(&store)?.is_empty());
+
+    store_byte.call(&mut store, (65536, b'I' as i32))?;
+    store_byte.call(&mut store, (3 * 65536 + 5, 1))?;
+    assert_eq!(memory.dirty_pages(&store)?, [1, 3]);
+
+    memory.reset_dirty(&mut store)?;
+    assert!(memory.dirty_pages(&store)?.is_empty());
+    assert_eq!(memory.read_vec(&store, 65536, 7)?, b"initial");
+    assert_eq!(memory.read_vec(&store, 3 * 65536 + 5, 1)?, [0]);
+
+    // clearing makes the current contents the image restored on reset
+    store_byte.call(&mut store, (65536, b'I' as i32))?;
+    memory.clear_dirty(&mut store)?;
+    memory.fill(&mut store, 65536, 7, 0)?;
+    memory.reset_dirty(&mut store)?;
+    assert_eq!(memory.read_vec(&store, 65536, 7)?, b"Initial");
+
+    let mut store = Store::default();
+    let untracked = Memory::new(&mut store, MemoryType::default())?;
+    assert!(matches!(untracked.dirty_pages(&store), Err(tinywasm::Error::UnsupportedFeature(_))));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_backed_memory_survives_the_store() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1 4)
+          (data (i32.const 0) "hello")
+          (func (export "store") (param i32 i32) (i32.store8 (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    // restoring must not depend on whether instantiation is atomic
+    for atomic in [false, true] {
+        let path = std::env::temp_dir().join(format!("tinywasm-file-memory-{}-{atomic}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config =
+            Config::new().with_memory_backend(MemoryBackend::file(&path, 4096)).with_atomic_instantiation(atomic);
+        let engine = Engine::new(config);
+
+        {
+            let mut store = Store::new(engine.clone());
+            let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+            let memory = instance.memory("memory")?;
+            instance.func::<(i32, i32), ()>(&store, "store")?.call(&mut store, (0, b'H' as i32))?;
+            memory.grow(&mut store, 1)?;
+            memory.copy_from_slice(&mut store, 65536 + 10, b"grown")?;
+            memory.sync(&mut store)?;
+        }
+        assert_eq!(std::fs::metadata(&path)?.len(), 2 * 65536);
+
+        // the restored memory keeps its size and contents, and the data segment is not applied again
+        let mut store = Store::new(engine);
+        let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+        let memory = instance.memory("memory")?;
+        assert_eq!(memory.page_count(&store)?, 2);
+        assert_eq!(memory.read_vec(&store, 0, 5)?, b"Hello");
+        assert_eq!(memory.read_vec(&store, 65536 + 10, 5)?, b"grown");
+
+        drop(store);
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn failed_instantiations_do_not_persist_file_backed_memories() -> Result<()> {
+    let failing = tinywasm::parse_bytes(&wat::parse_str(
+        r#"(module (memory 1) (data (i32.const 0) "f") (data (i32.const 65536) "out of bounds"))"#,
+    )?)?;
+    let module =
+        tinywasm::parse_bytes(&wat::parse_str(r#"(module (memory (export "memory") 1) (data (i32.const 0) "s"))"#)?)?;
+    for atomic in [false, true] {
+        let path = std::env::temp_dir().join(format!("tinywasm-file-memory-failed-{}-{atomic}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config =
+            Config::new().with_memory_backend(MemoryBackend::file(&path, 4096)).with_atomic_instantiation(atomic);
+        let engine = Engine::new(config);
+
+        let mut store = Store::new(engine.clone());
+        assert!(ModuleInstance::instantiate(&mut store, &failing, None).is_err());
+        drop(store);
+        assert_eq!(std::fs::metadata(&path)?.len(), 0);
+
+        // the file is not restored, so the data segments of the next module are applied
+        let mut store = Store::new(engine);
+        let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+        assert_eq!(instance.memory("memory")?.read_vec(&store, 0, 1)?, b"s");
+
+        drop(store);
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn shared_memories_ignore_initialized_backends() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(r#"(module (memory (export "memory") 1 1 shared))"#)?)?;
+    let path = std::env::temp_dir().join(format!("tinywasm-file-memory-shared-{}", std::process::id()));
+    std::fs::write(&path, vec![1; 65536])?;
+    let engine = Engine::new(Config::new().with_memory_backend(MemoryBackend::file(&path, 4096)));
+
+    // a restored file memory would not be shared between threads
+    let mut store = Store::new(engine);
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    assert!(instance.memory("memory")?.shared(&store)?.is_some());
+
+    drop(store);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_backed_memory_is_locked_while_in_use() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(r#"(module (memory (export "memory") 1))"#)?)?;
+    let path = std::env::temp_dir().join(format!("tinywasm-file-memory-lock-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let engine = Engine::new(Config::new().with_memory_backend(MemoryBackend::file(&path, 4096)));
+
+    let mut store = Store::new(engine.clone());
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    instance.memory("memory")?.copy_from_slice(&mut store, 0, b"first")?;
+
+    // a second memory on the same file would overwrite the first one
+    let mut second = Store::new(engine.clone());
+    assert!(ModuleInstance::instantiate(&mut second, &module, None).is_err());
+    assert!(tinywasm::engine::FileMemory::open(&path, 4096).is_err());
+
+    drop(store);
+    let mut store = Store::new(engine);
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    assert_eq!(instance.memory("memory")?.read_vec(&store, 0, 5)?, b"first");
+
+    drop(store);
+    assert!(tinywasm::engine::FileMemory::open(&path, 0).is_err());
+    assert!(tinywasm::engine::FileMemory::open(&path, 3000).is_err());
+    assert!(tinywasm::engine::FileMemory::open(&path, 4096).is_ok());
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn memory_backend_selector_chooses_backends_per_memory() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "env" "shared" (memory 1))
+          (memory $scratch (export "scratch") 1 1)
+          (memory $heap (export "heap") 2)
+          (data (memory $heap) (i32.const 20) "heap"))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+
+    let selected = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = selected.clone();
+    let heaps = Arc::new(AtomicUsize::new(0));
+    let heaps_created = heaps.clone();
+    let heap = MemoryBackend::custom(move |ty| {
+        heaps_created.fetch_add(1, Ordering::Relaxed);
+        Ok(PagedMemory::try_new(ty.initial_size() as usize, 16)?)
+    });
+    let config = Config::new().with_memory_backend_selector(move |ty, _, idx| {
+        seen.lock().unwrap().push((idx, ty.page_count_initial()));
+        if idx == 2 { heap.clone() } else { MemoryBackend::vec() }
+    });
+    let mut store = Store::new(Engine::new(config));
+
+    // host memories use the default backend
+    let imported = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+    let mut imports = Imports::new();
+    imports.define("env", "shared", imported);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+    assert_eq!(*selected.lock().unwrap(), [(1, 1), (2, 2)]);
+    assert_eq!(heaps.load(Ordering::Relaxed), 1);
+    assert_eq!(instance.memory("heap")?.read_vec(&store, 20, 4)?, b"heap");
+    Ok(())
+}
+
+#[test]
+fn memory_discard_zeroes_page_ranges() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 3)
+          (data (i32.const 0) "keep")
+          (data (i32.const 0x10000) "drop")
+          (func (export "discard") (param i32 i32) (memory.discard (local.get 0) (local.get 1))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+
+    for backend in [MemoryBackend::vec(), MemoryBackend::paged(4096)] {
+        let mut store = Store::new(Engine::new(Config::new().with_memory_backend(backend)));
+        let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+        let memory = instance.memory("memory")?;
+        let discard = instance.func::<(i32, i32), ()>(&store, "discard")?;
+
+        discard.call(&mut store, (0x10000, 0x10000))?;
+        assert_eq!(memory.read_vec(&store, 0, 4)?, b"keep");
+        assert_eq!(memory.read_vec(&store, 0x10000, 4)?, [0; 4]);
+        assert!(matches!(
+            discard.call(&mut store, (0x100, 0x10000)),
+            Err(tinywasm::Error::Trap(Trap::UnalignedDiscard { offset: 0x100, len: 0x10000 }))
+        ));
+        assert!(discard.call(&mut store, (0x20000, 0x20000)).is_err());
+
+        memory.discard(&mut store, 1, 2)?;
+        assert_eq!(memory.read_vec(&store, 0, 4)?, b"k\0\0p");
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use eyre::Result;
-use tinywasm::{ModuleInstance, Store};
+use tinywasm::{Error, MemoryProtection, ModuleInstance, Store, Trap, WasmPtr};
 
 #[test]
 fn memory_ref_mut_copy_within_uses_src_then_dst_order() -> Result<()> {
@@ -23,3 +23,129 @@ fn memory_ref_mut_copy_within_uses_src_then_dst_order() -> Result<()> {
 
     Ok(())
 }
+
+fn is_protection_violation<T>(result: tinywasm::Result<T>) -> bool {
+    matches!(result, Err(Error::Trap(Trap::MemoryProtectionViolation { .. })))
+}
+
+#[test]
+fn protected_ranges_trap_guest_accesses() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 16) "\2a")
+          (func (export "load") (param i32) (result i32) (i32.load8_u (local.get 0)))
+          (func (export "store") (param i32) (i32.store8 (local.get 0) (i32.const 7)))
+          (func (export "fill") (param i32 i32) (memory.fill (local.get 0) (i32.const 1) (local.get 1)))
+          (func (export "copy") (param i32 i32 i32) (memory.copy (local.get 0) (local.get 1) (local.get 2)))
+        )
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let memory = instance.memory("memory")?;
+    let load = instance.func::<i32, i32>(&store, "load")?;
+    let store_byte = instance.func::<i32, ()>(&store, "store")?;
+    let fill = instance.func::<(i32, i32), ()>(&store, "fill")?;
+    let copy = instance.func::<(i32, i32, i32), ()>(&store, "copy")?;
+
+    memory.protect(&mut store, 16, 16, MemoryProtection::ReadOnly)?;
+    memory.protect(&mut store, 64, 16, MemoryProtection::NoAccess)?;
+
+    assert_eq!(load.call(&mut store, 16)?, 42);
+    assert!(is_protection_violation(store_byte.call(&mut store, 31)));
+    assert!(is_protection_violation(load.call(&mut store, 70)));
+    assert!(is_protection_violation(fill.call(&mut store, (0, 17))));
+    assert!(is_protection_violation(copy.call(&mut store, (0, 60, 8))));
+    copy.call(&mut store, (0, 16, 16))?;
+    assert!(is_protection_violation(memory.copy_from_slice(&mut store, 20, &[1])));
+    assert!(matches!(load.call(&mut store, 0x10000), Err(Error::Trap(Trap::MemoryOutOfBounds { .. }))));
+
+    store_byte.call(&mut store, 15)?;
+    memory.unprotect(&mut store, 16, 8)?;
+    store_byte.call(&mut store, 16)?;
+    assert!(is_protection_violation(store_byte.call(&mut store, 24)));
+    assert_eq!(memory.read_vec(&store, 0, 1)?, [42]);
+    assert_eq!(memory.read_vec(&store, 15, 2)?, [7, 7]);
+    Ok(())
+}
+
+#[test]
+fn protected_ranges_trap_copies_between_memories_up_front() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory $a (export "a") 2)
+          (memory $b (export "b") 2)
+          (data (memory $b) (i32.const 0) "\07")
+          (func (export "copy") (param i32 i32 i32) (memory.copy $a $b (local.get 0) (local.get 1) (local.get 2)))
+        )
+        "#,
+    )?;
+
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let (a, b) = (instance.memory("a")?, instance.memory("b")?);
+    let copy = instance.func::<(i32, i32, i32), ()>(&store, "copy")?;
+
+    a.protect(&mut store, 90000, 16, MemoryProtection::ReadOnly)?;
+    let err = copy.call(&mut store, (0, 0, 100000)).unwrap_err();
+    assert_eq!(err, Error::Trap(Trap::MemoryProtectionViolation { offset: 0, len: 100000 }));
+    assert_eq!(a.read_vec(&store, 0, 1)?, [0]);
+
+    a.unprotect(&mut store, 90000, 16)?;
+    b.protect(&mut store, 90000, 16, MemoryProtection::NoAccess)?;
+    let err = copy.call(&mut store, (0, 0, 100000)).unwrap_err();
+    assert_eq!(err, Error::Trap(Trap::MemoryProtectionViolation { offset: 0, len: 100000 }));
+    assert_eq!(a.read_vec(&store, 0, 1)?, [0]);
+
+    copy.call(&mut store, (0, 0, 80000))?;
+    assert_eq!(a.read_vec(&store, 0, 1)?, [7]);
+    Ok(())
+}
+
+#[test]
+fn protected_ranges_trap_host_views() -> Result<()> {
+    let wasm = wat::parse_str(r#"(module (memory (export "memory") 1))"#)?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let memory = instance.memory("memory")?;
+    memory.protect(&mut store, 16, 16, MemoryProtection::ReadOnly)?;
+    memory.protect(&mut store, 64, 16, MemoryProtection::NoAccess)?;
+
+    assert!(is_protection_violation(memory.with_slice(&store, 60, 8, |_| ())));
+    assert!(is_protection_violation(memory.with_slice_mut(&mut store, 12, 8, |_| unreachable!())));
+    assert_eq!(memory.with_slice(&store, 12, 8, |bytes| bytes.len())?, 8);
+
+    let mut view = memory.view(&mut store)?;
+    assert!(is_protection_violation(view.read(WasmPtr::<u32>::new(64))));
+    assert!(is_protection_violation(view.write(WasmPtr::<u32>::new(28), &1)));
+    assert_eq!(view.read(WasmPtr::<u32>::new(28))?, 0);
+    assert!(matches!(view.read(WasmPtr::<u32>::new(0x10000)), Err(Error::Trap(Trap::MemoryOutOfBounds { .. }))));
+    Ok(())
+}
+
+#[test]
+fn protected_ranges_follow_memory_growth() -> Result<()> {
+    let wasm = wat::parse_str(r#"(module (memory (export "memory") 1))"#)?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &module, None)?;
+    let memory = instance.memory("memory")?;
+
+    assert!(matches!(
+        memory.protect(&mut store, 0x10000, 1, MemoryProtection::NoAccess),
+        Err(Error::Trap(Trap::MemoryOutOfBounds { .. }))
+    ));
+    memory.protect(&mut store, 0xfff0, 16, MemoryProtection::NoAccess)?;
+    memory.grow(&mut store, 1)?;
+    memory.copy_from_slice(&mut store, 0x10000, &[1, 2])?;
+    assert!(is_protection_violation(memory.read_vec(&store, 0xfff8, 16)));
+    assert_eq!(memory.read_vec(&store, 0x10000, 2)?, [1, 2]);
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use eyre::Result;
+use tinywasm::types::{MemoryArch, MemoryType};
+use tinywasm::{
+    Error, FuncContext, HostFunction, Imports, Memory, ModuleInstance, Store, Trap, WasmPtr, WasmPtr64, WasmRepr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    origin: [i32; 2],
+    scale: f32,
+}
+
+impl WasmRepr for Rect {
+    const SIZE: usize = 12;
+
+    fn read_le(bytes: &[u8]) -> Self {
+        Self { origin: <[i32; 2]>::read_le(&bytes[..8]), scale: f32::read_le(&bytes[8..]) }
+    }
+
+    fn write_le(&self, bytes: &mut [u8]) {
+        self.origin.write_le(&mut bytes[..8]);
+        self.scale.write_le(&mut bytes[8..]);
+    }
+}
+
+#[test]
+fn values_are_read_and_written_unaligned() -> Result<()> {
+    let mut store = Store::default();
+    let memory = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+    let rect = Rect { origin: [-1, 2], scale: 0.5 };
+
+    let mut view = memory.view(&mut store)?;
+    let ptr = WasmPtr::<Rect>::new(3);
+    ptr.write(&mut view, &rect)?;
+    assert_eq!(ptr.read(&view)?, rect);
+    assert_eq!(view.read(ptr.cast::<i32>())?, -1);
+    assert_eq!(view.read(ptr.cast::<u16>().checked_add(2).unwrap())?, 2);
+
+    view.write_array(WasmPtr::<u16>::new(101), &[1, 0x0302])?;
+    assert_eq!(view.read_array(WasmPtr::<u8>::new(101), 4)?, [1, 0, 2, 3]);
+    assert_eq!(memory.read_vec(&store, 3, 4)?, [0xff; 4]);
+    Ok(())
+}
+
+#[test]
+fn accesses_are_bounds_checked() -> Result<()> {
+    let mut store = Store::default();
+    let memory = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+    let mut view = memory.view(&mut store)?;
+
+    assert_eq!(view.read(WasmPtr::<u32>::new(65532))?, 0);
+    assert!(matches!(view.read(WasmPtr::<u32>::new(65533)), Err(Error::Trap(Trap::MemoryOutOfBounds { .. }))));
+    assert!(view.read_array(WasmPtr::<u64>::new(0), usize::MAX).is_err());
+    assert!(view.write(WasmPtr64::<u8>::new(u64::MAX), &1).is_err());
+    assert!(view.write_array(WasmPtr::<i16>::new(65534), &[1, 2]).is_err());
+    assert_eq!(memory.read_vec(&store, 65534, 2)?, [0, 0]);
+
+    assert_eq!(WasmPtr::<u64>::new(u32::MAX - 7).checked_add(1), None);
+    assert!(WasmPtr::<u8>::default().is_null());
+    Ok(())
+}
+
+#[test]
+fn pointers_are_host_function_parameters() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (import "env" "grow" (func $grow (param i32 i32) (result i32)))
+          (import "env" "len" (func $len (param i64) (result i64)))
+          (memory (export "memory") 1)
+          (memory $wide (export "wide") i64 1)
+          (data (i32.const 1) "\01\00\00\00\02\00\00\00\00\00\00\40")
+          (data (memory $wide) (i64.const 9) "\05\00\00\00\00\00\00\00")
+          (func (export "grow") (result i32) (call $grow (i32.const 1) (i32.const 20)))
+          (func (export "len") (result i64) (call $len (i64.const 9))))
+        "#,
+    )?;
+    let module = tinywasm::parse_bytes(&wasm)?;
+    let mut store = Store::default();
+
+    // doubles the scale of a rect and returns a pointer to its copy
+    let grow =
+        HostFunction::from(&mut store, |mut ctx: FuncContext<'_>, (src, dst): (WasmPtr<Rect>, WasmPtr<Rect>)| {
+            let mut view = ctx.memory("memory")?.view(&mut ctx)?;
+            let rect = view.read(src)?;
+            view.write(dst, &Rect { scale: rect.scale * 2.0, ..rect })?;
+            Ok(dst)
+        });
+    let len = HostFunction::from(&mut store, |mut ctx: FuncContext<'_>, len: WasmPtr64<u64>| {
+        let view = ctx.memory("wide")?.view(&mut ctx)?;
+        Ok(view.read(len)? as i64)
+    });
+
+    let mut imports = Imports::new();
+    imports.define("env", "grow", grow).define("env", "len", len);
+    let instance = ModuleInstance::instantiate(&mut store, &module, Some(imports))?;
+
+    assert_eq!(instance.func::<(), i32>(&store, "grow")?.call(&mut store, ())?, 20);
+    let memory = instance.memory("memory")?;
+    assert_eq!(memory.view(&mut store)?.read(WasmPtr::<Rect>::new(20))?, Rect { origin: [1, 2], scale: 4.0 });
+
+    assert_eq!(instance.func::<(), i64>(&store, "len")?.call(&mut store, ())?, 5);
+    assert_eq!(instance.memory("wide")?.ty(&store)?.arch(), MemoryArch::I64);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use eyre::Result;
+use tinywasm::engine::Config;
+use tinywasm::types::MemoryType;
+use tinywasm::{Engine, ExecProgress, Imports, Memory, ModuleInstance, Store, Trap};
+
+const COUNTER: &str = r#"
+    (module
+      (global $count (export "count") (mut i32) (i32.const 0))
+      (func $start
+        (loop $loop
+          (global.set $count (i32.add (global.get $count) (i32.const 1)))
+          (br_if $loop (i32.lt_u (global.get $count) (i32.const 10000)))))
+      (start $start))
+"#;
+
+const SEGMENTS: &str = r#"
+    (module
+      (import "env" "memory" (memory 1))
+      (global $offset i32 (i32.const 16))
+      (data (i32.const 0) "0123456789abcdef")
+      (data (global.get $offset) "ghijklmnopqrstuv"))
+"#;
+
+const OUT_OF_BOUNDS: &str = r#"
+    (module
+      (import "env" "memory" (memory 1))
+      (data (i32.const 0) "0123456789abcdef")
+      (data (i32.const 16) "ghijklmnopqrstuv")
+      (data (i32.const 65536) "out of bounds"))
+"#;
+
+#[test]
+fn start_function_is_metered() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(COUNTER)?)?;
+    let mut store = Store::default();
+    let mut instantiation = ModuleInstance::instantiate_resumable(&mut store, &module, None)?;
+
+    let mut suspensions = 0;
+    let instance = loop {
+        match instantiation.resume_with_fuel(1_000)? {
+            ExecProgress::Completed(instance) => break instance,
+            ExecProgress::Suspended => suspensions += 1,
+        }
+    };
+    assert!(suspensions > 10);
+    assert!(instantiation.is_completed());
+    assert!(instantiation.resume_with_fuel(1_000).is_err());
+    drop(instantiation);
+
+    assert_eq!(instance.global_get(&store, "count")?, 10000.into());
+    Ok(())
+}
+
+#[test]
+fn segments_are_initialized_in_steps() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(SEGMENTS)?)?;
+    let mut store = Store::default();
+    let memory = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+    let mut imports = Imports::new();
+    imports.define("env", "memory", memory);
+
+    let mut instantiation = ModuleInstance::instantiate_resumable(&mut store, &module, Some(imports))?;
+    assert!(matches!(instantiation.resume_with_fuel(0)?, ExecProgress::Suspended));
+    // the global and the first segment
+    assert!(matches!(instantiation.resume_with_fuel(2)?, ExecProgress::Suspended));
+    assert!(matches!(instantiation.resume_with_fuel(2)?, ExecProgress::Completed(_)));
+    drop(instantiation);
+
+    assert_eq!(memory.read_vec(&store, 0, 32)?, b"0123456789abcdefghijklmnopqrstuv");
+    Ok(())
+}
+
+#[test]
+fn failed_or_abandoned_instantiations_are_rolled_back() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(OUT_OF_BOUNDS)?)?;
+    let mut store = Store::new(Engine::new(Config::new().with_atomic_instantiation(true)));
+    let memory = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+    let mut imports = Imports::new();
+    imports.define("env", "memory", memory);
+
+    let mut instantiation = ModuleInstance::instantiate_resumable(&mut store, &module, Some(imports.clone()))?;
+    assert!(matches!(instantiation.resume_with_fuel(3)?, ExecProgress::Suspended));
+    drop(instantiation);
+    assert_eq!(memory.read_vec(&store, 0, 32)?, [0; 32]);
+
+    let mut instantiation = ModuleInstance::instantiate_resumable(&mut store, &module, Some(imports))?;
+    let result = instantiation.resume_with_fuel(u32::MAX);
+    assert!(matches!(result, Err(tinywasm::Error::Trap(Trap::MemoryOutOfBounds { .. }))));
+    assert!(instantiation.is_completed());
+    drop(instantiation);
+    assert_eq!(memory.read_vec(&store, 0, 32)?, [0; 32]);
+
+    let instance =
+        ModuleInstance::instantiate(&mut store, &tinywasm::parse_bytes(&wat::parse_str("(module)")?)?, None)?;
+    assert_eq!(instance.id(), 0);
+    Ok(())
+}
+
+#[test]
+fn start_function_is_limited_by_time() -> Result<()> {
+    let wat = r#"(module (func $start (loop br 0)) (start $start))"#;
+    let module = tinywasm::parse_bytes(&wat::parse_str(wat)?)?;
+    let mut store = Store::default();
+    let mut instantiation = ModuleInstance::instantiate_resumable(&mut store, &module, None)?;
+
+    let budget = std::time::Duration::from_millis(5);
+    assert!(matches!(instantiation.resume_with_time_budget(budget)?, ExecProgress::Suspended));
+    assert!(matches!(instantiation.resume_with_time_budget(budget)?, ExecProgress::Suspended));
+    assert!(!instantiation.is_completed());
+    Ok(())
+}
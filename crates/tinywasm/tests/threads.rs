@@ -0,0 +1,134 @@
+use std::thread;
+use std::time::Duration;
+
+use eyre::Result;
+use tinywasm::types::MemoryType;
+use tinywasm::{Error, Imports, Memory, ModuleInstance, SharedMemory, Store};
+
+const WASM: &str = r#"
+    (module
+      (import "env" "memory" (memory 1 1 shared))
+
+      (func (export "add") (param $n i32)
+        (loop $loop
+          (drop (i32.atomic.rmw.add (i32.const 0) (i32.const 1)))
+          (br_if $loop (local.tee $n (i32.sub (local.get $n) (i32.const 1))))))
+
+      (func (export "wait") (result i32)
+        (memory.atomic.wait32 (i32.const 8) (i32.const 0) (i64.const -1)))
+
+      (func (export "wake") (result i32)
+        (memory.atomic.notify (i32.const 8) (i32.const 1))))
+"#;
+
+fn shared_type() -> MemoryType {
+    MemoryType::default().with_page_count_initial(1).with_page_count_max(Some(1)).with_shared(true)
+}
+
+fn instantiate(module: &tinywasm::Module, memory: &SharedMemory) -> Result<(Store, ModuleInstance)> {
+    let mut store = Store::default();
+    let mut imports = Imports::new();
+    imports.define("env", "memory", memory.clone());
+    let instance = ModuleInstance::instantiate(&mut store, module, Some(imports))?;
+    Ok((store, instance))
+}
+
+#[test]
+fn atomic_rmw_is_atomic_across_stores_on_different_threads() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+    let memory = SharedMemory::new(shared_type())?;
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let (module, memory) = (module.clone(), memory.clone());
+            thread::spawn(move || -> Result<()> {
+                let (mut store, instance) = instantiate(&module, &memory)?;
+                instance.func::<i32, ()>(&store, "add")?.call(&mut store, 1000)?;
+                Ok(())
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().expect("thread panicked")?;
+    }
+
+    assert_eq!(memory.read_vec(0, 4)?, 4000i32.to_le_bytes());
+    Ok(())
+}
+
+#[test]
+fn atomic_wait_is_woken_by_notify_from_another_thread() -> Result<()> {
+    let module = tinywasm::parse_bytes(&wat::parse_str(WASM)?)?;
+    let memory = SharedMemory::new(shared_type())?;
+
+    let waiter = thread::spawn({
+        let (module, memory) = (module.clone(), memory.clone());
+        move || -> Result<i32> {
+            let (mut store, instance) = instantiate(&module, &memory)?;
+            Ok(instance.func::<(), i32>(&store, "wait")?.call(&mut store, ())?)
+        }
+    });
+
+    let (mut store, instance) = instantiate(&module, &memory)?;
+    let wake = instance.func::<(), i32>(&store, "wake")?;
+    // the waiter may not have started waiting yet, so keep notifying until it has been woken
+    while wake.call(&mut store, ())? == 0 {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(waiter.join().expect("thread panicked")?, 0);
+    Ok(())
+}
+
+#[test]
+fn exported_shared_memory_can_be_imported_by_another_store() -> Result<()> {
+    let wasm = wat::parse_str(r#"(module (memory (export "memory") 1 2 shared) (data (i32.const 0) "hi"))"#)?;
+    let mut store = Store::default();
+    let instance = ModuleInstance::instantiate(&mut store, &tinywasm::parse_bytes(&wasm)?, None)?;
+    let shared = instance.memory("memory")?.shared(&store)?.expect("memory should be shared");
+
+    let other = thread::spawn(move || -> Result<()> {
+        let mut store = Store::default();
+        let memory = Memory::new(&mut store, MemoryType::default().with_page_count_initial(1))?;
+        assert!(memory.shared(&store)?.is_none());
+
+        let wasm = wat::parse_str(
+            r#"(module
+                (import "env" "memory" (memory 1 2 shared))
+                (func (export "grow") (result i32) (memory.grow (i32.const 1))))"#,
+        )?;
+        let mut imports = Imports::new();
+        imports.define("env", "memory", shared);
+        let instance = ModuleInstance::instantiate(&mut store, &tinywasm::parse_bytes(&wasm)?, Some(imports))?;
+        assert_eq!(instance.func::<(), i32>(&store, "grow")?.call(&mut store, ())?, 1);
+        Ok(())
+    });
+    other.join().expect("thread panicked")?;
+
+    let memory = instance.memory("memory")?;
+    assert_eq!(memory.page_count(&store)?, 2);
+    assert_eq!(memory.read_vec(&store, 0, 2)?, b"hi");
+    Ok(())
+}
+
+#[test]
+fn shared_memory_imports_require_matching_sharedness() -> Result<()> {
+    assert!(SharedMemory::new(MemoryType::default().with_page_count_initial(1)).is_err());
+    let invalid = MemoryType::default().with_page_count_initial(4).with_page_count_max(Some(1)).with_shared(true);
+    assert!(matches!(SharedMemory::new(invalid), Err(Error::Other(_))));
+
+    let wasm = wat::parse_str(r#"(module (import "env" "memory" (memory 1 1)))"#)?;
+    let mut store = Store::default();
+    let mut imports = Imports::new();
+    imports.define("env", "memory", SharedMemory::new(shared_type())?);
+    let res = ModuleInstance::instantiate(&mut store, &tinywasm::parse_bytes(&wasm)?, Some(imports));
+    assert!(matches!(res, Err(Error::Linker(_))));
+    Ok(())
+}
+
+#[test]
+fn shared_memory_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SharedMemory>();
+}
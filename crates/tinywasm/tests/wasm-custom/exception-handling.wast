@@ -0,0 +1,100 @@
+;; Exception handling: unwinding across calls, rethrowing and escaping continuations.
+(module
+  (tag $e (param i32))
+  (tag $e2 (param i64 i32))
+  (tag $empty)
+
+  (func $throw (param i32)
+    (throw $e (local.get 0)))
+
+  (func $deep (param i32)
+    (if (i32.eqz (local.get 0)) (then (call $throw (i32.const 7)) (return)))
+    (call $deep (i32.sub (local.get 0) (i32.const 1))))
+
+  ;; values pushed before the try_table body stay below the caught payload
+  (func (export "across-calls") (result i32)
+    (i32.const 100)
+    (block $h (result i32)
+      (try_table (catch $e $h)
+        (i32.const 1) (i32.const 2) (drop) (drop)
+        (call $deep (i32.const 20)))
+      (i32.const 0))
+    (i32.add))
+
+  ;; the innermost matching clause wins, clauses of one try_table are tried in order
+  (func (export "innermost") (result i32)
+    (block $outer (result i32)
+      (try_table (catch $e $outer)
+        (block $inner (result i64 i32)
+          (try_table (catch $empty 1) (catch $e2 $inner) (catch_all 1)
+            (throw $e2 (i64.const 3) (i32.const 4)))
+          (unreachable))
+        (drop) (i32.wrap_i64) (return))
+      (unreachable)))
+
+  (func (export "catch-all") (result i32)
+    (block $h
+      (try_table (catch_all $h) (call $throw (i32.const 1)))
+      (return (i32.const 0)))
+    (i32.const 1))
+
+  ;; the payload survives being rethrown with throw_ref
+  (func (export "rethrow") (result i32)
+    (block $h (result i32)
+      (try_table (catch $e $h)
+        (block $r (result i32 exnref)
+          (try_table (catch_ref $e $r) (call $throw (i32.const 42)))
+          (unreachable))
+        (throw_ref))
+      (unreachable)))
+
+  (func (export "uncaught")
+    (call $throw (i32.const 1)))
+
+  (func (export "null-ref")
+    (throw_ref (ref.null exn)))
+
+  ;; a try_table without catch clauses only acts as a block
+  (func (export "no-catch") (result i32)
+    (try_table (result i32) (i32.const 5)))
+)
+
+(assert_return (invoke "across-calls") (i32.const 107))
+(assert_return (invoke "innermost") (i32.const 3))
+(assert_return (invoke "catch-all") (i32.const 1))
+(assert_return (invoke "rethrow") (i32.const 42))
+(assert_exception (invoke "uncaught"))
+(assert_trap (invoke "null-ref") "null exception reference")
+(assert_return (invoke "no-catch") (i32.const 5))
+
+;; exceptions escape a continuation to the resume that entered it
+(module
+  (type $f (func (result i32)))
+  (type $c (cont $f))
+  (tag $e (param i32))
+  (tag $yield)
+
+  (func $throws (result i32)
+    (suspend $yield)
+    (throw $e (i32.const 9)))
+
+  (elem declare func $throws)
+
+  (func (export "escape") (result i32)
+    (local $k (ref null $c))
+    (local.set $k (cont.new $c (ref.func $throws)))
+    (block $h (result i32)
+      (try_table (catch $e $h)
+        (block $y (result (ref $c))
+          (return (resume $c (on $yield $y) (local.get $k))))
+        (local.set $k)
+        (drop (resume $c (local.get $k))))
+      (unreachable)))
+
+  (func (export "escape-uncaught") (result i32)
+    (resume $c (cont.new $c (ref.func $throws))))
+)
+
+(assert_return (invoke "escape") (i32.const 9))
+(assert_trap (invoke "escape-uncaught") "unhandled tag")
+(assert_return (invoke "escape") (i32.const 9))
@@ -0,0 +1,180 @@
+;; Stack switching: suspend/resume, cont.bind, switch and trapping edge cases.
+(module
+  (type $f0 (func (result i32)))
+  (type $c0 (cont $f0))
+  (type $fv (func))
+  (type $cv (cont $fv))
+  (type $f1 (func (param i32) (result i32)))
+  (type $c1 (cont $f1))
+  (type $f2 (func (param i32 i32) (result i32)))
+  (type $c2 (cont $f2))
+
+  (tag $yield (param i32))
+  (tag $ask (param i32) (result i32))
+  (tag $unused)
+
+  (func $gen
+    (suspend $yield (i32.const 1))
+    (suspend $yield (i32.const 2))
+    (suspend $yield (i32.const 3)))
+
+  (func $asker (param i32) (result i32)
+    (i32.add (suspend $ask (local.get 0)) (i32.const 10)))
+
+  (func $sub (param i32 i32) (result i32)
+    (i32.sub (local.get 0) (local.get 1)))
+
+  (func $inner (result i32)
+    (suspend $yield (i32.const 100))
+    (i32.const 1))
+
+  (func $middle (result i32)
+    (block $h (result i32 (ref $c1))
+      (resume $c0 (on $ask $h) (cont.new $c0 (ref.func $inner)))
+      (return (i32.add (i32.const 1000))))
+    (unreachable))
+
+  (func $trapping (result i32)
+    (suspend $yield (i32.const 5))
+    (unreachable))
+
+  (elem declare func $gen $asker $sub $inner $middle $trapping)
+
+  ;; generator: sums the values yielded by $gen
+  (func (export "sum") (result i32)
+    (local $k (ref null $cv))
+    (local $sum i32)
+    (local.set $k (cont.new $cv (ref.func $gen)))
+    (block $done
+      (loop $next
+        (block $on_yield (result i32 (ref $cv))
+          (resume $cv (on $yield $on_yield) (local.get $k))
+          (br $done))
+        (local.set $k)
+        (local.set $sum (i32.add (local.get $sum)))
+        (br $next)))
+    (local.get $sum))
+
+  ;; tags with results pass values back into the suspended continuation
+  (func (export "ask") (param i32) (result i32)
+    (local $k (ref null $c1))
+    (block $h (result i32 (ref $c1))
+      (resume $c1 (on $ask $h) (local.get 0) (cont.new $c1 (ref.func $asker)))
+      (return))
+    (local.set $k)
+    (i32.mul (i32.const 2))
+    (resume $c1 (local.get $k)))
+
+  ;; bound arguments come before the ones passed to resume
+  (func (export "bind") (param i32 i32) (result i32)
+    (resume $c1
+      (local.get 1)
+      (cont.bind $c2 $c1 (local.get 0) (cont.new $c2 (ref.func $sub)))))
+
+  (func (export "bind-all") (result i32)
+    (resume $c0
+      (cont.bind $c1 $c0 (i32.const 5)
+        (cont.bind $c2 $c1 (i32.const 7) (cont.new $c2 (ref.func $sub))))))
+
+  ;; $yield is not handled by the inner resume, so it reaches the outer one
+  (func (export "nested") (result i32)
+    (local $k (ref null $c0))
+    (local $acc i32)
+    (block $h (result i32 (ref $c0))
+      (resume $c0 (on $yield $h) (cont.new $c0 (ref.func $middle)))
+      (return))
+    (local.set $k)
+    (local.set $acc)
+    (i32.add (local.get $acc) (resume $c0 (local.get $k))))
+
+  (func (export "null-func") (result i32)
+    (resume $c0 (cont.new $c0 (ref.null $f0))))
+
+  (func (export "null-cont") (result i32)
+    (resume $c0 (ref.null $c0)))
+
+  (func (export "consumed") (result i32)
+    (local $k (ref null $c2))
+    (local.set $k (cont.new $c2 (ref.func $sub)))
+    (drop (resume $c2 (i32.const 1) (i32.const 2) (local.get $k)))
+    (resume $c2 (i32.const 1) (i32.const 2) (local.get $k)))
+
+  (func (export "consumed-by-bind") (result i32)
+    (local $k (ref null $c2))
+    (local.set $k (cont.new $c2 (ref.func $sub)))
+    (drop (cont.bind $c2 $c1 (i32.const 1) (local.get $k)))
+    (resume $c2 (i32.const 1) (i32.const 2) (local.get $k)))
+
+  (func (export "unhandled") (result i32)
+    (call $asker (i32.const 1)))
+
+  (func (export "unhandled-in-resume") (result i32)
+    (block $h (result (ref $c0))
+      (resume $c0 (on $unused $h) (cont.new $c0 (ref.func $inner)))
+      (return))
+    (unreachable))
+
+  (func (export "trap-in-continuation") (result i32)
+    (block $h (result i32 (ref $c0))
+      (resume $c0 (on $yield $h) (cont.new $c0 (ref.func $trapping)))
+      (return))
+    (resume $c0)
+    (i32.add))
+)
+
+(assert_return (invoke "sum") (i32.const 6))
+(assert_return (invoke "ask" (i32.const 5)) (i32.const 20))
+(assert_return (invoke "bind" (i32.const 10) (i32.const 3)) (i32.const 7))
+(assert_return (invoke "bind-all") (i32.const 2))
+(assert_return (invoke "nested") (i32.const 1101))
+(assert_trap (invoke "null-func") "null function reference")
+(assert_trap (invoke "null-cont") "null continuation reference")
+(assert_trap (invoke "consumed") "continuation already consumed")
+(assert_trap (invoke "consumed-by-bind") "continuation already consumed")
+(assert_trap (invoke "unhandled") "unhandled tag")
+(assert_trap (invoke "unhandled-in-resume") "unhandled tag")
+(assert_trap (invoke "trap-in-continuation") "unreachable")
+;; the store is still usable after trapping inside of a continuation
+(assert_return (invoke "sum") (i32.const 6))
+
+;; switch transfers control directly between continuations under the same handler
+(module
+  (type $f0 (func (result i32)))
+  (type $c0 (cont $f0))
+  (type $fb (func (param (ref null $c0)) (result i32)))
+  (type $cb (cont $fb))
+  (type $fa (func (param (ref null $cb)) (result i32)))
+  (type $ca (cont $fa))
+
+  (tag $sw (result i32))
+  (global $log (mut i32) (i32.const 0))
+
+  (func $log (param i32)
+    (global.set $log (i32.add (i32.mul (global.get $log) (i32.const 10)) (local.get 0))))
+
+  (func $a (param $b (ref null $cb)) (result i32)
+    (call $log (i32.const 1))
+    (switch $cb $sw (local.get $b))
+    (call $log (i32.const 3))
+    (i32.const 10))
+
+  (func $b (param $a (ref null $c0)) (result i32)
+    (call $log (i32.const 2))
+    (resume $c0 (local.get $a))
+    (call $log (i32.const 4))
+    (i32.add (i32.const 32)))
+
+  (elem declare func $a $b)
+
+  (func (export "switch") (result i32 i32)
+    (resume $c0 (on $sw switch)
+      (cont.bind $ca $c0 (cont.new $cb (ref.func $b)) (cont.new $ca (ref.func $a))))
+    (global.get $log))
+
+  (func (export "unhandled-switch") (result i32)
+    (resume $c0
+      (cont.bind $ca $c0 (cont.new $cb (ref.func $b)) (cont.new $ca (ref.func $a)))))
+)
+
+(assert_return (invoke "switch") (i32.const 42) (i32.const 1234))
+(assert_trap (invoke "unhandled-switch") "unhandled tag")
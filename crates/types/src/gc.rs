@@ -0,0 +1,195 @@
+use alloc::boxed::Box;
+
+use crate::{TypeAddr, WasmType};
+
+/// The type a reference points to.
+///
+/// Concrete types are given by their index in the module's type section.
+///
+/// See <https://webassembly.github.io/gc/core/syntax/types.html#heap-types>
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeapType {
+    Func,
+    NoFunc,
+    Extern,
+    NoExtern,
+    Any,
+    Eq,
+    I31,
+    Struct,
+    Array,
+    None,
+    Exn,
+    NoExn,
+    Cont,
+    NoCont,
+    Concrete(TypeAddr),
+}
+
+impl HeapType {
+    /// Whether this is the bottom type of its hierarchy, which only the null reference inhabits.
+    #[inline]
+    pub const fn is_bottom(&self) -> bool {
+        matches!(self, Self::NoFunc | Self::NoExtern | Self::None | Self::NoExn | Self::NoCont)
+    }
+}
+
+/// A reference type.
+///
+/// See <https://webassembly.github.io/gc/core/syntax/types.html#reference-types>
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub struct RefType {
+    pub nullable: bool,
+    pub heap_type: HeapType,
+}
+
+impl RefType {
+    /// Create a new reference type.
+    pub const fn new(nullable: bool, heap_type: HeapType) -> Self {
+        Self { nullable, heap_type }
+    }
+
+    /// The nullable reference to the top type of a [`WasmType`], e.g. `funcref` for [`WasmType::RefFunc`].
+    ///
+    /// Returns `None` for number and vector types.
+    pub const fn top_of(ty: WasmType) -> Option<Self> {
+        let heap_type = match ty {
+            WasmType::RefFunc => HeapType::Func,
+            WasmType::RefExtern => HeapType::Extern,
+            WasmType::RefCont => HeapType::Cont,
+            WasmType::RefExn => HeapType::Exn,
+            WasmType::RefAny => HeapType::Any,
+            WasmType::I32 | WasmType::I64 | WasmType::F32 | WasmType::F64 | WasmType::V128 => return None,
+        };
+        Some(Self::new(true, heap_type))
+    }
+}
+
+impl core::fmt::Display for HeapType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Func => "func",
+            Self::NoFunc => "nofunc",
+            Self::Extern => "extern",
+            Self::NoExtern => "noextern",
+            Self::Any => "any",
+            Self::Eq => "eq",
+            Self::I31 => "i31",
+            Self::Struct => "struct",
+            Self::Array => "array",
+            Self::None => "none",
+            Self::Exn => "exn",
+            Self::NoExn => "noexn",
+            Self::Cont => "cont",
+            Self::NoCont => "nocont",
+            Self::Concrete(idx) => return write!(f, "{idx}"),
+        })
+    }
+}
+
+impl core::fmt::Display for RefType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.nullable {
+            true => write!(f, "(ref null {})", self.heap_type),
+            false => write!(f, "(ref {})", self.heap_type),
+        }
+    }
+}
+
+/// A value type with precise reference types.
+///
+/// Unlike [`WasmType`], this keeps the heap type and nullability of references,
+/// which is needed to check subtyping between GC types.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+    Ref(RefType),
+}
+
+/// The type of a struct field or array element.
+///
+/// See <https://webassembly.github.io/gc/core/syntax/types.html#aggregate-types>
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub enum StorageType {
+    /// A packed 8-bit integer, read as an `i32`.
+    I8,
+    /// A packed 16-bit integer, read as an `i32`.
+    I16,
+    Val(ValType),
+}
+
+impl StorageType {
+    /// The size of a value of this type, in bytes.
+    #[inline]
+    pub const fn size(&self) -> usize {
+        match self {
+            Self::I8 => 1,
+            Self::I16 => 2,
+            Self::Val(ValType::I32 | ValType::F32 | ValType::Ref(_)) => 4,
+            Self::Val(ValType::I64 | ValType::F64) => 8,
+            Self::Val(ValType::V128) => 16,
+        }
+    }
+}
+
+/// A struct field or the element of an array.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldType {
+    pub storage: StorageType,
+    pub mutable: bool,
+}
+
+/// The structure of a type defined in the type section.
+///
+/// See <https://webassembly.github.io/gc/core/syntax/types.html#composite-types>
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub enum CompositeType {
+    Func {
+        params: Box<[ValType]>,
+        results: Box<[ValType]>,
+    },
+    Struct(Box<[FieldType]>),
+    Array(FieldType),
+    /// A continuation type, given as the index of the function type it wraps.
+    Cont(TypeAddr),
+}
+
+impl CompositeType {
+    /// The coarse [`WasmType`] of references to this type.
+    #[inline]
+    pub const fn ref_type(&self) -> WasmType {
+        match self {
+            Self::Func { .. } => WasmType::RefFunc,
+            Self::Struct(_) | Self::Array(_) => WasmType::RefAny,
+            Self::Cont(_) => WasmType::RefCont,
+        }
+    }
+}
+
+/// A type defined in the type section, with its declared supertype.
+///
+/// See <https://webassembly.github.io/gc/core/syntax/types.html#recursive-types>
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubType {
+    pub is_final: bool,
+    pub supertype: Option<TypeAddr>,
+    pub composite: CompositeType,
+}
@@ -1,4 +1,4 @@
-use super::{FuncAddr, GlobalAddr, LocalAddr, TableAddr, TypeAddr, ValueCounts, WasmType};
+use super::{FuncAddr, GlobalAddr, LocalAddr, RefType, TableAddr, TagAddr, TypeAddr, ValueCounts, WasmType};
 use crate::{ConstIdx, DataAddr, ElemAddr, ExternAddr, MemAddr};
 
 /// Represents a memory immediate in a WebAssembly memory instruction.
@@ -61,6 +61,17 @@ pub enum ConstInstruction {
     I64Add,
     I64Sub,
     I64Mul,
+    RefNullCont,
+    RefNullExn,
+    RefNullAny,
+    RefI31,
+    StructNew(TypeAddr),
+    StructNewDefault(TypeAddr),
+    ArrayNew(TypeAddr),
+    ArrayNewDefault(TypeAddr),
+    ArrayNewFixed(TypeAddr, u32),
+    AnyConvertExtern,
+    ExternConvertAny,
 }
 
 /// An integer comparison operator, currently only used for conditional jumps.
@@ -389,7 +400,74 @@ pub enum Instruction {
     F64x2RelaxedMin, F64x2RelaxedMax,
     I16x8RelaxedQ15mulrS,
     I16x8RelaxedDotI8x16I7x16S,
-    I32x4RelaxedDotI8x16I7x16AddS
+    I32x4RelaxedDotI8x16I7x16AddS,
+
+    // > Stack Switching
+    // See <https://github.com/WebAssembly/stack-switching/blob/main/proposals/stack-switching/Explainer.md>
+    ContNew(TypeAddr),
+    ContBind(TypeAddr, TypeAddr), // (argument_cont_type, result_cont_type)
+    Suspend(TagAddr),
+    Resume(TypeAddr, u32, u32), // (cont_type, resume_handlers_start, handler_count)
+    Switch(TypeAddr, TagAddr),
+
+    // > Exception Handling
+    // See <https://github.com/WebAssembly/exception-handling/blob/main/proposals/exception-handling/Exceptions.md>
+    Throw(TagAddr),
+    ThrowRef,
+
+    // > Threads
+    MemoryAtomicNotify(MemoryArg), MemoryAtomicWait32(MemoryArg), MemoryAtomicWait64(MemoryArg), AtomicFence,
+    I32AtomicLoad(MemoryArg), I64AtomicLoad(MemoryArg),
+    I32AtomicLoad8U(MemoryArg), I32AtomicLoad16U(MemoryArg),
+    I64AtomicLoad8U(MemoryArg), I64AtomicLoad16U(MemoryArg), I64AtomicLoad32U(MemoryArg),
+    I32AtomicStore(MemoryArg), I64AtomicStore(MemoryArg),
+    I32AtomicStore8(MemoryArg), I32AtomicStore16(MemoryArg),
+    I64AtomicStore8(MemoryArg), I64AtomicStore16(MemoryArg), I64AtomicStore32(MemoryArg),
+    I32AtomicRmwAdd(MemoryArg), I64AtomicRmwAdd(MemoryArg),
+    I32AtomicRmw8AddU(MemoryArg), I32AtomicRmw16AddU(MemoryArg),
+    I64AtomicRmw8AddU(MemoryArg), I64AtomicRmw16AddU(MemoryArg), I64AtomicRmw32AddU(MemoryArg),
+    I32AtomicRmwSub(MemoryArg), I64AtomicRmwSub(MemoryArg),
+    I32AtomicRmw8SubU(MemoryArg), I32AtomicRmw16SubU(MemoryArg),
+    I64AtomicRmw8SubU(MemoryArg), I64AtomicRmw16SubU(MemoryArg), I64AtomicRmw32SubU(MemoryArg),
+    I32AtomicRmwAnd(MemoryArg), I64AtomicRmwAnd(MemoryArg),
+    I32AtomicRmw8AndU(MemoryArg), I32AtomicRmw16AndU(MemoryArg),
+    I64AtomicRmw8AndU(MemoryArg), I64AtomicRmw16AndU(MemoryArg), I64AtomicRmw32AndU(MemoryArg),
+    I32AtomicRmwOr(MemoryArg), I64AtomicRmwOr(MemoryArg),
+    I32AtomicRmw8OrU(MemoryArg), I32AtomicRmw16OrU(MemoryArg),
+    I64AtomicRmw8OrU(MemoryArg), I64AtomicRmw16OrU(MemoryArg), I64AtomicRmw32OrU(MemoryArg),
+    I32AtomicRmwXor(MemoryArg), I64AtomicRmwXor(MemoryArg),
+    I32AtomicRmw8XorU(MemoryArg), I32AtomicRmw16XorU(MemoryArg),
+    I64AtomicRmw8XorU(MemoryArg), I64AtomicRmw16XorU(MemoryArg), I64AtomicRmw32XorU(MemoryArg),
+    I32AtomicRmwXchg(MemoryArg), I64AtomicRmwXchg(MemoryArg),
+    I32AtomicRmw8XchgU(MemoryArg), I32AtomicRmw16XchgU(MemoryArg),
+    I64AtomicRmw8XchgU(MemoryArg), I64AtomicRmw16XchgU(MemoryArg), I64AtomicRmw32XchgU(MemoryArg),
+    I32AtomicRmwCmpxchg(MemoryArg), I64AtomicRmwCmpxchg(MemoryArg),
+    I32AtomicRmw8CmpxchgU(MemoryArg), I32AtomicRmw16CmpxchgU(MemoryArg),
+    I64AtomicRmw8CmpxchgU(MemoryArg), I64AtomicRmw16CmpxchgU(MemoryArg), I64AtomicRmw32CmpxchgU(MemoryArg),
+
+    // > Garbage Collection
+    // See <https://github.com/WebAssembly/gc/blob/main/proposals/gc/MVP.md>
+    StructNew(TypeAddr), StructNewDefault(TypeAddr),
+    StructGet(TypeAddr, u32), StructGetS(TypeAddr, u32), StructGetU(TypeAddr, u32), StructSet(TypeAddr, u32),
+    ArrayNew(TypeAddr), ArrayNewDefault(TypeAddr), ArrayNewFixed(TypeAddr, u32),
+    ArrayNewData(TypeAddr, DataAddr), ArrayNewElem(TypeAddr, ElemAddr),
+    ArrayGet(TypeAddr), ArrayGetS(TypeAddr), ArrayGetU(TypeAddr), ArraySet(TypeAddr), ArrayLen,
+    ArrayFill(TypeAddr), ArrayCopy(TypeAddr, TypeAddr), // (dst_type, src_type)
+    ArrayInitData(TypeAddr, DataAddr), ArrayInitElem(TypeAddr, ElemAddr),
+    RefEq, RefTest(RefType), RefCast(RefType),
+    BrOnCastTest(RefType), // like `RefTest`, but keeps the reference on the stack for the `br_if` that follows
+    RefI31, I31GetS, I31GetU,
+    AnyConvertExtern, ExternConvertAny,
+
+    // > Typed Function References
+    // See <https://github.com/WebAssembly/function-references/blob/main/proposals/function-references/Overview.md>
+    CallRef(TypeAddr),
+    ReturnCallRef(TypeAddr),
+    RefAsNonNull,
+    BrOnNullTest, // like `RefIsNull`, but keeps the reference on the stack for the branch that follows
+
+    // > Memory Control
+    MemoryDiscard(MemAddr),
 }
 
 impl Instruction {
@@ -465,12 +543,79 @@ impl Instruction {
             | Self::V128Store8Lane(arg, ..)
             | Self::V128Store16Lane(arg, ..)
             | Self::V128Store32Lane(arg, ..)
-            | Self::V128Store64Lane(arg, ..) => Some(arg.mem_addr()),
+            | Self::V128Store64Lane(arg, ..)
+            | Self::MemoryAtomicNotify(arg)
+            | Self::MemoryAtomicWait32(arg)
+            | Self::MemoryAtomicWait64(arg)
+            | Self::I32AtomicLoad(arg)
+            | Self::I64AtomicLoad(arg)
+            | Self::I32AtomicLoad8U(arg)
+            | Self::I32AtomicLoad16U(arg)
+            | Self::I64AtomicLoad8U(arg)
+            | Self::I64AtomicLoad16U(arg)
+            | Self::I64AtomicLoad32U(arg)
+            | Self::I32AtomicStore(arg)
+            | Self::I64AtomicStore(arg)
+            | Self::I32AtomicStore8(arg)
+            | Self::I32AtomicStore16(arg)
+            | Self::I64AtomicStore8(arg)
+            | Self::I64AtomicStore16(arg)
+            | Self::I64AtomicStore32(arg)
+            | Self::I32AtomicRmwAdd(arg)
+            | Self::I64AtomicRmwAdd(arg)
+            | Self::I32AtomicRmw8AddU(arg)
+            | Self::I32AtomicRmw16AddU(arg)
+            | Self::I64AtomicRmw8AddU(arg)
+            | Self::I64AtomicRmw16AddU(arg)
+            | Self::I64AtomicRmw32AddU(arg)
+            | Self::I32AtomicRmwSub(arg)
+            | Self::I64AtomicRmwSub(arg)
+            | Self::I32AtomicRmw8SubU(arg)
+            | Self::I32AtomicRmw16SubU(arg)
+            | Self::I64AtomicRmw8SubU(arg)
+            | Self::I64AtomicRmw16SubU(arg)
+            | Self::I64AtomicRmw32SubU(arg)
+            | Self::I32AtomicRmwAnd(arg)
+            | Self::I64AtomicRmwAnd(arg)
+            | Self::I32AtomicRmw8AndU(arg)
+            | Self::I32AtomicRmw16AndU(arg)
+            | Self::I64AtomicRmw8AndU(arg)
+            | Self::I64AtomicRmw16AndU(arg)
+            | Self::I64AtomicRmw32AndU(arg)
+            | Self::I32AtomicRmwOr(arg)
+            | Self::I64AtomicRmwOr(arg)
+            | Self::I32AtomicRmw8OrU(arg)
+            | Self::I32AtomicRmw16OrU(arg)
+            | Self::I64AtomicRmw8OrU(arg)
+            | Self::I64AtomicRmw16OrU(arg)
+            | Self::I64AtomicRmw32OrU(arg)
+            | Self::I32AtomicRmwXor(arg)
+            | Self::I64AtomicRmwXor(arg)
+            | Self::I32AtomicRmw8XorU(arg)
+            | Self::I32AtomicRmw16XorU(arg)
+            | Self::I64AtomicRmw8XorU(arg)
+            | Self::I64AtomicRmw16XorU(arg)
+            | Self::I64AtomicRmw32XorU(arg)
+            | Self::I32AtomicRmwXchg(arg)
+            | Self::I64AtomicRmwXchg(arg)
+            | Self::I32AtomicRmw8XchgU(arg)
+            | Self::I32AtomicRmw16XchgU(arg)
+            | Self::I64AtomicRmw8XchgU(arg)
+            | Self::I64AtomicRmw16XchgU(arg)
+            | Self::I64AtomicRmw32XchgU(arg)
+            | Self::I32AtomicRmwCmpxchg(arg)
+            | Self::I64AtomicRmwCmpxchg(arg)
+            | Self::I32AtomicRmw8CmpxchgU(arg)
+            | Self::I32AtomicRmw16CmpxchgU(arg)
+            | Self::I64AtomicRmw8CmpxchgU(arg)
+            | Self::I64AtomicRmw16CmpxchgU(arg)
+            | Self::I64AtomicRmw32CmpxchgU(arg) => Some(arg.mem_addr()),
             Self::MemorySize(mem)
             | Self::MemoryGrow(mem)
             | Self::MemoryInit(mem, ..)
             | Self::MemoryFill(mem)
-            | Self::MemoryFillImm(mem, ..) => Some(*mem),
+            | Self::MemoryFillImm(mem, ..)
+            | Self::MemoryDiscard(mem) => Some(*mem),
             Self::MemoryCopy { dst_mem, src_mem } => Some(if *dst_mem >= *src_mem { *dst_mem } else { *src_mem }),
             _ => None,
         }
@@ -9,7 +9,10 @@
 //! Types used by [`tinywasm`](https://docs.rs/tinywasm) and [`tinywasm_parser`](https://docs.rs/tinywasm_parser).
 
 extern crate alloc;
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
 use core::ops::{Deref, Range};
 
 // Memory defaults
@@ -20,9 +23,11 @@ const fn max_page_count(page_size: u64) -> u64 {
     MAX_MEMORY_SIZE / page_size
 }
 
+mod gc;
 mod instructions;
 mod reference;
 mod value;
+pub use gc::*;
 pub use instructions::*;
 pub use reference::*;
 pub use value::*;
@@ -84,8 +89,17 @@ pub struct ModuleInner {
     /// A vector of type definitions, indexed by `TypeAddr`
     ///
     /// Corresponds to the `type` section of the original WebAssembly module.
+    /// Continuation types are represented by the function type they wrap.
     pub func_types: Arc<[Arc<FuncType>]>,
 
+    /// The types of the type section with precise reference types, indexed by `TypeAddr`
+    ///
+    /// Struct and array types only have an empty placeholder in `func_types`.
+    pub types: Box<[SubType]>,
+
+    /// The recursion groups of the type section, as ranges of `TypeAddr`s.
+    pub rec_groups: Box<[Range<TypeAddr>]>,
+
     /// Function index to type index mapping in module index space, including imports.
     pub func_type_idxs: Arc<[u32]>,
 
@@ -104,11 +118,21 @@ pub struct ModuleInner {
     /// Corresponds to the `table` section of the original WebAssembly module.
     pub table_types: Box<[TableType]>,
 
+    /// Initializer expressions of the tables in `table_types`.
+    ///
+    /// Tables without one start out filled with null references.
+    pub table_inits: Box<[Option<Box<[ConstInstruction]>>]>,
+
     /// Memory components of the WebAssembly module used to initialize memories.
     ///
     /// Corresponds to the `memory` section of the original WebAssembly module.
     pub memory_types: Box<[MemoryType]>,
 
+    /// Tags of the WebAssembly module, given as the index of their function type.
+    ///
+    /// Corresponds to the `tag` section of the original WebAssembly module.
+    pub tags: Box<[TypeAddr]>,
+
     /// Imports of the WebAssembly module.
     ///
     /// Corresponds to the `import` section of the original WebAssembly module.
@@ -129,6 +153,12 @@ pub struct ModuleInner {
 }
 
 impl Module {
+    /// Returns a weak reference to the module's contents, used to cache data derived from the module.
+    #[doc(hidden)]
+    pub fn downgrade(&self) -> Weak<ModuleInner> {
+        Arc::downgrade(&self.0)
+    }
+
     /// Returns an iterator over the module's import descriptors.
     ///
     /// The returned data mirrors the module's import section and preserves order.
@@ -139,6 +169,7 @@ impl Module {
                 ImportKind::Table(table_ty) => Some(ImportType::Table(table_ty)),
                 ImportKind::Memory(memory_ty) => Some(ImportType::Memory(memory_ty)),
                 ImportKind::Global(global_ty) => Some(ImportType::Global(global_ty)),
+                ImportKind::Tag(type_idx) => Some(ImportType::Tag(self.0.func_types.get(*type_idx as usize)?)),
             }?;
 
             Some(ModuleImport { module: import.module.as_ref(), name: import.name.as_ref(), ty })
@@ -160,6 +191,7 @@ impl Module {
                             | (ExternalKind::Table, ImportKind::Table(_))
                             | (ExternalKind::Memory, ImportKind::Memory(_))
                             | (ExternalKind::Global, ImportKind::Global(_))
+                            | (ExternalKind::Tag, ImportKind::Tag(_))
                     )
                 })
                 .count()
@@ -173,6 +205,7 @@ impl Module {
                         | (ExternalKind::Table, ImportKind::Table(_))
                         | (ExternalKind::Memory, ImportKind::Memory(_))
                         | (ExternalKind::Global, ImportKind::Global(_))
+                        | (ExternalKind::Tag, ImportKind::Tag(_))
                 )
             });
             let import = imports.nth(index)?;
@@ -182,6 +215,7 @@ impl Module {
                 ImportKind::Table(table_ty) => Some(ExportType::Table(table_ty)),
                 ImportKind::Memory(memory_ty) => Some(ExportType::Memory(memory_ty)),
                 ImportKind::Global(global_ty) => Some(ExportType::Global(global_ty)),
+                ImportKind::Tag(type_idx) => Some(ExportType::Tag(module.func_types.get(*type_idx as usize)?)),
             }
         }
 
@@ -220,6 +254,15 @@ impl Module {
                         ExportType::Global(&self.0.globals.get(idx - imported_globals)?.ty)
                     }
                 }
+                ExternalKind::Tag => {
+                    let imported_tags = imported_count(&self.0, ExternalKind::Tag);
+                    if idx < imported_tags {
+                        imported_type(&self.0, ExternalKind::Tag, idx)?
+                    } else {
+                        let type_idx = *self.0.tags.get(idx - imported_tags)?;
+                        ExportType::Tag(self.0.func_types.get(type_idx as usize)?)
+                    }
+                }
             };
 
             Some(ModuleExport { name: export.name.as_ref(), ty })
@@ -255,6 +298,8 @@ pub enum ImportType<'a> {
     Memory(&'a MemoryType),
     /// Imported global type.
     Global(&'a GlobalType),
+    /// Imported tag type, given as the function type of its payload.
+    Tag(&'a FuncType),
 }
 
 /// Exported entity type.
@@ -267,6 +312,97 @@ pub enum ExportType<'a> {
     Memory(&'a MemoryType),
     /// Exported global type.
     Global(&'a GlobalType),
+    /// Exported tag type, given as the function type of its payload.
+    Tag(&'a FuncType),
+}
+
+/// The type of an import or of an external value provided for it.
+///
+/// Formats like the WebAssembly text format, e.g. `(func (param i32) (result i64))`.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+pub enum ExternType {
+    /// A function type.
+    Func(FuncType),
+    /// A table type.
+    Table(TableType),
+    /// A memory type.
+    Memory(MemoryType),
+    /// A global type.
+    Global(GlobalType),
+    /// A tag type, given as the function type of its payload.
+    Tag(FuncType),
+}
+
+impl core::fmt::Display for ExternType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fn signature(f: &mut core::fmt::Formatter<'_>, ty: &FuncType) -> core::fmt::Result {
+            for (keyword, types) in [("param", ty.params()), ("result", ty.results())] {
+                if !types.is_empty() {
+                    write!(f, " ({keyword}")?;
+                    types.iter().try_for_each(|ty| write!(f, " {ty}"))?;
+                    f.write_str(")")?;
+                }
+            }
+            Ok(())
+        }
+
+        fn limits(
+            f: &mut core::fmt::Formatter<'_>,
+            arch: MemoryArch,
+            initial: u64,
+            max: Option<u64>,
+        ) -> core::fmt::Result {
+            if arch == MemoryArch::I64 {
+                f.write_str(" i64")?;
+            }
+            write!(f, " {initial}")?;
+            max.map_or(Ok(()), |max| write!(f, " {max}"))
+        }
+
+        match self {
+            Self::Func(ty) => {
+                f.write_str("(func")?;
+                signature(f, ty)?;
+            }
+            Self::Tag(ty) => {
+                f.write_str("(tag")?;
+                signature(f, ty)?;
+            }
+            Self::Table(ty) => {
+                f.write_str("(table")?;
+                limits(f, ty.arch, ty.size_initial, ty.size_max)?;
+                match ty.element_ref_type {
+                    Some(ref_type) => write!(f, " {ref_type}")?,
+                    None => write!(f, " {}", ty.element_type)?,
+                }
+            }
+            Self::Memory(ty) => {
+                f.write_str("(memory")?;
+                limits(f, ty.arch, ty.page_count_initial, ty.page_count_max)?;
+                if ty.shared {
+                    f.write_str(" shared")?;
+                }
+                if ty.page_size() != MEM_PAGE_SIZE {
+                    write!(f, " (pagesize {})", ty.page_size())?;
+                }
+            }
+            Self::Global(ty) => {
+                f.write_str("(global ")?;
+                if ty.mutable {
+                    f.write_str("(mut ")?;
+                }
+                match ty.ref_type {
+                    Some(ref_type) => write!(f, "{ref_type}")?,
+                    None => write!(f, "{}", ty.ty)?,
+                }
+                if ty.mutable {
+                    f.write_str(")")?;
+                }
+            }
+        }
+        f.write_str(")")
+    }
 }
 
 /// How instantiation should prepare local memories declared by the module.
@@ -298,6 +434,8 @@ pub enum ExternalKind {
     Memory,
     /// A WebAssembly Global.
     Global,
+    /// A WebAssembly Tag.
+    Tag,
 }
 
 /// A WebAssembly Address.
@@ -315,6 +453,9 @@ pub type GlobalAddr = Addr;
 pub type ElemAddr = Addr;
 pub type DataAddr = Addr;
 pub type ExternAddr = Addr;
+pub type TagAddr = Addr;
+pub type ContAddr = Addr;
+pub type ExnAddr = Addr;
 pub type ConstIdx = Addr;
 
 // additional internal addresses
@@ -332,6 +473,7 @@ pub enum ExternVal {
     Table(TableAddr),
     Memory(MemAddr),
     Global(GlobalAddr),
+    Tag(TagAddr),
 }
 
 impl ExternVal {
@@ -342,6 +484,7 @@ impl ExternVal {
             Self::Table(_) => ExternalKind::Table,
             Self::Memory(_) => ExternalKind::Memory,
             Self::Global(_) => ExternalKind::Global,
+            Self::Tag(_) => ExternalKind::Tag,
         }
     }
 
@@ -352,6 +495,7 @@ impl ExternVal {
             ExternalKind::Table => Self::Table(addr),
             ExternalKind::Memory => Self::Memory(addr),
             ExternalKind::Global => Self::Global(addr),
+            ExternalKind::Tag => Self::Tag(addr),
         }
     }
 }
@@ -408,7 +552,13 @@ impl<'a> FromIterator<&'a WasmType> for ValueCounts {
 
         for ty in iter {
             match ty {
-                WasmType::I32 | WasmType::F32 | WasmType::RefExtern | WasmType::RefFunc => counts.c32 += 1,
+                WasmType::I32
+                | WasmType::F32
+                | WasmType::RefExtern
+                | WasmType::RefFunc
+                | WasmType::RefCont
+                | WasmType::RefExn
+                | WasmType::RefAny => counts.c32 += 1,
                 WasmType::I64 | WasmType::F64 => counts.c64 += 1,
                 WasmType::V128 => counts.c128 += 1,
             }
@@ -435,6 +585,75 @@ pub struct WasmFunction {
 pub struct WasmFunctionData {
     pub v128_constants: Box<[[u8; 16]]>,
     pub branch_table_targets: Box<[u32]>,
+    pub resume_handlers: Box<[ResumeHandler]>,
+    pub try_ranges: Box<[TryRange]>,
+    pub catch_handlers: Box<[CatchHandler]>,
+}
+
+/// The instructions covered by a `try_table` block.
+///
+/// Ranges are ordered by their start, so nested blocks come after the blocks containing them.
+///
+/// See <https://github.com/WebAssembly/exception-handling/blob/main/proposals/exception-handling/Exceptions.md#try-blocks>
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub struct TryRange {
+    /// The first instruction of the block's body.
+    pub start: u32,
+    /// The instruction after the block's body.
+    pub end: u32,
+    /// The operand stack height, excluding locals, when the block was entered.
+    pub base: ValueCounts,
+    /// The first of the block's `catch_handlers`.
+    pub handlers_start: u32,
+    /// The number of `catch_handlers`.
+    pub handler_count: u32,
+}
+
+impl TryRange {
+    /// Whether the instruction at `ip` is part of this block.
+    #[inline]
+    pub const fn contains(&self, ip: u32) -> bool {
+        self.start <= ip && ip < self.end
+    }
+}
+
+/// A catch clause of a `try_table` block.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub struct CatchHandler {
+    /// The caught tag, or `None` for `catch_all` and `catch_all_ref`.
+    pub tag: Option<TagAddr>,
+    /// Whether the exception reference is pushed after the payload (`catch_ref` and `catch_all_ref`).
+    pub with_ref: bool,
+    /// The landing pad that branches to the clause's label.
+    pub target_ip: u32,
+}
+
+/// A handler installed by a `resume` instruction.
+///
+/// See <https://github.com/WebAssembly/stack-switching/blob/main/proposals/stack-switching/Explainer.md#resuming-continuations>
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(feature = "archive", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResumeHandler {
+    /// `(on $tag $label)`: suspending with `tag` continues at `target_ip` with the tag's
+    /// parameters and the suspended continuation on the stack.
+    Label { tag: TagAddr, target_ip: u32 },
+    /// `(on $tag switch)`: switching with `tag` replaces the running continuation in place.
+    Switch { tag: TagAddr },
+}
+
+impl ResumeHandler {
+    /// The tag handled by this handler.
+    #[inline]
+    pub const fn tag(&self) -> TagAddr {
+        match self {
+            Self::Label { tag, .. } | Self::Switch { tag } => *tag,
+        }
+    }
 }
 
 impl WasmFunctionData {
@@ -472,20 +691,39 @@ pub struct Global {
 pub struct GlobalType {
     pub mutable: bool,
     pub ty: WasmType,
+    /// The precise reference type of the global, if it is more specific than `ty`.
+    ///
+    /// `None` stands for a nullable reference to the top type of `ty`, e.g. `funcref`.
+    pub ref_type: Option<RefType>,
 }
 
 impl GlobalType {
     /// Create a new global type.
     pub const fn new(ty: WasmType, mutable: bool) -> Self {
-        Self { mutable, ty }
+        Self { mutable, ty, ref_type: None }
     }
 
     /// Set a different value type.
     pub const fn with_ty(mut self, ty: WasmType) -> Self {
         self.ty = ty;
+        self.ref_type = None;
+        self
+    }
+
+    /// Set a precise reference type, e.g. a non-nullable or typed function reference.
+    pub const fn with_ref_type(mut self, ref_type: RefType) -> Self {
+        self.ref_type = Some(ref_type);
         self
     }
 
+    /// The precise reference type of the global, or `None` if it doesn't hold references.
+    pub const fn precise_ref_type(&self) -> Option<RefType> {
+        match self.ref_type {
+            Some(ty) => Some(ty),
+            None => RefType::top_of(self.ty),
+        }
+    }
+
     /// Set global mutability.
     pub const fn with_mutable(mut self, mutable: bool) -> Self {
         self.mutable = mutable;
@@ -505,6 +743,10 @@ impl Default for GlobalType {
 pub struct TableType {
     arch: MemoryArch,
     pub element_type: WasmType,
+    /// The precise element type of the table, if it is more specific than `element_type`.
+    ///
+    /// `None` stands for a nullable reference to the top type of `element_type`, e.g. `funcref`.
+    pub element_ref_type: Option<RefType>,
     pub size_initial: u64,
     pub size_max: Option<u64>,
 }
@@ -516,12 +758,26 @@ impl TableType {
 
     /// Create a table with 32-bit indices.
     pub const fn new(element_type: WasmType, size_initial: u64, size_max: Option<u64>) -> Self {
-        Self { arch: MemoryArch::I32, element_type, size_initial, size_max }
+        Self { arch: MemoryArch::I32, element_type, element_ref_type: None, size_initial, size_max }
     }
 
     /// Create a table with 64-bit indices.
     pub const fn new64(element_type: WasmType, size_initial: u64, size_max: Option<u64>) -> Self {
-        Self { arch: MemoryArch::I64, element_type, size_initial, size_max }
+        Self { arch: MemoryArch::I64, element_type, element_ref_type: None, size_initial, size_max }
+    }
+
+    /// Set a precise element type, e.g. a non-nullable or typed function reference.
+    pub const fn with_element_ref_type(mut self, ty: RefType) -> Self {
+        self.element_ref_type = Some(ty);
+        self
+    }
+
+    /// The precise element type of the table.
+    pub const fn precise_element_type(&self) -> Option<RefType> {
+        match self.element_ref_type {
+            Some(ty) => Some(ty),
+            None => RefType::top_of(self.element_type),
+        }
     }
 
     #[inline]
@@ -539,6 +795,7 @@ pub struct MemoryType {
     page_count_initial: u64,
     page_count_max: Option<u64>,
     page_size: Option<u64>,
+    shared: bool,
 }
 
 impl MemoryType {
@@ -549,7 +806,7 @@ impl MemoryType {
         page_count_max: Option<u64>,
         page_size: Option<u64>,
     ) -> Self {
-        Self { arch, page_count_initial, page_count_max, page_size }
+        Self { arch, page_count_initial, page_count_max, page_size, shared: false }
     }
 
     #[inline]
@@ -572,6 +829,12 @@ impl MemoryType {
         if let Some(page_size) = self.page_size { page_size } else { MEM_PAGE_SIZE }
     }
 
+    /// Whether this memory can be shared between threads.
+    #[inline]
+    pub const fn is_shared(&self) -> bool {
+        self.shared
+    }
+
     #[inline]
     pub const fn initial_size(&self) -> u64 {
         self.page_count_initial * self.page_size()
@@ -605,6 +868,12 @@ impl MemoryType {
         self.page_size = page_size;
         self
     }
+
+    /// Mark the memory as shared or unshared.
+    pub const fn with_shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
 }
 
 impl Default for MemoryType {
@@ -638,6 +907,7 @@ pub enum ImportKind {
     Table(TableType),
     Memory(MemoryType),
     Global(GlobalType),
+    Tag(TypeAddr),
 }
 
 impl From<&ImportKind> for ExternalKind {
@@ -647,6 +917,7 @@ impl From<&ImportKind> for ExternalKind {
             ImportKind::Table(_) => Self::Table,
             ImportKind::Memory(_) => Self::Memory,
             ImportKind::Global(_) => Self::Global,
+            ImportKind::Tag(_) => Self::Tag,
         }
     }
 }
@@ -1,13 +1,27 @@
-use crate::{ExternAddr, FuncAddr};
+use crate::{ContAddr, ExnAddr, ExternAddr, FuncAddr};
 
 const NULL_REF: u32 = u32::MAX;
 
+/// Raw values from here up to (excluding) [`NULL_REF`] are unboxed 31-bit integers.
+const I31_BASE: u32 = 0x7FFF_FFFF;
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct ExternRef(u32);
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct FuncRef(u32);
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ContRef(u32);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ExnRef(u32);
+
+/// A reference in the `any` hierarchy: a struct, an array, an internalized
+/// external reference or an unboxed 31-bit integer (`i31ref`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct AnyRef(u32);
+
 #[cfg(feature = "debug")]
 impl core::fmt::Debug for ExternRef {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -28,6 +42,37 @@ impl core::fmt::Debug for FuncRef {
     }
 }
 
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for ContRef {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.addr() {
+            Some(addr) => write!(f, "cont({addr:?})"),
+            None => write!(f, "cont(null)"),
+        }
+    }
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for ExnRef {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.addr() {
+            Some(addr) => write!(f, "exn({addr:?})"),
+            None => write!(f, "exn(null)"),
+        }
+    }
+}
+
+#[cfg(feature = "debug")]
+impl core::fmt::Debug for AnyRef {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.as_i31_s(), self.heap_index()) {
+            (Some(value), _) => write!(f, "i31({value})"),
+            (_, Some(index)) => write!(f, "any({index})"),
+            _ => write!(f, "any(null)"),
+        }
+    }
+}
+
 impl FuncRef {
     #[inline]
     /// Create a new [`FuncRef`] from a [`FuncAddr`].
@@ -70,13 +115,21 @@ impl FuncRef {
 }
 
 impl ExternRef {
+    /// The highest address an [`ExternRef`] created with [`ExternRef::new`] can have.
+    ///
+    /// Addresses above it are reserved for references created by the store, e.g. with `Store::new_extern_ref`.
+    pub const MAX_ADDR: ExternAddr = 0x7FFF_FFFF;
+
     #[inline]
     /// Create a new [`ExternRef`] from an [`ExternAddr`].
-    /// Should only be used by the runtime.
-    pub const fn new(addr: Option<ExternAddr>) -> Self {
+    ///
+    /// Returns `None` if `addr` is above [`ExternRef::MAX_ADDR`].
+    /// To pass host values to WebAssembly, use `Store::new_extern_ref` instead.
+    pub const fn new(addr: Option<ExternAddr>) -> Option<Self> {
         match addr {
-            Some(addr) => Self(addr),
-            None => Self::null(),
+            Some(addr) if addr > Self::MAX_ADDR => None,
+            Some(addr) => Some(Self(addr)),
+            None => Some(Self::null()),
         }
     }
 
@@ -110,3 +163,147 @@ impl ExternRef {
         self.0
     }
 }
+
+impl ContRef {
+    #[inline]
+    /// Create a new [`ContRef`] from a [`ContAddr`].
+    /// Should only be used by the runtime.
+    pub const fn new(addr: Option<ContAddr>) -> Self {
+        match addr {
+            Some(addr) => Self(addr),
+            None => Self::null(),
+        }
+    }
+
+    /// Create a null [`ContRef`].
+    #[inline]
+    pub const fn null() -> Self {
+        Self(NULL_REF)
+    }
+
+    /// Check if the [`ContRef`] is null.
+    #[inline]
+    pub const fn is_null(&self) -> bool {
+        self.0 == NULL_REF
+    }
+
+    /// Get the [`ContAddr`] from the [`ContRef`].
+    #[inline]
+    pub const fn addr(&self) -> Option<ContAddr> {
+        if self.is_null() { None } else { Some(self.0) }
+    }
+
+    #[inline]
+    #[doc(hidden)]
+    pub const fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+
+    #[inline]
+    #[doc(hidden)]
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+}
+
+impl ExnRef {
+    #[inline]
+    /// Create a new [`ExnRef`] from a [`ExnAddr`].
+    /// Should only be used by the runtime.
+    pub const fn new(addr: Option<ExnAddr>) -> Self {
+        match addr {
+            Some(addr) => Self(addr),
+            None => Self::null(),
+        }
+    }
+
+    /// Create a null [`ExnRef`].
+    #[inline]
+    pub const fn null() -> Self {
+        Self(NULL_REF)
+    }
+
+    /// Check if the [`ExnRef`] is null.
+    #[inline]
+    pub const fn is_null(&self) -> bool {
+        self.0 == NULL_REF
+    }
+
+    /// Get the [`ExnAddr`] from the [`ExnRef`].
+    #[inline]
+    pub const fn addr(&self) -> Option<ExnAddr> {
+        if self.is_null() { None } else { Some(self.0) }
+    }
+
+    #[inline]
+    #[doc(hidden)]
+    pub const fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+
+    #[inline]
+    #[doc(hidden)]
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+}
+
+impl AnyRef {
+    /// Create a null [`AnyRef`].
+    #[inline]
+    pub const fn null() -> Self {
+        Self(NULL_REF)
+    }
+
+    /// Create an `i31ref` from the lower 31 bits of `value`.
+    #[inline]
+    pub const fn i31(value: i32) -> Self {
+        Self(I31_BASE + (value as u32 & 0x7FFF_FFFF))
+    }
+
+    /// Check if the [`AnyRef`] is null.
+    #[inline]
+    pub const fn is_null(&self) -> bool {
+        self.0 == NULL_REF
+    }
+
+    /// Check if the [`AnyRef`] is an `i31ref`.
+    #[inline]
+    pub const fn is_i31(&self) -> bool {
+        self.0 >= I31_BASE && self.0 != NULL_REF
+    }
+
+    /// Get the value of an `i31ref`, sign-extended to 32 bits.
+    #[inline]
+    pub const fn as_i31_s(&self) -> Option<i32> {
+        match self.as_i31_u() {
+            Some(value) => Some(((value << 1) as i32) >> 1),
+            None => None,
+        }
+    }
+
+    /// Get the value of an `i31ref`, zero-extended to 32 bits.
+    #[inline]
+    pub const fn as_i31_u(&self) -> Option<u32> {
+        if self.is_i31() { Some(self.0 - I31_BASE) } else { None }
+    }
+
+    /// Get the index of the referenced object in the store's GC heap.
+    #[inline]
+    #[doc(hidden)]
+    pub const fn heap_index(&self) -> Option<u32> {
+        if self.0 < I31_BASE { Some(self.0) } else { None }
+    }
+
+    #[inline]
+    #[doc(hidden)]
+    pub const fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+
+    #[inline]
+    #[doc(hidden)]
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+}
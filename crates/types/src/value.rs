@@ -1,6 +1,6 @@
 use core::fmt::Debug;
 
-use crate::{ConstInstruction, ExternRef, FuncRef};
+use crate::{AnyRef, ConstInstruction, ContRef, ExnRef, ExternRef, FuncRef};
 
 /// A WebAssembly value.
 ///
@@ -20,6 +20,9 @@ pub enum WasmValue {
     V128([u8; 16]),
     RefExtern(ExternRef),
     RefFunc(FuncRef),
+    RefCont(ContRef),
+    RefExn(ExnRef),
+    RefAny(AnyRef),
 }
 
 impl Debug for WasmValue {
@@ -34,10 +37,22 @@ impl Debug for WasmValue {
             Self::RefExtern(i) => write!(f, "ref({i:?})"),
             #[cfg(feature = "debug")]
             Self::RefFunc(i) => write!(f, "func({i:?})"),
+            #[cfg(feature = "debug")]
+            Self::RefCont(i) => write!(f, "cont({i:?})"),
+            #[cfg(feature = "debug")]
+            Self::RefExn(i) => write!(f, "exn({i:?})"),
+            #[cfg(feature = "debug")]
+            Self::RefAny(i) => write!(f, "any({i:?})"),
             #[cfg(not(feature = "debug"))]
             Self::RefExtern(_) => write!(f, "ref()"),
             #[cfg(not(feature = "debug"))]
             Self::RefFunc(_) => write!(f, "func()"),
+            #[cfg(not(feature = "debug"))]
+            Self::RefCont(_) => write!(f, "cont()"),
+            #[cfg(not(feature = "debug"))]
+            Self::RefExn(_) => write!(f, "exn()"),
+            #[cfg(not(feature = "debug"))]
+            Self::RefAny(_) => write!(f, "any()"),
         }
     }
 }
@@ -46,6 +61,12 @@ impl WasmValue {
     #[inline]
     /// Get the matching [`ConstInstruction`] for this value.
     pub fn const_instr(&self) -> alloc::boxed::Box<[ConstInstruction]> {
+        if let Self::RefAny(i) = self
+            && let Some(value) = i.as_i31_s()
+        {
+            return alloc::boxed::Box::new([ConstInstruction::I32Const(value), ConstInstruction::RefI31]);
+        }
+
         alloc::boxed::Box::new([match self {
             Self::I32(i) => ConstInstruction::I32Const(*i),
             Self::I64(i) => ConstInstruction::I64Const(*i),
@@ -54,6 +75,9 @@ impl WasmValue {
             Self::V128(i) => ConstInstruction::V128Const(*i),
             Self::RefFunc(i) => ConstInstruction::RefFunc(i.addr()),
             Self::RefExtern(i) => ConstInstruction::RefExtern(i.addr()),
+            Self::RefCont(_) => ConstInstruction::RefNullCont,
+            Self::RefExn(_) => ConstInstruction::RefNullExn,
+            Self::RefAny(_) => ConstInstruction::RefNullAny,
         }])
     }
 
@@ -68,6 +92,9 @@ impl WasmValue {
             WasmType::V128 => Self::V128([0; 16]),
             WasmType::RefFunc => Self::RefFunc(FuncRef::null()),
             WasmType::RefExtern => Self::RefExtern(ExternRef::null()),
+            WasmType::RefCont => Self::RefCont(ContRef::null()),
+            WasmType::RefExn => Self::RefExn(ExnRef::null()),
+            WasmType::RefAny => Self::RefAny(AnyRef::null()),
         }
     }
 
@@ -80,6 +107,9 @@ impl WasmValue {
             (Self::V128(a), Self::V128(b)) => a == b || Self::v128_nan_eq(*a, *b),
             (Self::RefExtern(addr), Self::RefExtern(addr2)) => addr == addr2,
             (Self::RefFunc(addr), Self::RefFunc(addr2)) => addr == addr2,
+            (Self::RefCont(addr), Self::RefCont(addr2)) => addr == addr2,
+            (Self::RefExn(addr), Self::RefExn(addr2)) => addr == addr2,
+            (Self::RefAny(addr), Self::RefAny(addr2)) => addr == addr2,
             (Self::F32(a), Self::F32(b)) => a.is_nan() && b.is_nan() || a.to_bits() == b.to_bits(),
             (Self::F64(a), Self::F64(b)) => a.is_nan() && b.is_nan() || a.to_bits() == b.to_bits(),
             _ => false,
@@ -146,6 +176,9 @@ impl From<&WasmValue> for WasmType {
             WasmValue::V128(_) => WasmType::V128,
             WasmValue::RefExtern(_) => WasmType::RefExtern,
             WasmValue::RefFunc(_) => WasmType::RefFunc,
+            WasmValue::RefCont(_) => WasmType::RefCont,
+            WasmValue::RefExn(_) => WasmType::RefExn,
+            WasmValue::RefAny(_) => WasmType::RefAny,
         }
     }
 }
@@ -175,6 +208,29 @@ pub enum WasmType {
     RefFunc,
     /// A reference to an external value.
     RefExtern,
+    /// A reference to a continuation.
+    RefCont,
+    /// A reference to an exception.
+    RefExn,
+    /// A reference to a struct, an array or an `i31ref`.
+    RefAny,
+}
+
+impl core::fmt::Display for WasmType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::V128 => "v128",
+            Self::RefFunc => "funcref",
+            Self::RefExtern => "externref",
+            Self::RefCont => "contref",
+            Self::RefExn => "exnref",
+            Self::RefAny => "anyref",
+        })
+    }
 }
 
 impl WasmType {
@@ -231,4 +287,7 @@ impl_conversion_for_wasmvalue! {
     [u8; 16] => V128, as_v128, "Return the raw little-endian bytes from a `WasmValue`, if it is a `V128`.";
     ExternRef => RefExtern, as_ref_extern, "Return the [`ExternRef`] from a `WasmValue`, if it is one";
     FuncRef => RefFunc, as_ref_func, "Return the [`FuncRef`] from a `WasmValue`, if it is one";
+    ContRef => RefCont, as_ref_cont, "Return the [`ContRef`] from a `WasmValue`, if it is one";
+    ExnRef => RefExn, as_ref_exn, "Return the [`ExnRef`] from a `WasmValue`, if it is one";
+    AnyRef => RefAny, as_ref_any, "Return the [`AnyRef`] from a `WasmValue`, if it is one";
 }
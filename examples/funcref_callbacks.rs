@@ -1,5 +1,5 @@
 use eyre::Result;
-use tinywasm::{FuncContext, HostFunction, Imports, ModuleInstance, Store, types::FuncRef};
+use tinywasm::{FuncContext, Function, HostFunction, Imports, ModuleInstance, Store, types::FuncRef};
 
 const LHS: i32 = 5;
 const RHS: i32 = 3;
@@ -53,9 +53,11 @@ fn run_passed_funcref_example() -> Result<()> {
     let mul = HostFunction::from(&mut store, |_, (lhs, rhs): (i32, i32)| -> tinywasm::Result<i32> { Ok(lhs * rhs) });
     let call_this =
         HostFunction::from(&mut store, |mut ctx: FuncContext<'_>, func_ref: FuncRef| -> tinywasm::Result<()> {
-            // Host cannot call a funcref directly, so it routes through Wasm.
-            let call_by_ref = ctx.module().func::<(FuncRef, i32, i32), i32>(ctx.store(), "call_binop_by_ref")?;
-            let _result = call_by_ref.call(ctx.store_mut(), (func_ref, LHS, RHS))?;
+            // Look up the callback's signature and call it directly.
+            let Some(callback) = Function::from_funcref(ctx.store(), func_ref)? else {
+                return Err(tinywasm::Error::Other("null callback".into()));
+            };
+            let _result = ctx.call(&callback.typed::<(i32, i32), i32>()?, (LHS, RHS))?;
             Ok(())
         });
 
@@ -116,9 +118,13 @@ fn run_returned_funcref_example() -> Result<()> {
         get_funcrefs.call(&mut store, ())?
     };
 
+    // Calling through Wasm and calling the function handle directly give the same result.
     let call_by_ref = instance.func::<(FuncRef, i32, i32), i32>(&store, "call_binop_by_ref")?;
     for func_ref in [add_ref, sub_ref, mul_ref] {
-        let _result = call_by_ref.call(&mut store, (func_ref, LHS, RHS))?;
+        let via_wasm = call_by_ref.call(&mut store, (func_ref, LHS, RHS))?;
+        let func = Function::from_funcref(&store, func_ref)?.expect("non-null funcref");
+        let direct = func.typed::<(i32, i32), i32>()?.call(&mut store, (LHS, RHS))?;
+        assert_eq!(via_wasm, direct);
     }
     Ok(())
 }